@@ -1,48 +1,605 @@
 // ASTノード定義
+use crate::float::FloatTy;
+use crate::int::IntTy;
+use crate::lexer::Span;
+use crate::resolve::Binding;
+use std::cell::Cell;
+use std::fmt;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum Expr {
     Number(i64),
+    // 接尾辞のある整数リテラル: 255u8（spanはリテラルの位置）。
+    // 接尾辞がなく i64 に収まらないリテラルは None で、型は型注釈や使われ方で決まる
+    // （決まらなければ u64 / u128）
+    TypedNumber(u128, Option<IntTy>, Span),
+    // 小数リテラル: 1.5 / 2.0f32（接尾辞がなければ型は使われ方で決まる）
+    Float(f64, Option<FloatTy>),
     Bool(bool),
+    Char(char),
     Str(String),
-    Add(Box<Expr>, Box<Expr>),
-    Var(String),
-    Call(String, Vec<Expr>),
+    // 二項演算（spanは演算子の位置。型エラーの報告用）
+    Binary(BinOp, Box<Expr>, Box<Expr>, Span),
+    // 単項演算（spanは演算子の位置。あふれたときの報告用）
+    Unary(UnaryOp, Box<Expr>, Span),
+    // 参照 &x / &mut x（spanは & の位置）。関数の引数にだけ書ける。
+    // 値はそのまま渡し、&mut の引数は呼び出しの後に呼び出し元へ書き戻す
+    Ref(Box<Expr>, bool, Span),
+    // 変数・関数の呼び出し。名前が指すものは評価の前に名前の解決で書き込む
+    Var(String, Span, Resolved),
+    Call(String, Vec<Expr>, Span, Resolved),
+    // メソッド呼び出し: <recv>.<name>(<args>)（spanはメソッド名の位置）
+    MethodCall(Box<Expr>, String, Vec<Expr>, Span),
     Block(Vec<Stmt>),
-    StructInit(String, Vec<(String, Expr)>),
+    // タプル: (a, b, c)。() はユニット
+    Tuple(Vec<Expr>),
+    // 配列リテラル: [a, b, c] / vec![a, b, c]
+    Array(Vec<Expr>),
+    // 繰り返し配列リテラル: [value; count]
+    ArrayRepeat(Box<Expr>, Box<Expr>),
+    // vec![...]（中身は Array / ArrayRepeat。値は配列と同じだが、所有権の検査では Copy にならない）
+    Vec(Box<Expr>),
+    // 添字アクセス: target[index]（spanは範囲外エラーの報告用）
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    // 代入: target = value / target += value など
+    Assign {
+        target: Box<Expr>,
+        op: Option<BinOp>,
+        value: Box<Expr>,
+    },
+    // 構造体リテラル: Name { field: expr }（フィールドごとにフィールド名の位置を持つ）
+    StructInit(String, Vec<(String, Expr, Span)>),
+    // フィールドアクセス: p.x / タプルの要素 t.0
     FieldAccess(Box<Expr>, String),
     If {
         cond: Box<Expr>,
         then_branch: Box<Expr>,
         else_branch: Option<Box<Expr>>,
     },
+    // for <pattern> in <iter> <body>
     For {
-        var: String,
-        start: Box<Expr>,
-        end: Box<Expr>,
+        pattern: Pattern,
+        iter: Box<Expr>,
+        body: Box<Expr>,
+    },
+    // 範囲式: a..b / a..=b / a.. / ..b
+    Range {
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+        inclusive: bool,
+    },
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<(Pattern, Expr)>,
+    },
+    // 型変換: <expr> as <型>（spanは as の位置）
+    Cast(Box<Expr>, String, Span),
+    // return式（値なしの `return;` は None）
+    Return(Option<Box<Expr>>),
+    // <expr>? : None / Err なら関数から早期リターンする
+    Try(Box<Expr>),
+    // クロージャ: |a, b| a + b / move || { ... }
+    Closure {
+        params: Vec<Pattern>,
         body: Box<Expr>,
     },
+    // format!("{} {:.3}", a, b)（println! は print(format!(..)) になる）
+    Format {
+        pieces: Vec<FmtPiece>,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    // panic!(..) / unreachable!(..) / todo!(..) / assert!(cond, ..) / assert_eq!(a, b, ..) など。
+    // args は検査する式、text はそのソース上の文字列、message は続きの書式を format! にしたもの
+    Panic {
+        kind: PanicKind,
+        args: Vec<Expr>,
+        text: Vec<String>,
+        message: Option<Box<Expr>>,
+        span: Span,
+    },
+}
+
+// 失敗すると実行時エラーになるマクロ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PanicKind {
+    Panic,
+    Unreachable,
+    Todo,
+    Assert,
+    AssertEq,
+    AssertNe,
+}
+
+impl PanicKind {
+    pub fn from_name(name: &str) -> Option<PanicKind> {
+        match name {
+            "panic" => Some(PanicKind::Panic),
+            "unreachable" => Some(PanicKind::Unreachable),
+            "todo" => Some(PanicKind::Todo),
+            "assert" => Some(PanicKind::Assert),
+            "assert_eq" => Some(PanicKind::AssertEq),
+            "assert_ne" => Some(PanicKind::AssertNe),
+            _ => None,
+        }
+    }
+
+    // メッセージより前に書く式の数
+    pub fn arity(self) -> usize {
+        match self {
+            PanicKind::Panic | PanicKind::Unreachable | PanicKind::Todo => 0,
+            PanicKind::Assert => 1,
+            PanicKind::AssertEq | PanicKind::AssertNe => 2,
+        }
+    }
+}
+
+// 書式文字列を分解したもの
+#[derive(Debug, Clone)]
+pub enum FmtPiece {
+    Lit(String),
+    // {} / {0:?} / {name:>8.3} など（index は args の位置）
+    Arg { index: usize, spec: FmtSpec },
+    // 解釈できない {..}。型検査で報告する
+    Invalid(String),
+}
+
+// {:<fill><align><0><width>.<precision><?>}
+#[derive(Debug, Clone, Default)]
+pub struct FmtSpec {
+    pub fill: Option<char>,
+    // '<' '^' '>' のいずれか
+    pub align: Option<char>,
+    pub zero: bool,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+    pub debug: bool,
+}
+
+// 名前の解決（crate::resolve）の結果。解決する前と、見つからなかった名前は None
+#[derive(Debug, Clone, Default)]
+pub struct Resolved(Cell<Option<Binding>>);
+
+impl Resolved {
+    pub fn get(&self) -> Option<Binding> {
+        self.0.get()
+    }
+
+    pub fn set(&self, binding: Option<Binding>) {
+        self.0.set(binding);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    // *x（値はそのまま。代入先では中身の場所を表す）
+    Deref,
+}
+
+// let・関数の引数・for・matchのパターン
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard,
+    Number(i64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    // 変数名と、それを書いた位置（型の問い合わせ用）
+    Bind(String, Span),
+    Tuple(Vec<Pattern>),
+    // Point { x, y: py, .. }
+    Struct {
+        name: String,
+        fields: Vec<(String, Pattern)>,
+        rest: bool,
+    },
+    // [first, .., last]
+    Slice(Vec<Pattern>),
+    // タプル・スライス中の `..`（`rest @ ..` なら残りを束縛する）
+    Rest(Option<String>),
+    // Some(x) / None / Shape::Circle(r) / Color::Red（名前はパスの最後の部分だけ）
+    Variant(String, Vec<Pattern>),
 }
 
+impl Pattern {
+    // 必ず一致するパターンか（let・関数の引数・forに書けるのはこれだけ）。
+    // スライスパターンは配列の長さに依存するので実行時に検査する
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Bind(..) | Pattern::Rest(_) => true,
+            Pattern::Number(_) | Pattern::Bool(_) | Pattern::Char(_) | Pattern::Str(_) => false,
+            Pattern::Tuple(items) | Pattern::Slice(items) => {
+                items.iter().all(Pattern::is_irrefutable)
+            }
+            Pattern::Struct { fields, .. } => fields.iter().all(|(_, p)| p.is_irrefutable()),
+            Pattern::Variant(..) => false,
+        }
+    }
+
+    // パターンが束縛する変数の名前（最初に現れた順。同じ名前は1つにする）
+    pub fn names(&self) -> Vec<String> {
+        fn collect(p: &Pattern, names: &mut Vec<String>) {
+            match p {
+                Pattern::Bind(name, _) | Pattern::Rest(Some(name)) if !names.contains(name) => {
+                    names.push(name.clone());
+                }
+                Pattern::Tuple(items) | Pattern::Slice(items) | Pattern::Variant(_, items) => {
+                    items.iter().for_each(|p| collect(p, names))
+                }
+                Pattern::Struct { fields, .. } => {
+                    fields.iter().for_each(|(_, p)| collect(p, names))
+                }
+                _ => {}
+            }
+        }
+        let mut names = Vec::new();
+        collect(self, &mut names);
+        names
+    }
+}
+
+// エラーメッセージ用に、書かれたときの形で表示する
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |items: &[Pattern]| {
+            items
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Number(n) => write!(f, "{}", n),
+            Pattern::Bool(b) => write!(f, "{}", b),
+            Pattern::Char(c) => write!(f, "{:?}", c),
+            Pattern::Str(s) => write!(f, "{:?}", s),
+            Pattern::Bind(name, _) => write!(f, "{}", name),
+            Pattern::Tuple(items) if items.len() == 1 => write!(f, "({},)", items[0]),
+            Pattern::Tuple(items) => write!(f, "({})", list(items)),
+            Pattern::Struct { name, fields, rest } => {
+                let mut parts: Vec<String> = fields
+                    .iter()
+                    .map(|(field, p)| match p {
+                        Pattern::Bind(name, _) if name == field => field.clone(),
+                        p => format!("{}: {}", field, p),
+                    })
+                    .collect();
+                if *rest {
+                    parts.push("..".to_string());
+                }
+                write!(f, "{} {{ {} }}", name, parts.join(", "))
+            }
+            Pattern::Slice(items) => write!(f, "[{}]", list(items)),
+            Pattern::Rest(None) => write!(f, ".."),
+            Pattern::Rest(Some(name)) => write!(f, "{} @ ..", name),
+            Pattern::Variant(name, items) if items.is_empty() => write!(f, "{}", name),
+            Pattern::Variant(name, items) => write!(f, "{}({})", name, list(items)),
+        }
+    }
+}
+
+// 型引数の並び: <T: PartialOrd + Clone, U>（where 節の境界もここに入る）
+pub type Generics = Vec<(String, Vec<String>)>;
+
 #[derive(Debug, Clone)]
 pub enum Stmt {
     Expr(Expr),
+    // fn name<T>(a: i64, b) -> i64 { ... }（型注釈は省略できる）
+    // self を取るメソッドでは self の型注釈が "Self" / "&Self" / "&mut Self" になる
     FuncDef {
         name: String,
-        params: Vec<String>,
+        generics: Generics,
+        params: Vec<Pattern>,
+        param_tys: Vec<Option<String>>,
+        ret: Option<String>,
         body: Box<Expr>,
+        // #[test] が付いていれば真（nasl --test で実行する）
+        test: bool,
+        span: Span,
     },
+    // struct Name<T> { field: Type, ... }
+    // #[derive(...)] で指定されたトレイト名は derives に入る
     StructDef {
         name: String,
-        fields: Vec<String>,
+        generics: Generics,
+        fields: Vec<(String, String)>,
+        derives: Vec<String>,
+        span: Span,
     },
-    Let {
+    // enum Name<T> { A, B(T, i64), ... }（バリアントの名前と中身の型）
+    EnumDef {
         name: String,
+        generics: Generics,
+        variants: Vec<(String, Vec<String>)>,
+        derives: Vec<String>,
+        span: Span,
+    },
+    // trait Name<T> { type Item; fn f(&self) -> i64; fn g(&self) { ... } }
+    // 本体のないメソッドは本体が空のブロックになり、名前が required に入る
+    TraitDef {
+        name: String,
+        generics: Generics,
+        // 関連型の名前
+        assoc: Vec<String>,
+        methods: Vec<Stmt>,
+        required: Vec<String>,
+        span: Span,
+    },
+    // impl<T> [Trait for] Type<T> { type Item = T; fn ... }
+    // メソッド本体の Self はパーサーで型名に置き換えてある
+    ImplDef {
+        generics: Generics,
+        trait_name: Option<String>,
+        target: String,
+        // 関連型（名前, 型）
+        assoc: Vec<(String, String)>,
+        methods: Vec<Stmt>,
+        span: Span,
+    },
+    Let {
+        pattern: Pattern,
         value: Expr,
         mutable: bool,
         ty: Option<String>,
+        span: Span,
     },
     Print(Box<Expr>),
     Import(String),
-    Error(String),
+    // 解析エラー（メッセージと、エラーを見つけた位置）
+    Error(String, Span),
+}
+
+impl Expr {
+    // 式とその中のすべての式（ブロック内の文も含む）を順にたどる
+    pub fn walk(&self, f: &mut impl FnMut(&Expr)) {
+        f(self);
+        match self {
+            Expr::Number(_)
+            | Expr::TypedNumber(..)
+            | Expr::Float(..)
+            | Expr::Bool(_)
+            | Expr::Char(_)
+            | Expr::Str(_)
+            | Expr::Var(..) => {}
+            Expr::Binary(_, a, b, _) | Expr::ArrayRepeat(a, b) => {
+                a.walk(f);
+                b.walk(f);
+            }
+            Expr::Index { target, index, .. } => {
+                target.walk(f);
+                index.walk(f);
+            }
+            Expr::Assign { target, value, .. } => {
+                target.walk(f);
+                value.walk(f);
+            }
+            Expr::Unary(_, e, _)
+            | Expr::Ref(e, ..)
+            | Expr::Vec(e)
+            | Expr::FieldAccess(e, _)
+            | Expr::Try(e)
+            | Expr::Cast(e, ..)
+            | Expr::Closure { body: e, .. } => e.walk(f),
+            Expr::Call(_, args, ..)
+            | Expr::Tuple(args)
+            | Expr::Array(args)
+            | Expr::Format { args, .. } => args.iter().for_each(|a| a.walk(f)),
+            Expr::MethodCall(recv, _, args, _) => {
+                recv.walk(f);
+                args.iter().for_each(|a| a.walk(f));
+            }
+            Expr::StructInit(_, inits) => inits.iter().for_each(|(_, e, _)| e.walk(f)),
+            Expr::Block(stmts) => stmts.iter().for_each(|s| s.walk(f)),
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                cond.walk(f);
+                then_branch.walk(f);
+                if let Some(e) = else_branch {
+                    e.walk(f);
+                }
+            }
+            Expr::For { iter, body, .. } => {
+                iter.walk(f);
+                body.walk(f);
+            }
+            Expr::Range { start, end, .. } => {
+                for e in [start, end].into_iter().flatten() {
+                    e.walk(f);
+                }
+            }
+            Expr::Match { scrutinee, arms } => {
+                scrutinee.walk(f);
+                arms.iter().for_each(|(_, e)| e.walk(f));
+            }
+            Expr::Return(e) => {
+                if let Some(e) = e {
+                    e.walk(f);
+                }
+            }
+            Expr::Panic { args, message, .. } => {
+                args.iter().for_each(|a| a.walk(f));
+                if let Some(e) = message {
+                    e.walk(f);
+                }
+            }
+        }
+    }
+
+    // walk と同じ順に、式を書き換えながらたどる
+    pub fn walk_mut(&mut self, f: &mut impl FnMut(&mut Expr)) {
+        f(self);
+        match self {
+            Expr::Number(_)
+            | Expr::TypedNumber(..)
+            | Expr::Float(..)
+            | Expr::Bool(_)
+            | Expr::Char(_)
+            | Expr::Str(_)
+            | Expr::Var(..) => {}
+            Expr::Binary(_, a, b, _) | Expr::ArrayRepeat(a, b) => {
+                a.walk_mut(f);
+                b.walk_mut(f);
+            }
+            Expr::Index { target, index, .. } => {
+                target.walk_mut(f);
+                index.walk_mut(f);
+            }
+            Expr::Assign { target, value, .. } => {
+                target.walk_mut(f);
+                value.walk_mut(f);
+            }
+            Expr::Unary(_, e, _)
+            | Expr::Ref(e, ..)
+            | Expr::Vec(e)
+            | Expr::FieldAccess(e, _)
+            | Expr::Try(e)
+            | Expr::Cast(e, ..)
+            | Expr::Closure { body: e, .. } => e.walk_mut(f),
+            Expr::Call(_, args, ..)
+            | Expr::Tuple(args)
+            | Expr::Array(args)
+            | Expr::Format { args, .. } => args.iter_mut().for_each(|a| a.walk_mut(f)),
+            Expr::MethodCall(recv, _, args, _) => {
+                recv.walk_mut(f);
+                args.iter_mut().for_each(|a| a.walk_mut(f));
+            }
+            Expr::StructInit(_, inits) => inits.iter_mut().for_each(|(_, e, _)| e.walk_mut(f)),
+            Expr::Block(stmts) => stmts.iter_mut().for_each(|s| s.walk_mut(f)),
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                cond.walk_mut(f);
+                then_branch.walk_mut(f);
+                if let Some(e) = else_branch {
+                    e.walk_mut(f);
+                }
+            }
+            Expr::For { iter, body, .. } => {
+                iter.walk_mut(f);
+                body.walk_mut(f);
+            }
+            Expr::Range { start, end, .. } => {
+                for e in [start, end].into_iter().flatten() {
+                    e.walk_mut(f);
+                }
+            }
+            Expr::Match { scrutinee, arms } => {
+                scrutinee.walk_mut(f);
+                arms.iter_mut().for_each(|(_, e)| e.walk_mut(f));
+            }
+            Expr::Return(e) => {
+                if let Some(e) = e {
+                    e.walk_mut(f);
+                }
+            }
+            Expr::Panic { args, message, .. } => {
+                args.iter_mut().for_each(|a| a.walk_mut(f));
+                if let Some(e) = message {
+                    e.walk_mut(f);
+                }
+            }
+        }
+    }
+}
+
+impl Stmt {
+    // 文の中の式をたどる（関数定義なら本体）
+    pub fn walk(&self, f: &mut impl FnMut(&Expr)) {
+        match self {
+            Stmt::Expr(e) | Stmt::Let { value: e, .. } => e.walk(f),
+            Stmt::Print(e) | Stmt::FuncDef { body: e, .. } => e.walk(f),
+            Stmt::TraitDef { methods, .. } | Stmt::ImplDef { methods, .. } => {
+                methods.iter().for_each(|m| m.walk(f))
+            }
+            Stmt::StructDef { .. } | Stmt::EnumDef { .. } | Stmt::Import(_) | Stmt::Error(..) => {}
+        }
+    }
+
+    // walk と同じ順に、式を書き換えながらたどる
+    pub fn walk_mut(&mut self, f: &mut impl FnMut(&mut Expr)) {
+        match self {
+            Stmt::Expr(e) | Stmt::Let { value: e, .. } => e.walk_mut(f),
+            Stmt::Print(e) | Stmt::FuncDef { body: e, .. } => e.walk_mut(f),
+            Stmt::TraitDef { methods, .. } | Stmt::ImplDef { methods, .. } => {
+                methods.iter_mut().for_each(|m| m.walk_mut(f))
+            }
+            Stmt::StructDef { .. } | Stmt::EnumDef { .. } | Stmt::Import(_) | Stmt::Error(..) => {}
+        }
+    }
+
+    // impl の中の Self::new() / Self { .. } / Self::A などを型名に置き換える
+    pub fn replace_self(&mut self, target: &str) {
+        if let Stmt::Let { pattern, .. } = self {
+            pattern.replace_self(target);
+        }
+        if let Stmt::FuncDef { params, .. } = self {
+            params.iter_mut().for_each(|p| p.replace_self(target));
+        }
+        let rename = |name: &mut String| {
+            if name == "Self" {
+                *name = target.to_string();
+            } else if let Some(rest) = name.strip_prefix("Self::") {
+                *name = format!("{}::{}", target, rest);
+            }
+        };
+        self.walk_mut(&mut |e| match e {
+            Expr::Var(name, ..) | Expr::Call(name, ..) | Expr::StructInit(name, _) => rename(name),
+            Expr::Block(stmts) => stmts.iter_mut().for_each(|s| {
+                if let Stmt::Let { pattern, .. } = s {
+                    pattern.replace_self(target);
+                }
+            }),
+            Expr::For { pattern, .. } => pattern.replace_self(target),
+            Expr::Closure { params, .. } => params.iter_mut().for_each(|p| p.replace_self(target)),
+            Expr::Match { arms, .. } => arms.iter_mut().for_each(|(p, _)| p.replace_self(target)),
+            _ => {}
+        });
+    }
+}
+
+impl Pattern {
+    fn replace_self(&mut self, target: &str) {
+        match self {
+            Pattern::Struct { name, fields, .. } => {
+                if name == "Self" {
+                    *name = target.to_string();
+                }
+                fields.iter_mut().for_each(|(_, p)| p.replace_self(target));
+            }
+            Pattern::Tuple(items) | Pattern::Slice(items) | Pattern::Variant(_, items) => {
+                items.iter_mut().for_each(|p| p.replace_self(target))
+            }
+            _ => {}
+        }
+    }
 }
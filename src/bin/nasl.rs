@@ -1,27 +1,269 @@
-use nanai_simple_lang::eval::eval_stmts;
-use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::ast::Stmt;
+use nanai_simple_lang::check::check;
+use nanai_simple_lang::eval::{
+    self, Capabilities, CountingAlloc, ErrorKind, Frame, Interpreter, Limits, PathAccess,
+    RuntimeError,
+};
+use nanai_simple_lang::int::{self, Overflow};
+use nanai_simple_lang::lexer::{Span, tokenize};
 use nanai_simple_lang::parser::parse;
 use std::env;
 use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// --max-heap でメモリ使用量を数えるため
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    // --wrapping: 整数演算のあふれをパニックにせず切り捨てる
+    let wrapping = take_flag(&mut args, "--wrapping");
+    // --vm: バイトコードにコンパイルして VM で実行する
+    // --disasm: コンパイルしたバイトコードを表示する（実行はしない）
+    let engine = match (
+        take_flag(&mut args, "--vm"),
+        take_flag(&mut args, "--disasm"),
+    ) {
+        (_, true) => Engine::Disasm,
+        (true, false) => Engine::Vm,
+        (false, false) => Engine::Tree,
+    };
+    // --test: main の代わりに #[test] の付いた関数を実行して結果を表示する
+    let test = take_flag(&mut args, "--test");
+    // --max-depth N: 関数呼び出しの深さの上限
+    let max_depth = take_number(&mut args, "--max-depth").unwrap_or(eval::DEFAULT_MAX_DEPTH);
+    // --stack-size MB: 評価するスレッドのスタックの大きさ（MiB）
+    let stack_size = take_number(&mut args, "--stack-size")
+        .map(|mb| mb << 20)
+        .unwrap_or(eval::DEFAULT_STACK_SIZE);
+    // --max-steps N・--max-heap MB・--timeout SECS: 信頼できないスクリプトの実行の制限
+    let limits = Limits {
+        max_steps: take_number(&mut args, "--max-steps").map(|n| n as u64),
+        max_heap: take_number(&mut args, "--max-heap").map(|mb| mb << 20),
+        timeout: take_number(&mut args, "--timeout").map(|s| Duration::from_secs(s as u64)),
+    };
+    let capabilities = take_capabilities(&mut args);
     if args.len() < 2 {
-        eprintln!("Usage: nasl <file.nasl>");
+        eprintln!(
+            "Usage: nasl [--wrapping] [--vm] [--disasm] [--test] [--max-depth N] [--stack-size MB] [--max-steps N] \
+             [--max-heap MB] [--timeout SECS] [--sandbox] [--allow-read[=DIR,..]] \
+             [--allow-write[=DIR,..]] [--allow-stdin] [--allow-stdout] [--allow-env] \
+             [--allow-process] [--allow-clock] <file.nasl>"
+        );
         std::process::exit(1);
     }
-    let filename = &args[1];
-    let code = fs::read_to_string(filename).expect("ファイルが読み込めません");
+    // 深い再帰でもスタックがあふれないよう、大きなスタックのスレッドで実行する
+    // （設定はスレッドごとなので、スレッドの中で行う）
+    eval::with_stack(stack_size, move || {
+        if wrapping {
+            int::set_overflow(Overflow::Wrap);
+        }
+        eval::set_max_depth(max_depth);
+        run(&args[1], engine, test, limits, capabilities);
+    });
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Engine {
+    // 構文木をたどって評価する
+    Tree,
+    Vm,
+    Disasm,
+}
+
+// args から flag を取り除いて、あったかどうかを返す
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|a| a == flag) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+// args から `flag N` を取り除いて N を返す
+fn take_number(args: &mut Vec<String>, flag: &str) -> Option<usize> {
+    let i = args.iter().position(|a| a == flag)?;
+    let value = args.get(i + 1).and_then(|n| n.parse().ok());
+    let Some(value) = value else {
+        eprintln!("{} には数を指定してください", flag);
+        std::process::exit(1);
+    };
+    args.drain(i..i + 2);
+    Some(value)
+}
+
+// --sandbox・--allow-*: スクリプトに許可する入出力。
+// どれかを指定すると、指定したもの以外は許可しない（指定しなければすべて許可する）
+fn take_capabilities(args: &mut Vec<String>) -> Capabilities {
+    let sandbox = take_flag(args, "--sandbox");
+    let read = take_allow(args, "--allow-read");
+    let write = take_allow(args, "--allow-write");
+    let mut flag = |name: &str| take_allow(args, name).is_some();
+    let caps = Capabilities {
+        stdin: flag("--allow-stdin"),
+        stdout: flag("--allow-stdout"),
+        env: flag("--allow-env"),
+        process: flag("--allow-process"),
+        clock: flag("--allow-clock"),
+        read: read.unwrap_or(PathAccess::Deny),
+        write: write.unwrap_or(PathAccess::Deny),
+    };
+    if sandbox || caps != Capabilities::none() {
+        caps
+    } else {
+        Capabilities::all()
+    }
+}
+
+// args から `flag`・`flag=DIR,DIR` を取り除く。DIR がなければどこでも許可する
+fn take_allow(args: &mut Vec<String>, flag: &str) -> Option<PathAccess> {
+    let mut access = None;
+    args.retain(|a| {
+        let dirs = match a.strip_prefix(flag) {
+            Some("") => None,
+            Some(rest) if rest.starts_with('=') => Some(&rest[1..]),
+            // --allow-readme のような別の引数
+            _ => return true,
+        };
+        access = Some(match (access.take(), dirs) {
+            (Some(PathAccess::Any), _) | (_, None) => PathAccess::Any,
+            (Some(PathAccess::Within(mut roots)), Some(dirs)) => {
+                roots.extend(dirs.split(',').map(PathBuf::from));
+                PathAccess::Within(roots)
+            }
+            (_, Some(dirs)) => PathAccess::Within(dirs.split(',').map(PathBuf::from).collect()),
+        });
+        false
+    });
+    access
+}
+
+fn run(filename: &str, engine: Engine, test: bool, limits: Limits, capabilities: Capabilities) {
+    let code = match fs::read_to_string(filename) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("[エラー] {}: {}", filename, e);
+            std::process::exit(1);
+        }
+    };
     let tokens = tokenize(&code);
     let mut stmts = parse(&tokens);
-    // main関数が定義されていれば自動で main() を呼び出す
-    let has_main = stmts
-        .iter()
-        .any(|s| matches!(s, nanai_simple_lang::ast::Stmt::FuncDef { name, .. } if name == "main"));
+    // main関数が定義されていれば自動で main() を呼び出す（--test のときは呼ばない）
+    let has_main = !test
+        && stmts
+            .iter()
+            .any(|s| matches!(s, Stmt::FuncDef { name, .. } if name == "main"));
     if has_main {
-        use nanai_simple_lang::ast::{Expr, Stmt};
-        stmts.push(Stmt::Expr(Expr::Call("main".to_string(), vec![])));
+        use nanai_simple_lang::ast::Expr;
+        stmts.push(Stmt::Expr(Expr::Call(
+            "main".to_string(),
+            vec![],
+            Default::default(),
+            Default::default(),
+        )));
+    }
+    // 評価の前に型を検査する
+    let errors = check(&stmts);
+    if !errors.is_empty() {
+        for e in &errors {
+            eprintln!("[型エラー] {}:{}: {}", filename, e.span, e.message);
+            if let Some((span, note)) = &e.note {
+                eprintln!("    {}:{}: {}", filename, span, note);
+            }
+        }
+        std::process::exit(1);
+    }
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .set_limits(limits)
+        .set_capabilities(capabilities);
+    if test {
+        run_tests(filename, &mut interpreter, &stmts);
+        return;
+    }
+    let result = match engine {
+        Engine::Tree => interpreter.eval(&stmts),
+        Engine::Vm => interpreter.eval_vm(&stmts),
+        Engine::Disasm => match interpreter.compile(&stmts) {
+            Ok(program) => {
+                print!("{}", program);
+                return;
+            }
+            Err(e) => Err(e),
+        },
+    };
+    match result {
+        Ok(result) => println!("結果: {}", result),
+        Err(e) => {
+            report(filename, &e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// #[test] の関数を実行し、1つでも失敗すれば終了コード 1 で終わる
+fn run_tests(filename: &str, interpreter: &mut Interpreter, stmts: &[Stmt]) {
+    let results = match interpreter.run_tests(stmts) {
+        Ok(results) => results,
+        Err(e) => {
+            report(filename, &e);
+            std::process::exit(1);
+        }
+    };
+    let mut failed = 0;
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("test {} ... ok", name),
+            Err(e) => {
+                println!("test {} ... FAILED", name);
+                report(filename, e);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "テスト結果: {} 件成功, {} 件失敗",
+        results.len() - failed,
+        failed
+    );
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+// 実行時エラーと、呼び出しの履歴を表示する
+fn report(filename: &str, e: &RuntimeError) {
+    match e.span {
+        Some(span) => eprintln!("[実行時エラー] {}:{}: {}", filename, span, e.message),
+        None => eprintln!("[実行時エラー] {}: {}", filename, e.message),
+    }
+    if e.kind == ErrorKind::Permission {
+        eprintln!("    （--allow-read などの --allow-* のフラグで許可できます）");
+    }
+    // 内側の呼び出しから順に、どこで呼び出されたかを表示する
+    // 深い再帰では途中を省く
+    let (inner, omitted, outer) = e.shown_stack();
+    print_frames(filename, inner);
+    if omitted > 0 {
+        eprintln!("    ...（{} 個の呼び出しを省略）", omitted);
+    }
+    print_frames(filename, outer);
+}
+
+// 自動で呼び出した main には位置がない
+fn print_frames(filename: &str, frames: &[Frame]) {
+    for frame in frames {
+        if frame.span == Span::default() {
+            eprintln!("    {}", frame.name);
+        } else {
+            eprintln!(
+                "    {} ({}:{} で呼び出し)",
+                frame.name, filename, frame.span
+            );
+        }
     }
-    let result = eval_stmts(&stmts);
-    println!("結果: {}", result);
 }
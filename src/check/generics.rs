@@ -0,0 +1,760 @@
+// 総称型・列挙型・トレイト・impl の検査
+// 総称関数・impl の本体では型引数を Ty::Param（中身の分からない型）として扱い、
+// 境界のトレイトにある演算子・メソッドだけを使えるようにする
+use super::ty::Ty;
+use super::{Checker, Method, Scheme, TraitDef, TypeDef};
+use crate::ast::{BinOp, Generics, Pattern, Stmt};
+use crate::lexer::Span;
+use std::collections::HashMap;
+
+// 標準ライブラリのトレイト（derive できるものと演算子のトレイト）
+const STD_TRAITS: [&str; 16] = [
+    "PartialEq",
+    "Eq",
+    "PartialOrd",
+    "Ord",
+    "Clone",
+    "Copy",
+    "Debug",
+    "Display",
+    "Hash",
+    "Default",
+    "Add",
+    "Sub",
+    "Mul",
+    "Div",
+    "Rem",
+    "Neg",
+];
+
+// bound を実装している型は tr も実装している（Ord なら PartialOrd も、など）
+fn implies(bound: &str, tr: &str) -> bool {
+    bound == tr
+        || matches!(
+            (bound, tr),
+            ("Ord", "PartialOrd" | "Eq" | "PartialEq")
+                | ("PartialOrd" | "Eq", "PartialEq")
+                | ("Copy", "Clone")
+        )
+}
+
+// 型の中の型引数 generics を args に置き換える
+pub(super) fn subst(ty: &Ty, generics: &[String], args: &[Ty]) -> Ty {
+    ty.map(&mut |t| match t {
+        Ty::Param(p) => generics
+            .iter()
+            .position(|g| g == p)
+            .map(|i| args.get(i).cloned().unwrap_or(Ty::Unknown)),
+        _ => None,
+    })
+}
+
+// 構造体・列挙型の型。プレリュードの Option・Result は組み込みの型にする
+fn adt(name: &str, args: Vec<Ty>) -> Ty {
+    let arg = |i: usize| Box::new(args.get(i).cloned().unwrap_or(Ty::Unknown));
+    match name {
+        "Option" => Ty::Option(arg(0)),
+        "Result" => Ty::Result(arg(0), arg(1)),
+        _ => Ty::Named(name.to_string(), args),
+    }
+}
+
+// adt の逆（列挙型・構造体の名前と型引数）
+fn adt_parts(ty: &Ty) -> Option<(String, Vec<Ty>)> {
+    match ty {
+        Ty::Option(t) => Some(("Option".to_string(), vec![(**t).clone()])),
+        Ty::Result(t, e) => Some(("Result".to_string(), vec![(**t).clone(), (**e).clone()])),
+        Ty::Named(name, args) => Some((name.clone(), args.clone())),
+        _ => None,
+    }
+}
+
+fn op_trait(op: BinOp) -> Option<&'static str> {
+    match op {
+        BinOp::Add => Some("Add"),
+        BinOp::Sub => Some("Sub"),
+        BinOp::Mul => Some("Mul"),
+        BinOp::Div => Some("Div"),
+        BinOp::Rem => Some("Rem"),
+        BinOp::Eq | BinOp::Ne => Some("PartialEq"),
+        BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => Some("PartialOrd"),
+        BinOp::And | BinOp::Or => None,
+    }
+}
+
+impl Checker {
+    // 型引数の文脈（bounds・self_ty）を f の間だけ切り替える。bounds は空から始める
+    pub(super) fn with_generics<R>(
+        &mut self,
+        self_ty: Option<Ty>,
+        f: impl FnOnce(&mut Checker) -> R,
+    ) -> R {
+        let saved_bounds = std::mem::take(&mut self.bounds);
+        let saved_self = std::mem::replace(&mut self.self_ty, self_ty);
+        let r = f(self);
+        self.bounds = saved_bounds;
+        self.self_ty = saved_self;
+        r
+    }
+
+    // 型引数の宣言を今の文脈に加え、境界を型にした並びを返す
+    pub(super) fn enter_generics(
+        &mut self,
+        generics: &Generics,
+        span: Span,
+    ) -> Vec<(String, Vec<Ty>)> {
+        // 境界の中で互いの型引数を使えるよう、名前を先に入れる
+        for (name, _) in generics {
+            self.bounds.insert(name.clone(), Vec::new());
+        }
+        let mut list = Vec::new();
+        for (name, bounds) in generics {
+            let bounds: Vec<Ty> = bounds.iter().filter_map(|b| self.bound(b, span)).collect();
+            self.bounds.insert(name.clone(), bounds.clone());
+            list.push((name.clone(), bounds));
+        }
+        list
+    }
+
+    // トレイト境界: PartialOrd / Container<T>
+    fn bound(&mut self, bound: &str, span: Span) -> Option<Ty> {
+        let Ty::Named(name, args) = Ty::parse(bound) else {
+            self.error(Some(span), format!("トレイト境界が不正です: {}", bound));
+            return None;
+        };
+        if !STD_TRAITS.contains(&name.as_str()) && !self.traits.contains_key(&name) {
+            self.error(Some(span), format!("未定義のトレイト: {}", name));
+            return None;
+        }
+        let args = args
+            .iter()
+            .map(|a| self.resolve_names(a, Some(span)))
+            .collect();
+        Some(Ty::Named(name, args))
+    }
+
+    // 型注釈の中の名前を解決する。Self は impl の型に、型引数は Ty::Param に、
+    // 構造体・列挙型は型引数の数を確かめる（省略されていれば型変数にする）
+    pub(super) fn resolve_names(&mut self, ty: &Ty, span: Option<Span>) -> Ty {
+        ty.map(&mut |t| {
+            let Ty::Named(name, args) = t else {
+                return None;
+            };
+            if args.is_empty() && name == "Self" {
+                return Some(self.self_ty.clone().unwrap_or_else(|| {
+                    self.error(
+                        span,
+                        "Self は impl・トレイトの中でしか使えません".to_string(),
+                    );
+                    Ty::Unknown
+                }));
+            }
+            if args.is_empty() && self.bounds.contains_key(name) {
+                return Some(Ty::Param(name.clone()));
+            }
+            if args.is_empty()
+                && let Some((owner, item)) = name.split_once("::")
+                && let Some(t) = self.assoc_ty(owner, item, span)
+            {
+                return Some(t);
+            }
+            let args: Vec<Ty> = args.iter().map(|a| self.resolve_names(a, span)).collect();
+            let Some(n) = self.typedefs.get(name).map(|def| def.generics.len()) else {
+                self.error(span, format!("未定義の型: {}", name));
+                return Some(Ty::Unknown);
+            };
+            if args.is_empty() {
+                return Some(adt(name, (0..n).map(|_| self.fresh()).collect()));
+            }
+            if args.len() != n {
+                self.error(
+                    span,
+                    format!(
+                        "型 {} の型引数の数が一致しません: {} 個必要ですが {} 個です",
+                        name,
+                        n,
+                        args.len()
+                    ),
+                );
+                return Some(Ty::Unknown);
+            }
+            Some(adt(name, args))
+        })
+    }
+
+    // 関連型 Self::Item・T::Item。トレイトの中と境界を持つ型引数では中身の分からない型にする
+    fn assoc_ty(&mut self, owner: &str, item: &str, span: Option<Span>) -> Option<Ty> {
+        let owner = match owner {
+            "Self" => self.self_ty.clone()?,
+            p if self.bounds.contains_key(p) => Ty::Param(p.to_string()),
+            _ => return None,
+        };
+        if let Ty::Param(p) = owner {
+            return Some(Ty::Param(format!("{}::{}", p, item)));
+        }
+        let found = owner
+            .key()
+            .and_then(|key| self.assoc.get(&(key, item.to_string())).cloned());
+        Some(found.unwrap_or_else(|| {
+            self.error(span, format!("未定義の関連型: Self::{}", item));
+            Ty::Unknown
+        }))
+    }
+
+    // Iterator を実装した型を for で回したときの要素の型（next の戻り値 Option<Item> の中身）
+    pub(super) fn iterator_item(&mut self, ty: &Ty, span: Span) -> Option<Ty> {
+        let ret = self.user_method(ty, "next", &[], span)?;
+        match self.resolve(&ret) {
+            Ty::Option(item) => Some(*item),
+            _ => None,
+        }
+    }
+
+    // 構造体・列挙型・トレイト・impl の定義を集める
+    pub(super) fn collect(&mut self, stmts: &[Stmt]) {
+        // 型は互いに参照できるので、名前を先に登録してから中身の型を読む
+        for stmt in stmts {
+            match stmt {
+                Stmt::StructDef {
+                    name,
+                    generics,
+                    derives,
+                    ..
+                }
+                | Stmt::EnumDef {
+                    name,
+                    generics,
+                    derives,
+                    ..
+                } => {
+                    let def = TypeDef {
+                        generics: generics.iter().map(|(g, _)| g.clone()).collect(),
+                        fields: Vec::new(),
+                        variants: Vec::new(),
+                        derives: derives.clone(),
+                    };
+                    self.typedefs.insert(name.clone(), def);
+                }
+                Stmt::TraitDef {
+                    name,
+                    generics,
+                    assoc,
+                    required,
+                    ..
+                } => {
+                    let def = TraitDef {
+                        generics: generics.iter().map(|(g, _)| g.clone()).collect(),
+                        assoc: assoc.clone(),
+                        methods: HashMap::new(),
+                        required: required.clone(),
+                        context: Vec::new(),
+                    };
+                    self.traits.insert(name.clone(), def);
+                }
+                _ => {}
+            }
+        }
+        for stmt in stmts {
+            match stmt {
+                Stmt::StructDef {
+                    name,
+                    generics,
+                    fields,
+                    span,
+                    ..
+                } => {
+                    let fields = self.with_generics(None, |c| {
+                        c.enter_generics(generics, *span);
+                        fields
+                            .iter()
+                            .map(|(f, t)| (f.clone(), c.resolve_names(&Ty::parse(t), Some(*span))))
+                            .collect()
+                    });
+                    if let Some(def) = self.typedefs.get_mut(name) {
+                        def.fields = fields;
+                    }
+                }
+                Stmt::EnumDef {
+                    name,
+                    generics,
+                    variants,
+                    span,
+                    ..
+                } => {
+                    let variants = self.with_generics(None, |c| {
+                        c.enter_generics(generics, *span);
+                        variants
+                            .iter()
+                            .map(|(v, tys)| {
+                                let tys = tys
+                                    .iter()
+                                    .map(|t| c.resolve_names(&Ty::parse(t), Some(*span)))
+                                    .collect();
+                                (v.clone(), tys)
+                            })
+                            .collect()
+                    });
+                    if let Some(def) = self.typedefs.get_mut(name) {
+                        def.variants = variants;
+                    }
+                }
+                Stmt::TraitDef {
+                    name,
+                    generics,
+                    methods,
+                    span,
+                    ..
+                } => {
+                    let self_ty = Ty::Param("Self".to_string());
+                    let (context, methods) = self.with_generics(Some(self_ty), |c| {
+                        let mut context = c.enter_generics(generics, *span);
+                        let args = generics.iter().map(|(g, _)| Ty::Param(g.clone())).collect();
+                        let bound = vec![Ty::Named(name.clone(), args)];
+                        c.bounds.insert("Self".to_string(), bound.clone());
+                        context.push(("Self".to_string(), bound));
+                        let methods: HashMap<String, Method> = methods
+                            .iter()
+                            .filter_map(|m| c.method_sig(m, &[]))
+                            .collect();
+                        (context, methods)
+                    });
+                    if let Some(def) = self.traits.get_mut(name) {
+                        def.methods = methods;
+                        def.context = context;
+                    }
+                }
+                Stmt::ImplDef { .. } => self.collect_impl(stmt),
+                _ => {}
+            }
+        }
+    }
+
+    // メソッドの型。outer は impl の型引数（メソッド自身の型引数の前に並ぶ）
+    fn method_sig(&mut self, stmt: &Stmt, outer: &[(String, Vec<Ty>)]) -> Option<(String, Method)> {
+        let Stmt::FuncDef {
+            name,
+            generics,
+            params,
+            param_tys,
+            ret,
+            span,
+            ..
+        } = stmt
+        else {
+            return None;
+        };
+        let saved = self.bounds.clone();
+        let own = self.enter_generics(generics, *span);
+        let tys = param_tys
+            .iter()
+            .map(|t| self.annotation(t, *span))
+            .collect();
+        let ret = self.annotation(ret, *span);
+        self.bounds = saved;
+        let method = Method {
+            scheme: Scheme {
+                vars: Vec::new(),
+                params: outer.iter().cloned().chain(own).collect(),
+                ty: Ty::Fn(tys, Box::new(ret)),
+            },
+            has_self: matches!(params.first(), Some(Pattern::Bind(p, _)) if p == "self"),
+            self_ty: self.self_ty.clone().unwrap_or(Ty::Unknown),
+        };
+        Some((name.clone(), method))
+    }
+
+    // impl [Trait for] Type のメソッドを登録し、トレイトのメソッドがそろっているか確かめる
+    fn collect_impl(&mut self, stmt: &Stmt) {
+        let Stmt::ImplDef {
+            generics,
+            trait_name,
+            target,
+            assoc,
+            methods,
+            span,
+        } = stmt
+        else {
+            return;
+        };
+        let span = *span;
+        self.with_generics(None, |c| {
+            let outer = c.enter_generics(generics, span);
+            let target_ty = c.resolve_names(&Ty::parse(target), Some(span));
+            let Some(key) = Ty::parse(target).key() else {
+                c.error(Some(span), format!("{} には impl を書けません", target));
+                return;
+            };
+            c.self_ty = Some(target_ty.clone());
+            // メソッドの型注釈の Self::Item などに使うので、関連型を先に登録する
+            for (item, ty) in assoc {
+                let ty = c.resolve_names(&Ty::parse(ty), Some(span));
+                c.assoc.insert((key.clone(), item.clone()), ty);
+            }
+            let mut defined = Vec::new();
+            for m in methods {
+                if let Some((name, method)) = c.method_sig(m, &outer) {
+                    c.methods.insert((key.clone(), name.clone()), method);
+                    defined.push(name);
+                }
+            }
+            let Some(trait_name) = trait_name else {
+                return;
+            };
+            let Ty::Named(tr, trait_args) = Ty::parse(trait_name) else {
+                c.error(Some(span), format!("トレイト名が不正です: {}", trait_name));
+                return;
+            };
+            c.impls.insert((key.clone(), tr.clone()));
+            let Some(def) = c.traits.get(&tr).cloned() else {
+                if !STD_TRAITS.contains(&tr.as_str()) {
+                    c.error(Some(span), format!("未定義のトレイト: {}", tr));
+                }
+                return;
+            };
+            for name in &def.required {
+                if !defined.contains(name) {
+                    c.error(
+                        Some(span),
+                        format!("トレイト {} のメソッド {} が実装されていません", tr, name),
+                    );
+                }
+            }
+            for item in &def.assoc {
+                if !assoc.iter().any(|(a, _)| a == item) {
+                    c.error(
+                        Some(span),
+                        format!("トレイト {} の関連型 {} が定義されていません", tr, item),
+                    );
+                }
+            }
+            for (item, _) in assoc {
+                if !def.assoc.contains(item) {
+                    c.error(
+                        Some(span),
+                        format!("{} はトレイト {} の関連型ではありません", item, tr),
+                    );
+                }
+            }
+            for name in &defined {
+                if !def.methods.contains_key(name) {
+                    c.error(
+                        Some(span),
+                        format!("{} はトレイト {} のメソッドではありません", name, tr),
+                    );
+                }
+            }
+            // 書かれなかったメソッドはトレイトの既定の実装を、Self を target にして使う
+            let trait_args: Vec<Ty> = trait_args
+                .iter()
+                .map(|a| c.resolve_names(a, Some(span)))
+                .collect();
+            let mut fixed = vec![("Self".to_string(), target_ty.clone())];
+            fixed.extend(def.generics.iter().cloned().zip(trait_args));
+            for item in &def.assoc {
+                let ty = c.assoc.get(&(key.clone(), item.clone()));
+                let ty = ty.cloned().unwrap_or(Ty::Unknown);
+                fixed.push((format!("Self::{}", item), ty));
+            }
+            for (name, m) in &def.methods {
+                if defined.contains(name) {
+                    continue;
+                }
+                let ty = m.scheme.ty.map(&mut |t| match t {
+                    Ty::Param(p) => fixed.iter().find(|(f, _)| f == p).map(|(_, t)| t.clone()),
+                    _ => None,
+                });
+                let method = Method {
+                    scheme: Scheme {
+                        vars: Vec::new(),
+                        params: outer.iter().chain(&m.scheme.params).cloned().collect(),
+                        ty,
+                    },
+                    has_self: m.has_self,
+                    self_ty: target_ty.clone(),
+                };
+                c.methods.insert((key.clone(), name.clone()), method);
+            }
+        });
+    }
+
+    // impl のメソッドとトレイトの既定の実装の本体を検査する
+    pub(super) fn check_items(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::ImplDef {
+                    target, methods, ..
+                } => {
+                    let Some(key) = Ty::parse(target).key() else {
+                        continue;
+                    };
+                    for m in methods {
+                        let Stmt::FuncDef { name, .. } = m else {
+                            continue;
+                        };
+                        if let Some(method) =
+                            self.methods.get(&(key.clone(), name.clone())).cloned()
+                        {
+                            self.method_body(m, &method, &[]);
+                        }
+                    }
+                }
+                Stmt::TraitDef { name, methods, .. } => {
+                    let Some(def) = self.traits.get(name).cloned() else {
+                        continue;
+                    };
+                    for m in methods {
+                        if let Stmt::FuncDef { name, .. } = m
+                            && !def.required.contains(name)
+                            && let Some(method) = def.methods.get(name)
+                        {
+                            self.method_body(m, method, &def.context);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn method_body(&mut self, stmt: &Stmt, method: &Method, outer: &[(String, Vec<Ty>)]) {
+        self.with_generics(Some(method.self_ty.clone()), |c| {
+            c.bounds.extend(outer.iter().cloned());
+            c.bounds.extend(method.scheme.params.iter().cloned());
+            c.body(stmt, method.scheme.ty.clone());
+        });
+    }
+
+    // 構造体のフィールドの型と構造体の型（型引数は新しい型変数にする）
+    pub(super) fn struct_type(&mut self, name: &str) -> Option<(Vec<(String, Ty)>, Ty)> {
+        let def = self.typedefs.get(name)?;
+        let (generics, fields) = (def.generics.clone(), def.fields.clone());
+        let args: Vec<Ty> = generics.iter().map(|_| self.fresh()).collect();
+        let fields = fields
+            .iter()
+            .map(|(f, t)| (f.clone(), subst(t, &generics, &args)))
+            .collect();
+        Some((fields, adt(name, args)))
+    }
+
+    // バリアント Shape::Circle / Some の中身の型と列挙型の型（パスのない名前は Option・Result のもの）
+    pub(super) fn variant(&mut self, path: &str) -> Option<(Vec<Ty>, Ty)> {
+        let (enum_name, variant) = match path.rsplit_once("::") {
+            Some((prefix, v)) => (prefix.rsplit("::").next().unwrap_or(prefix), v),
+            None => {
+                let prelude = ["Option", "Result"].into_iter().find(|e| {
+                    self.typedefs
+                        .get(*e)
+                        .is_some_and(|def| def.variants.iter().any(|(v, _)| v == path))
+                })?;
+                (prelude, path)
+            }
+        };
+        let def = self.typedefs.get(enum_name)?;
+        let (_, fields) = def.variants.iter().find(|(v, _)| v == variant)?;
+        let (generics, fields) = (def.generics.clone(), fields.clone());
+        let args: Vec<Ty> = generics.iter().map(|_| self.fresh()).collect();
+        let fields = fields.iter().map(|t| subst(t, &generics, &args)).collect();
+        Some((fields, adt(enum_name, args)))
+    }
+
+    // パターン Circle(r) を ty に当てはめたときの中身の型。列挙型は ty から、
+    // まだ決まっていなければバリアント名から探す（Option・Result を優先する）
+    pub(super) fn variant_fields(&mut self, name: &str, ty: &Ty) -> Option<Vec<Ty>> {
+        let enum_name = match adt_parts(&self.resolve(ty)) {
+            Some((enum_name, _)) => enum_name,
+            None => {
+                let mut candidates: Vec<&String> = self
+                    .typedefs
+                    .iter()
+                    .filter(|(_, def)| def.variants.iter().any(|(v, _)| v == name))
+                    .map(|(n, _)| n)
+                    .collect();
+                candidates.sort_by_key(|n| (!matches!(n.as_str(), "Option" | "Result"), *n));
+                candidates.first()?.to_string()
+            }
+        };
+        let (fields, result) = self.variant(&format!("{}::{}", enum_name, name))?;
+        self.unify(ty, &result).then_some(fields)
+    }
+
+    // impl・境界のトレイトのメソッドを recv.name(args) として呼んだときの戻り値の型
+    pub(super) fn user_method(
+        &mut self,
+        recv: &Ty,
+        name: &str,
+        args: &[Ty],
+        span: Span,
+    ) -> Option<Ty> {
+        let recv = self.resolve(recv);
+        let (f, has_self) = self.method_type(&recv, name, span)?;
+        let Ty::Fn(params, ret) = f else {
+            return None;
+        };
+        match params.split_first() {
+            Some((this, rest)) if has_self => {
+                self.unify(this, &recv);
+                self.check_args(name, rest, args, span);
+            }
+            _ => {
+                let recv = self.shown(&recv);
+                self.error(
+                    Some(span),
+                    format!(
+                        "{}::{} は self を取らないのでメソッドとして呼べません",
+                        recv, name
+                    ),
+                );
+            }
+        }
+        Some(*ret)
+    }
+
+    // メソッドの型（self も引数に含む）と self を取るか
+    fn method_type(&mut self, recv: &Ty, name: &str, span: Span) -> Option<(Ty, bool)> {
+        let Ty::Param(p) = recv else {
+            let m = self
+                .methods
+                .get(&(recv.key()?, name.to_string()))
+                .cloned()?;
+            return Some((self.instantiate(&m.scheme, span), m.has_self));
+        };
+        for bound in self.bounds.get(p).cloned().unwrap_or_default() {
+            let Ty::Named(tr, args) = bound else {
+                continue;
+            };
+            let Some(def) = self.traits.get(&tr) else {
+                continue;
+            };
+            let Some(m) = def.methods.get(name).cloned() else {
+                continue;
+            };
+            let mut fixed = vec![("Self".to_string(), recv.clone())];
+            fixed.extend(def.generics.iter().cloned().zip(args));
+            for item in &def.assoc {
+                fixed.push((
+                    format!("Self::{}", item),
+                    Ty::Param(format!("{}::{}", p, item)),
+                ));
+            }
+            return Some((self.instantiate_with(&m.scheme, &fixed, span), m.has_self));
+        }
+        self.std_method(recv, name, span)
+    }
+
+    // 標準のトレイトのメソッド（型引数の値に使う）
+    fn std_method(&mut self, recv: &Ty, name: &str, span: Span) -> Option<(Ty, bool)> {
+        let this = || recv.clone();
+        let (tr, params, ret, has_self) = match name {
+            "clone" => ("Clone", vec![this()], this(), true),
+            "to_string" => ("Display", vec![this()], Ty::Str, true),
+            "default" => ("Default", vec![], this(), false),
+            "max" | "min" => ("Ord", vec![this(), this()], this(), true),
+            "eq" | "ne" => ("PartialEq", vec![this(), this()], Ty::Bool, true),
+            _ => return None,
+        };
+        self.require(recv, tr, span);
+        Some((Ty::Fn(params, Box::new(ret)), has_self))
+    }
+
+    // Type::name / T::name / Self::name の関連関数の型
+    pub(super) fn static_method(&mut self, path: &str, span: Span) -> Option<Ty> {
+        let (prefix, name) = path.rsplit_once("::")?;
+        let prefix = prefix.rsplit("::").next().unwrap_or(prefix);
+        let recv = match prefix {
+            "Self" => self.self_ty.clone()?,
+            p if self.bounds.contains_key(p) => Ty::Param(p.to_string()),
+            p if self.typedefs.contains_key(p) => self.struct_type(p)?.1,
+            p => Ty::parse(p),
+        };
+        self.method_type(&recv, name, span).map(|(f, _)| f)
+    }
+
+    // ty がトレイト tr を実装しているか（型が決まっていなければ実装しているとみなす）
+    pub(super) fn implements(&self, ty: &Ty, tr: &str) -> bool {
+        let ty = self.resolve(ty);
+        if let Some(key) = ty.key()
+            && self.impls.contains(&(key, tr.to_string()))
+        {
+            return true;
+        }
+        let all = |items: &[&Ty]| items.iter().all(|t| self.implements(t, tr));
+        let cmp = matches!(tr, "PartialEq" | "Eq" | "PartialOrd" | "Ord");
+        let common = cmp || matches!(tr, "Clone" | "Debug" | "Hash");
+        match &ty {
+            Ty::Var(_) | Ty::Unknown => true,
+            Ty::Param(p) => self.bounds.get(p).is_some_and(|bounds| {
+                bounds
+                    .iter()
+                    .any(|b| matches!(b, Ty::Named(n, _) if implies(n, tr)))
+            }),
+            Ty::Int(t) => STD_TRAITS.contains(&tr) && (tr != "Neg" || t.is_signed()),
+            // 浮動小数点数は NaN があるので Eq・Ord・Hash ではない
+            Ty::Float(_) => STD_TRAITS.contains(&tr) && !matches!(tr, "Eq" | "Ord" | "Hash"),
+            Ty::Bool | Ty::Char => common || matches!(tr, "Copy" | "Display" | "Default"),
+            Ty::Str => common || matches!(tr, "Display" | "Default" | "Add"),
+            Ty::Unit => common || matches!(tr, "Copy" | "Default"),
+            Ty::Tuple(items) => {
+                (common || matches!(tr, "Copy" | "Default"))
+                    && all(&items.iter().collect::<Vec<_>>())
+            }
+            Ty::Vec(t) => (common || tr == "Default") && all(&[t]),
+            Ty::Option(t) => (common || matches!(tr, "Copy" | "Default")) && all(&[t]),
+            Ty::Result(t, e) => (common || tr == "Copy") && all(&[t, e]),
+            Ty::Map(k, v) => {
+                matches!(tr, "PartialEq" | "Eq" | "Clone" | "Debug" | "Default") && all(&[k, v])
+            }
+            Ty::Set(t) => {
+                matches!(tr, "PartialEq" | "Eq" | "Clone" | "Debug" | "Default") && all(&[t])
+            }
+            Ty::Range(_) => matches!(tr, "PartialEq" | "Eq" | "Clone" | "Debug" | "Hash"),
+            // derive したトレイトは、型引数に入った型も実装していれば使える
+            Ty::Named(name, args) => {
+                self.typedefs
+                    .get(name)
+                    .is_some_and(|def| def.derives.iter().any(|d| implies(d, tr)))
+                    && all(&args.iter().collect::<Vec<_>>())
+            }
+            Ty::Fn(..) | Ty::Iter(_) => false,
+        }
+    }
+
+    // ty が tr を実装していなければ報告する
+    pub(super) fn require(&mut self, ty: &Ty, tr: &str, span: Span) -> bool {
+        if self.implements(ty, tr) {
+            return true;
+        }
+        let ty = self.shown(ty);
+        self.error(Some(span), format!("{} は {} を実装していません", ty, tr));
+        false
+    }
+
+    // 型引数・構造体・列挙型の値の演算子には、対応するトレイトが必要
+    pub(super) fn operator_trait(&mut self, op: BinOp, operand: &Ty, span: Span) {
+        let resolved = self.resolve(operand);
+        if let (Ty::Param(_) | Ty::Named(..), Some(tr)) = (&resolved, op_trait(op))
+            && !self.implements(&resolved, tr)
+        {
+            let ty = self.shown(&resolved);
+            self.error(
+                Some(span),
+                format!(
+                    "演算子 {} は {} に使えません: {} を実装していません",
+                    super::op_symbol(op),
+                    ty,
+                    tr
+                ),
+            );
+        }
+    }
+
+    // 型引数の境界から生じた条件を確かめる。last でなければ型が決まっていないものは後回しにする
+    pub(super) fn solve_obligations(&mut self, last: bool) {
+        for (ty, tr, span) in std::mem::take(&mut self.obligations) {
+            if !last && matches!(self.resolve(&ty), Ty::Var(_)) {
+                self.obligations.push((ty, tr, span));
+                continue;
+            }
+            self.require(&ty, &tr, span);
+        }
+    }
+}
@@ -0,0 +1,1583 @@
+// 静的な型検査と型推論（評価の前に実行する）
+// 型注釈は省略でき、省略した型は使われ方から推論する（Hindley-Milner 風）。
+// トップレベルの関数は呼び出し関係の強連結成分ごとに推論し、一般化してから使う
+mod generics;
+mod moves;
+pub mod ty;
+
+use crate::ast::{BinOp, Expr, FmtPiece, PanicKind, Pattern, Stmt, UnaryOp};
+use crate::eval::grow_stack;
+use crate::float::{self, FloatTy};
+use crate::int::{self, IntTy};
+use crate::lexer::Span;
+use generics::subst;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use ty::Ty;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub span: Span,
+    pub message: String,
+    // 関係するもう一つの位置と説明（移動した場所など）
+    pub note: Option<(Span, String)>,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.span)?;
+        if let Some((span, note)) = &self.note {
+            write!(f, "; {} ({})", note, span)?;
+        }
+        Ok(())
+    }
+}
+
+// 推論の結果。型エラーと、位置ごとに推論した型を持つ
+#[derive(Debug, Default)]
+pub struct Typed {
+    pub errors: Vec<TypeError>,
+    types: HashMap<Span, Ty>,
+}
+
+impl Typed {
+    // その位置にある変数（使う位置と束縛する位置）・呼び出し・演算子・添字・let・fn の型（エディタのホバー表示用）
+    pub fn type_of(&self, span: Span) -> Option<&Ty> {
+        self.types.get(&span)
+    }
+}
+
+// 型注釈のない数値リテラルの型変数が取りうる型
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Int,
+    Float,
+}
+
+// 関数の型。vars の型変数と params の型引数は使うたびに新しい型変数に置き換える。
+// 型引数に入る型は、境界のトレイト（Ty::Named）を実装していなければならない
+#[derive(Clone)]
+struct Scheme {
+    vars: Vec<u32>,
+    params: Vec<(String, Vec<Ty>)>,
+    ty: Ty,
+}
+
+// 構造体・列挙型の定義。中身の型の型引数は Ty::Param で表す
+struct TypeDef {
+    generics: Vec<String>,
+    fields: Vec<(String, Ty)>,
+    // 列挙型のバリアント（名前, 中身の型）
+    variants: Vec<(String, Vec<Ty>)>,
+    derives: Vec<String>,
+}
+
+// トレイトの定義。メソッドの型の Self とトレイトの型引数は Ty::Param のまま持つ
+#[derive(Clone)]
+struct TraitDef {
+    generics: Vec<String>,
+    // 関連型の名前
+    assoc: Vec<String>,
+    methods: HashMap<String, Method>,
+    required: Vec<String>,
+    // 既定の実装を検査するときの型引数（トレイトの型引数と Self: トレイト）
+    context: Vec<(String, Vec<Ty>)>,
+}
+
+// impl・トレイトのメソッド。self を取るなら型の最初の引数が self
+#[derive(Clone)]
+struct Method {
+    scheme: Scheme,
+    has_self: bool,
+    self_ty: Ty,
+}
+
+struct Checker {
+    funcs: HashMap<String, Scheme>,
+    // 構造体・列挙型（Option・Result はプレリュードの列挙型として入る）
+    typedefs: HashMap<String, TypeDef>,
+    traits: HashMap<String, TraitDef>,
+    // (型の名前, メソッド名) → impl で定義されたメソッド（型の名前は Ty::key）
+    methods: HashMap<(String, String), Method>,
+    // impl Trait for Type のある (型の名前, トレイト名)
+    impls: HashSet<(String, String)>,
+    // (型の名前, 関連型の名前) → impl で決めた関連型
+    assoc: HashMap<(String, String), Ty>,
+    // 検査中の総称関数・impl の型引数とその境界
+    bounds: HashMap<String, Vec<Ty>>,
+    // impl・トレイトの中での Self の型
+    self_ty: Option<Ty>,
+    // 型引数に入れた型が実装していなければならないトレイト。型が決まってから確かめる
+    obligations: Vec<(Ty, String, Span)>,
+    scopes: Vec<HashMap<String, Ty>>,
+    // 型変数に割り当てた型（None はまだ決まっていない）
+    subst: Vec<Option<Ty>>,
+    // 整数型・浮動小数点数型にしかならない型変数（型注釈のない数値リテラルの型。
+    // 最後まで決まらなければ i64 / f64）
+    kinds: HashMap<u32, Kind>,
+    // 整数リテラルの型・値・負の数か・位置。型が決まってから範囲に収まるかを確かめる
+    literals: Vec<(Ty, u128, bool, Span)>,
+    // i64 に収まらない接尾辞なしのリテラルの型と、最後まで決まらなかったときの型（u64 など）
+    unsuffixed: Vec<(Ty, IntTy)>,
+    // 単項 - を使った整数の型（符号なし整数なら報告する）
+    negated: Vec<(Ty, Span)>,
+    // 検査中の関数の戻り値の型（トップレベルでは Unknown）
+    ret: Ty,
+    // 直近の文の位置（位置を持たない式の報告先）
+    span: Span,
+    types: HashMap<Span, Ty>,
+    errors: Vec<TypeError>,
+}
+
+// プログラム全体を検査し、見つかった型エラーを返す
+pub fn check(stmts: &[Stmt]) -> Vec<TypeError> {
+    infer(stmts).errors
+}
+
+// プログラム全体の型を推論する
+pub fn infer(stmts: &[Stmt]) -> Typed {
+    let mut c = Checker {
+        funcs: HashMap::new(),
+        typedefs: HashMap::new(),
+        traits: HashMap::new(),
+        methods: HashMap::new(),
+        impls: HashSet::new(),
+        assoc: HashMap::new(),
+        bounds: HashMap::new(),
+        self_ty: None,
+        obligations: Vec::new(),
+        scopes: vec![HashMap::new()],
+        subst: Vec::new(),
+        kinds: HashMap::new(),
+        literals: Vec::new(),
+        unsuffixed: Vec::new(),
+        negated: Vec::new(),
+        ret: Ty::Unknown,
+        span: Span::default(),
+        types: HashMap::new(),
+        // 見つからない変数・関数は、型を調べる前の名前の解決で見つける
+        errors: crate::resolve::resolve(stmts).errors,
+    };
+    // 型・トレイト・impl は定義より前から使えるので先に集める
+    c.collect(&crate::prelude::stmts());
+    c.collect(stmts);
+    let funcs: Vec<&Stmt> = stmts
+        .iter()
+        .filter(|s| matches!(s, Stmt::FuncDef { .. }))
+        .collect();
+    // 注釈のない引数・戻り値は型変数にしておく
+    for f in &funcs {
+        let Stmt::FuncDef {
+            name,
+            generics,
+            param_tys,
+            ret,
+            span,
+            ..
+        } = f
+        else {
+            unreachable!()
+        };
+        let scheme = c.with_generics(None, |c| {
+            let params = c.enter_generics(generics, *span);
+            let tys = param_tys.iter().map(|t| c.annotation(t, *span)).collect();
+            let ret = c.annotation(ret, *span);
+            Scheme {
+                vars: Vec::new(),
+                params,
+                ty: Ty::Fn(tys, Box::new(ret)),
+            }
+        });
+        c.funcs.insert(name.clone(), scheme);
+    }
+    // 呼ばれる側の関数から順に推論し、成分ごとに一般化する
+    for group in call_groups(&funcs) {
+        for &i in &group {
+            c.func(funcs[i]);
+        }
+        for &i in &group {
+            let Stmt::FuncDef { name, .. } = funcs[i] else {
+                unreachable!()
+            };
+            let ty = c.zonk(&c.funcs[name].ty);
+            let mut vars = Vec::new();
+            ty.free_vars(&mut vars);
+            let params = c.funcs[name].params.clone();
+            c.funcs.insert(name.clone(), Scheme { vars, params, ty });
+        }
+    }
+    c.check_items(stmts);
+    for stmt in stmts {
+        if !matches!(stmt, Stmt::FuncDef { .. }) {
+            c.stmt(stmt);
+        }
+    }
+    c.check_ints();
+    c.solve_obligations(true);
+    // 型が決まってから、Copy でない値の移動を検査する
+    c.check_moves(stmts);
+    let types = std::mem::take(&mut c.types);
+    Typed {
+        types: types.into_iter().map(|(s, t)| (s, c.zonk(&t))).collect(),
+        errors: c.errors,
+    }
+}
+
+// 関数を呼び出し関係の強連結成分に分け、呼ばれる側が先になるように並べる（Tarjan法）
+fn call_groups(funcs: &[&Stmt]) -> Vec<Vec<usize>> {
+    let index: HashMap<&str, usize> = funcs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, f)| match f {
+            Stmt::FuncDef { name, .. } => Some((name.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+    let edges: Vec<Vec<usize>> = funcs
+        .iter()
+        .map(|f| {
+            let mut out = Vec::new();
+            f.walk(&mut |e| {
+                if let Expr::Call(name, ..) | Expr::Var(name, ..) = e
+                    && let Some(&j) = index.get(name.as_str())
+                {
+                    out.push(j);
+                }
+            });
+            out
+        })
+        .collect();
+
+    struct Tarjan<'a> {
+        edges: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        groups: Vec<Vec<usize>>,
+    }
+    fn visit(t: &mut Tarjan<'_>, v: usize) {
+        t.index[v] = Some(t.next);
+        t.low[v] = t.next;
+        t.next += 1;
+        t.stack.push(v);
+        t.on_stack[v] = true;
+        for &w in &t.edges[v] {
+            match t.index[w] {
+                None => {
+                    visit(t, w);
+                    t.low[v] = t.low[v].min(t.low[w]);
+                }
+                Some(i) if t.on_stack[w] => t.low[v] = t.low[v].min(i),
+                Some(_) => {}
+            }
+        }
+        if Some(t.low[v]) == t.index[v] {
+            let mut group = Vec::new();
+            while let Some(w) = t.stack.pop() {
+                t.on_stack[w] = false;
+                group.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            group.reverse();
+            t.groups.push(group);
+        }
+    }
+    let n = funcs.len();
+    let mut t = Tarjan {
+        edges: &edges,
+        index: vec![None; n],
+        low: vec![0; n],
+        on_stack: vec![false; n],
+        stack: Vec::new(),
+        next: 0,
+        groups: Vec::new(),
+    };
+    for v in 0..n {
+        if t.index[v].is_none() {
+            visit(&mut t, v);
+        }
+    }
+    t.groups
+}
+
+impl Checker {
+    fn error(&mut self, span: Option<Span>, message: String) {
+        self.errors.push(TypeError {
+            span: span.unwrap_or(self.span),
+            message,
+            note: None,
+        });
+    }
+
+    fn fresh(&mut self) -> Ty {
+        self.subst.push(None);
+        Ty::Var(self.subst.len() as u32 - 1)
+    }
+
+    fn fresh_kind(&mut self, kind: Kind) -> Ty {
+        let t = self.fresh();
+        if let Ty::Var(v) = t {
+            self.kinds.insert(v, kind);
+        }
+        t
+    }
+
+    fn fresh_int(&mut self) -> Ty {
+        self.fresh_kind(Kind::Int)
+    }
+
+    // 整数型か、整数にしかならない型変数か
+    fn is_int(&self, ty: &Ty) -> bool {
+        match self.resolve(ty) {
+            Ty::Int(_) => true,
+            Ty::Var(v) => self.kinds.get(&v) == Some(&Kind::Int),
+            _ => false,
+        }
+    }
+
+    fn is_float(&self, ty: &Ty) -> bool {
+        match self.resolve(ty) {
+            Ty::Float(_) => true,
+            Ty::Var(v) => self.kinds.get(&v) == Some(&Kind::Float),
+            _ => false,
+        }
+    }
+
+    // 決まらなかった数値リテラルの型を i64 / f64 にし、整数リテラルの範囲と符号を確かめる
+    fn check_ints(&mut self) {
+        for (t, ty) in std::mem::take(&mut self.unsuffixed) {
+            if let Ty::Var(_) = self.resolve(&t) {
+                self.unify(&t, &Ty::Int(ty));
+            }
+        }
+        for (&v, kind) in &self.kinds {
+            if self.subst[v as usize].is_none() {
+                self.subst[v as usize] = Some(match kind {
+                    Kind::Int => Ty::Int(IntTy::I64),
+                    Kind::Float => Ty::Float(FloatTy::F64),
+                });
+            }
+        }
+        for (ty, n, negative, span) in std::mem::take(&mut self.literals) {
+            if let Ty::Int(ty) = self.zonk(&ty)
+                && ty.literal(n, negative).is_none()
+            {
+                let sign = if negative { "-" } else { "" };
+                self.error(
+                    Some(span),
+                    format!("リテラル {}{} は {} の範囲外です", sign, n, ty.name()),
+                );
+            }
+        }
+        for (ty, span) in std::mem::take(&mut self.negated) {
+            if let Ty::Int(ty) = self.zonk(&ty)
+                && !ty.is_signed()
+            {
+                self.error(
+                    Some(span),
+                    format!("単項演算子 - は {} に使えません", ty.name()),
+                );
+            }
+        }
+    }
+
+    // 割り当て済みの型変数をたどる（外側の一段だけ）
+    fn resolve(&self, ty: &Ty) -> Ty {
+        let mut ty = ty.clone();
+        while let Ty::Var(v) = ty {
+            match &self.subst[v as usize] {
+                Some(t) => ty = t.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    // 型の中の型変数をすべて割り当て済みの型に置き換える
+    fn zonk(&self, ty: &Ty) -> Ty {
+        ty.map_vars(&mut |v| match &self.subst[v as usize] {
+            Some(t) => self.zonk(t),
+            None => Ty::Var(v),
+        })
+    }
+
+    // エラーメッセージ用の型。決まっていない数値リテラルの型は既定の i64 / f64 として表示する
+    fn shown(&self, ty: &Ty) -> Ty {
+        self.zonk(ty).map_vars(&mut |v| match self.kinds.get(&v) {
+            Some(Kind::Int) => Ty::Int(IntTy::I64),
+            Some(Kind::Float) => Ty::Float(FloatTy::F64),
+            None => Ty::Var(v),
+        })
+    }
+
+    // 2つの型を同じ型にする。できなければ false
+    fn unify(&mut self, a: &Ty, b: &Ty) -> bool {
+        grow_stack(|| self.unify_inner(a, b))
+    }
+
+    fn unify_inner(&mut self, a: &Ty, b: &Ty) -> bool {
+        let (a, b) = (self.resolve(a), self.resolve(b));
+        match (&a, &b) {
+            (Ty::Unknown, _) | (_, Ty::Unknown) => true,
+            (Ty::Var(x), Ty::Var(y)) if x == y => true,
+            (Ty::Var(v), t) | (t, Ty::Var(v)) => {
+                // 数値リテラルの型変数は整数型・浮動小数点数型にしかならない
+                if let Some(&kind) = self.kinds.get(v) {
+                    match (kind, t) {
+                        (Kind::Int, Ty::Int(_)) | (Kind::Float, Ty::Float(_)) => {}
+                        (_, Ty::Var(w)) => match self.kinds.get(w) {
+                            Some(&k) if k != kind => return false,
+                            _ => {
+                                self.kinds.insert(*w, kind);
+                            }
+                        },
+                        _ => return false,
+                    }
+                }
+                let mut vars = Vec::new();
+                self.zonk(t).free_vars(&mut vars);
+                // T = Vec<T> のような無限の型は作らない
+                if vars.contains(v) {
+                    return false;
+                }
+                self.subst[*v as usize] = Some(t.clone());
+                true
+            }
+            (Ty::Tuple(x), Ty::Tuple(y)) => {
+                x.len() == y.len() && x.iter().zip(y).all(|(x, y)| self.unify(x, y))
+            }
+            (Ty::Vec(x), Ty::Vec(y))
+            | (Ty::Set(x), Ty::Set(y))
+            | (Ty::Range(x), Ty::Range(y))
+            | (Ty::Option(x), Ty::Option(y))
+            | (Ty::Iter(x), Ty::Iter(y)) => self.unify(x, y),
+            (Ty::Map(k1, v1), Ty::Map(k2, v2)) | (Ty::Result(k1, v1), Ty::Result(k2, v2)) => {
+                self.unify(k1, k2) && self.unify(v1, v2)
+            }
+            (Ty::Fn(p1, r1), Ty::Fn(p2, r2)) => {
+                p1.len() == p2.len()
+                    && p1.iter().zip(p2).all(|(x, y)| self.unify(x, y))
+                    && self.unify(r1, r2)
+            }
+            (Ty::Named(n1, a1), Ty::Named(n2, a2)) => {
+                n1 == n2 && a1.len() == a2.len() && a1.iter().zip(a2).all(|(x, y)| self.unify(x, y))
+            }
+            _ => a == b,
+        }
+    }
+
+    // expected と同じ型にできなければ報告する
+    fn expect(&mut self, expected: &Ty, actual: &Ty, span: Option<Span>) {
+        if !self.unify(expected, actual) {
+            let (expected, actual) = (self.shown(expected), self.shown(actual));
+            self.error(
+                span,
+                format!(
+                    "型が一致しません: {} が必要ですが {} です",
+                    expected, actual
+                ),
+            );
+        }
+    }
+
+    fn record(&mut self, span: Span, ty: &Ty) {
+        self.types.insert(span, ty.clone());
+    }
+
+    // 型注釈を型にする。省略や `_` は型変数に、未定義の型名は報告して Unknown にする
+    fn annotation(&mut self, ty: &Option<String>, span: Span) -> Ty {
+        let Some(ty) = ty else {
+            return self.fresh();
+        };
+        let ty = self.resolve_names(&Ty::parse(ty), Some(span));
+        self.fill_holes(&ty)
+    }
+
+    // Vec<_> などの省略された部分を型変数にする
+    fn fill_holes(&mut self, ty: &Ty) -> Ty {
+        ty.map(&mut |t| (*t == Ty::Unknown).then(|| self.fresh()))
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme, span: Span) -> Ty {
+        self.instantiate_with(scheme, &[], span)
+    }
+
+    // fixed の型引数は指定の型に、それ以外の型引数と型変数は新しい型変数に置き換える
+    fn instantiate_with(&mut self, scheme: &Scheme, fixed: &[(String, Ty)], span: Span) -> Ty {
+        let mut params: HashMap<String, Ty> = fixed.iter().cloned().collect();
+        for (name, bounds) in &scheme.params {
+            let t = self.fresh();
+            for b in bounds {
+                if let Ty::Named(tr, _) = b {
+                    self.obligations.push((t.clone(), tr.clone(), span));
+                }
+            }
+            params.insert(name.clone(), t);
+        }
+        let fresh: HashMap<u32, Ty> = scheme
+            .vars
+            .iter()
+            .map(|&v| {
+                let t = match self.kinds.get(&v) {
+                    Some(&kind) => self.fresh_kind(kind),
+                    None => self.fresh(),
+                };
+                (v, t)
+            })
+            .collect();
+        self.zonk(&scheme.ty).map(&mut |t| match t {
+            Ty::Var(v) => fresh.get(v).cloned(),
+            Ty::Param(p) => params.get(p).cloned(),
+            _ => None,
+        })
+    }
+
+    fn define(&mut self, name: &str, ty: Ty) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), ty);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Ty> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+    }
+
+    fn func(&mut self, stmt: &Stmt) {
+        let Stmt::FuncDef { name, .. } = stmt else {
+            return;
+        };
+        let scheme = self.funcs[name].clone();
+        self.with_generics(None, |c| {
+            c.bounds.extend(scheme.params.iter().cloned());
+            c.body(stmt, scheme.ty.clone());
+        });
+    }
+
+    // 関数・メソッドの本体を、引数と戻り値の型 fn_ty で検査する
+    fn body(&mut self, stmt: &Stmt, fn_ty: Ty) {
+        let Stmt::FuncDef {
+            name,
+            params,
+            body,
+            span,
+            ..
+        } = stmt
+        else {
+            return;
+        };
+        self.span = *span;
+        self.record(*span, &fn_ty);
+        let Ty::Fn(param_tys, ret) = fn_ty else {
+            unreachable!()
+        };
+        let saved_scopes = std::mem::replace(&mut self.scopes, vec![HashMap::new()]);
+        let saved_ret = std::mem::replace(&mut self.ret, (*ret).clone());
+        for (p, t) in params.iter().zip(&param_tys) {
+            self.bind(p, t);
+        }
+        let body_ty = self.expr(body);
+        if !self.unify(&ret, &body_ty) {
+            let (ret, body_ty) = (self.shown(&ret), self.shown(&body_ty));
+            self.error(
+                Some(*span),
+                format!(
+                    "関数 {} の戻り値の型が一致しません: {} が必要ですが {} です",
+                    name, ret, body_ty
+                ),
+            );
+        }
+        self.ret = saved_ret;
+        self.scopes = saved_scopes;
+        // 本体の中の型引数の境界は、境界の見えているここで確かめる
+        self.solve_obligations(false);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Ty {
+        match stmt {
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::Print(e) => {
+                self.expr(e);
+                Ty::Unit
+            }
+            Stmt::Let {
+                pattern,
+                value,
+                ty,
+                span,
+                ..
+            } => {
+                self.span = *span;
+                let actual = self.expr(value);
+                let ty = match ty {
+                    Some(_) => {
+                        let expected = self.annotation(ty, *span);
+                        self.expect(&expected, &actual, Some(*span));
+                        expected
+                    }
+                    None => actual,
+                };
+                self.record(*span, &ty);
+                self.bind(pattern, &ty);
+                Ty::Unit
+            }
+            // トップレベルの関数・impl は infer で検査済み（ブロック内の定義は評価器も未対応）
+            Stmt::FuncDef { .. }
+            | Stmt::StructDef { .. }
+            | Stmt::EnumDef { .. }
+            | Stmt::TraitDef { .. }
+            | Stmt::ImplDef { .. }
+            | Stmt::Import(_) => Ty::Unit,
+            // 解析エラーの文は評価しても何もしないので、ここで誤りとして報告する
+            Stmt::Error(msg, span) => {
+                self.error(Some(*span), msg.clone());
+                Ty::Unit
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Ty {
+        grow_stack(|| self.expr_inner(expr))
+    }
+
+    fn expr_inner(&mut self, expr: &Expr) -> Ty {
+        match expr {
+            Expr::Number(n) => {
+                let t = self.fresh_int();
+                self.literals
+                    .push((t.clone(), *n as u128, false, self.span));
+                t
+            }
+            Expr::Float(_, Some(ty)) => Ty::Float(*ty),
+            Expr::Float(_, None) => self.fresh_kind(Kind::Float),
+            Expr::Format { pieces, args, span } => {
+                let mut used = vec![false; args.len()];
+                for piece in pieces {
+                    match piece {
+                        FmtPiece::Arg { index, .. } if *index < args.len() => used[*index] = true,
+                        FmtPiece::Arg { index, .. } => self.error(
+                            Some(*span),
+                            format!("書式文字列の引数が足りません: {}", index),
+                        ),
+                        FmtPiece::Invalid(s) => {
+                            self.error(Some(*span), format!("書式指定 {} には対応していません", s))
+                        }
+                        FmtPiece::Lit(_) => {}
+                    }
+                }
+                if used.contains(&false) {
+                    self.error(
+                        Some(*span),
+                        "書式文字列で使われていない引数があります".to_string(),
+                    );
+                }
+                let arg_tys: Vec<Ty> = args.iter().map(|a| self.expr(a)).collect();
+                // 型引数の値の表示には Display / Debug の境界が必要
+                for piece in pieces {
+                    if let FmtPiece::Arg { index, spec } = piece
+                        && let Some(t) = arg_tys.get(*index)
+                        && let Ty::Param(_) = self.resolve(t)
+                    {
+                        let tr = if spec.debug { "Debug" } else { "Display" };
+                        self.require(t, tr, *span);
+                    }
+                }
+                Ty::Str
+            }
+            Expr::TypedNumber(n, ty, span) => self.int_literal(*n, *ty, false, *span),
+            // -128i8 のように、負の数のリテラルは符号を含めて範囲を確かめる
+            Expr::Unary(UnaryOp::Neg, operand, span)
+                if matches!(**operand, Expr::TypedNumber(..)) =>
+            {
+                let Expr::TypedNumber(n, ty, _) = **operand else {
+                    unreachable!()
+                };
+                self.int_literal(n, ty, true, *span)
+            }
+            Expr::Unary(UnaryOp::Neg, operand, span) if matches!(**operand, Expr::Number(_)) => {
+                let Expr::Number(n) = **operand else {
+                    unreachable!()
+                };
+                let t = self.fresh_int();
+                self.literals.push((t.clone(), n as u128, true, *span));
+                self.negated.push((t.clone(), *span));
+                t
+            }
+            Expr::Bool(_) => Ty::Bool,
+            Expr::Char(_) => Ty::Char,
+            Expr::Str(_) => Ty::Str,
+            Expr::Binary(op, lhs, rhs, span) => {
+                let l = self.expr(lhs);
+                let r = self.expr(rhs);
+                let t = self.binary(*op, &l, &r, *span);
+                self.record(*span, &t);
+                t
+            }
+            // 参照の型は中身の型と区別しない
+            Expr::Ref(e, ..) | Expr::Vec(e) => self.expr(e),
+            Expr::Unary(op, operand, span) => {
+                let t = self.expr(operand);
+                match (op, self.resolve(&t)) {
+                    (UnaryOp::Neg, Ty::Var(_)) if self.is_int(&t) => {
+                        self.negated.push((t.clone(), *span));
+                        t
+                    }
+                    (UnaryOp::Not, Ty::Var(_)) if self.is_float(&t) => {
+                        let resolved = self.shown(&t);
+                        self.error(
+                            Some(*span),
+                            format!("単項演算子 {:?} は {} に使えません", op, resolved),
+                        );
+                        Ty::Unknown
+                    }
+                    (UnaryOp::Deref, _) | (_, Ty::Var(_) | Ty::Unknown) => t,
+                    (UnaryOp::Not, Ty::Int(_) | Ty::Bool) => t,
+                    (UnaryOp::Neg, Ty::Float(_)) => t,
+                    (UnaryOp::Neg, Ty::Int(ty)) if ty.is_signed() => t,
+                    (_, resolved) => {
+                        self.error(
+                            Some(*span),
+                            format!("単項演算子 {:?} は {} に使えません", op, resolved),
+                        );
+                        Ty::Unknown
+                    }
+                }
+            }
+            Expr::Var(name, span, _) => {
+                let t = match self.lookup(name) {
+                    Some(t) => t,
+                    None => match self.funcs.get(name).cloned() {
+                        Some(scheme) => self.instantiate(&scheme, *span),
+                        None => match (self.variant(name), float::constant(name)) {
+                            // None / Color::Red（中身のあるバリアントは関数として使える）
+                            (Some((fields, ty)), _) if fields.is_empty() => ty,
+                            (Some((fields, ty)), _) => Ty::Fn(fields, Box::new(ty)),
+                            (None, Some((ty, _))) => Ty::Float(ty),
+                            (None, None) if let Some((ty, _)) = int::constant(name) => Ty::Int(ty),
+                            // 見つからない名前は名前の解決で報告する
+                            (None, None) => Ty::Unknown,
+                        },
+                    },
+                };
+                self.record(*span, &t);
+                t
+            }
+            Expr::Call(name, args, span, _) => {
+                let arg_tys: Vec<Ty> = args.iter().map(|a| self.expr(a)).collect();
+                let t = self.call(name, &arg_tys, *span);
+                self.record(*span, &t);
+                t
+            }
+            Expr::MethodCall(recv, name, args, span) => {
+                let recv_ty = self.expr(recv);
+                let arg_tys: Vec<Ty> = args.iter().map(|a| self.expr(a)).collect();
+                let t = self.method(&recv_ty, name, &arg_tys, *span);
+                self.record(*span, &t);
+                t
+            }
+            Expr::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                let mut last = Ty::Unit;
+                for stmt in stmts {
+                    last = self.stmt(stmt);
+                }
+                self.scopes.pop();
+                last
+            }
+            Expr::Tuple(items) => {
+                if items.is_empty() {
+                    return Ty::Unit;
+                }
+                Ty::Tuple(items.iter().map(|e| self.expr(e)).collect())
+            }
+            Expr::Array(items) => {
+                let elem = self.fresh();
+                for item in items {
+                    let t = self.expr(item);
+                    self.expect(&elem, &t, span_of(item));
+                }
+                Ty::Vec(Box::new(elem))
+            }
+            Expr::ArrayRepeat(value, count) => {
+                let elem = self.expr(value);
+                let n = self.expr(count);
+                let int = self.fresh_int();
+                self.expect(&int, &n, span_of(count));
+                Ty::Vec(Box::new(elem))
+            }
+            Expr::Index {
+                target,
+                index,
+                span,
+            } => {
+                let t = self.expr(target);
+                let i = self.expr(index);
+                let result = match (self.resolve(&t), self.resolve(&i)) {
+                    (Ty::Vec(_) | Ty::Str, Ty::Range(_)) => t.clone(),
+                    // 添字はどの整数型でもよい
+                    (Ty::Vec(elem), _) => {
+                        let int = self.fresh_int();
+                        self.expect(&int, &i, Some(*span));
+                        *elem
+                    }
+                    (Ty::Map(k, v), _) => {
+                        self.expect(&k, &i, Some(*span));
+                        *v
+                    }
+                    (Ty::Var(_) | Ty::Unknown, _) => self.fresh(),
+                    (t, i) => {
+                        let (t, i) = (self.shown(&t), self.shown(&i));
+                        self.error(
+                            Some(*span),
+                            format!("{} を {} で添字アクセスできません", t, i),
+                        );
+                        Ty::Unknown
+                    }
+                };
+                self.record(*span, &result);
+                result
+            }
+            Expr::Assign { target, op, value } => {
+                let t = self.expr(target);
+                let v = self.expr(value);
+                let span = span_of(target);
+                match op {
+                    Some(op) => {
+                        let result = self.binary(*op, &t, &v, span.unwrap_or(self.span));
+                        self.expect(&t, &result, span);
+                    }
+                    None => self.expect(&t, &v, span),
+                }
+                Ty::Unit
+            }
+            Expr::StructInit(name, inits) => {
+                let Some((defs, result)) = self.struct_type(name) else {
+                    self.error(None, format!("未定義の構造体: {}", name));
+                    return Ty::Unknown;
+                };
+                for (field, e, span) in inits {
+                    let t = self.expr(e);
+                    match defs.iter().find(|(f, _)| f == field) {
+                        Some((_, expected)) => {
+                            self.expect(expected, &t, span_of(e).or(Some(*span)))
+                        }
+                        None => self.error(
+                            Some(*span),
+                            format!("構造体 {} にフィールド {} はありません", name, field),
+                        ),
+                    }
+                }
+                result
+            }
+            Expr::FieldAccess(target, field) => {
+                let t = self.expr(target);
+                self.field(&t, field, span_of(target))
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let c = self.expr(cond);
+                self.expect(&Ty::Bool, &c, span_of(cond));
+                let then_ty = self.expr(then_branch);
+                match else_branch {
+                    Some(e) => {
+                        let else_ty = self.expr(e);
+                        self.expect(&then_ty, &else_ty, span_of(e));
+                        then_ty
+                    }
+                    None => Ty::Unit,
+                }
+            }
+            Expr::For {
+                pattern,
+                iter,
+                body,
+            } => {
+                let t = self.expr(iter);
+                let elem = match self.resolve(&t) {
+                    Ty::Var(_) | Ty::Unknown => self.fresh(),
+                    resolved => match resolved.elem() {
+                        Some(elem) => Some(elem),
+                        // Iterator を実装した型は next が返す要素の型
+                        None if self.implements(&resolved, "Iterator") => {
+                            let span = span_of(iter).unwrap_or(self.span);
+                            self.iterator_item(&resolved, span)
+                        }
+                        None => None,
+                    }
+                    .unwrap_or_else(|| {
+                        let resolved = self.shown(&resolved);
+                        self.error(span_of(iter), format!("{} は反復できません", resolved));
+                        Ty::Unknown
+                    }),
+                };
+                self.scopes.push(HashMap::new());
+                self.bind(pattern, &elem);
+                self.expr(body);
+                self.scopes.pop();
+                Ty::Unit
+            }
+            Expr::Range { start, end, .. } => {
+                let elem = self.fresh_int();
+                for e in [start, end].into_iter().flatten() {
+                    let t = self.expr(e);
+                    self.expect(&elem, &t, span_of(e));
+                }
+                Ty::Range(Box::new(elem))
+            }
+            Expr::Match { scrutinee, arms } => {
+                let t = self.expr(scrutinee);
+                if arms.is_empty() {
+                    return Ty::Unit;
+                }
+                let result = self.fresh();
+                for (pattern, body) in arms {
+                    self.scopes.push(HashMap::new());
+                    self.bind(pattern, &t);
+                    let arm_ty = self.expr(body);
+                    self.scopes.pop();
+                    self.expect(&result, &arm_ty, span_of(body));
+                }
+                result
+            }
+            Expr::Panic {
+                kind,
+                args,
+                message,
+                span,
+                ..
+            } => {
+                let tys: Vec<Ty> = args.iter().map(|a| self.expr(a)).collect();
+                match &tys[..] {
+                    [cond] => self.expect(&Ty::Bool, cond, span_of(&args[0]).or(Some(*span))),
+                    [l, r] => {
+                        self.expect(l, r, span_of(&args[1]).or(Some(*span)));
+                        // 失敗したときは両辺を {:?} で表示する
+                        self.operator_trait(BinOp::Eq, l, *span);
+                        if let Ty::Param(_) = self.resolve(l) {
+                            self.require(l, "Debug", *span);
+                        }
+                    }
+                    _ => {}
+                }
+                if let Some(m) = message {
+                    self.expr(m);
+                }
+                match kind {
+                    PanicKind::Assert | PanicKind::AssertEq | PanicKind::AssertNe => Ty::Unit,
+                    // panic! などは戻らないので、どの型の位置にも書ける
+                    PanicKind::Panic | PanicKind::Unreachable | PanicKind::Todo => Ty::Unknown,
+                }
+            }
+            Expr::Return(value) => {
+                let t = match value {
+                    Some(e) => self.expr(e),
+                    None => Ty::Unit,
+                };
+                let expected = self.ret.clone();
+                self.expect(&expected, &t, value.as_deref().and_then(span_of));
+                // return 自体はどの型の位置にも書ける
+                Ty::Unknown
+            }
+            Expr::Try(e) => {
+                let t = self.expr(e);
+                let ret = self.ret.clone();
+                match (self.resolve(&t), self.resolve(&ret)) {
+                    (Ty::Option(inner), Ty::Option(_) | Ty::Var(_) | Ty::Unknown) => {
+                        let expected = Ty::Option(Box::new(self.fresh()));
+                        self.expect(&ret, &expected, span_of(e));
+                        *inner
+                    }
+                    (Ty::Result(inner, err), Ty::Result(..) | Ty::Var(_) | Ty::Unknown) => {
+                        let expected = Ty::Result(Box::new(self.fresh()), err);
+                        self.expect(&ret, &expected, span_of(e));
+                        *inner
+                    }
+                    // 中身の型が未定なら、戻り値の型に合わせる
+                    (Ty::Var(_), Ty::Option(_)) => {
+                        let inner = self.fresh();
+                        self.unify(&t, &Ty::Option(Box::new(inner.clone())));
+                        inner
+                    }
+                    (Ty::Var(_), Ty::Result(_, err)) => {
+                        let inner = self.fresh();
+                        self.unify(&t, &Ty::Result(Box::new(inner.clone()), err));
+                        inner
+                    }
+                    (Ty::Var(_) | Ty::Unknown, _) => Ty::Unknown,
+                    (Ty::Option(_) | Ty::Result(..), ret) => {
+                        let ret = self.shown(&ret);
+                        self.error(
+                            span_of(e),
+                            format!("? は戻り値が {} の関数では使えません", ret),
+                        );
+                        Ty::Unknown
+                    }
+                    (t, _) => {
+                        let t = self.shown(&t);
+                        self.error(
+                            span_of(e),
+                            format!("? は Option か Result にしか使えません: {}", t),
+                        );
+                        Ty::Unknown
+                    }
+                }
+            }
+            Expr::Cast(e, ty, span) => {
+                let from = self.expr(e);
+                let to = Ty::parse(ty);
+                // 数値は数値に、bool・char は整数に、u8 は char に変換できる
+                let numeric = self.is_int(&from) || self.is_float(&from);
+                let ok = match (self.resolve(&from), &to) {
+                    (_, Ty::Int(_) | Ty::Float(_)) if numeric => true,
+                    (Ty::Unknown, Ty::Float(_)) => true,
+                    (Ty::Bool | Ty::Char | Ty::Unknown, Ty::Int(_)) => true,
+                    (Ty::Var(_), Ty::Char) if self.is_int(&from) => {
+                        self.unify(&from, &Ty::Int(IntTy::U8))
+                    }
+                    (Ty::Int(IntTy::U8) | Ty::Char | Ty::Unknown, Ty::Char) => true,
+                    _ => false,
+                };
+                if !ok {
+                    let from = self.shown(&from);
+                    self.error(Some(*span), format!("{} を {} に変換できません", from, to));
+                }
+                self.record(*span, &to);
+                to
+            }
+            Expr::Closure { params, body } => {
+                self.scopes.push(HashMap::new());
+                let mut param_tys = Vec::new();
+                for p in params {
+                    let t = self.fresh();
+                    self.bind(p, &t);
+                    param_tys.push(t);
+                }
+                // クロージャ内の return はクロージャから戻る
+                let ret = self.fresh();
+                let saved_ret = std::mem::replace(&mut self.ret, ret.clone());
+                let body_ty = self.expr(body);
+                self.expect(&ret, &body_ty, span_of(body));
+                self.ret = saved_ret;
+                self.scopes.pop();
+                Ty::Fn(param_tys, Box::new(ret))
+            }
+        }
+    }
+
+    // 接尾辞付きか i64 に収まらない整数リテラル（negative なら符号を含めて）の型。
+    // 接尾辞のないものは型注釈や使われ方で型が決まり、決まってから範囲を確かめる
+    fn int_literal(&mut self, n: u128, ty: Option<IntTy>, negative: bool, span: Span) -> Ty {
+        let Some(ty) = ty else {
+            let t = self.fresh_int();
+            self.literals.push((t.clone(), n, negative, span));
+            self.unsuffixed
+                .push((t.clone(), IntTy::unsuffixed(n, negative)));
+            if negative {
+                self.negated.push((t.clone(), span));
+            }
+            return t;
+        };
+        if negative && !ty.is_signed() {
+            self.error(
+                Some(span),
+                format!("単項演算子 - は {} に使えません", ty.name()),
+            );
+        } else if ty.literal(n, negative).is_none() {
+            let sign = if negative { "-" } else { "" };
+            self.error(
+                Some(span),
+                format!("リテラル {}{} は {} の範囲外です", sign, n, ty.name()),
+            );
+        }
+        Ty::Int(ty)
+    }
+
+    fn binary(&mut self, op: BinOp, l: &Ty, r: &Ty, span: Span) -> Ty {
+        let result = match op {
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                // トレイトがない場合は operator_trait が報告するので、型は bool のままにする
+                self.unify(l, r).then(|| {
+                    self.operator_trait(op, l, span);
+                    Ty::Bool
+                })
+            }
+            BinOp::And | BinOp::Or => {
+                (self.unify(&Ty::Bool, l) && self.unify(&Ty::Bool, r)).then_some(Ty::Bool)
+            }
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => {
+                // 両辺は同じ型（+ は文字列同士も）。片方が決まっていればもう片方もその型にする
+                let operand = match (self.resolve(l), self.resolve(r)) {
+                    (Ty::Var(_) | Ty::Unknown, Ty::Var(_) | Ty::Unknown) => l.clone(),
+                    (Ty::Var(_) | Ty::Unknown, t) | (t, _) => t,
+                };
+                let usable = match self.resolve(&operand) {
+                    Ty::Int(_) | Ty::Float(_) | Ty::Var(_) | Ty::Unknown => true,
+                    Ty::Str => op == BinOp::Add,
+                    Ty::Param(_) => {
+                        self.operator_trait(op, &operand, span);
+                        true
+                    }
+                    _ => false,
+                };
+                (usable && self.unify(l, &operand) && self.unify(r, &operand)).then_some(operand)
+            }
+        };
+        result.unwrap_or_else(|| {
+            let (l, r) = (self.shown(l), self.shown(r));
+            self.error(
+                Some(span),
+                format!("演算子 {} は {} と {} に使えません", op_symbol(op), l, r),
+            );
+            Ty::Unknown
+        })
+    }
+
+    // 関数型 f の値を args で呼び出したときの戻り値の型
+    fn apply(&mut self, name: &str, f: &Ty, args: &[Ty], span: Span) -> Ty {
+        match self.resolve(f) {
+            Ty::Fn(params, ret) => {
+                self.check_args(name, &params, args, span);
+                *ret
+            }
+            Ty::Var(_) => {
+                let ret = self.fresh();
+                self.unify(f, &Ty::Fn(args.to_vec(), Box::new(ret.clone())));
+                ret
+            }
+            Ty::Unknown => Ty::Unknown,
+            other => {
+                let other = self.shown(&other);
+                self.error(Some(span), format!("{} は呼び出せません: {}", name, other));
+                Ty::Unknown
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Ty], span: Span) -> Ty {
+        // クロージャを入れた変数の呼び出し
+        if let Some(f) = self.lookup(name) {
+            return self.apply(name, &f, args, span);
+        }
+        if let Some(scheme) = self.funcs.get(name).cloned() {
+            let f = self.instantiate(&scheme, span);
+            return self.apply(name, &f, args, span);
+        }
+        // Some(x) / Shape::Circle(r) などのバリアント
+        if let Some((fields, ty)) = self.variant(name) {
+            self.check_args(name, &fields, args, span);
+            return ty;
+        }
+        // Point::new(..) / T::default() などの関連関数
+        if let Some(f) = self.static_method(name, span) {
+            return self.apply(name, &f, args, span);
+        }
+        match name {
+            "print" => Ty::Unit,
+            "input" => Ty::Result(Box::new(Ty::Int(IntTy::I64)), Box::new(Ty::Str)),
+            "fs::read_to_string" | "std::fs::read_to_string" => {
+                Ty::Result(Box::new(Ty::Str), Box::new(Ty::Str))
+            }
+            "fs::write" | "std::fs::write" => Ty::Result(Box::new(Ty::Unit), Box::new(Ty::Str)),
+            "env::var" | "std::env::var" => Ty::Result(Box::new(Ty::Str), Box::new(Ty::Str)),
+            "process::exit" | "std::process::exit" => Ty::Unit,
+            "time::now_millis" => Ty::Int(IntTy::I64),
+            "Vec::new" | "Vec::with_capacity" => Ty::Vec(Box::new(self.fresh())),
+            "HashMap::new" => Ty::Map(Box::new(self.fresh()), Box::new(self.fresh())),
+            "HashSet::new" => Ty::Set(Box::new(self.fresh())),
+            // import したファイルの関数などは実行時に確かめる
+            _ => Ty::Unknown,
+        }
+    }
+
+    fn check_args(&mut self, name: &str, params: &[Ty], args: &[Ty], span: Span) {
+        if params.len() != args.len() {
+            self.error(
+                Some(span),
+                format!(
+                    "{} の引数の数が一致しません: {} 個必要ですが {} 個です",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+            );
+            return;
+        }
+        for (i, (p, a)) in params.iter().zip(args).enumerate() {
+            if !self.unify(p, a) {
+                let (p, a) = (self.shown(p), self.shown(a));
+                self.error(
+                    Some(span),
+                    format!(
+                        "{} の {} 番目の引数の型が一致しません: {} が必要ですが {} です",
+                        name,
+                        i + 1,
+                        p,
+                        a
+                    ),
+                );
+            }
+        }
+    }
+
+    // メソッドの戻り値の型。impl・境界のトレイトのメソッドを先に探し、
+    // なければ組み込み型のメソッド（分からないものは新しい型変数）
+    fn method(&mut self, recv: &Ty, name: &str, args: &[Ty], span: Span) -> Ty {
+        if let Some(t) = self.user_method(recv, name, args, span) {
+            return t;
+        }
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Ty::Unknown);
+        let b = Box::new;
+        // len や enumerate の添字は、使われ方に合わせてどの整数型にもなる（決まらなければ i64）
+        let int = self.fresh_int();
+        if let Ty::Param(_) = self.resolve(recv) {
+            let recv = self.shown(recv);
+            self.error(
+                Some(span),
+                format!("{} にメソッド {} はありません", recv, name),
+            );
+            return Ty::Unknown;
+        }
+        if name == "clone" {
+            return self.resolve(recv);
+        }
+        if name == "to_string" {
+            self.require(recv, "Display", span);
+            return Ty::Str;
+        }
+        if self.is_float(recv) {
+            let sig = match name {
+                "sqrt" | "abs" | "floor" | "ceil" | "round" | "trunc" | "sin" | "cos" | "tan"
+                | "ln" | "log10" | "exp" => Some((vec![], recv.clone())),
+                "powi" => Some((vec![Ty::Int(IntTy::I32)], recv.clone())),
+                "powf" | "min" | "max" => Some((vec![recv.clone()], recv.clone())),
+                "is_nan" => Some((vec![], Ty::Bool)),
+                _ => None,
+            };
+            if let Some((params, ret)) = sig {
+                self.check_args(name, &params, args, span);
+                return ret;
+            }
+        }
+        // wrapping_add / checked_add / saturating_add など
+        if self.is_int(recv)
+            && let Some((mode, op)) = name.split_once('_')
+            && matches!(mode, "wrapping" | "checked" | "saturating")
+            && matches!(op, "add" | "sub" | "mul" | "div" | "rem")
+        {
+            self.check_args(name, std::slice::from_ref(recv), args, span);
+            return match mode {
+                "checked" => Ty::Option(b(recv.clone())),
+                _ => recv.clone(),
+            };
+        }
+        let recv = self.resolve(recv);
+        match (&recv, name) {
+            (Ty::Str, "len") => int,
+            (Ty::Str, "chars") => Ty::Iter(b(Ty::Char)),
+            (Ty::Str, "parse") => Ty::Result(b(int), b(Ty::Str)),
+            (Ty::Range(elem), "contains") => {
+                self.expect(elem, &arg(0), Some(span));
+                Ty::Bool
+            }
+            (Ty::Vec(_) | Ty::Map(..) | Ty::Set(_) | Ty::Iter(_), "len") => int,
+            (Ty::Vec(_) | Ty::Map(..) | Ty::Set(_), "is_empty") => Ty::Bool,
+            (Ty::Vec(elem) | Ty::Set(elem), "contains") | (Ty::Map(elem, _), "contains_key") => {
+                self.expect(elem, &arg(0), Some(span));
+                Ty::Bool
+            }
+            (Ty::Vec(_) | Ty::Map(..) | Ty::Set(_), "clear") | (Ty::Vec(_), "sort" | "reverse") => {
+                Ty::Unit
+            }
+            (Ty::Vec(elem), "push") => {
+                self.expect(elem, &arg(0), Some(span));
+                Ty::Unit
+            }
+            (Ty::Vec(elem), "insert") => {
+                self.expect(elem, &arg(1), Some(span));
+                Ty::Unit
+            }
+            (Ty::Vec(elem), "pop") => Ty::Option(elem.clone()),
+            (Ty::Vec(elem), "remove") => (**elem).clone(),
+            (Ty::Map(k, v), "insert") => {
+                self.expect(k, &arg(0), Some(span));
+                self.expect(v, &arg(1), Some(span));
+                Ty::Option(v.clone())
+            }
+            (Ty::Map(k, v), "get" | "remove") => {
+                self.expect(k, &arg(0), Some(span));
+                Ty::Option(v.clone())
+            }
+            // map.entry(k).or_insert(v) の途中は HashMap の型のまま持ち回る
+            (Ty::Map(k, _), "entry") => {
+                self.expect(k, &arg(0), Some(span));
+                recv.clone()
+            }
+            (Ty::Map(_, v), "or_insert") => {
+                self.expect(v, &arg(0), Some(span));
+                (**v).clone()
+            }
+            (Ty::Map(k, _), "keys") => Ty::Iter(k.clone()),
+            (Ty::Map(_, v), "values") => Ty::Iter(v.clone()),
+            (Ty::Set(elem), "insert") => {
+                self.expect(elem, &arg(0), Some(span));
+                Ty::Bool
+            }
+            (Ty::Set(_), "remove") => Ty::Bool,
+            (
+                Ty::Vec(_) | Ty::Map(..) | Ty::Set(_) | Ty::Range(_) | Ty::Iter(_),
+                "iter" | "into_iter",
+            ) => Ty::Iter(b(recv.elem().unwrap_or(Ty::Unknown))),
+            (Ty::Range(_) | Ty::Iter(_), _) => {
+                let elem = recv.elem().unwrap_or(Ty::Unknown);
+                match name {
+                    "collect" => Ty::Vec(b(elem)),
+                    "next" => Ty::Option(b(elem)),
+                    "rev" | "step_by" => Ty::Iter(b(elem)),
+                    "enumerate" => Ty::Iter(b(Ty::Tuple(vec![int, elem]))),
+                    _ => self.fresh(),
+                }
+            }
+            (Ty::Option(_), "is_some" | "is_none") | (Ty::Result(..), "is_ok" | "is_err") => {
+                Ty::Bool
+            }
+            (Ty::Option(t) | Ty::Result(t, _), "unwrap" | "expect") => (**t).clone(),
+            (Ty::Option(t) | Ty::Result(t, _), "unwrap_or") => {
+                self.expect(t, &arg(0), Some(span));
+                (**t).clone()
+            }
+            (Ty::Option(t), "unwrap_or_else") => {
+                let v = self.apply(name, &arg(0), &[], span);
+                self.expect(t, &v, Some(span));
+                (**t).clone()
+            }
+            (Ty::Result(t, e), "unwrap_or_else") => {
+                let v = self.apply(name, &arg(0), &[(**e).clone()], span);
+                self.expect(t, &v, Some(span));
+                (**t).clone()
+            }
+            (Ty::Option(_), "take") => recv.clone(),
+            (Ty::Option(t), "map") => {
+                Ty::Option(b(self.apply(name, &arg(0), &[(**t).clone()], span)))
+            }
+            (Ty::Option(t), "ok_or") => Ty::Result(t.clone(), b(arg(0))),
+            (Ty::Result(t, _), "ok") => Ty::Option(t.clone()),
+            (Ty::Result(_, e), "err") => Ty::Option(e.clone()),
+            (Ty::Result(t, e), "map") => Ty::Result(
+                b(self.apply(name, &arg(0), &[(**t).clone()], span)),
+                e.clone(),
+            ),
+            (Ty::Result(t, e), "map_err") => Ty::Result(
+                t.clone(),
+                b(self.apply(name, &arg(0), &[(**e).clone()], span)),
+            ),
+            (Ty::Option(t), "and_then") => {
+                let r = self.apply(name, &arg(0), &[(**t).clone()], span);
+                let expected = Ty::Option(b(self.fresh()));
+                self.expect(&expected, &r, Some(span));
+                r
+            }
+            (Ty::Result(t, e), "and_then") => {
+                let r = self.apply(name, &arg(0), &[(**t).clone()], span);
+                let expected = Ty::Result(b(self.fresh()), e.clone());
+                self.expect(&expected, &r, Some(span));
+                r
+            }
+            // 構造体・列挙型のメソッドは impl にあるものだけ
+            (Ty::Named(..), _) => {
+                let recv = self.shown(&recv);
+                self.error(
+                    Some(span),
+                    format!("{} にメソッド {} はありません", recv, name),
+                );
+                Ty::Unknown
+            }
+            // 型がまだ決まらない値のメソッドは実行時に確かめる
+            _ => self.fresh(),
+        }
+    }
+
+    fn field(&mut self, target: &Ty, field: &str, span: Option<Span>) -> Ty {
+        let target = self.resolve(target);
+        let found = match &target {
+            Ty::Var(_) | Ty::Unknown => return self.fresh(),
+            Ty::Named(name, args) => self.typedefs.get(name).and_then(|def| {
+                let (_, t) = def.fields.iter().find(|(f, _)| f == field)?;
+                Some(subst(t, &def.generics, args))
+            }),
+            Ty::Tuple(items) => field
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| items.get(i).cloned()),
+            _ => None,
+        };
+        found.unwrap_or_else(|| {
+            let target = self.shown(&target);
+            self.error(
+                span,
+                format!("{} にフィールド {} はありません", target, field),
+            );
+            Ty::Unknown
+        })
+    }
+
+    // パターンの変数に型を割り当てる。パターンの形から値の型も推論する
+    fn bind(&mut self, pattern: &Pattern, ty: &Ty) {
+        match pattern {
+            Pattern::Wildcard | Pattern::Rest(None) => {}
+            Pattern::Bind(name, span) => {
+                self.record(*span, ty);
+                self.define(name, ty.clone());
+            }
+            Pattern::Rest(Some(name)) => self.define(name, ty.clone()),
+            Pattern::Number(_) => {
+                let int = self.fresh_int();
+                self.expect(ty, &int, None);
+            }
+            Pattern::Bool(_) => self.expect(ty, &Ty::Bool, None),
+            Pattern::Char(_) => self.expect(ty, &Ty::Char, None),
+            Pattern::Str(_) => self.expect(ty, &Ty::Str, None),
+            Pattern::Tuple(pats) if pats.is_empty() => self.expect(ty, &Ty::Unit, None),
+            Pattern::Tuple(pats) => match self.resolve(ty) {
+                Ty::Tuple(items) => self.bind_seq(pats, &items),
+                Ty::Unknown => {
+                    for p in pats {
+                        self.bind(p, &Ty::Unknown);
+                    }
+                }
+                // `..` がなければ要素数が分かるので、タプル型として推論できる
+                Ty::Var(_) if !pats.iter().any(|p| matches!(p, Pattern::Rest(_))) => {
+                    let items: Vec<Ty> = pats.iter().map(|_| self.fresh()).collect();
+                    self.unify(ty, &Ty::Tuple(items.clone()));
+                    self.bind_seq(pats, &items);
+                }
+                Ty::Var(_) => {
+                    for p in pats {
+                        self.bind(p, &Ty::Unknown);
+                    }
+                }
+                other => {
+                    let other = self.shown(&other);
+                    self.error(
+                        None,
+                        format!("パターン {} は {} に一致しません", pattern, other),
+                    );
+                }
+            },
+            Pattern::Slice(pats) => {
+                let elem = self.fresh();
+                self.expect(ty, &Ty::Vec(Box::new(elem.clone())), None);
+                for p in pats {
+                    match p {
+                        Pattern::Rest(Some(name)) => self.define(name, ty.clone()),
+                        p => self.bind(p, &elem),
+                    }
+                }
+            }
+            Pattern::Struct { name, fields, .. } => {
+                let Some((_, target)) = self.struct_type(name) else {
+                    self.error(None, format!("未定義の構造体: {}", name));
+                    return;
+                };
+                self.expect(ty, &target, None);
+                for (field, p) in fields {
+                    let t = self.field(&target, field, None);
+                    self.bind(p, &t);
+                }
+            }
+            Pattern::Variant(name, pats) => match self.variant_fields(name, ty) {
+                Some(fields) => self.bind_seq(pats, &fields),
+                None => {
+                    let ty = self.shown(ty);
+                    self.error(None, format!("パターン {} は {} に一致しません", name, ty));
+                }
+            },
+        }
+    }
+
+    // タプルパターンの要素ごとの割り当て。`..` は残りの要素に一致する
+    fn bind_seq(&mut self, pats: &[Pattern], items: &[Ty]) {
+        let Some(rest) = pats.iter().position(|p| matches!(p, Pattern::Rest(_))) else {
+            if pats.len() != items.len() {
+                self.error(
+                    None,
+                    format!(
+                        "パターンの要素数が一致しません: {} 個必要ですが {} 個です",
+                        items.len(),
+                        pats.len()
+                    ),
+                );
+            }
+            for (p, t) in pats.iter().zip(items) {
+                self.bind(p, t);
+            }
+            return;
+        };
+        let (before, after) = (&pats[..rest], &pats[rest + 1..]);
+        if before.len() + after.len() > items.len() {
+            self.error(None, "パターンの要素が多すぎます".to_string());
+            return;
+        }
+        let tail = items.len() - after.len();
+        if let Pattern::Rest(Some(name)) = &pats[rest] {
+            self.define(name, Ty::Tuple(items[before.len()..tail].to_vec()));
+        }
+        for (p, t) in before.iter().zip(items) {
+            self.bind(p, t);
+        }
+        for (p, t) in after.iter().zip(&items[tail..]) {
+            self.bind(p, t);
+        }
+    }
+}
+
+// 式の中で最初に見つかる位置（型エラーの報告先）
+fn span_of(expr: &Expr) -> Option<Span> {
+    match expr {
+        Expr::Var(_, span, _) | Expr::Call(_, _, span, _) | Expr::Index { span, .. } => Some(*span),
+        Expr::Binary(_, lhs, _, span) => span_of(lhs).or(Some(*span)),
+        Expr::MethodCall(recv, _, _, span) => span_of(recv).or(Some(*span)),
+        Expr::Unary(_, e, _) | Expr::FieldAccess(e, _) | Expr::Try(e) => span_of(e),
+        Expr::Ref(e, _, span) => span_of(e).or(Some(*span)),
+        Expr::Assign { target, .. } => span_of(target),
+        Expr::Tuple(items) | Expr::Array(items) => items.iter().find_map(span_of),
+        Expr::ArrayRepeat(e, _) | Expr::Vec(e) => span_of(e),
+        Expr::StructInit(_, inits) => inits.first().map(|(_, _, span)| *span),
+        Expr::If { cond, .. } => span_of(cond),
+        Expr::Match { scrutinee, .. } => span_of(scrutinee),
+        Expr::Block(stmts) => stmts.iter().find_map(|s| match s {
+            Stmt::Expr(e) => span_of(e),
+            Stmt::Let { span, .. } => Some(*span),
+            _ => None,
+        }),
+        Expr::Return(Some(e)) => span_of(e),
+        Expr::Panic { span, .. } => Some(*span),
+        _ => None,
+    }
+}
+
+fn op_symbol(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Rem => "%",
+        BinOp::Eq => "==",
+        BinOp::Ne => "!=",
+        BinOp::Lt => "<",
+        BinOp::Le => "<=",
+        BinOp::Gt => ">",
+        BinOp::Ge => ">=",
+        BinOp::And => "&&",
+        BinOp::Or => "||",
+    }
+}
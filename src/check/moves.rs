@@ -0,0 +1,846 @@
+// 所有権の検査（型推論の後に実行する）
+// Copy でない値は let・代入・関数の引数・return などで移動し、移動した変数はもう使えない。
+// 参照 &x / &mut x は関数の引数（と for の対象）にだけ書け、参照で受け取った引数は移動できない。
+// 参照が呼び出しの外に出ないので、ライフタイムを書かなくてよい
+use super::generics::subst;
+use super::ty::Ty;
+use super::{Checker, TypeError};
+use crate::ast::{Expr, Generics, Pattern, Stmt, UnaryOp};
+use crate::eval::grow_stack;
+use crate::lexer::Span;
+use std::collections::HashMap;
+
+// 式の値の使われ方
+#[derive(Clone, Copy, PartialEq)]
+enum Use {
+    // 値を持っていく（let の右辺・引数・return など）
+    Move,
+    // 読むだけ（演算子・メソッドの受け手・println! など）
+    Read,
+}
+
+#[derive(Clone)]
+struct Local {
+    // 移動した位置
+    moved: Option<Span>,
+    // 参照で受け取った引数か
+    borrowed: bool,
+    // 配列リテラル [..] の値か（要素が Copy なら配列も Copy。vec! の値は含まない）
+    array: bool,
+}
+
+// 関数の引数の型注釈（参照で受け取るかを調べる）
+type Params = HashMap<String, Vec<Option<String>>>;
+
+struct Moves<'a> {
+    c: &'a mut Checker,
+    // 関数名・"型名::メソッド名"・トレイトのメソッド名 → 引数の型注釈
+    params: &'a Params,
+    scopes: Vec<HashMap<String, Local>>,
+    // 囲むクロージャの本体が始まる scopes の位置（内側ほど後）
+    closures: Vec<usize>,
+    // return の後など、ここには来ない
+    diverged: bool,
+    errors: Vec<TypeError>,
+}
+
+// 移動する値を引数に持っていく組み込みのメソッド
+const STORING_METHODS: [&str; 7] = [
+    "push",
+    "insert",
+    "push_back",
+    "push_front",
+    "extend",
+    "append",
+    "or_insert",
+];
+
+// self を書き換える組み込みのメソッド
+const MUTATING_METHODS: [&str; 15] = [
+    "push",
+    "pop",
+    "insert",
+    "remove",
+    "sort",
+    "reverse",
+    "clear",
+    "take",
+    "entry",
+    "push_back",
+    "push_front",
+    "pop_back",
+    "pop_front",
+    "extend",
+    "append",
+];
+
+// 書き換える場所の元の変数（v[i].x なら v）
+fn place_root(expr: &Expr) -> Option<(&str, Span)> {
+    match expr {
+        Expr::Var(name, span, _) => Some((name, *span)),
+        Expr::FieldAccess(e, _)
+        | Expr::Index { target: e, .. }
+        | Expr::Unary(UnaryOp::Deref, e, _) => place_root(e),
+        // *map.entry(k).or_insert(0) += 1
+        Expr::MethodCall(e, name, ..) if name == "entry" || name == "or_insert" => place_root(e),
+        _ => None,
+    }
+}
+
+// self を移動するメソッドか（組み込みの型）
+fn consumes(recv: &Ty, name: &str) -> bool {
+    match recv {
+        Ty::Iter(_) => name != "next",
+        Ty::Option(_) | Ty::Result(..) => matches!(
+            name,
+            "unwrap"
+                | "expect"
+                | "unwrap_or"
+                | "unwrap_or_else"
+                | "unwrap_or_default"
+                | "unwrap_err"
+                | "ok"
+                | "err"
+                | "ok_or"
+                | "map"
+                | "map_err"
+                | "and_then"
+                | "or_else"
+        ),
+        _ => name == "into_iter",
+    }
+}
+
+fn is_ref(ty: &Option<String>) -> bool {
+    ty.as_deref().is_some_and(|t| t.starts_with('&'))
+}
+
+// 参照を書けるのは引数の型注釈の先頭だけ（&str は String と同じ型なので除く）
+fn check_annotation(ty: &str, param: bool) -> bool {
+    let ty = ty.replace("&'static str", "str").replace("&str", "str");
+    let inner = if param {
+        ty.trim_start_matches("&mut ").trim_start_matches('&')
+    } else {
+        &ty
+    };
+    !inner.contains('&')
+}
+
+fn is_array_type(ty: &Option<String>) -> bool {
+    ty.as_deref()
+        .is_some_and(|t| t.starts_with('[') && t.contains(';'))
+}
+
+impl Checker {
+    pub(super) fn check_moves(&mut self, stmts: &[Stmt]) {
+        let mut params = Params::new();
+        collect_params(stmts, &mut params);
+        let mut m = Moves {
+            c: self,
+            params: &params,
+            scopes: vec![HashMap::new()],
+            closures: Vec::new(),
+            diverged: false,
+            errors: Vec::new(),
+        };
+        for stmt in stmts {
+            m.stmt(stmt, false);
+        }
+        // ループの本体は 2 回たどるので、同じエラーは一つにまとめる
+        let mut errors: Vec<TypeError> = Vec::new();
+        for e in m.errors {
+            if !errors.contains(&e) {
+                errors.push(e);
+            }
+        }
+        self.errors.extend(errors);
+    }
+}
+
+fn collect_params(stmts: &[Stmt], out: &mut Params) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::FuncDef {
+                name, param_tys, ..
+            } => {
+                out.insert(name.clone(), param_tys.clone());
+            }
+            Stmt::ImplDef {
+                target, methods, ..
+            } => {
+                let key = Ty::parse(target).key().unwrap_or_default();
+                for m in methods {
+                    if let Stmt::FuncDef {
+                        name, param_tys, ..
+                    } = m
+                    {
+                        out.insert(format!("{}::{}", key, name), param_tys.clone());
+                    }
+                }
+            }
+            Stmt::TraitDef { methods, .. } => {
+                for m in methods {
+                    if let Stmt::FuncDef {
+                        name, param_tys, ..
+                    } = m
+                    {
+                        out.entry(name.clone()).or_insert_with(|| param_tys.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Moves<'_> {
+    fn error(&mut self, span: Span, message: String, note: Option<(Span, String)>) {
+        self.errors.push(TypeError {
+            span,
+            message,
+            note,
+        });
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Local> {
+        self.scopes.iter_mut().rev().find_map(|s| s.get_mut(name))
+    }
+
+    // クロージャは作られた時点の変数をコピーして持つので、取り込んだ変数を書き換えても
+    // 外側の変数は変わらない。黙って捨てられないよう、書き換えをエラーにする
+    fn mutate(&mut self, place: &Expr) {
+        let Some((name, span)) = place_root(place) else {
+            return;
+        };
+        let Some(&start) = self.closures.last() else {
+            return;
+        };
+        if let Some(i) = self.scopes.iter().rposition(|s| s.contains_key(name))
+            && i < start
+        {
+            self.error(
+                span,
+                format!(
+                    "クロージャの外の変数 {} は書き換えられません（クロージャは作られた時点の値をコピーして持ちます）",
+                    name
+                ),
+                None,
+            );
+        }
+    }
+
+    fn bind(&mut self, pattern: &Pattern, borrowed: bool, array: bool) {
+        for name in pattern_names(pattern) {
+            let local = Local {
+                moved: None,
+                borrowed,
+                array: array && matches!(pattern, Pattern::Bind(..)),
+            };
+            self.scopes.last_mut().unwrap().insert(name, local);
+        }
+    }
+
+    // 配列リテラルか、配列リテラルで作った変数か
+    fn is_array(&mut self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Array(_) | Expr::ArrayRepeat(..) => true,
+            Expr::Var(name, ..) => self.lookup(name).is_some_and(|l| l.array),
+            _ => false,
+        }
+    }
+
+    // 参照で受け取った変数か（for・match の対象なら中身も借用になる）
+    fn is_borrowed(&mut self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Var(name, ..) => self.lookup(name).is_some_and(|l| l.borrowed),
+            _ => false,
+        }
+    }
+
+    // パターンが Copy でない値を束縛するか（そうでなければ照合する値は移動しない）
+    fn binds_owned(&self, pattern: &Pattern, ty: &Ty) -> bool {
+        let c = &self.c;
+        let ty = c.zonk(ty);
+        let any = |ps: &[Pattern], tys: &[Ty]| {
+            ps.iter()
+                .zip(tys.iter().chain(std::iter::repeat(&Ty::Unknown)))
+                .any(|(p, t)| self.binds_owned(p, t))
+        };
+        match (pattern, &ty) {
+            (
+                Pattern::Wildcard
+                | Pattern::Number(_)
+                | Pattern::Bool(_)
+                | Pattern::Char(_)
+                | Pattern::Str(_)
+                | Pattern::Rest(None),
+                _,
+            ) => false,
+            (Pattern::Bind(..) | Pattern::Rest(Some(_)), _) => !c.implements(&ty, "Copy"),
+            (Pattern::Tuple(ps), Ty::Tuple(tys)) => any(ps, tys),
+            (Pattern::Slice(ps), Ty::Vec(t)) => ps.iter().any(|p| match p {
+                Pattern::Rest(_) => self.binds_owned(p, &ty),
+                _ => self.binds_owned(p, t),
+            }),
+            (Pattern::Struct { name, fields, .. }, Ty::Named(_, args)) => {
+                let Some(def) = c.typedefs.get(name) else {
+                    return true;
+                };
+                fields.iter().any(|(f, p)| {
+                    let t = def
+                        .fields
+                        .iter()
+                        .find(|(n, _)| n == f)
+                        .map_or(Ty::Unknown, |(_, t)| subst(t, &def.generics, args));
+                    self.binds_owned(p, &t)
+                })
+            }
+            (Pattern::Variant(_, ps), Ty::Option(t)) => any(ps, &[(**t).clone()]),
+            (Pattern::Variant(name, ps), Ty::Result(t, e)) => {
+                let t = if name == "Err" { e } else { t };
+                any(ps, &[(**t).clone()])
+            }
+            (Pattern::Variant(name, ps), Ty::Named(enum_name, args)) => {
+                let Some(def) = c.typedefs.get(enum_name) else {
+                    return true;
+                };
+                let Some((_, tys)) = def.variants.iter().find(|(v, _)| v == name) else {
+                    return true;
+                };
+                let tys: Vec<Ty> = tys.iter().map(|t| subst(t, &def.generics, args)).collect();
+                any(ps, &tys)
+            }
+            (_, Ty::Var(_) | Ty::Unknown) => false,
+            _ => true,
+        }
+    }
+
+    // 照合する式の型（変数のときだけ分かる）
+    fn type_of(&self, expr: &Expr) -> Ty {
+        match expr {
+            Expr::Var(_, span, _) => self.type_at(*span),
+            _ => Ty::Unknown,
+        }
+    }
+
+    // その位置の値が Copy か（型の分からないものは Copy とみなす）
+    fn is_copy(&self, span: Span) -> bool {
+        match self.c.types.get(&span) {
+            Some(ty) => self.c.implements(&self.c.zonk(ty), "Copy"),
+            None => true,
+        }
+    }
+
+    fn type_at(&self, span: Span) -> Ty {
+        self.c
+            .types
+            .get(&span)
+            .map(|t| self.c.zonk(t))
+            .unwrap_or(Ty::Unknown)
+    }
+
+    // 関数・メソッド本体。引数の型注釈が & で始まるものは借用として束縛する
+    fn func(&mut self, stmt: &Stmt, outer: &Generics) {
+        let Stmt::FuncDef {
+            generics,
+            params,
+            param_tys,
+            ret,
+            body,
+            span,
+            ..
+        } = stmt
+        else {
+            return;
+        };
+        for ty in param_tys.iter().flatten() {
+            if !check_annotation(ty, true) {
+                self.annotation_error(*span, ty);
+            }
+        }
+        if let Some(ty) = ret
+            && !check_annotation(ty, false)
+        {
+            self.error(
+                *span,
+                format!("戻り値の型 {} に参照は使えません（値を返してください）", ty),
+                None,
+            );
+        }
+        // 型引数の境界（T: Copy の値は移動しない）
+        let bounds = outer
+            .iter()
+            .chain(generics)
+            .map(|(name, bounds)| {
+                let bounds = bounds.iter().map(|b| Ty::parse(b)).collect();
+                (name.clone(), bounds)
+            })
+            .collect();
+        let saved = std::mem::replace(&mut self.c.bounds, bounds);
+        let scopes = std::mem::replace(&mut self.scopes, vec![HashMap::new()]);
+        let closures = std::mem::take(&mut self.closures);
+        let diverged = self.diverged;
+        for (p, ty) in params.iter().zip(param_tys) {
+            self.bind(p, is_ref(ty), is_array_type(ty));
+        }
+        self.expr(body, Use::Move);
+        self.scopes = scopes;
+        self.closures = closures;
+        self.diverged = diverged;
+        self.c.bounds = saved;
+    }
+
+    fn annotation_error(&mut self, span: Span, ty: &str) {
+        self.error(
+            span,
+            format!("型 {} の参照は関数の引数の型の先頭にしか書けません", ty),
+            None,
+        );
+    }
+
+    // last はブロックの最後の式（ブロックの値）か
+    fn stmt(&mut self, stmt: &Stmt, last: bool) {
+        match stmt {
+            Stmt::FuncDef { .. } => self.func(stmt, &Vec::new()),
+            Stmt::ImplDef {
+                generics, methods, ..
+            } => {
+                for m in methods {
+                    self.func(m, generics);
+                }
+            }
+            Stmt::TraitDef {
+                generics,
+                methods,
+                required,
+                ..
+            } => {
+                for m in methods {
+                    if let Stmt::FuncDef { name, .. } = m
+                        && !required.contains(name)
+                    {
+                        self.func(m, generics);
+                    }
+                }
+            }
+            Stmt::StructDef { fields, span, .. } => {
+                for (_, ty) in fields {
+                    if !check_annotation(ty, false) {
+                        self.annotation_error(*span, ty);
+                    }
+                }
+            }
+            Stmt::EnumDef { variants, span, .. } => {
+                for ty in variants.iter().flat_map(|(_, tys)| tys) {
+                    if !check_annotation(ty, false) {
+                        self.annotation_error(*span, ty);
+                    }
+                }
+            }
+            Stmt::Let {
+                pattern,
+                value,
+                ty,
+                span,
+                ..
+            } => {
+                if let Some(ty) = ty
+                    && !check_annotation(ty, false)
+                {
+                    self.annotation_error(*span, ty);
+                }
+                // let (a, b) = t のように Copy な部分だけを取り出すなら t は移動しない
+                let how = match pattern {
+                    Pattern::Bind(..) => Use::Move,
+                    _ if self.binds_owned(pattern, &self.type_of(value)) => Use::Move,
+                    _ => Use::Read,
+                };
+                let array = self.is_array(value);
+                self.expr(value, how);
+                self.bind(pattern, false, array);
+            }
+            Stmt::Expr(e) => self.expr(e, if last { Use::Move } else { Use::Read }),
+            Stmt::Print(e) => self.expr(e, Use::Read),
+            Stmt::Import(_) | Stmt::Error(..) => {}
+        }
+    }
+
+    // 変数を使う。Move なら Copy でない値を移動する
+    fn var(&mut self, name: &str, span: Span, how: Use) {
+        let Some(local) = self.lookup(name) else {
+            return;
+        };
+        let local = local.clone();
+        let copy = how == Use::Read
+            || self.is_copy(span)
+            || local.array
+                && matches!(self.type_at(span), Ty::Vec(t) if self.c.implements(&t, "Copy"));
+        if let Some(moved) = local.moved {
+            // ループの本体で移動した値を次の繰り返しで使うと、同じ位置になる
+            let note = if moved == span {
+                format!("{} は前の繰り返しで移動しました", name)
+            } else {
+                format!("{} はここで移動しました", name)
+            };
+            self.error(
+                span,
+                format!("移動した値 {} を使っています", name),
+                Some((moved, note)),
+            );
+            return;
+        }
+        if copy {
+            return;
+        }
+        if local.borrowed {
+            self.error(
+                span,
+                format!(
+                    "参照で受け取った {} は移動できません（.clone() で複製してください）",
+                    name
+                ),
+                None,
+            );
+            return;
+        }
+        if !self.diverged
+            && let Some(local) = self.lookup(name)
+        {
+            local.moved = Some(span);
+        }
+    }
+
+    // 関数・メソッドの引数。参照の引数は借用なので移動しない
+    fn args(&mut self, args: &[Expr], params: Option<&[Option<String>]>, default: Use) {
+        for (i, a) in args.iter().enumerate() {
+            let param = params.and_then(|p| p.get(i));
+            match a {
+                Expr::Ref(target, mutable, span) => {
+                    if *mutable {
+                        self.mutate(target);
+                    }
+                    if let Some(param) = param {
+                        let expected = param.as_deref().unwrap_or("_");
+                        if !is_ref(param) {
+                            self.error(
+                                *span,
+                                format!("引数は {} で受け取るので & は書けません", expected),
+                                None,
+                            );
+                        } else if !mutable && expected.starts_with("&mut ") {
+                            self.error(
+                                *span,
+                                format!("引数は {} で受け取るので &mut と書いてください", expected),
+                                None,
+                            );
+                        }
+                    }
+                    self.expr(target, Use::Read);
+                }
+                _ if param.is_some_and(is_ref) => self.expr(a, Use::Read),
+                _ => self.expr(a, default),
+            }
+        }
+    }
+
+    // 分岐を合流する。どちらかで移動していれば移動したものとする（return した側は数えない）
+    fn branches(&mut self, count: usize, branch: &mut dyn FnMut(&mut Self, usize)) {
+        let before = self.scopes.clone();
+        let diverged = self.diverged;
+        let mut merged: Option<Vec<HashMap<String, Local>>> = None;
+        let mut all_diverged = true;
+        for i in 0..count {
+            self.scopes = before.clone();
+            self.diverged = diverged;
+            branch(self, i);
+            if self.diverged && !diverged {
+                continue;
+            }
+            all_diverged = false;
+            merged = Some(match merged {
+                None => self.scopes.clone(),
+                Some(mut m) => {
+                    for (ms, s) in m.iter_mut().zip(&self.scopes) {
+                        for (name, local) in ms.iter_mut() {
+                            if local.moved.is_none()
+                                && let Some(l) = s.get(name)
+                            {
+                                local.moved = l.moved;
+                            }
+                        }
+                    }
+                    m
+                }
+            });
+        }
+        self.scopes = merged.unwrap_or(before);
+        self.diverged = diverged || (all_diverged && count > 0);
+    }
+
+    fn expr(&mut self, expr: &Expr, how: Use) {
+        grow_stack(|| self.expr_inner(expr, how))
+    }
+
+    fn expr_inner(&mut self, expr: &Expr, how: Use) {
+        match expr {
+            Expr::Number(_)
+            | Expr::TypedNumber(..)
+            | Expr::Float(..)
+            | Expr::Bool(_)
+            | Expr::Char(_)
+            | Expr::Str(_) => {}
+            Expr::Var(name, span, _) => self.var(name, *span, how),
+            Expr::Ref(target, _, span) => {
+                self.error(
+                    *span,
+                    "参照 & は関数の引数にしか書けません（参照を変数・戻り値・フィールドに入れることはできません）"
+                        .to_string(),
+                    None,
+                );
+                self.expr(target, Use::Read);
+            }
+            Expr::Binary(_, a, b, _) => {
+                self.expr(a, Use::Read);
+                self.expr(b, Use::Read);
+            }
+            // *x を移動するのは x を移動するのと同じ（参照で受け取った x なら報告する）
+            Expr::Unary(_, e, _) => self.expr(e, how),
+            Expr::Call(name, args, ..) => {
+                let local = self.scopes.iter().any(|s| s.contains_key(name));
+                if local {
+                    self.args(args, None, Use::Move);
+                } else if let Some(params) = self.params.get(name) {
+                    // 関数・関連関数（Type::new）
+                    self.args(args, Some(params), Use::Move);
+                } else if name.contains("::") || name.starts_with(|c: char| c.is_uppercase()) {
+                    // バリアントの中身は移動する
+                    self.args(args, None, Use::Move);
+                } else {
+                    // 組み込みの関数は引数を読むだけ
+                    self.args(args, None, Use::Read);
+                }
+            }
+            Expr::MethodCall(recv, name, args, _) => {
+                let recv_ty = match &**recv {
+                    Expr::Var(_, span, _) => self.type_at(*span),
+                    _ => Ty::Unknown,
+                };
+                let key = recv_ty.key().map(|k| format!("{}::{}", k, name));
+                let params = key
+                    .and_then(|k| self.params.get(&k))
+                    .or_else(|| self.params.get(name))
+                    .cloned();
+                match params {
+                    // impl のメソッドは self の型注釈で決まる（Self なら移動する）
+                    Some(params) if !params.is_empty() => {
+                        if params[0].as_deref() == Some("&mut Self") {
+                            self.mutate(recv);
+                        }
+                        let by_value = params[0].as_deref() == Some("Self");
+                        self.expr(recv, if by_value { Use::Move } else { Use::Read });
+                        self.args(args, Some(&params[1..]), Use::Move);
+                    }
+                    _ => {
+                        if MUTATING_METHODS.contains(&name.as_str()) {
+                            self.mutate(recv);
+                        }
+                        let take = consumes(&recv_ty, name);
+                        self.expr(recv, if take { Use::Move } else { Use::Read });
+                        let stores = STORING_METHODS.contains(&name.as_str());
+                        self.args(args, None, if stores { Use::Move } else { Use::Read });
+                    }
+                }
+            }
+            Expr::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                for (i, s) in stmts.iter().enumerate() {
+                    let last = i + 1 == stmts.len() && how == Use::Move;
+                    self.stmt(s, last);
+                }
+                self.scopes.pop();
+            }
+            Expr::Tuple(items) | Expr::Array(items) => {
+                for item in items {
+                    self.expr(item, how);
+                }
+            }
+            Expr::Vec(e) => self.expr(e, how),
+            Expr::ArrayRepeat(value, count) => {
+                self.expr(value, Use::Read);
+                self.expr(count, Use::Read);
+            }
+            // 添字・フィールドは中身だけを読む
+            Expr::Index { target, index, .. } => {
+                self.expr(target, Use::Read);
+                self.expr(index, Use::Read);
+            }
+            Expr::FieldAccess(target, _) => self.expr(target, Use::Read),
+            Expr::Assign { target, op, value } => {
+                self.expr(value, Use::Move);
+                self.mutate(target);
+                match (&**target, op) {
+                    // 代入し直した変数はまた使える
+                    (Expr::Var(name, ..), None) => {
+                        let diverged = self.diverged;
+                        if let Some(local) = self.lookup(name)
+                            && !diverged
+                        {
+                            local.moved = None;
+                        }
+                    }
+                    _ => self.expr(target, Use::Read),
+                }
+            }
+            Expr::StructInit(_, inits) => {
+                for (_, e, _) in inits {
+                    self.expr(e, Use::Move);
+                }
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond, Use::Read);
+                self.branches(2, &mut |m, i| match (i, else_branch) {
+                    (0, _) => m.expr(then_branch, how),
+                    (_, Some(e)) => m.expr(e, how),
+                    (_, None) => {}
+                });
+            }
+            Expr::Match { scrutinee, arms } => {
+                // Copy でない値を束縛するパターンがあれば、照合する値を移動する。
+                // 参照で受け取った値なら、束縛した中身も借用になる
+                let ty = self.type_of(scrutinee);
+                let borrowed = self.is_borrowed(scrutinee);
+                let binds = arms.iter().any(|(p, _)| self.binds_owned(p, &ty));
+                let take = if binds && !borrowed {
+                    Use::Move
+                } else {
+                    Use::Read
+                };
+                self.expr(scrutinee, take);
+                self.branches(arms.len(), &mut |m, i| {
+                    let (p, body) = &arms[i];
+                    m.scopes.push(HashMap::new());
+                    m.bind(p, borrowed, false);
+                    m.expr(body, how);
+                    m.scopes.pop();
+                });
+            }
+            Expr::For {
+                pattern,
+                iter,
+                body,
+            } => {
+                // for x in &v はループの間だけの借用
+                let borrowed = match &**iter {
+                    Expr::Ref(target, ..) => {
+                        self.expr(target, Use::Read);
+                        true
+                    }
+                    _ if self.is_borrowed(iter) => {
+                        self.expr(iter, Use::Read);
+                        true
+                    }
+                    _ => {
+                        self.expr(iter, Use::Move);
+                        false
+                    }
+                };
+                // 一度も回らない場合と合流する。本体は 2 回たどり、
+                // 前の繰り返しで移動した値を使っていないかも調べる
+                self.branches(2, &mut |m, i| {
+                    if i == 0 {
+                        return;
+                    }
+                    for _ in 0..2 {
+                        m.scopes.push(HashMap::new());
+                        m.bind(pattern, borrowed, false);
+                        m.expr(body, Use::Read);
+                        m.scopes.pop();
+                    }
+                    // ループの中の return は、ループを抜けた後には影響しない
+                    m.diverged = false;
+                });
+            }
+            Expr::Range { start, end, .. } => {
+                for e in [start, end].into_iter().flatten() {
+                    self.expr(e, Use::Read);
+                }
+            }
+            Expr::Cast(e, ..) => self.expr(e, Use::Read),
+            Expr::Return(e) => {
+                if let Some(e) = e {
+                    self.expr(e, Use::Move);
+                }
+                self.diverged = true;
+            }
+            Expr::Try(e) => self.expr(e, how),
+            Expr::Closure { params, body } => {
+                self.closures.push(self.scopes.len());
+                self.scopes.push(HashMap::new());
+                for p in params {
+                    self.bind(p, false, false);
+                }
+                let diverged = self.diverged;
+                self.expr(body, Use::Move);
+                self.diverged = diverged;
+                self.scopes.pop();
+                self.closures.pop();
+            }
+            // println! などは引数を読むだけ
+            Expr::Format { args, .. } => {
+                for a in args {
+                    match a {
+                        Expr::Ref(target, ..) => self.expr(target, Use::Read),
+                        _ => self.expr(a, Use::Read),
+                    }
+                }
+            }
+            // assert_eq! なども引数を読むだけ。panic! などはそこで評価を打ち切る
+            Expr::Panic {
+                kind,
+                args,
+                message,
+                ..
+            } => {
+                for a in args {
+                    match a {
+                        Expr::Ref(target, ..) => self.expr(target, Use::Read),
+                        _ => self.expr(a, Use::Read),
+                    }
+                }
+                if let Some(m) = message {
+                    self.expr(m, Use::Read);
+                }
+                if kind.arity() == 0 {
+                    self.diverged = true;
+                }
+            }
+        }
+    }
+}
+
+// パターンが束縛する変数名
+fn pattern_names(pattern: &Pattern) -> Vec<String> {
+    let mut names = Vec::new();
+    collect_names(pattern, &mut names);
+    names
+}
+
+fn collect_names(pattern: &Pattern, out: &mut Vec<String>) {
+    match pattern {
+        Pattern::Bind(name, _) | Pattern::Rest(Some(name)) => out.push(name.clone()),
+        Pattern::Tuple(items) | Pattern::Slice(items) | Pattern::Variant(_, items) => {
+            items.iter().for_each(|p| collect_names(p, out))
+        }
+        Pattern::Struct { fields, .. } => fields.iter().for_each(|(_, p)| collect_names(p, out)),
+        Pattern::Wildcard
+        | Pattern::Number(_)
+        | Pattern::Bool(_)
+        | Pattern::Char(_)
+        | Pattern::Str(_)
+        | Pattern::Rest(None) => {}
+    }
+}
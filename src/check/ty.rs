@@ -0,0 +1,284 @@
+// 型検査で使う型
+use crate::eval::grow_stack;
+use crate::float::FloatTy;
+use crate::int::IntTy;
+use crate::lexer::{Token, Tokens, tokenize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ty {
+    Unit,
+    Int(IntTy),
+    Float(FloatTy),
+    Bool,
+    Char,
+    // String と &str は区別しない
+    Str,
+    Tuple(Vec<Ty>),
+    // Vec<T> / [T; N] / [T]（実行時は同じ配列）
+    Vec(Box<Ty>),
+    Map(Box<Ty>, Box<Ty>),
+    Set(Box<Ty>),
+    Option(Box<Ty>),
+    Result(Box<Ty>, Box<Ty>),
+    // 範囲（要素は整数）
+    Range(Box<Ty>),
+    Iter(Box<Ty>),
+    Fn(Vec<Ty>, Box<Ty>),
+    // 構造体・列挙型: Pair<i64, String>
+    Named(String, Vec<Ty>),
+    // 総称関数・impl の中での型引数 T（中身の分からない型。境界のトレイトだけが使える）
+    Param(String),
+    // 推論中の型変数
+    Var(u32),
+    // 推論できない型（import した関数の戻り値など）。どの型とも一致するものとして扱う
+    Unknown,
+}
+
+impl Ty {
+    // 型注釈の文字列（parser::ty::parse_type の結果）から型を作る
+    pub fn parse(annotation: &str) -> Ty {
+        let tokens = tokenize(annotation);
+        let mut pos = 0;
+        parse_ty(&tokens, &mut pos)
+    }
+
+    // 型の中の未解決の型変数
+    pub fn free_vars(&self, out: &mut Vec<u32>) {
+        match self {
+            Ty::Var(v) => {
+                if !out.contains(v) {
+                    out.push(*v);
+                }
+            }
+            _ => self.children().into_iter().for_each(|t| t.free_vars(out)),
+        }
+    }
+
+    // 型変数を置き換えた型を作る
+    pub fn map_vars(&self, f: &mut dyn FnMut(u32) -> Ty) -> Ty {
+        self.map(&mut |t| match t {
+            Ty::Var(v) => Some(f(*v)),
+            _ => None,
+        })
+    }
+
+    // f が Some を返した部分を置き換えた型を作る（None なら中の型をたどる）
+    pub fn map(&self, f: &mut dyn FnMut(&Ty) -> Option<Ty>) -> Ty {
+        grow_stack(|| self.map_inner(f))
+    }
+
+    fn map_inner(&self, f: &mut dyn FnMut(&Ty) -> Option<Ty>) -> Ty {
+        if let Some(t) = f(self) {
+            return t;
+        }
+        let b = |t: &Ty, f: &mut dyn FnMut(&Ty) -> Option<Ty>| Box::new(t.map(f));
+        match self {
+            Ty::Tuple(items) => Ty::Tuple(items.iter().map(|t| t.map(f)).collect()),
+            Ty::Vec(t) => Ty::Vec(b(t, f)),
+            Ty::Set(t) => Ty::Set(b(t, f)),
+            Ty::Option(t) => Ty::Option(b(t, f)),
+            Ty::Range(t) => Ty::Range(b(t, f)),
+            Ty::Iter(t) => Ty::Iter(b(t, f)),
+            Ty::Map(k, v) => Ty::Map(b(k, f), b(v, f)),
+            Ty::Result(t, e) => Ty::Result(b(t, f), b(e, f)),
+            Ty::Fn(params, ret) => Ty::Fn(params.iter().map(|t| t.map(f)).collect(), b(ret, f)),
+            Ty::Named(name, args) => {
+                Ty::Named(name.clone(), args.iter().map(|t| t.map(f)).collect())
+            }
+            _ => self.clone(),
+        }
+    }
+
+    // impl のメソッドを探すときの型の名前（実行時の Value::type_name と同じ）
+    pub fn key(&self) -> Option<String> {
+        let key = match self {
+            Ty::Unit => "()",
+            Ty::Int(ty) => ty.name(),
+            Ty::Float(ty) => ty.name(),
+            Ty::Bool => "bool",
+            Ty::Char => "char",
+            Ty::Str => "String",
+            Ty::Tuple(_) => "tuple",
+            Ty::Vec(_) => "Vec",
+            Ty::Map(..) => "HashMap",
+            Ty::Set(_) => "HashSet",
+            Ty::Option(_) => "Option",
+            Ty::Result(..) => "Result",
+            Ty::Range(_) => "Range",
+            Ty::Named(name, _) => name,
+            _ => return None,
+        };
+        Some(key.to_string())
+    }
+
+    fn children(&self) -> Vec<&Ty> {
+        match self {
+            Ty::Tuple(items) => items.iter().collect(),
+            Ty::Vec(t) | Ty::Set(t) | Ty::Option(t) | Ty::Range(t) | Ty::Iter(t) => vec![t],
+            Ty::Map(a, b) | Ty::Result(a, b) => vec![a, b],
+            Ty::Fn(params, ret) => params.iter().chain([&**ret]).collect(),
+            Ty::Named(_, args) => args.iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // for で取り出される要素の型
+    pub fn elem(&self) -> Option<Ty> {
+        match self {
+            Ty::Vec(t) | Ty::Set(t) | Ty::Range(t) | Ty::Iter(t) => Some((**t).clone()),
+            Ty::Map(k, v) => Some(Ty::Tuple(vec![(**k).clone(), (**v).clone()])),
+            Ty::Str => Some(Ty::Char),
+            _ => None,
+        }
+    }
+}
+
+fn parse_ty(tokens: &Tokens, pos: &mut usize) -> Ty {
+    match tokens.get(*pos) {
+        Some(Token::Amp) => {
+            // 参照は値そのものとして扱う
+            *pos += 1;
+            if tokens.get(*pos) == Some(&Token::Mut) {
+                *pos += 1;
+            }
+            parse_ty(tokens, pos)
+        }
+        Some(Token::LBracket) => {
+            *pos += 1;
+            let elem = parse_ty(tokens, pos);
+            while !matches!(
+                tokens.get(*pos),
+                Some(Token::RBracket) | Some(Token::EOF) | None
+            ) {
+                *pos += 1;
+            }
+            *pos += 1;
+            Ty::Vec(Box::new(elem))
+        }
+        Some(Token::LParen) => {
+            *pos += 1;
+            let mut items = Vec::new();
+            while !matches!(
+                tokens.get(*pos),
+                Some(Token::RParen) | Some(Token::EOF) | None
+            ) {
+                items.push(parse_ty(tokens, pos));
+                if tokens.get(*pos) == Some(&Token::Comma) {
+                    *pos += 1;
+                }
+            }
+            *pos += 1;
+            if items.is_empty() {
+                Ty::Unit
+            } else {
+                Ty::Tuple(items)
+            }
+        }
+        Some(Token::Ident(name)) => {
+            let mut name = name.clone();
+            *pos += 1;
+            // std::collections::HashMap などは最後の名前だけを見る。
+            // 関連型 Self::Item・T::Item（大文字で始まる名前の後）はそのまま残す
+            while tokens.get(*pos) == Some(&Token::ColonColon)
+                && let Some(Token::Ident(seg)) = tokens.get(*pos + 1)
+            {
+                if name.starts_with(char::is_uppercase) {
+                    name = format!("{}::{}", name, seg);
+                } else {
+                    name = seg.clone();
+                }
+                *pos += 2;
+            }
+            let mut args = Vec::new();
+            if tokens.get(*pos) == Some(&Token::Lt) {
+                *pos += 1;
+                while !matches!(tokens.get(*pos), Some(Token::Gt) | Some(Token::EOF) | None) {
+                    args.push(parse_ty(tokens, pos));
+                    if tokens.get(*pos) == Some(&Token::Comma) {
+                        *pos += 1;
+                    }
+                }
+                *pos += 1;
+            }
+            let arg = |i: usize| Box::new(args.get(i).cloned().unwrap_or(Ty::Unknown));
+            if let Some(ty) = IntTy::from_name(&name) {
+                return Ty::Int(ty);
+            }
+            if let Some(ty) = FloatTy::from_name(&name) {
+                return Ty::Float(ty);
+            }
+            match name.as_str() {
+                "bool" => Ty::Bool,
+                "char" => Ty::Char,
+                "String" | "str" => Ty::Str,
+                "Vec" => Ty::Vec(arg(0)),
+                "HashMap" => Ty::Map(arg(0), arg(1)),
+                "HashSet" => Ty::Set(arg(0)),
+                "Option" => Ty::Option(arg(0)),
+                "Result" => Ty::Result(arg(0), arg(1)),
+                "_" => Ty::Unknown,
+                _ => Ty::Named(name, args),
+            }
+        }
+        _ => Ty::Unknown,
+    }
+}
+
+// Rustと同じ書き方で表示する（型変数は T0, T1, ...、Unknown は _）
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Unit => write!(f, "()"),
+            Ty::Int(ty) => write!(f, "{}", ty.name()),
+            Ty::Float(ty) => write!(f, "{}", ty.name()),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Char => write!(f, "char"),
+            Ty::Str => write!(f, "String"),
+            Ty::Tuple(items) => {
+                write!(f, "(")?;
+                for (i, t) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Ty::Vec(t) => write!(f, "Vec<{}>", t),
+            Ty::Map(k, v) => write!(f, "HashMap<{}, {}>", k, v),
+            Ty::Set(t) => write!(f, "HashSet<{}>", t),
+            Ty::Option(t) => write!(f, "Option<{}>", t),
+            Ty::Result(t, e) => write!(f, "Result<{}, {}>", t, e),
+            Ty::Range(t) => write!(f, "Range<{}>", t),
+            Ty::Iter(t) => write!(f, "impl Iterator<Item = {}>", t),
+            Ty::Fn(params, ret) => {
+                write!(f, "fn(")?;
+                for (i, t) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                write!(f, ") -> {}", ret)
+            }
+            Ty::Named(name, args) => {
+                write!(f, "{}", name)?;
+                for (i, t) in args.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "<" } else { ", " }, t)?;
+                }
+                if args.is_empty() {
+                    Ok(())
+                } else {
+                    write!(f, ">")
+                }
+            }
+            Ty::Param(name) => write!(f, "{}", name),
+            Ty::Var(v) => write!(f, "T{}", v),
+            Ty::Unknown => write!(f, "_"),
+        }
+    }
+}
@@ -0,0 +1,801 @@
+// serde との橋渡し。nasl の値を serde で Rust の型に読み込み、
+// serde で書き出せる Rust の値を nasl の値にする（feature = "serde"）
+use super::{IntoNasl, expr_span};
+use crate::ast::Expr;
+use crate::lexer::Span;
+use crate::value::{MapKey, Value};
+use serde::de::value::StrDeserializer;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+// 変換に失敗した場所までのたどり方の1段
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathItem {
+    // 構造体のフィールド
+    Field(String),
+    // 配列・タプル・列挙型の中身の位置
+    Index(usize),
+    // HashMap のキー（{:?} で表示したもの）
+    Key(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerdeError {
+    pub message: String,
+    // 外側から順に並べる
+    pub path: Vec<PathItem>,
+}
+
+impl SerdeError {
+    fn new(message: impl Into<String>) -> Self {
+        SerdeError {
+            message: message.into(),
+            path: Vec::new(),
+        }
+    }
+
+    // 内側で起きたエラーに、そこまでのたどり方を前から足す
+    fn inside(mut self, item: PathItem) -> Self {
+        self.path.insert(0, item);
+        self
+    }
+}
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, item) in self.path.iter().enumerate() {
+            match item {
+                PathItem::Field(name) if i == 0 => write!(f, "{}", name)?,
+                PathItem::Field(name) => write!(f, ".{}", name)?,
+                PathItem::Index(index) => write!(f, "[{}]", index)?,
+                PathItem::Key(key) => write!(f, "[{}]", key)?,
+            }
+        }
+        if !self.path.is_empty() {
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError::new(msg.to_string())
+    }
+
+    fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        SerdeError::new(format!("{} が必要ですが {} が渡されました", exp, unexp))
+    }
+
+    fn invalid_value(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        SerdeError::new(format!("{} は使えません（{} が必要です）", unexp, exp))
+    }
+
+    fn invalid_length(len: usize, exp: &dyn de::Expected) -> Self {
+        SerdeError::new(format!(
+            "要素の数 {} が一致しません（{} が必要です）",
+            len, exp
+        ))
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        SerdeError::new(format!(
+            "不明なバリアント {} です（{} のいずれかが必要です）",
+            variant,
+            expected.join(", ")
+        ))
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        SerdeError::new(format!(
+            "不明なフィールド {} です（{} のいずれかが必要です）",
+            field,
+            expected.join(", ")
+        ))
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        SerdeError::new(format!("フィールド {} がありません", field))
+    }
+
+    fn duplicate_field(field: &'static str) -> Self {
+        SerdeError::new(format!("フィールド {} が重複しています", field))
+    }
+}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError::new(msg.to_string())
+    }
+}
+
+// nasl の値を Rust の型に読み込む
+pub fn from_nasl<T: DeserializeOwned>(value: &Value) -> Result<T, SerdeError> {
+    T::deserialize(Deserializer(value))
+}
+
+// Rust の値を nasl の値にする。構造体は同じ名前の構造体、列挙型は列挙型の値になる
+pub fn to_nasl<T: Serialize + ?Sized>(value: &T) -> Result<Value, SerdeError> {
+    value.serialize(Serializer)
+}
+
+// 変数を作った式 expr の中で、path の場所を作った式の位置を探す。
+// 構造体リテラル・配列・タプル・vec! をたどれるところまでたどる
+pub(super) fn locate(expr: &Expr, path: &[PathItem]) -> Option<Span> {
+    let mut expr = expr;
+    let mut span = expr_span(expr);
+    for item in path {
+        let next = match (expr, item) {
+            (Expr::Vec(inner), _) => match (&**inner, item) {
+                (Expr::Array(items), PathItem::Index(i)) => items.get(*i),
+                _ => None,
+            },
+            (Expr::Array(items) | Expr::Tuple(items), PathItem::Index(i)) => items.get(*i),
+            (Expr::StructInit(_, fields), PathItem::Field(name)) => fields
+                .iter()
+                .find(|(f, ..)| f == name)
+                .map(|(_, e, field_span)| {
+                    span = Some(*field_span);
+                    e
+                }),
+            _ => None,
+        };
+        let Some(next) = next else {
+            break;
+        };
+        expr = next;
+        span = expr_span(expr).or(span);
+    }
+    span
+}
+
+struct Deserializer<'a>(&'a Value);
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Value::Unit => visitor.visit_unit(),
+            Value::Int(n) => visitor.visit_i64(*n),
+            Value::Sized(ty, bits) if ty.is_signed() => {
+                let n = ty.to_i128(*bits).unwrap_or_default();
+                match i64::try_from(n) {
+                    Ok(n) => visitor.visit_i64(n),
+                    Err(_) => visitor.visit_i128(n),
+                }
+            }
+            Value::Sized(_, bits) => match u64::try_from(*bits) {
+                Ok(n) => visitor.visit_u64(n),
+                Err(_) => visitor.visit_u128(*bits),
+            },
+            Value::Float(x) => visitor.visit_f64(*x),
+            Value::F32(x) => visitor.visit_f32(*x),
+            Value::Bool(b) => visitor.visit_bool(*b),
+            Value::Char(c) => visitor.visit_char(*c),
+            Value::Str(s) => visitor.visit_str(s),
+            Value::Tuple(items) => visitor.visit_seq(Seq::new(items.iter())),
+            Value::Array(items) => visitor.visit_seq(Seq::new(items.iter())),
+            Value::Set(set) => visitor.visit_seq(Seq::new(set.iter().map(|k| &k.0))),
+            Value::Struct { fields, .. } => visitor.visit_map(Fields::new(
+                fields.iter().map(|(name, v)| (name.as_str(), v)),
+            )),
+            Value::Map(map) => visitor.visit_map(Entries {
+                iter: map.iter(),
+                value: None,
+            }),
+            Value::Option(None) => visitor.visit_none(),
+            Value::Option(Some(v)) => visitor.visit_some(Deserializer(v)),
+            Value::Result(Ok(v)) => visitor.visit_enum(Variant {
+                name: "Ok",
+                fields: std::slice::from_ref(&**v),
+            }),
+            Value::Result(Err(e)) => visitor.visit_enum(Variant {
+                name: "Err",
+                fields: std::slice::from_ref(&**e),
+            }),
+            Value::Enum {
+                variant, fields, ..
+            } => visitor.visit_enum(Variant {
+                name: variant,
+                fields,
+            }),
+            Value::Range { .. } | Value::Closure(_) | Value::Iter(_) | Value::Host(_) => Err(
+                SerdeError::new(format!("{} は変換できません", self.0.type_name())),
+            ),
+        }
+    }
+
+    // Option でない値は Some として読む（設定ファイルで Some(...) を書かずに済むように）
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Value::Option(None) => visitor.visit_none(),
+            Value::Option(Some(v)) => visitor.visit_some(Deserializer(v)),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    // 文字列は中身のないバリアントの名前として読む
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Value::Str(s) => {
+                let name: StrDeserializer<'_, SerdeError> = s.as_str().into_deserializer();
+                visitor.visit_enum(name)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+// 配列・タプル・HashSet の要素
+struct Seq<I> {
+    iter: I,
+    index: usize,
+}
+
+impl<I> Seq<I> {
+    fn new(iter: I) -> Self {
+        Seq { iter, index: 0 }
+    }
+}
+
+impl<'de, 'a, I: Iterator<Item = &'a Value>> de::SeqAccess<'de> for Seq<I> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SerdeError> {
+        let Some(v) = self.iter.next() else {
+            return Ok(None);
+        };
+        let index = self.index;
+        self.index += 1;
+        seed.deserialize(Deserializer(v))
+            .map(Some)
+            .map_err(|e| e.inside(PathItem::Index(index)))
+    }
+}
+
+// 構造体のフィールド・列挙型の名前付きの中身
+struct Fields<'a, I> {
+    iter: I,
+    value: Option<(&'a str, &'a Value)>,
+}
+
+impl<I> Fields<'_, I> {
+    fn new(iter: I) -> Self {
+        Fields { iter, value: None }
+    }
+}
+
+impl<'de, 'a, I: Iterator<Item = (&'a str, &'a Value)>> de::MapAccess<'de> for Fields<'a, I> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerdeError> {
+        let Some((name, v)) = self.iter.next() else {
+            return Ok(None);
+        };
+        self.value = Some((name, v));
+        let key: StrDeserializer<'_, SerdeError> = name.into_deserializer();
+        seed.deserialize(key)
+            .map(Some)
+            .map_err(|e| e.inside(PathItem::Field(name.to_string())))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SerdeError> {
+        let Some((name, v)) = self.value.take() else {
+            return Err(SerdeError::new("フィールドの値がありません"));
+        };
+        seed.deserialize(Deserializer(v))
+            .map_err(|e| e.inside(PathItem::Field(name.to_string())))
+    }
+}
+
+// HashMap の要素
+struct Entries<'a, I> {
+    iter: I,
+    value: Option<(&'a MapKey, &'a Value)>,
+}
+
+impl<'de, 'a, I: Iterator<Item = (&'a MapKey, &'a Value)>> de::MapAccess<'de> for Entries<'a, I> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerdeError> {
+        let Some((k, v)) = self.iter.next() else {
+            return Ok(None);
+        };
+        self.value = Some((k, v));
+        seed.deserialize(Deserializer(&k.0))
+            .map(Some)
+            .map_err(|e| e.inside(PathItem::Key(format!("{:?}", k.0))))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SerdeError> {
+        let Some((k, v)) = self.value.take() else {
+            return Err(SerdeError::new("キーに対応する値がありません"));
+        };
+        seed.deserialize(Deserializer(v))
+            .map_err(|e| e.inside(PathItem::Key(format!("{:?}", k.0))))
+    }
+}
+
+// 列挙型の値（Result の Ok / Err も含む）
+struct Variant<'a> {
+    name: &'a str,
+    fields: &'a [Value],
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for Variant<'a> {
+    type Error = SerdeError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), SerdeError> {
+        let name: StrDeserializer<'_, SerdeError> = self.name.into_deserializer();
+        Ok((seed.deserialize(name)?, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant<'_> {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        if self.fields.is_empty() {
+            return Ok(());
+        }
+        Err(SerdeError::new(format!(
+            "バリアント {} は中身を持たないはずですが {} 個の値があります",
+            self.name,
+            self.fields.len()
+        )))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SerdeError> {
+        match self.fields {
+            [v] => seed
+                .deserialize(Deserializer(v))
+                .map_err(|e| e.inside(PathItem::Index(0))),
+            _ => Err(SerdeError::new(format!(
+                "バリアント {} は1個の値を持つはずですが {} 個あります",
+                self.name,
+                self.fields.len()
+            ))),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_seq(Seq::new(self.fields.iter()))
+    }
+
+    // nasl の列挙型の中身には名前がないので、定義順にフィールドへ割り当てる
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_map(Fields::new(fields.iter().copied().zip(self.fields)))
+    }
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    type SerializeSeq = SeqBuilder;
+    type SerializeTuple = SeqBuilder;
+    type SerializeTupleStruct = SeqBuilder;
+    type SerializeTupleVariant = VariantBuilder;
+    type SerializeMap = MapBuilder;
+    type SerializeStruct = StructBuilder;
+    type SerializeStructVariant = VariantBuilder;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, SerdeError> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, SerdeError> {
+        Ok(v.into_nasl())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, SerdeError> {
+        Ok(Value::F32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, SerdeError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, SerdeError> {
+        Ok(Value::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, SerdeError> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SerdeError> {
+        Ok(v.to_vec().into_nasl())
+    }
+
+    fn serialize_none(self) -> Result<Value, SerdeError> {
+        Ok(Value::none())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, SerdeError> {
+        Ok(Value::some(value.serialize(Serializer)?))
+    }
+
+    fn serialize_unit(self) -> Result<Value, SerdeError> {
+        Ok(Value::Unit)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Value, SerdeError> {
+        Ok(Value::Struct {
+            name: Rc::from(name),
+            fields: Rc::new(Vec::new()),
+        })
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<Value, SerdeError> {
+        Ok(enum_value(name, index, variant, Vec::new()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, SerdeError> {
+        value.serialize(Serializer)
+    }
+
+    // Rust の Result は nasl の Result にする
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, SerdeError> {
+        let v = value
+            .serialize(Serializer)
+            .map_err(|e| e.inside(PathItem::Index(0)))?;
+        Ok(match (name, variant) {
+            ("Result", "Ok") => Value::ok(v),
+            ("Result", "Err") => Value::err(v),
+            _ => enum_value(name, index, variant, vec![v]),
+        })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqBuilder, SerdeError> {
+        Ok(SeqBuilder {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            tuple: false,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqBuilder, SerdeError> {
+        Ok(SeqBuilder {
+            items: Vec::with_capacity(len),
+            tuple: true,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqBuilder, SerdeError> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantBuilder, SerdeError> {
+        Ok(VariantBuilder {
+            name,
+            index,
+            variant,
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapBuilder, SerdeError> {
+        Ok(MapBuilder {
+            map: BTreeMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<StructBuilder, SerdeError> {
+        Ok(StructBuilder {
+            name,
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantBuilder, SerdeError> {
+        self.serialize_tuple_variant(name, index, variant, len)
+    }
+}
+
+fn enum_value(name: &str, index: u32, variant: &str, fields: Vec<Value>) -> Value {
+    Value::Enum {
+        name: Rc::from(name),
+        index: index as usize,
+        variant: Rc::from(variant),
+        fields: Rc::new(fields),
+    }
+}
+
+struct SeqBuilder {
+    items: Vec<Value>,
+    // タプルなら Value::Tuple、そうでなければ配列にする
+    tuple: bool,
+}
+
+impl SeqBuilder {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let index = self.items.len();
+        let v = value
+            .serialize(Serializer)
+            .map_err(|e| e.inside(PathItem::Index(index)))?;
+        self.items.push(v);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        match self.tuple {
+            true if self.items.is_empty() => Value::Unit,
+            true => Value::Tuple(self.items),
+            false => Value::Array(Rc::new(self.items)),
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqBuilder {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SeqBuilder {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqBuilder {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.finish())
+    }
+}
+
+// 中身のあるバリアント。名前付きの中身も定義順の値として持つ
+struct VariantBuilder {
+    name: &'static str,
+    index: u32,
+    variant: &'static str,
+    fields: Vec<Value>,
+}
+
+impl VariantBuilder {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let index = self.fields.len();
+        let v = value
+            .serialize(Serializer)
+            .map_err(|e| e.inside(PathItem::Index(index)))?;
+        self.fields.push(v);
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantBuilder {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(enum_value(self.name, self.index, self.variant, self.fields))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantBuilder {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(enum_value(self.name, self.index, self.variant, self.fields))
+    }
+}
+
+struct MapBuilder {
+    map: BTreeMap<MapKey, Value>,
+    key: Option<Value>,
+}
+
+impl ser::SerializeMap for MapBuilder {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let Some(key) = self.key.take() else {
+            return Err(SerdeError::new("値に対応するキーがありません"));
+        };
+        let item = PathItem::Key(format!("{:?}", key));
+        let v = value
+            .serialize(Serializer)
+            .map_err(|e| e.inside(item.clone()))?;
+        let key = MapKey::new(key).map_err(|e| SerdeError::new(e.message).inside(item))?;
+        self.map.insert(key, v);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(Value::Map(Rc::new(self.map)))
+    }
+}
+
+struct StructBuilder {
+    name: &'static str,
+    fields: Vec<(String, Value)>,
+}
+
+impl ser::SerializeStruct for StructBuilder {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        let v = value
+            .serialize(Serializer)
+            .map_err(|e| e.inside(PathItem::Field(key.to_string())))?;
+        self.fields.push((key.to_string(), v));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(Value::Struct {
+            name: Rc::from(self.name),
+            fields: Rc::new(self.fields),
+        })
+    }
+}
@@ -0,0 +1,191 @@
+// スクリプトに許可する入出力（標準入出力・ファイル・環境変数・プロセス・時刻）。
+// 許可されていない操作は Permission の実行時エラーにする
+use super::error::{ErrorKind, RuntimeError, fail};
+use std::cell::RefCell;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+// ファイルの読み書きを許可する範囲
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathAccess {
+    Deny,
+    // このディレクトリ（またはファイル）の下だけ
+    Within(Vec<PathBuf>),
+    Any,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    // input
+    pub stdin: bool,
+    // print・println!
+    pub stdout: bool,
+    // fs::read_to_string・import
+    pub read: PathAccess,
+    // fs::write
+    pub write: PathAccess,
+    // env::var
+    pub env: bool,
+    // process::exit
+    pub process: bool,
+    // time::now_millis
+    pub clock: bool,
+}
+
+impl Capabilities {
+    // すべて許可する（Interpreter の既定）
+    pub fn all() -> Self {
+        Capabilities {
+            stdin: true,
+            stdout: true,
+            read: PathAccess::Any,
+            write: PathAccess::Any,
+            env: true,
+            process: true,
+            clock: true,
+        }
+    }
+
+    // 何も許可しない
+    pub fn none() -> Self {
+        Capabilities {
+            stdin: false,
+            stdout: false,
+            read: PathAccess::Deny,
+            write: PathAccess::Deny,
+            env: false,
+            process: false,
+            clock: false,
+        }
+    }
+}
+
+thread_local! {
+    // 評価中の Interpreter の許可。Rust の関数の中で別の Interpreter を評価すると、
+    // そのあいだは内側の Interpreter の許可になる
+    static CURRENT: RefCell<Option<Capabilities>> = const { RefCell::new(None) };
+}
+
+// 評価が終わると、評価を始める前の許可に戻す
+pub(super) struct Granted(Option<Capabilities>);
+
+impl Drop for Granted {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        CURRENT.with(|c| *c.borrow_mut() = outer);
+    }
+}
+
+pub(super) fn enter(caps: &Capabilities) -> Granted {
+    Granted(CURRENT.with(|c| c.replace(Some(caps.clone()))))
+}
+
+// Interpreter の外で呼ばれたときはすべて許可する
+fn allowed(f: impl FnOnce(&Capabilities) -> bool) -> bool {
+    CURRENT.with(|c| c.borrow().as_ref().is_none_or(f))
+}
+
+fn denied<T>(what: String) -> Result<T, RuntimeError> {
+    fail(
+        ErrorKind::Permission,
+        format!("{}は許可されていません", what),
+    )
+}
+
+pub(super) fn stdin() -> Result<(), RuntimeError> {
+    if allowed(|c| c.stdin) {
+        Ok(())
+    } else {
+        denied("標準入力の読み込み".to_string())
+    }
+}
+
+pub(super) fn stdout() -> Result<(), RuntimeError> {
+    if allowed(|c| c.stdout) {
+        Ok(())
+    } else {
+        denied("標準出力への書き込み".to_string())
+    }
+}
+
+pub(super) fn env(name: &str) -> Result<(), RuntimeError> {
+    if allowed(|c| c.env) {
+        Ok(())
+    } else {
+        denied(format!("環境変数 {} の読み込み", name))
+    }
+}
+
+pub(super) fn process() -> Result<(), RuntimeError> {
+    if allowed(|c| c.process) {
+        Ok(())
+    } else {
+        denied("プロセスの操作".to_string())
+    }
+}
+
+pub(super) fn clock() -> Result<(), RuntimeError> {
+    if allowed(|c| c.clock) {
+        Ok(())
+    } else {
+        denied("時刻の読み出し".to_string())
+    }
+}
+
+pub(super) fn read(path: &str) -> Result<(), RuntimeError> {
+    if allowed(|c| within(&c.read, path)) {
+        Ok(())
+    } else {
+        denied(format!("ファイル {} の読み込み", path))
+    }
+}
+
+pub(super) fn write(path: &str) -> Result<(), RuntimeError> {
+    if allowed(|c| within(&c.write, path)) {
+        Ok(())
+    } else {
+        denied(format!("ファイル {} への書き込み", path))
+    }
+}
+
+// シンボリックリンクや .. で許可した範囲の外に出られないよう、絶対パスにしてから比べる
+fn within(access: &PathAccess, path: &str) -> bool {
+    match access {
+        PathAccess::Deny => false,
+        PathAccess::Any => true,
+        PathAccess::Within(roots) => {
+            let path = absolute(Path::new(path));
+            roots.iter().any(|root| path.starts_with(absolute(root)))
+        }
+    }
+}
+
+// まだないファイル（書き込み先など）は、存在する一番深い親ディレクトリでリンクをたどる
+fn absolute(path: &Path) -> PathBuf {
+    let mut p = env::current_dir().unwrap_or_default();
+    for c in path.components() {
+        match c {
+            Component::ParentDir => {
+                // リンク先の親に戻る
+                p = p.canonicalize().unwrap_or(p);
+                p.pop();
+            }
+            Component::CurDir => {}
+            c => p.push(c),
+        }
+    }
+    let mut rest = Vec::new();
+    let mut base = p.as_path();
+    loop {
+        if let Ok(real) = base.canonicalize() {
+            return rest.iter().rev().fold(real, |p, name| p.join(name));
+        }
+        match (base.parent(), base.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                base = parent;
+            }
+            _ => return p,
+        }
+    }
+}
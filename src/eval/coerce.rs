@@ -0,0 +1,42 @@
+// 型注釈に合わせた値の変換
+// 型注釈のない整数リテラルは i64、小数リテラルは f64 として評価されるので、let x: u8 = 1 や
+// 引数・戻り値・フィールドの注釈が整数型・f32 なら、その型の値に置き換える。
+// i64 に収まらない接尾辞なしのリテラル（u64 / u128 などとして評価される）も注釈の整数型にする
+use super::error::RuntimeError;
+use crate::check::ty::Ty;
+use crate::float::FloatTy;
+use crate::int;
+use crate::value::Value;
+use std::rc::Rc;
+
+// 型の範囲に収まらない整数リテラルはエラー
+pub fn coerce(v: Value, ty: &Ty) -> Result<Value, RuntimeError> {
+    let v = match (ty, v) {
+        (Ty::Int(ty), Value::Int(n)) => Value::from_int(*ty, int::fit(*ty, n)?),
+        (Ty::Int(ty), Value::Sized(from, bits)) if from != *ty => {
+            Value::from_int(*ty, int::convert(from, bits, *ty)?)
+        }
+        (Ty::Float(FloatTy::F32), Value::Float(x)) => Value::F32(x as f32),
+        (Ty::Vec(elem), Value::Array(items)) => {
+            let items = Rc::unwrap_or_clone(items);
+            Value::Array(Rc::new(
+                items
+                    .into_iter()
+                    .map(|v| coerce(v, elem))
+                    .collect::<Result<_, _>>()?,
+            ))
+        }
+        (Ty::Tuple(tys), Value::Tuple(items)) if tys.len() == items.len() => Value::Tuple(
+            items
+                .into_iter()
+                .zip(tys)
+                .map(|(v, t)| coerce(v, t))
+                .collect::<Result<_, _>>()?,
+        ),
+        (Ty::Option(t), Value::Option(Some(v))) => Value::some(coerce(*v, t)?),
+        (Ty::Result(t, _), Value::Result(Ok(v))) => Value::ok(coerce(*v, t)?),
+        (Ty::Result(_, e), Value::Result(Err(v))) => Value::err(coerce(*v, e)?),
+        (_, v) => v,
+    };
+    Ok(v)
+}
@@ -0,0 +1,128 @@
+// 実行時エラー。スクリプトの誤りはパニックさせず、発生位置と nasl の関数呼び出しの履歴を付けて返す
+use crate::int::ArithError;
+use crate::lexer::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // 未定義の変数・関数・構造体・フィールド・メソッド
+    Undefined,
+    // 演算・メソッド・引数に合わない値、引数の数の違い
+    Type,
+    // let・for・引数のパターンに一致しない
+    Pattern,
+    // 添字・スライスの範囲外、見つからないキー
+    Index,
+    // 整数のあふれ・型の範囲に収まらない値
+    Overflow,
+    DivideByZero,
+    // None・Err に対する unwrap・expect
+    Unwrap,
+    // panic!・unreachable!・todo!
+    Panic,
+    // assert!・assert_eq!・assert_ne! の失敗
+    Assert,
+    // import したファイルが読み込めない
+    Io,
+    // 関数呼び出しの深さが上限を超えた
+    StackOverflow,
+    // Interpreter に設定した実行ステップ数・実行時間・メモリ使用量の上限を超えた
+    LimitExceeded,
+    // Interpreter で許可されていない入出力
+    Permission,
+}
+
+// 呼び出し中の関数と、それを呼び出した位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    // エラーが起きた式の位置（位置を持つ式を通るときに付ける）
+    pub span: Option<Span>,
+    // 内側の呼び出しから順に並べる
+    pub stack: Vec<Frame>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        RuntimeError {
+            kind,
+            message: message.into(),
+            span: None,
+            stack: Vec::new(),
+        }
+    }
+
+    // まだ位置が決まっていなければ span にする
+    pub fn at(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+
+    // 関数 name の呼び出しから抜けるときに、呼び出した位置を積む
+    pub fn called(mut self, name: &str, span: Span) -> Self {
+        self.stack.push(Frame {
+            name: name.to_string(),
+            span,
+        });
+        self
+    }
+
+    // 表示するフレーム。深い再帰では内側と外側の SHOWN_FRAMES 個ずつだけにして、省いた数を返す
+    pub fn shown_stack(&self) -> (&[Frame], usize, &[Frame]) {
+        let n = self.stack.len();
+        if n <= SHOWN_FRAMES * 2 {
+            return (&self.stack, 0, &[]);
+        }
+        (
+            &self.stack[..SHOWN_FRAMES],
+            n - SHOWN_FRAMES * 2,
+            &self.stack[n - SHOWN_FRAMES..],
+        )
+    }
+}
+
+const SHOWN_FRAMES: usize = 10;
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " ({})", span)?;
+        }
+        let (inner, omitted, outer) = self.shown_stack();
+        for frame in inner {
+            write!(f, "\n    {} ({} で呼び出し)", frame.name, frame.span)?;
+        }
+        if omitted > 0 {
+            write!(f, "\n    ...（{} 個の呼び出しを省略）", omitted)?;
+        }
+        for frame in outer {
+            write!(f, "\n    {} ({} で呼び出し)", frame.name, frame.span)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<ArithError> for RuntimeError {
+    fn from(e: ArithError) -> Self {
+        let kind = match e {
+            ArithError::DivideByZero => ErrorKind::DivideByZero,
+            ArithError::Overflow(_) => ErrorKind::Overflow,
+        };
+        RuntimeError::new(kind, e.to_string())
+    }
+}
+
+// 評価器の中では Flow にも変換して返せる
+pub fn fail<T, E: From<RuntimeError>>(kind: ErrorKind, message: impl Into<String>) -> Result<T, E> {
+    Err(RuntimeError::new(kind, message).into())
+}
@@ -0,0 +1,51 @@
+// format! / println! の書式に従った値の表示
+use super::error::RuntimeError;
+use super::limits;
+use crate::ast::FmtSpec;
+use crate::value::Value;
+
+// 幅・精度の分だけ表示は長くなるので、文字列を作る前にメモリ使用量の上限を確かめる
+pub fn format_value(v: &Value, spec: &FmtSpec) -> Result<String, RuntimeError> {
+    if let Some(p) = spec.precision {
+        limits::reserve(p)?;
+    }
+    // 精度は Value の Display / Debug から中の値の表示に渡る
+    let body = match (spec.debug, spec.precision) {
+        (false, None) => format!("{}", v),
+        (false, Some(p)) => format!("{:.*}", p, v),
+        (true, None) => format!("{:?}", v),
+        (true, Some(p)) => format!("{:.*?}", p, v),
+    };
+    let Some(width) = spec.width else {
+        return Ok(body);
+    };
+    let len = body.chars().count();
+    if len >= width {
+        return Ok(body);
+    }
+    let pad = width - len;
+    let numeric = v.as_int().is_some() || v.as_float().is_some();
+    // {:08.3} は符号の後ろを 0 で埋める
+    if spec.zero && numeric {
+        limits::reserve(body.len().saturating_add(pad))?;
+        let (sign, digits) = match body.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", body.as_str()),
+        };
+        return Ok(format!("{}{}{}", sign, "0".repeat(pad), digits));
+    }
+    let fill = spec.fill.unwrap_or(' ').to_string();
+    limits::reserve(body.len().saturating_add(pad.saturating_mul(fill.len())))?;
+    // 数値は右寄せ、それ以外は左寄せが既定
+    let (left, right) = match spec.align.unwrap_or(if numeric { '>' } else { '<' }) {
+        '<' => (0, pad),
+        '^' => (pad / 2, pad - pad / 2),
+        _ => (pad, 0),
+    };
+    Ok(format!(
+        "{}{}{}",
+        fill.repeat(left),
+        body,
+        fill.repeat(right)
+    ))
+}
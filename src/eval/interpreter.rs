@@ -0,0 +1,483 @@
+// 状態を持ち続けるインタプリタ。スクリプトを一度読み込んでおき、Rust 側から何度も関数を呼び出せる
+#[cfg(feature = "serde")]
+use super::bridge::locate;
+use super::{
+    Capabilities, Env, ErrorKind, Flow, FromNasl, FuncTable, Globals, IntoArgs, IntoNasl, Limits,
+    NativeFunction, NativeModule, Program, RuntimeError, Vars, call_body, call_closure, capability,
+    check_arity, coerce, define, eval_stmt, fail, get_std_funcs, limits, stream, vm,
+};
+#[cfg(feature = "serde")]
+use super::{from_nasl, to_nasl};
+use crate::ast::Stmt;
+#[cfg(feature = "serde")]
+use crate::ast::{Expr, Pattern};
+use crate::lexer::{Span, tokenize};
+use crate::parser::parse;
+use crate::resolve::{Known, Resolution, resolve, resolve_with};
+use crate::value::Value;
+#[cfg(feature = "serde")]
+use serde::{Serialize, de::DeserializeOwned};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+// run_tests の結果: #[test] の関数の名前と、その関数を呼び出した結果
+pub type TestResult = (String, Result<(), RuntimeError>);
+
+// 関数・型の定義、トップレベルの変数、読み込み済みのファイルを評価のあいだ持ち続ける。
+// 型検査はしないので、必要なら評価の前に check を呼ぶ
+pub struct Interpreter {
+    globals: Globals,
+    // トップレベルの変数
+    vars: Vars,
+    // eval_file・import で読み込んだファイル（同じファイルは二度評価しない）
+    loaded: HashSet<String>,
+    // eval・関数の呼び出しごとにかける実行の制限
+    limits: Limits,
+    // スクリプトに許可する入出力
+    capabilities: Capabilities,
+    // input・print・解析エラーの入出力
+    streams: stream::Streams,
+    // トップレベルの let で変数を作った式（serde の変換エラーの位置を探すのに使う）
+    #[cfg(feature = "serde")]
+    lets: HashMap<String, (Expr, Span)>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let mut interpreter = Interpreter {
+            globals: Globals {
+                funcs: FuncTable::default(),
+                structs: HashMap::new(),
+                variants: HashMap::new(),
+                traits: HashMap::new(),
+                // 標準関数テーブル
+                std_funcs: get_std_funcs(),
+                natives: HashMap::new(),
+                depth: Cell::new(0),
+            },
+            vars: Vars::default(),
+            loaded: HashSet::new(),
+            limits: Limits::default(),
+            capabilities: Capabilities::all(),
+            streams: stream::Streams::default(),
+            #[cfg(feature = "serde")]
+            lets: HashMap::new(),
+        };
+        // プレリュードのトレイト（Iterator の既定の実装など）
+        let prelude = crate::prelude::stmts();
+        resolve(&prelude);
+        for stmt in &prelude {
+            if let Stmt::TraitDef { .. } = stmt {
+                define(&mut interpreter.globals, stmt);
+            }
+        }
+        interpreter
+    }
+
+    // eval・call などの呼び出し1回ごとにかける制限を設定する。上限を超えると評価を止め、
+    // LimitExceeded の実行時エラーを返す（インタプリタはそのあとも使える）
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    // スクリプトに許可する入出力を設定する（既定ではすべて許可する）。
+    // 登録した Rust の関数は制限しない
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) -> &mut Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    // input で読む入力を設定する（既定ではプロセスの標準入力）
+    pub fn set_stdin(&mut self, stdin: impl BufRead + 'static) -> &mut Self {
+        self.streams.stdin = Some(Rc::new(RefCell::new(stdin)));
+        self
+    }
+
+    // print・println! の出力先を設定する（既定ではプロセスの標準出力）。
+    // 出力を取っておくには OutputBuffer を渡す
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) -> &mut Self {
+        self.streams.stdout = Some(Rc::new(RefCell::new(stdout)));
+        self
+    }
+
+    // 解析エラーの出力先を設定する（既定ではプロセスの標準エラー出力）
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) -> &mut Self {
+        self.streams.stderr = Some(Rc::new(RefCell::new(stderr)));
+        self
+    }
+
+    // input で入力を待つときに標準出力に表示するプロンプト（既定は "> "）
+    pub fn set_prompt(&mut self, prompt: &str) -> &mut Self {
+        self.streams.prompt = prompt.to_string();
+        self
+    }
+
+    // 評価のあいだ、このインタプリタの制限・許可・入出力を使う。
+    // 評価中は self が動かないので、制限を数える Interpreter はアドレスで見分ける
+    fn enter(&self) -> (limits::Active, capability::Granted, stream::Attached) {
+        (
+            limits::enter(&self.limits, self as *const Self as usize),
+            capability::enter(&self.capabilities),
+            stream::enter(&self.streams),
+        )
+    }
+
+    // 文の並びを評価して最後の文の値を返す。定義と変数はこのあとの評価にも残る。
+    // 見つからない変数・関数の名前があれば、評価する前にエラーを返す
+    pub fn eval(&mut self, stmts: &[Stmt]) -> Result<Value, RuntimeError> {
+        let _entered = self.enter();
+        let resolution = self.prepare(stmts)?;
+        // 前の評価で作った変数は、トップレベルの文を囲む関数の変数になる
+        let mut env = Env {
+            frames: vec![std::mem::take(&mut self.vars.values), Vec::new()],
+        };
+        let mut result = Ok(Value::Unit);
+        for stmt in stmts {
+            if !matches!(
+                stmt,
+                Stmt::Expr(_) | Stmt::Print(_) | Stmt::Let { .. } | Stmt::Error(..)
+            ) {
+                continue;
+            }
+            let mark = env.mark();
+            match eval_stmt(stmt, &self.globals, &mut env) {
+                Ok(v) => result = Ok(v),
+                // トップレベルのreturnはスクリプト全体の評価を終える
+                Err(flow) => {
+                    env.reset(mark);
+                    result = match flow {
+                        Flow::Return(v) => Ok(v),
+                        Flow::Error(e) => Err(e),
+                    };
+                    break;
+                }
+            }
+        }
+        // 実行したところまでのトップレベルの変数を残す
+        let top = env.frames.pop().unwrap_or_default();
+        self.vars.values = env.frames.pop().unwrap_or_default();
+        self.vars.extend(resolution.vars.into_iter().zip(top));
+        result
+    }
+
+    // 評価・コンパイルの前の準備。import したファイルを読み込み、名前を解決してから定義を登録する。
+    // 見つからない名前があれば最初のものをエラーにする
+    fn prepare(&mut self, stmts: &[Stmt]) -> Result<Resolution, RuntimeError> {
+        for stmt in stmts {
+            self.load(stmt)?;
+        }
+        let mut known = Known {
+            funcs: self.globals.funcs.names.clone(),
+            builtins: self.globals.natives.keys().cloned().collect(),
+            vars: self.vars.names.clone(),
+        };
+        known.builtins.extend(self.globals.variants.keys().cloned());
+        let resolution = resolve_with(stmts, &known);
+        if let Some(e) = resolution.errors.first() {
+            return Err(RuntimeError::new(ErrorKind::Undefined, e.message.clone()).at(e.span));
+        }
+        // 関数の番号を名前の解決と揃える
+        for name in &resolution.globals {
+            self.globals.funcs.reserve(name);
+        }
+        self.define_all(stmts);
+        Ok(resolution)
+    }
+
+    // 定義を文より先に登録する（関数は定義より前の文からも呼べる）。
+    // impl より後に書かれたトレイトの既定の実装も使えるよう、トレイトは最初に登録する
+    fn define_all(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            if let Stmt::TraitDef { .. } = stmt {
+                define(&mut self.globals, stmt);
+            }
+        }
+        for stmt in stmts {
+            if let Stmt::FuncDef { .. }
+            | Stmt::StructDef { .. }
+            | Stmt::EnumDef { .. }
+            | Stmt::ImplDef { .. } = stmt
+            {
+                define(&mut self.globals, stmt);
+            }
+        }
+    }
+
+    // import したファイルを読み込む
+    fn load(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        #[cfg(feature = "serde")]
+        if let Stmt::Let {
+            pattern: Pattern::Bind(name, _),
+            value,
+            span,
+            ..
+        } = stmt
+        {
+            self.lets.insert(name.clone(), (value.clone(), *span));
+        }
+        // import したファイルの定義・変数は同じインタプリタに読み込む
+        // （use std::... の標準ライブラリは組み込み済み）
+        if let Stmt::Import(filename) = stmt
+            && !filename.starts_with("std::")
+            && !self.loaded.contains(filename)
+        {
+            capability::read(filename)?;
+            let code = read_file(filename, "import するファイル")?;
+            self.loaded.insert(filename.clone());
+            self.eval_str(&code)?;
+        }
+        Ok(())
+    }
+
+    // 文の並びをバイトコードにコンパイルする。import を読み込み、定義はすべて先に登録する。
+    // 変数・関数の名前はコンパイルするときに解決するので、Rust の関数はこの前に登録しておく
+    pub fn compile(&mut self, stmts: &[Stmt]) -> Result<Program, RuntimeError> {
+        let _entered = self.enter();
+        let resolution = self.prepare(stmts)?;
+        Ok(vm::compile(&self.globals, stmts, &resolution))
+    }
+
+    // コンパイルしたプログラムを VM で実行して最後の文の値を返す。
+    // eval と同じく、トップレベルの変数はこのあとの評価にも残る
+    pub fn run(&mut self, program: &Program) -> Result<Value, RuntimeError> {
+        let _entered = self.enter();
+        vm::run(&self.globals, program, &mut self.vars)
+    }
+
+    // eval と同じ評価を、コンパイルしてから VM で行う
+    pub fn eval_vm(&mut self, stmts: &[Stmt]) -> Result<Value, RuntimeError> {
+        let program = self.compile(stmts)?;
+        self.run(&program)
+    }
+
+    pub fn eval_str(&mut self, code: &str) -> Result<Value, RuntimeError> {
+        let tokens = tokenize(code);
+        let stmts = parse(&tokens);
+        self.eval(&stmts)
+    }
+
+    pub fn eval_file(&mut self, path: &str) -> Result<Value, RuntimeError> {
+        let code = read_file(path, "ファイル")?;
+        self.loaded.insert(path.to_string());
+        self.eval_str(&code)
+    }
+
+    // トップレベルの関数（またはクロージャを入れた変数・標準関数）を呼び出す
+    pub fn call_function(&self, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let globals = &self.globals;
+        let _entered = self.enter();
+        if let Some(f) = globals.funcs.get(name) {
+            check_arity(name, f.params.len(), args.len())?;
+            let mut arg_vals = Vec::new();
+            for (v, ty) in args.into_iter().zip(&f.param_tys) {
+                arg_vals.push(coerce::coerce(v, ty)?);
+            }
+            call_body(name, &f.params, &f.body, Env::new(), arg_vals, globals)
+                .and_then(|(v, _)| coerce::coerce(v, &f.ret))
+                .map_err(|e| e.called(name, Span::default()))
+        } else if let Some(Value::Closure(f)) = self.vars.get(name) {
+            call_closure(f, args, globals).map_err(|e| e.called(name, Span::default()))
+        } else if let Some(f) = globals.natives.get(name) {
+            f(args)
+        } else if let Some(f) = globals.std_funcs.get(name) {
+            f(args)
+        } else {
+            fail(ErrorKind::Undefined, format!("未定義の関数: {}", name))
+        }
+    }
+
+    // stmts を評価してから、#[test] の付いたトップレベルの関数を順に呼び出す
+    // assert! などが失敗した関数の結果は Err になる
+    pub fn run_tests(&mut self, stmts: &[Stmt]) -> Result<Vec<TestResult>, RuntimeError> {
+        self.eval(stmts)?;
+        let tests = stmts.iter().filter_map(|s| match s {
+            Stmt::FuncDef {
+                name, test: true, ..
+            } => Some(name),
+            _ => None,
+        });
+        Ok(tests
+            .map(|name| {
+                let result = self.call_function(name, vec![]).map(|_| ());
+                (name.clone(), result)
+            })
+            .collect())
+    }
+
+    // 引数・戻り値を Rust の型で受け渡して関数を呼び出す
+    // 例: interp.call::<(i64, String), bool>("validate", (42, "x".into()))
+    pub fn call<Args: IntoArgs, Ret: FromNasl>(
+        &self,
+        name: &str,
+        args: Args,
+    ) -> Result<Ret, RuntimeError> {
+        let v = self.call_function(name, args.into_args())?;
+        ret(name, v)
+    }
+
+    // 関数 name を Rust の関数として取り出す。関数は呼び出すときに探す
+    pub fn get_fn<Args: IntoArgs, Ret: FromNasl>(
+        &self,
+        name: &str,
+    ) -> impl Fn(Args) -> Result<Ret, RuntimeError> + '_ {
+        let name = name.to_string();
+        move |args| self.call(&name, args)
+    }
+
+    // スクリプトから受け取ったクロージャを Rust の関数にする
+    pub fn closure<Args: IntoArgs, Ret: FromNasl>(
+        &self,
+        f: Value,
+    ) -> Result<impl Fn(Args) -> Result<Ret, RuntimeError> + '_, RuntimeError> {
+        let Value::Closure(f) = f else {
+            return fail(
+                ErrorKind::Type,
+                format!("{} は呼び出せません", f.type_name()),
+            );
+        };
+        Ok(move |args: Args| {
+            let _entered = self.enter();
+            let v = call_closure(&f, args.into_args(), &self.globals)
+                .map_err(|e| e.called("クロージャ", Span::default()))?;
+            ret("クロージャ", v)
+        })
+    }
+
+    // Rust の関数をスクリプトから呼べるようにする。引数は関数の引数の型に変換し、
+    // 数や型が合わなければ実行時エラーにする。同じ名前の標準関数より優先する
+    pub fn register_fn<Args>(&mut self, name: &str, f: impl NativeFunction<Args>) -> &mut Self {
+        let native = f.into_native(name.to_string());
+        self.globals.natives.insert(name.to_string(), native);
+        self
+    }
+
+    // nasl の値をそのまま受け取り、実行時エラーも返せる関数を登録する
+    pub fn register_raw(
+        &mut self,
+        name: &str,
+        f: impl Fn(Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) -> &mut Self {
+        self.globals.natives.insert(name.to_string(), Rc::new(f));
+        self
+    }
+
+    // type_name の値のメソッドを登録する。f は受け手を最初の引数として受け取る。
+    // 同じ名前の組み込みのメソッドより優先し、impl で定義したメソッドよりは後になる
+    pub fn register_method<Args>(
+        &mut self,
+        type_name: &str,
+        name: &str,
+        f: impl NativeFunction<Args>,
+    ) -> &mut Self {
+        self.register_fn(&format!("{}::{}", type_name, name), f)
+    }
+
+    // type_name のホストの値のプロパティ name の読み出し（obj.name）を登録する。
+    // f は受け手を引数に取り、プロパティの値を返す
+    pub fn register_getter<Args>(
+        &mut self,
+        type_name: &str,
+        name: &str,
+        f: impl NativeFunction<Args>,
+    ) -> &mut Self {
+        self.register_fn(&format!("{}.{}", type_name, name), f)
+    }
+
+    // プロパティへの代入（obj.name = v・obj.name += v）を登録する。f は受け手と代入する値を取る
+    pub fn register_setter<Args>(
+        &mut self,
+        type_name: &str,
+        name: &str,
+        f: impl NativeFunction<Args>,
+    ) -> &mut Self {
+        self.register_fn(&format!("{}.{}=", type_name, name), f)
+    }
+
+    // モジュールの関数を "モジュール名::関数名" でまとめて登録する
+    pub fn register_module(&mut self, module: NativeModule) -> &mut Self {
+        self.globals.natives.extend(module.funcs);
+        self
+    }
+
+    // トップレベルの変数の値
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.vars.get(name).cloned()
+    }
+
+    // トップレベルの変数の値を serde で Rust の型に読み込む。
+    // 失敗したときは、変数を作った式の中の該当する場所の位置を付ける
+    #[cfg(feature = "serde")]
+    pub fn deserialize_global<T: DeserializeOwned>(&self, name: &str) -> Result<T, RuntimeError> {
+        let Some(v) = self.vars.get(name) else {
+            return fail(ErrorKind::Undefined, format!("未定義の変数: {}", name));
+        };
+        from_nasl(v).map_err(|e| {
+            let error = RuntimeError::new(
+                ErrorKind::Type,
+                format!("変数 {} を変換できません: {}", name, e),
+            );
+            match self.lets.get(name) {
+                Some((expr, span)) => error.at(locate(expr, &e.path).unwrap_or(*span)),
+                None => error,
+            }
+        })
+    }
+
+    // serde で書き出せる Rust の値をトップレベルの変数にする
+    #[cfg(feature = "serde")]
+    pub fn serialize_global<T: Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &T,
+    ) -> Result<(), RuntimeError> {
+        let v = to_nasl(value).map_err(|e| {
+            RuntimeError::new(
+                ErrorKind::Type,
+                format!("{} を nasl の値にできません: {}", name, e),
+            )
+        })?;
+        self.vars.set(name, v);
+        Ok(())
+    }
+
+    // トップレベルの変数を定義する（ホストの値をスクリプトに渡すのにも使う）
+    pub fn set_global(&mut self, name: &str, value: impl IntoNasl) {
+        self.vars.set(name, value.into_nasl());
+    }
+}
+
+// name の戻り値を Rust の型に変換する
+fn ret<T: FromNasl>(name: &str, v: Value) -> Result<T, RuntimeError> {
+    match T::from_nasl(&v) {
+        Some(x) => Ok(x),
+        None => fail(
+            ErrorKind::Type,
+            format!(
+                "{} の戻り値には {} が必要ですが {} が返されました: {:?}",
+                name,
+                T::type_name(),
+                v.type_name(),
+                v
+            ),
+        ),
+    }
+}
+
+fn read_file(path: &str, what: &str) -> Result<String, RuntimeError> {
+    fs::read_to_string(path).map_err(|e| {
+        RuntimeError::new(
+            ErrorKind::Io,
+            format!("{} {} を読み込めません: {}", what, path, e),
+        )
+    })
+}
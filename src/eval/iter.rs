@@ -0,0 +1,196 @@
+// forループ・イテレータアダプタ共通のイテレーションプロトコル
+use super::error::{ErrorKind, RuntimeError, fail};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub enum Iter {
+    // 整数の範囲（backは含まない。i64::MAXまで扱えるようi128で持つ）
+    Range {
+        front: i128,
+        back: i128,
+    },
+    // 要素列（配列・文字列のcharsなど）。配列とは中身を共有する
+    Seq {
+        items: Rc<Vec<Value>>,
+        front: usize,
+        back: usize,
+    },
+    Rev(Box<Iter>),
+    StepBy {
+        inner: Box<Iter>,
+        step: usize,
+        first_take: bool,
+    },
+    Enumerate {
+        inner: Box<Iter>,
+        count: usize,
+    },
+}
+
+impl Iter {
+    pub fn seq(items: Rc<Vec<Value>>) -> Self {
+        let back = items.len();
+        Iter::Seq {
+            items,
+            front: 0,
+            back,
+        }
+    }
+
+    // 残りの要素数
+    fn remaining(&self) -> usize {
+        match self {
+            Iter::Range { front, back } => (back - front).clamp(0, usize::MAX as i128) as usize,
+            Iter::Seq { front, back, .. } => back - front,
+            Iter::Rev(inner) | Iter::Enumerate { inner, .. } => inner.remaining(),
+            Iter::StepBy {
+                inner,
+                step,
+                first_take,
+            } => {
+                let n = inner.remaining();
+                if *first_take {
+                    n.div_ceil(*step)
+                } else {
+                    n / *step
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Iter {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        match self {
+            Iter::Range { front, back } => {
+                if front < back {
+                    let v = *front as i64;
+                    *front += 1;
+                    Some(Value::Int(v))
+                } else {
+                    None
+                }
+            }
+            Iter::Seq { items, front, back } => {
+                if front < back {
+                    let v = items[*front].clone();
+                    *front += 1;
+                    Some(v)
+                } else {
+                    None
+                }
+            }
+            Iter::Rev(inner) => inner.next_back(),
+            Iter::StepBy {
+                inner,
+                step,
+                first_take,
+            } => {
+                if *first_take {
+                    *first_take = false;
+                    inner.next()
+                } else {
+                    inner.nth(*step - 1)
+                }
+            }
+            Iter::Enumerate { inner, count } => {
+                let v = inner.next()?;
+                let i = *count;
+                *count += 1;
+                Some(Value::Tuple(vec![Value::Int(i as i64), v]))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.remaining();
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for Iter {}
+
+impl DoubleEndedIterator for Iter {
+    fn next_back(&mut self) -> Option<Value> {
+        match self {
+            Iter::Range { front, back } => {
+                if front < back {
+                    *back -= 1;
+                    Some(Value::Int(*back as i64))
+                } else {
+                    None
+                }
+            }
+            Iter::Seq { items, front, back } => {
+                if front < back {
+                    *back -= 1;
+                    Some(items[*back].clone())
+                } else {
+                    None
+                }
+            }
+            Iter::Rev(inner) => inner.next(),
+            Iter::StepBy {
+                inner,
+                step,
+                first_take,
+            } => {
+                // 末尾から見て最初に現れる「step刻みの要素」まで読み飛ばす（std::iter::StepByと同じ計算）
+                let rem = inner.len() % *step;
+                let skip = if *first_take {
+                    if rem == 0 { *step - 1 } else { rem - 1 }
+                } else {
+                    rem
+                };
+                inner.nth_back(skip)
+            }
+            Iter::Enumerate { inner, count } => {
+                let len = inner.len();
+                let v = inner.next_back()?;
+                Some(Value::Tuple(vec![Value::Int((*count + len - 1) as i64), v]))
+            }
+        }
+    }
+}
+
+// for ... in <value> で走査できる値をイテレータに変換する
+pub fn into_iter(value: Value) -> Result<Rc<RefCell<Iter>>, RuntimeError> {
+    let iter = match value {
+        // 既にイテレータなら状態を共有したまま進める
+        Value::Iter(it) => return Ok(it),
+        Value::Range {
+            start: Some(s),
+            end,
+            inclusive,
+        } => {
+            let back = match end {
+                Some(e) if inclusive => e as i128 + 1,
+                Some(e) => e as i128,
+                None => i64::MAX as i128 + 1,
+            };
+            Iter::Range {
+                front: s as i128,
+                back,
+            }
+        }
+        Value::Str(s) => Iter::seq(Rc::new(s.chars().map(Value::Char).collect())),
+        Value::Array(items) => Iter::seq(items),
+        // HashMapは (キー, 値) のタプル、HashSetは要素をキーの順に返す
+        Value::Map(map) => Iter::seq(Rc::new(
+            map.iter()
+                .map(|(k, v)| Value::Tuple(vec![k.0.clone(), v.clone()]))
+                .collect(),
+        )),
+        Value::Set(set) => Iter::seq(Rc::new(set.iter().map(|k| k.0.clone()).collect())),
+        other => {
+            return fail(
+                ErrorKind::Type,
+                format!("{} は反復できません: {:?}", other.type_name(), other),
+            );
+        }
+    };
+    Ok(Rc::new(RefCell::new(iter)))
+}
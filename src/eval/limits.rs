@@ -0,0 +1,197 @@
+// 信頼できないスクリプトを動かすための実行の制限（ステップ数・実行時間・メモリ使用量）。
+// 評価中の制限はスレッドごとに持ち、式を1つ評価するたびに tick で確かめる
+use super::error::{ErrorKind, RuntimeError, fail};
+use crate::value::Value;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+// None の制限はかけない
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    // 評価する式の数の上限。配列を作るときは要素の数だけ数える
+    pub max_steps: Option<u64>,
+    // 評価中に増えたヒープの大きさの上限（バイト）。
+    // 使うときは埋め込む側で `#[global_allocator] static ALLOC: CountingAlloc = CountingAlloc;`
+    // としておくこと。そうしていなければ実際の使用量は数えられず、
+    // [v; n]・collect・文字列の連結・書式の幅などで一度に確保する大きさだけを確かめる
+    pub max_heap: Option<usize>,
+    // 実行時間の上限
+    pub timeout: Option<Duration>,
+}
+
+// 実行時間・メモリ使用量はこのステップ数ごとに確かめる
+const CHECK_INTERVAL: u64 = 256;
+
+// 配列1つの要素数の上限。制限をかけていなくても、これより長い配列は作らない
+pub const MAX_ARRAY_LEN: usize = 1 << 24;
+
+thread_local! {
+    // 制限をかけて評価している Interpreter（enter の owner。評価中でなければ 0）
+    static OWNER: Cell<usize> = const { Cell::new(0) };
+    static STEPS: Cell<u64> = const { Cell::new(0) };
+    static MAX_STEPS: Cell<u64> = const { Cell::new(u64::MAX) };
+    static DEADLINE: Cell<Option<(Instant, Duration)>> = const { Cell::new(None) };
+    static MAX_HEAP: Cell<usize> = const { Cell::new(usize::MAX) };
+    static HEAP_BASE: Cell<usize> = const { Cell::new(0) };
+    // CountingAlloc が数えた、このスレッドで確保中のバイト数
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+// 別の Interpreter の評価を始める前の計測。その評価が終わるとこれに戻す
+struct Saved {
+    owner: usize,
+    steps: u64,
+    max_steps: u64,
+    deadline: Option<(Instant, Duration)>,
+    max_heap: usize,
+    heap_base: usize,
+}
+
+// 評価が終わると、評価を始める前の制限と計測に戻す
+pub(super) struct Active(Option<Saved>);
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        if let Some(outer) = self.0.take() {
+            OWNER.with(|o| o.set(outer.owner));
+            STEPS.with(|s| s.set(outer.steps));
+            MAX_STEPS.with(|m| m.set(outer.max_steps));
+            DEADLINE.with(|d| d.set(outer.deadline));
+            MAX_HEAP.with(|m| m.set(outer.max_heap));
+            HEAP_BASE.with(|b| b.set(outer.heap_base));
+        }
+    }
+}
+
+fn set(limits: &Limits) {
+    STEPS.with(|s| s.set(0));
+    MAX_STEPS.with(|m| m.set(limits.max_steps.unwrap_or(u64::MAX)));
+    DEADLINE.with(|d| d.set(limits.timeout.map(|t| (Instant::now() + t, t))));
+    MAX_HEAP.with(|m| m.set(limits.max_heap.unwrap_or(usize::MAX)));
+    HEAP_BASE.with(|b| b.set(allocated()));
+}
+
+// 評価を始める。owner は評価する Interpreter を見分ける 0 でない数。
+// 同じ Interpreter の中の入れ子の評価（import など）は外側の計測を続け、
+// Rust の関数の中で別の Interpreter を評価するときは、その制限で計測をやり直す
+pub(super) fn enter(limits: &Limits, owner: usize) -> Active {
+    if OWNER.with(|o| o.get()) == owner {
+        return Active(None);
+    }
+    let outer = Saved {
+        owner: OWNER.with(|o| o.replace(owner)),
+        steps: STEPS.with(|s| s.get()),
+        max_steps: MAX_STEPS.with(|m| m.get()),
+        deadline: DEADLINE.with(|d| d.get()),
+        max_heap: MAX_HEAP.with(|m| m.get()),
+        heap_base: HEAP_BASE.with(|b| b.get()),
+    };
+    set(limits);
+    Active(Some(outer))
+}
+
+// 式を1つ評価する前に呼ぶ
+pub(super) fn tick() -> Result<(), RuntimeError> {
+    tick_n(1)
+}
+
+// n ステップ進める（配列の要素を n 個作るときなど）
+pub(super) fn tick_n(n: u64) -> Result<(), RuntimeError> {
+    let before = STEPS.with(|s| s.get());
+    let steps = before.saturating_add(n);
+    STEPS.with(|s| s.set(steps));
+    let max = MAX_STEPS.with(|m| m.get());
+    if steps > max {
+        return fail(
+            ErrorKind::LimitExceeded,
+            format!("実行ステップ数が上限 {} を超えました", max),
+        );
+    }
+    if before / CHECK_INTERVAL == steps / CHECK_INTERVAL {
+        return Ok(());
+    }
+    if let Some((deadline, timeout)) = DEADLINE.with(|d| d.get())
+        && Instant::now() > deadline
+    {
+        return fail(
+            ErrorKind::LimitExceeded,
+            format!("実行時間が上限 {:?} を超えました", timeout),
+        );
+    }
+    reserve(0)
+}
+
+// bytes バイトを新たに確保してもメモリ使用量の上限を超えないか確かめる
+pub(super) fn reserve(bytes: usize) -> Result<(), RuntimeError> {
+    let max = MAX_HEAP.with(|m| m.get());
+    let used = allocated().saturating_sub(HEAP_BASE.with(|b| b.get()));
+    if used.saturating_add(bytes) > max {
+        return fail(
+            ErrorKind::LimitExceeded,
+            format!("メモリ使用量が上限 {} バイトを超えました", max),
+        );
+    }
+    Ok(())
+}
+
+// n 要素の配列を作る前に呼ぶ。要素数の上限とメモリ使用量の上限を確かめる
+pub(super) fn reserve_array(n: usize) -> Result<(), RuntimeError> {
+    check_len(n)?;
+    reserve(n.saturating_mul(size_of::<Value>()))
+}
+
+// 配列の要素数が上限を超えないか確かめる
+pub(super) fn check_len(n: usize) -> Result<(), RuntimeError> {
+    if n > MAX_ARRAY_LEN {
+        return fail(
+            ErrorKind::LimitExceeded,
+            format!("配列の要素数 {} が上限 {} を超えました", n, MAX_ARRAY_LEN),
+        );
+    }
+    Ok(())
+}
+
+// このスレッドで確保中のバイト数（CountingAlloc を使っていなければ 0）
+pub fn allocated() -> usize {
+    ALLOCATED.try_with(|a| a.get()).unwrap_or(0)
+}
+
+fn count(add: usize, sub: usize) {
+    let _ = ALLOCATED.try_with(|a| a.set((a.get() + add).saturating_sub(sub)));
+}
+
+// 確保したバイト数をスレッドごとに数えるアロケータ。Limits::max_heap を使うときは
+// `#[global_allocator] static ALLOC: CountingAlloc = CountingAlloc;` としておく
+pub struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = unsafe { System.alloc(layout) };
+        if !p.is_null() {
+            count(layout.size(), 0);
+        }
+        p
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let p = unsafe { System.alloc_zeroed(layout) };
+        if !p.is_null() {
+            count(layout.size(), 0);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        count(0, layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let p = unsafe { System.realloc(ptr, layout, new_size) };
+        if !p.is_null() {
+            count(new_size, layout.size());
+        }
+        p
+    }
+}
@@ -0,0 +1,463 @@
+// 組み込み型のメソッド
+use super::error::{ErrorKind, RuntimeError, fail};
+use super::iter::{Iter, into_iter};
+use super::limits;
+use crate::ast::BinOp;
+use crate::float;
+use crate::int;
+use crate::value::{MapKey, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// クロージャを呼び出す関数（評価器から渡される）
+pub type Call<'a> = dyn Fn(&Value, Vec<Value>) -> Result<Value, RuntimeError> + 'a;
+
+// recvは変数などの場所そのもの（push などはこれを書き換える）
+pub fn call_method(
+    recv: &mut Value,
+    name: &str,
+    args: Vec<Value>,
+    call: &Call,
+) -> Result<Value, RuntimeError> {
+    let v = match (&*recv, name) {
+        // 値はすべて値として振る舞うので clone は複製を返すだけ
+        (_, "clone") => recv.clone(),
+        // {} で表示したときの文字列
+        (_, "to_string") => Value::Str(recv.to_string()),
+        (Value::Str(s), "len") => Value::Int(s.len() as i64),
+        (Value::Str(_), "chars") => Value::Iter(into_iter(recv.clone())?),
+        (Value::Str(s), "parse") => super::parse_int(s.trim()),
+        (
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            },
+            "contains",
+        ) => {
+            let x = expect_int(&args, 0, name)?;
+            let above = start.is_none_or(|s| s <= x);
+            let below = match end {
+                Some(e) if *inclusive => x <= *e,
+                Some(e) => x < *e,
+                None => true,
+            };
+            Value::Bool(above && below)
+        }
+        (Value::Int(_) | Value::Sized(..), _) => return int_method(recv, name, args),
+        (Value::Float(_) | Value::F32(_), _) => return float_method(recv, name, args),
+        (Value::Array(_), _) => return array_method(recv, name, args),
+        (Value::Map(_), _) => return map_method(recv, name, args),
+        (Value::Set(_), _) => return set_method(recv, name, args),
+        (Value::Option(_), _) => return option_method(recv, name, args, call),
+        (Value::Result(_), _) => return result_method(recv, name, args, call),
+        (Value::Range { .. } | Value::Iter(_), _) => {
+            return iter_method(into_iter(recv.clone())?, name, args);
+        }
+        _ => return no_method(&recv.type_name(), name),
+    };
+    Ok(v)
+}
+
+fn no_method<T>(type_name: &str, name: &str) -> Result<T, RuntimeError> {
+    fail(
+        ErrorKind::Undefined,
+        format!("{} にメソッド {} はありません", type_name, name),
+    )
+}
+
+// wrapping_add / checked_add / saturating_add など、あふれたときの扱いを指定する演算
+fn int_method(recv: &Value, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (mode, op) = name.split_once('_').unwrap_or((name, ""));
+    let op = match op {
+        "add" => BinOp::Add,
+        "sub" => BinOp::Sub,
+        "mul" => BinOp::Mul,
+        "div" => BinOp::Div,
+        "rem" => BinOp::Rem,
+        _ => return no_method(&recv.type_name(), name),
+    };
+    let Some((ty, a, b)) = super::int_operands(recv, expect_arg(&args, 0, name)?)? else {
+        return fail(
+            ErrorKind::Type,
+            format!("{} の引数は整数である必要があります", name),
+        );
+    };
+    let v = match mode {
+        "wrapping" => Value::from_int(ty, int::wrapping(op, ty, a, b)?),
+        "checked" => {
+            Value::Option(int::checked(op, ty, a, b).map(|n| Box::new(Value::from_int(ty, n))))
+        }
+        "saturating" => Value::from_int(ty, int::saturating(op, ty, a, b)?),
+        _ => return no_method(&recv.type_name(), name),
+    };
+    Ok(v)
+}
+
+// sqrt / powi / min など。f32 の値は f32 のまま返す
+fn float_method(recv: &Value, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (ty, x) = recv.as_float().unwrap();
+    if name == "is_nan" {
+        return Ok(Value::Bool(x.is_nan()));
+    }
+    if name == "powi" {
+        let n = expect_int(&args, 0, name)?;
+        let Ok(n) = i32::try_from(n) else {
+            return fail(
+                ErrorKind::Overflow,
+                format!("powi の引数は i32 に収まる整数である必要があります: {}", n),
+            );
+        };
+        return Ok(Value::from_float(ty, float::powi(ty, x, n)));
+    }
+    if let Some(y) = float::unary_method(ty, name, x) {
+        return Ok(Value::from_float(ty, y));
+    }
+    if !matches!(name, "powf" | "min" | "max") {
+        return no_method(&recv.type_name(), name);
+    }
+    let arg = expect_arg(&args, 0, name)?;
+    let Some((_, y)) = arg.as_float() else {
+        return fail(
+            ErrorKind::Type,
+            format!(
+                "{} の引数は {} である必要があります: {:?}",
+                name,
+                ty.name(),
+                arg
+            ),
+        );
+    };
+    Ok(Value::from_float(
+        ty,
+        float::binary_method(ty, name, x, ty.round(y)).unwrap(),
+    ))
+}
+
+fn array_method(recv: &mut Value, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let Value::Array(items) = recv else {
+        unreachable!()
+    };
+    match name {
+        "len" => return Ok(Value::Int(items.len() as i64)),
+        "is_empty" => return Ok(Value::Bool(items.is_empty())),
+        "contains" => return Ok(Value::Bool(items.contains(expect_arg(&args, 0, name)?))),
+        "iter" | "into_iter" => {
+            return Ok(Value::Iter(into_iter(Value::Array(items.clone()))?));
+        }
+        _ => {}
+    }
+    // 要素を足すときは、配列を確保し直す（共有されていれば複製する）前に上限を確かめる
+    if matches!(name, "push" | "insert") {
+        limits::check_len(items.len() + 1)?;
+        if Rc::strong_count(items) > 1 || items.len() == items.capacity() {
+            limits::reserve(items.len().max(4) * 2 * size_of::<Value>())?;
+        }
+    }
+    // ここから先は配列を書き換えるメソッド
+    let items = Rc::make_mut(items);
+    match name {
+        "push" => items.push(expect_arg(&args, 0, name)?.clone()),
+        "pop" => return Ok(Value::Option(items.pop().map(Box::new))),
+        "insert" => {
+            let index = expect_int(&args, 0, name)?;
+            if index < 0 || index as usize > items.len() {
+                return fail(
+                    ErrorKind::Index,
+                    format!(
+                        "insert のインデックスが範囲外です: インデックス {} 長さ {}",
+                        index,
+                        items.len()
+                    ),
+                );
+            }
+            items.insert(index as usize, expect_arg(&args, 1, name)?.clone());
+        }
+        "remove" => {
+            let index = expect_int(&args, 0, name)?;
+            if index < 0 || index as usize >= items.len() {
+                return fail(
+                    ErrorKind::Index,
+                    format!(
+                        "remove のインデックスが範囲外です: インデックス {} 長さ {}",
+                        index,
+                        items.len()
+                    ),
+                );
+            }
+            return Ok(items.remove(index as usize));
+        }
+        "sort" => {
+            // 比較できない組（NaN など）があれば、並べ終えてからエラーにする
+            let mut failed = None;
+            items.sort_by(|a, b| {
+                a.partial_cmp(b).unwrap_or_else(|| {
+                    failed.get_or_insert_with(|| {
+                        format!("{} と {} は比較できません", a.type_name(), b.type_name())
+                    });
+                    std::cmp::Ordering::Equal
+                })
+            });
+            if let Some(message) = failed {
+                return fail(ErrorKind::Type, message);
+            }
+        }
+        "reverse" => items.reverse(),
+        "clear" => items.clear(),
+        _ => return no_method("Vec", name),
+    }
+    Ok(Value::Unit)
+}
+
+// MapKey::new でイテレータなど中身の変わる値はキーにできないようにしている
+#[allow(clippy::mutable_key_type)]
+fn map_method(recv: &mut Value, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let Value::Map(map) = recv else {
+        unreachable!()
+    };
+    match name {
+        "len" => return Ok(Value::Int(map.len() as i64)),
+        "is_empty" => return Ok(Value::Bool(map.is_empty())),
+        "contains_key" => {
+            let key = MapKey::new(expect_arg(&args, 0, name)?.clone())?;
+            return Ok(Value::Bool(map.contains_key(&key)));
+        }
+        "get" => {
+            let key = MapKey::new(expect_arg(&args, 0, name)?.clone())?;
+            return Ok(Value::Option(map.get(&key).cloned().map(Box::new)));
+        }
+        "keys" => {
+            let keys = map.keys().map(|k| k.0.clone()).collect();
+            return Ok(Value::Iter(into_iter(Value::Array(Rc::new(keys)))?));
+        }
+        "values" => {
+            let values = map.values().cloned().collect();
+            return Ok(Value::Iter(into_iter(Value::Array(Rc::new(values)))?));
+        }
+        "iter" | "into_iter" => return Ok(Value::Iter(into_iter(Value::Map(map.clone()))?)),
+        _ => {}
+    }
+    // ここから先はマップを書き換えるメソッド
+    let map = Rc::make_mut(map);
+    let v = match name {
+        // 以前の値を返す
+        "insert" => {
+            let key = MapKey::new(expect_arg(&args, 0, name)?.clone())?;
+            let old = map.insert(key, expect_arg(&args, 1, name)?.clone());
+            Value::Option(old.map(Box::new))
+        }
+        "remove" => {
+            let key = MapKey::new(expect_arg(&args, 0, name)?.clone())?;
+            Value::Option(map.remove(&key).map(Box::new))
+        }
+        "clear" => {
+            map.clear();
+            Value::Unit
+        }
+        _ => return no_method("HashMap", name),
+    };
+    Ok(v)
+}
+
+// MapKey::new でイテレータなど中身の変わる値はキーにできないようにしている
+#[allow(clippy::mutable_key_type)]
+fn set_method(recv: &mut Value, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let Value::Set(set) = recv else {
+        unreachable!()
+    };
+    match name {
+        "len" => return Ok(Value::Int(set.len() as i64)),
+        "is_empty" => return Ok(Value::Bool(set.is_empty())),
+        "contains" => {
+            let key = MapKey::new(expect_arg(&args, 0, name)?.clone())?;
+            return Ok(Value::Bool(set.contains(&key)));
+        }
+        "iter" | "into_iter" => return Ok(Value::Iter(into_iter(Value::Set(set.clone()))?)),
+        _ => {}
+    }
+    // ここから先は集合を書き換えるメソッド
+    let set = Rc::make_mut(set);
+    let v = match name {
+        "insert" => Value::Bool(set.insert(MapKey::new(expect_arg(&args, 0, name)?.clone())?)),
+        "remove" => {
+            let key = MapKey::new(expect_arg(&args, 0, name)?.clone())?;
+            Value::Bool(set.remove(&key))
+        }
+        "clear" => {
+            set.clear();
+            Value::Unit
+        }
+        _ => return no_method("HashSet", name),
+    };
+    Ok(v)
+}
+
+fn option_method(
+    recv: &mut Value,
+    name: &str,
+    args: Vec<Value>,
+    call: &Call,
+) -> Result<Value, RuntimeError> {
+    let Value::Option(opt) = recv else {
+        unreachable!()
+    };
+    let v = match name {
+        "is_some" => Value::Bool(opt.is_some()),
+        "is_none" => Value::Bool(opt.is_none()),
+        // opt.take(): 中身を取り出して None にする
+        "take" => Value::Option(opt.take()),
+        "unwrap" => match opt {
+            Some(v) => (**v).clone(),
+            None => return fail(ErrorKind::Unwrap, "None に対して unwrap が呼ばれました"),
+        },
+        "expect" => match opt {
+            Some(v) => (**v).clone(),
+            None => return fail(ErrorKind::Unwrap, expect_arg(&args, 0, name)?.to_string()),
+        },
+        "unwrap_or" => match opt {
+            Some(v) => (**v).clone(),
+            None => expect_arg(&args, 0, name)?.clone(),
+        },
+        "unwrap_or_else" => match opt {
+            Some(v) => (**v).clone(),
+            None => call(expect_arg(&args, 0, name)?, Vec::new())?,
+        },
+        "map" => match opt {
+            Some(v) => Value::some(call(expect_arg(&args, 0, name)?, vec![(**v).clone()])?),
+            None => Value::none(),
+        },
+        "and_then" => match opt {
+            Some(v) => call(expect_arg(&args, 0, name)?, vec![(**v).clone()])?,
+            None => Value::none(),
+        },
+        "ok_or" => match opt {
+            Some(v) => Value::ok((**v).clone()),
+            None => Value::err(expect_arg(&args, 0, name)?.clone()),
+        },
+        _ => return no_method("Option", name),
+    };
+    Ok(v)
+}
+
+fn result_method(
+    recv: &mut Value,
+    name: &str,
+    args: Vec<Value>,
+    call: &Call,
+) -> Result<Value, RuntimeError> {
+    let Value::Result(res) = recv else {
+        unreachable!()
+    };
+    let v = match name {
+        "is_ok" => Value::Bool(res.is_ok()),
+        "is_err" => Value::Bool(res.is_err()),
+        "ok" => Value::Option(res.clone().ok()),
+        "err" => Value::Option(res.clone().err()),
+        "unwrap" => match res {
+            Ok(v) => (**v).clone(),
+            Err(e) => {
+                return fail(
+                    ErrorKind::Unwrap,
+                    format!("Err に対して unwrap が呼ばれました: {:?}", e),
+                );
+            }
+        },
+        "expect" => match res {
+            Ok(v) => (**v).clone(),
+            Err(e) => {
+                let message = format!("{}: {:?}", expect_arg(&args, 0, name)?, e);
+                return fail(ErrorKind::Unwrap, message);
+            }
+        },
+        "unwrap_or" => match res {
+            Ok(v) => (**v).clone(),
+            Err(_) => expect_arg(&args, 0, name)?.clone(),
+        },
+        "unwrap_or_else" => match res {
+            Ok(v) => (**v).clone(),
+            Err(e) => call(expect_arg(&args, 0, name)?, vec![(**e).clone()])?,
+        },
+        "map" => match res {
+            Ok(v) => Value::ok(call(expect_arg(&args, 0, name)?, vec![(**v).clone()])?),
+            Err(_) => recv.clone(),
+        },
+        "map_err" => match res {
+            Ok(_) => recv.clone(),
+            Err(e) => Value::err(call(expect_arg(&args, 0, name)?, vec![(**e).clone()])?),
+        },
+        "and_then" => match res {
+            Ok(v) => call(expect_arg(&args, 0, name)?, vec![(**v).clone()])?,
+            Err(_) => recv.clone(),
+        },
+        _ => return no_method("Result", name),
+    };
+    Ok(v)
+}
+
+// イテレータのメソッド（アダプタは元のイテレータを消費する）
+fn iter_method(it: Rc<RefCell<Iter>>, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let adapted = match name {
+        "iter" | "into_iter" => return Ok(Value::Iter(it)),
+        "next" => return Ok(Value::Option(it.borrow_mut().next().map(Box::new))),
+        "len" => return Ok(Value::Int(it.borrow().len() as i64)),
+        "collect" => {
+            // 要素を1つ取り出すごとに1ステップ数える
+            limits::reserve_array(it.borrow().size_hint().0)?;
+            let mut items = Vec::new();
+            for v in take(&it) {
+                limits::tick()?;
+                limits::check_len(items.len() + 1)?;
+                items.push(v);
+            }
+            return Ok(Value::Array(Rc::new(items)));
+        }
+        "rev" => Iter::Rev(Box::new(take(&it))),
+        "step_by" => {
+            let step = expect_int(&args, 0, name)?;
+            if step <= 0 {
+                return fail(
+                    ErrorKind::Type,
+                    format!("step_by の刻み幅は1以上である必要があります: {}", step),
+                );
+            }
+            Iter::StepBy {
+                inner: Box::new(take(&it)),
+                step: step as usize,
+                first_take: true,
+            }
+        }
+        "enumerate" => Iter::Enumerate {
+            inner: Box::new(take(&it)),
+            count: 0,
+        },
+        _ => return no_method("Iterator", name),
+    };
+    Ok(Value::Iter(Rc::new(RefCell::new(adapted))))
+}
+
+// 共有されたイテレータの中身を取り出す（Rustのムーブに相当）
+fn take(it: &Rc<RefCell<Iter>>) -> Iter {
+    std::mem::replace(&mut *it.borrow_mut(), Iter::seq(Rc::default()))
+}
+
+fn expect_arg<'a>(
+    args: &'a [Value],
+    index: usize,
+    method: &str,
+) -> Result<&'a Value, RuntimeError> {
+    args.get(index)
+        .ok_or_else(|| RuntimeError::new(ErrorKind::Type, format!("{} の引数が足りません", method)))
+}
+
+fn expect_int(args: &[Value], index: usize, method: &str) -> Result<i64, RuntimeError> {
+    let arg = expect_arg(args, index, method)?;
+    arg.as_i64().ok_or_else(|| {
+        RuntimeError::new(
+            ErrorKind::Type,
+            format!(
+                "{} の引数は i64 に収まる整数である必要があります: {:?}",
+                method, arg
+            ),
+        )
+    })
+}
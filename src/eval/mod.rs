@@ -0,0 +1,1826 @@
+#[cfg(feature = "serde")]
+mod bridge;
+mod capability;
+mod coerce;
+mod error;
+mod format;
+mod interpreter;
+pub mod iter;
+mod limits;
+mod methods;
+mod native;
+mod place;
+mod stream;
+mod vm;
+
+use crate::ast::{BinOp, Expr, FmtPiece, PanicKind, Pattern, Resolved, Stmt, UnaryOp};
+use crate::check::ty::Ty;
+use crate::float::{self, FloatTy};
+use crate::int::{self, ArithError, IntTy};
+use crate::lexer::Span;
+use crate::resolve::Binding;
+use crate::value::{Closure, Value};
+#[cfg(feature = "serde")]
+pub use bridge::{PathItem, SerdeError, from_nasl, to_nasl};
+pub use capability::{Capabilities, PathAccess};
+pub(crate) use error::fail;
+pub use error::{ErrorKind, Frame, RuntimeError};
+pub use interpreter::{Interpreter, TestResult};
+pub use limits::{CountingAlloc, Limits, MAX_ARRAY_LEN};
+pub use native::{FromNasl, IntoArgs, IntoNasl, NativeFn, NativeFunction, NativeModule};
+use place::Step;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+pub use stream::OutputBuffer;
+pub use vm::Program;
+
+pub type StdFunc = fn(Vec<Value>) -> Result<Value, RuntimeError>;
+
+// 関数呼び出しの深さの既定の上限。スタックは足りなくなると伸ばすので、どのスレッドで
+// 評価してもあふれない。デバッグビルドでは nasl の呼び出し1段でスタックを数十KB使う
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+// with_stack で評価器を動かすスレッドのスタックの大きさの既定値（1GiB）。
+// 実際に使った分しかメモリは割り当てられない。大きくしておくとスタックを伸ばす回数が減る
+pub const DEFAULT_STACK_SIZE: usize = 1 << 30;
+
+thread_local! {
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_DEPTH) };
+}
+
+// 関数呼び出しの深さの上限を変える。超えると StackOverflow の実行時エラーになる
+pub fn set_max_depth(depth: usize) {
+    MAX_DEPTH.with(|d| d.set(depth));
+}
+
+pub fn max_depth() -> usize {
+    MAX_DEPTH.with(|d| d.get())
+}
+
+// ネイティブのスタックの残りがこれより少なくなったら、新しい領域に切り替えて続ける
+const STACK_RED_ZONE: usize = 1 << 20;
+// 切り替えるときに新しく確保するスタックの大きさ
+const STACK_GROWTH: usize = 64 << 20;
+
+// 再帰して評価する前に呼ぶ。スレッドのスタックの大きさによらず、
+// 呼び出しの深さが max_depth に達するまでスタックはあふれない。
+// 構文解析・名前の解決・型検査も入れ子の式をたどる前に呼ぶ
+pub(crate) fn grow_stack<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, f)
+}
+
+// 大きなスタックを持つスレッドで f を実行して結果を返す。
+// set_overflow・set_max_depth はスレッドごとの設定なので f の中で行う
+pub fn with_stack<T: Send + 'static>(size: usize, f: impl FnOnce() -> T + Send + 'static) -> T {
+    std::thread::Builder::new()
+        .name("nasl".to_string())
+        .stack_size(size)
+        .spawn(f)
+        .expect("評価用のスレッドを作れません")
+        .join()
+        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
+
+// ユーザー定義関数。型注釈は整数リテラルを注釈の型に合わせるのに使う（省略時は Unknown）
+// impl のメソッドは "型名::メソッド名" で登録する
+struct Func {
+    params: Vec<Pattern>,
+    param_tys: Vec<Ty>,
+    ret: Ty,
+    body: Expr,
+    // &mut self を取るメソッドか（呼び出し後の self を呼び出し元に書き戻す）
+    mut_self: bool,
+}
+
+// 関数の表。名前の解決で振った番号（Binding::Global）でも引けるよう、登録した順に並べる
+#[derive(Default)]
+struct FuncTable {
+    names: Vec<String>,
+    // 番号だけ先に振って、まだ定義していない関数は None
+    funcs: Vec<Option<Func>>,
+    index: HashMap<String, usize>,
+}
+
+impl FuncTable {
+    fn get(&self, name: &str) -> Option<&Func> {
+        self.index.get(name).and_then(|&i| self.at(i))
+    }
+
+    fn at(&self, i: usize) -> Option<&Func> {
+        self.funcs.get(i)?.as_ref()
+    }
+
+    fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // 関数の番号。初めての名前なら最後に加える
+    fn reserve(&mut self, name: &str) -> usize {
+        if let Some(&i) = self.index.get(name) {
+            return i;
+        }
+        self.index.insert(name.to_string(), self.names.len());
+        self.names.push(name.to_string());
+        self.funcs.push(None);
+        self.names.len() - 1
+    }
+
+    fn insert(&mut self, name: String, func: Func) {
+        let i = self.reserve(&name);
+        self.funcs[i] = Some(func);
+    }
+}
+
+impl std::ops::Index<&str> for FuncTable {
+    type Output = Func;
+
+    fn index(&self, name: &str) -> &Func {
+        self.get(name).expect("定義済みの関数です")
+    }
+}
+
+// トップレベルの定義（関数・構造体・列挙型・トレイト）と標準関数
+struct Globals {
+    funcs: FuncTable,
+    // 構造体名 → フィールド（名前, 型）の並び
+    structs: HashMap<String, Vec<(String, String)>>,
+    // "列挙型名::バリアント名" → (列挙型名, 定義順, 中身の数)
+    variants: HashMap<String, (Rc<str>, usize, usize)>,
+    // トレイト名 → メソッド定義（既定の実装を impl にコピーするのに使う）
+    traits: HashMap<String, (Vec<Stmt>, Vec<String>)>,
+    std_funcs: HashMap<String, StdFunc>,
+    // 埋め込む側が登録した関数。メソッドは "型名::メソッド名"、モジュールの関数は "モジュール名::関数名"
+    natives: HashMap<String, NativeFn>,
+    // 今の関数呼び出しの深さ
+    depth: Cell<usize>,
+}
+
+// 関数呼び出しの深さを1つ増やし、抜けるときに戻す
+struct Depth<'a>(&'a Cell<usize>);
+
+impl Drop for Depth<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
+// ネイティブのスタックがあふれる前に、上限を超えた呼び出しを実行時エラーにする
+fn enter(globals: &Globals) -> Result<Depth<'_>, RuntimeError> {
+    let depth = globals.depth.get();
+    let max = max_depth();
+    if depth >= max {
+        return fail(
+            ErrorKind::StackOverflow,
+            format!("関数呼び出しの深さが上限 {} を超えました", max),
+        );
+    }
+    globals.depth.set(depth + 1);
+    Ok(Depth(&globals.depth))
+}
+
+// 評価を途中で打ち切る制御フロー。`return` は関数呼び出しの境界まで、
+// 実行時エラーは呼び出し元の関数を積みながら eval_stmts まで巻き戻る。
+enum Flow {
+    Return(Value),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Flow {
+    fn from(e: RuntimeError) -> Self {
+        Flow::Error(e)
+    }
+}
+
+impl From<ArithError> for Flow {
+    fn from(e: ArithError) -> Self {
+        Flow::Error(e.into())
+    }
+}
+
+// 変数環境: 関数（クロージャ）ごとのフレームに、名前の解決で振ったスロットの順に変数を積む。
+// ブロックを抜けるときは、入ったときの数まで戻す
+struct Env {
+    // 外側の関数ほど前。最後が今の関数のフレーム
+    frames: Vec<Vec<Value>>,
+}
+
+impl Env {
+    fn new() -> Self {
+        Env {
+            frames: vec![Vec::new()],
+        }
+    }
+
+    // クロージャの本体の環境。取り込んだ外側の関数の変数の内側に新しいフレームを置く
+    fn closure(captured: &[Vec<Value>]) -> Self {
+        let mut frames = captured.to_vec();
+        frames.push(Vec::new());
+        Env { frames }
+    }
+
+    fn frame(&mut self) -> &mut Vec<Value> {
+        self.frames.last_mut().expect("フレームがありません")
+    }
+
+    // 今のフレームの変数の数（reset でここまで戻す）
+    fn mark(&self) -> usize {
+        self.frames.last().map_or(0, Vec::len)
+    }
+
+    fn reset(&mut self, mark: usize) {
+        self.frame().truncate(mark);
+    }
+
+    fn define(&mut self, value: Value) {
+        self.frame().push(value);
+    }
+
+    fn get(&self, depth: usize, slot: usize) -> Option<&Value> {
+        let i = self.frames.len().checked_sub(depth + 1)?;
+        self.frames[i].get(slot)
+    }
+
+    fn get_mut(&mut self, depth: usize, slot: usize) -> Option<&mut Value> {
+        let i = self.frames.len().checked_sub(depth + 1)?;
+        self.frames[i].get_mut(slot)
+    }
+}
+
+// トップレベルの変数。評価をまたいで残り、次の評価の名前の解決では
+// トップレベルの文を囲む関数の変数（名前の順にスロットを振る）になる
+#[derive(Default)]
+struct Vars {
+    names: Vec<String>,
+    values: Vec<Value>,
+}
+
+impl Vars {
+    fn get(&self, name: &str) -> Option<&Value> {
+        let i = self.names.iter().rposition(|n| n == name)?;
+        self.values.get(i)
+    }
+
+    fn set(&mut self, name: &str, value: Value) {
+        match self.names.iter().rposition(|n| n == name) {
+            Some(i) => self.values[i] = value,
+            None => {
+                self.names.push(name.to_string());
+                self.values.push(value);
+            }
+        }
+    }
+
+    // トップレベルの let で作った変数を加える。同じ名前の前の変数はもう見えないので除く
+    fn extend(&mut self, vars: impl IntoIterator<Item = (String, Value)>) {
+        for (name, value) in vars {
+            if let Some(i) = self.names.iter().position(|n| *n == name) {
+                self.names.remove(i);
+                self.values.remove(i);
+            }
+            self.names.push(name);
+            self.values.push(value);
+        }
+    }
+}
+
+fn print_fn(args: Vec<Value>) -> Result<Value, RuntimeError> {
+    capability::stdout()?;
+    for v in args {
+        stream::print(&v.to_string())?;
+    }
+    Ok(Value::Unit)
+}
+
+fn read_to_string(args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let path = match one_arg(args, "read_to_string")? {
+        Value::Str(path) => path,
+        other => {
+            return fail(
+                ErrorKind::Type,
+                format!(
+                    "read_to_string の引数は String である必要があります: {:?}",
+                    other
+                ),
+            );
+        }
+    };
+    capability::read(&path)?;
+    Ok(match std::fs::read_to_string(&path) {
+        Ok(s) => Value::ok(Value::Str(s)),
+        Err(e) => Value::err(Value::Str(format!("{} を読み込めません: {}", path, e))),
+    })
+}
+
+fn write(args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (path, contents) = match <[Value; 2]>::try_from(args) {
+        Ok([Value::Str(path), Value::Str(contents)]) => (path, contents),
+        _ => {
+            return fail(
+                ErrorKind::Type,
+                "write の引数はパスと内容の String 2つです".to_string(),
+            );
+        }
+    };
+    capability::write(&path)?;
+    Ok(match std::fs::write(&path, contents) {
+        Ok(()) => Value::ok(Value::Unit),
+        Err(e) => Value::err(Value::Str(format!("{} に書き込めません: {}", path, e))),
+    })
+}
+
+fn env_var(args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let Value::Str(name) = one_arg(args, "var")? else {
+        return fail(
+            ErrorKind::Type,
+            "var の引数は String である必要があります".to_string(),
+        );
+    };
+    capability::env(&name)?;
+    Ok(match std::env::var(&name) {
+        Ok(v) => Value::ok(Value::Str(v)),
+        Err(e) => Value::err(Value::Str(format!(
+            "環境変数 {} を読み込めません: {}",
+            name, e
+        ))),
+    })
+}
+
+fn exit(args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let code = match one_arg(args, "exit")?.as_i64() {
+        Some(code) => code,
+        None => {
+            return fail(
+                ErrorKind::Type,
+                "exit の引数は整数である必要があります".to_string(),
+            );
+        }
+    };
+    capability::process()?;
+    std::process::exit(code as i32)
+}
+
+// UNIX 時刻（ミリ秒）
+fn now_millis(args: Vec<Value>) -> Result<Value, RuntimeError> {
+    check_arity("now_millis", 0, args.len())?;
+    capability::clock()?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(Value::Int(now.as_millis() as i64))
+}
+
+// 文字列をi64に変換する（input・str.parse共通）
+pub(crate) fn parse_int(s: &str) -> Value {
+    match s.parse::<i64>() {
+        Ok(n) => Value::ok(Value::Int(n)),
+        Err(_) => Value::err(Value::Str(format!("{:?} を i64 に変換できません", s))),
+    }
+}
+
+fn one_arg(args: Vec<Value>, name: &str) -> Result<Value, RuntimeError> {
+    let Ok([arg]): Result<[Value; 1], _> = args.try_into() else {
+        return fail(ErrorKind::Type, format!("{} の引数は1つです", name));
+    };
+    Ok(arg)
+}
+
+pub fn get_std_funcs() -> HashMap<String, StdFunc> {
+    let mut map = HashMap::new();
+    map.insert("print".to_string(), print_fn as StdFunc);
+    map.insert("input".to_string(), |_args| {
+        capability::stdin()?;
+        match stream::read_line() {
+            // 入力値をi64に変換して返す
+            Ok(buf) => Ok(parse_int(buf.trim())),
+            Err(e) => Ok(Value::err(Value::Str(format!(
+                "入力を読み込めません: {}",
+                e
+            )))),
+        }
+    });
+    map.insert("fs::read_to_string".to_string(), read_to_string);
+    map.insert("std::fs::read_to_string".to_string(), read_to_string);
+    map.insert("fs::write".to_string(), write);
+    map.insert("std::fs::write".to_string(), write);
+    map.insert("env::var".to_string(), env_var);
+    map.insert("std::env::var".to_string(), env_var);
+    map.insert("process::exit".to_string(), exit);
+    map.insert("std::process::exit".to_string(), exit);
+    map.insert("time::now_millis".to_string(), now_millis);
+    map.insert("Some".to_string(), |args| {
+        Ok(Value::some(one_arg(args, "Some")?))
+    });
+    map.insert("Ok".to_string(), |args| Ok(Value::ok(one_arg(args, "Ok")?)));
+    map.insert("Err".to_string(), |args| {
+        Ok(Value::err(one_arg(args, "Err")?))
+    });
+    map.insert("Option::Some".to_string(), |args| {
+        Ok(Value::some(one_arg(args, "Some")?))
+    });
+    map.insert("Result::Ok".to_string(), |args| {
+        Ok(Value::ok(one_arg(args, "Ok")?))
+    });
+    map.insert("Result::Err".to_string(), |args| {
+        Ok(Value::err(one_arg(args, "Err")?))
+    });
+    map.insert("Vec::new".to_string(), |_args| {
+        Ok(Value::Array(Rc::default()))
+    });
+    map.insert("Vec::with_capacity".to_string(), |_args| {
+        Ok(Value::Array(Rc::default()))
+    });
+    map.insert("HashMap::new".to_string(), |_args| {
+        Ok(Value::Map(Rc::default()))
+    });
+    map.insert("HashSet::new".to_string(), |_args| {
+        Ok(Value::Set(Rc::default()))
+    });
+    map
+}
+
+// スクリプトを評価して最後の文の値を返す。スクリプトの誤りは実行時エラーとして返す
+pub fn eval_stmts(stmts: &[Stmt]) -> Result<Value, RuntimeError> {
+    Interpreter::new().eval(stmts)
+}
+
+// 関数・構造体・列挙型・トレイト・impl の定義を登録する
+fn define(globals: &mut Globals, stmt: &Stmt) {
+    match stmt {
+        Stmt::FuncDef { name, .. } => define_func(globals, name.clone(), stmt),
+        Stmt::StructDef { name, fields, .. } => {
+            globals.structs.insert(name.clone(), fields.clone());
+        }
+        Stmt::EnumDef { name, variants, .. } => {
+            let enum_name: Rc<str> = Rc::from(name.as_str());
+            for (i, (variant, fields)) in variants.iter().enumerate() {
+                globals.variants.insert(
+                    format!("{}::{}", name, variant),
+                    (enum_name.clone(), i, fields.len()),
+                );
+            }
+        }
+        Stmt::TraitDef {
+            name,
+            methods,
+            required,
+            ..
+        } => {
+            globals
+                .traits
+                .insert(name.clone(), (methods.clone(), required.clone()));
+        }
+        Stmt::ImplDef {
+            trait_name,
+            target,
+            methods,
+            ..
+        } => {
+            let key = Ty::parse(target).key().unwrap_or_else(|| target.clone());
+            let mut defined = Vec::new();
+            for m in methods {
+                if let Stmt::FuncDef { name, .. } = m {
+                    define_func(globals, format!("{}::{}", key, name), m);
+                    defined.push(name.clone());
+                }
+            }
+            // impl で書かれなかったメソッドはトレイトの既定の実装を使う
+            let trait_name = trait_name
+                .as_deref()
+                .map(|t| t.split('<').next().unwrap_or(t));
+            let Some((trait_methods, required)) = trait_name.and_then(|t| globals.traits.get(t))
+            else {
+                return;
+            };
+            let mut defaults = Vec::new();
+            for m in trait_methods {
+                if let Stmt::FuncDef { name, .. } = m
+                    && !defined.contains(name)
+                    && !required.contains(name)
+                {
+                    let mut m = m.clone();
+                    m.replace_self(&key);
+                    defaults.push((format!("{}::{}", key, name), m));
+                }
+            }
+            for (name, m) in defaults {
+                define_func(globals, name, &m);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn define_func(globals: &mut Globals, name: String, stmt: &Stmt) {
+    let Stmt::FuncDef {
+        params,
+        param_tys,
+        ret,
+        body,
+        ..
+    } = stmt
+    else {
+        return;
+    };
+    let annotation = |t: &Option<String>| t.as_deref().map_or(Ty::Unknown, Ty::parse);
+    let func = Func {
+        params: params.clone(),
+        param_tys: param_tys.iter().map(annotation).collect(),
+        ret: annotation(ret),
+        body: *body.clone(),
+        mut_self: param_tys.first() == Some(&Some("&mut Self".to_string())),
+    };
+    globals.funcs.insert(name, func);
+}
+
+// ブロック内・トップレベル共通の文の評価
+fn eval_stmt(stmt: &Stmt, globals: &Globals, vars: &mut Env) -> Result<Value, Flow> {
+    match stmt {
+        Stmt::Expr(e) => eval_expr(e, globals, vars),
+        Stmt::Print(e) => {
+            let v = eval_expr(e, globals, vars)?;
+            globals.std_funcs["print"](vec![v.clone()])?;
+            Ok(v)
+        }
+        Stmt::Let {
+            pattern,
+            value,
+            ty,
+            span,
+            ..
+        } => {
+            let mut v = eval_expr(value, globals, vars)?;
+            if let Some(ty) = ty {
+                v = coerce::coerce(v, &Ty::parse(ty)).map_err(|e| e.at(*span))?;
+            }
+            if !declare_pattern(pattern, &v, vars).map_err(|e| e.at(*span))? {
+                let message = format!("let のパターンに一致しません: {:?}", v);
+                return Err(RuntimeError::new(ErrorKind::Pattern, message)
+                    .at(*span)
+                    .into());
+            }
+            Ok(Value::Unit)
+        }
+        Stmt::Error(msg, _) => {
+            stream::eprint(&format!("[解析エラー] {}", msg))?;
+            Ok(Value::Unit)
+        }
+        // ブロック内の関数定義・import・型定義は未対応
+        Stmt::FuncDef { .. }
+        | Stmt::Import(_)
+        | Stmt::StructDef { .. }
+        | Stmt::EnumDef { .. }
+        | Stmt::TraitDef { .. }
+        | Stmt::ImplDef { .. } => Ok(Value::Unit),
+    }
+}
+
+// 位置のない実行時エラーには、それを囲む位置のある式のうち一番内側の位置を付ける
+fn eval_expr(expr: &Expr, globals: &Globals, vars: &mut Env) -> Result<Value, Flow> {
+    grow_stack(|| {
+        limits::tick()
+            .map_err(Flow::from)
+            .and_then(|()| eval_expr_inner(expr, globals, vars))
+            .map_err(|flow| match (flow, expr_span(expr)) {
+                (Flow::Error(e), Some(span)) => Flow::Error(e.at(span)),
+                (flow, _) => flow,
+            })
+    })
+}
+
+fn expr_span(expr: &Expr) -> Option<Span> {
+    match expr {
+        Expr::Binary(.., span)
+        | Expr::Unary(.., span)
+        | Expr::TypedNumber(.., span)
+        | Expr::Ref(.., span)
+        | Expr::Var(_, span, _)
+        | Expr::Call(_, _, span, _)
+        | Expr::MethodCall(.., span)
+        | Expr::Index { span, .. }
+        | Expr::Cast(.., span)
+        | Expr::Format { span, .. }
+        | Expr::Panic { span, .. } => Some(*span),
+        _ => None,
+    }
+}
+
+fn eval_expr_inner(expr: &Expr, globals: &Globals, vars: &mut Env) -> Result<Value, Flow> {
+    let v = match expr {
+        Expr::Number(n) => Value::Int(*n),
+        Expr::TypedNumber(n, ty, _) => int_literal(*n, *ty, false)?,
+        Expr::Float(x, ty) => Value::from_float(ty.unwrap_or(FloatTy::F64), *x),
+        Expr::Bool(b) => Value::Bool(*b),
+        Expr::Char(c) => Value::Char(*c),
+        Expr::Str(s) => Value::Str(s.clone()),
+        Expr::Format { pieces, args, .. } => {
+            let mut values = Vec::new();
+            for a in args {
+                values.push(eval_expr(a, globals, vars)?);
+            }
+            format_pieces(pieces, &values)?
+        }
+        Expr::Binary(op, lhs, rhs, _) => {
+            let l = eval_expr(lhs, globals, vars)?;
+            // && と || は短絡評価
+            match op {
+                BinOp::And if !expect_bool(&l, "&&")? => return Ok(Value::Bool(false)),
+                BinOp::Or if expect_bool(&l, "||")? => return Ok(Value::Bool(true)),
+                _ => {}
+            }
+            let r = eval_expr(rhs, globals, vars)?;
+            let (l, r) = match (unsuffixed_literal(lhs), unsuffixed_literal(rhs)) {
+                (true, false) => (adapt_literal(l, &r)?, r),
+                (false, true) => {
+                    let r = adapt_literal(r, &l)?;
+                    (l, r)
+                }
+                _ => (l, r),
+            };
+            binary_op(*op, l, r)?
+        }
+        // -128i8 のように、負の数のリテラルは符号を含めて範囲を確かめる
+        Expr::Unary(UnaryOp::Neg, operand, _) if matches!(**operand, Expr::TypedNumber(..)) => {
+            let Expr::TypedNumber(n, ty, _) = **operand else {
+                unreachable!()
+            };
+            int_literal(n, ty, true)?
+        }
+        Expr::Ref(e, ..) | Expr::Vec(e) => eval_expr(e, globals, vars)?,
+        Expr::Unary(op, operand, _) => unary_op(*op, eval_expr(operand, globals, vars)?)?,
+        Expr::Var(name, _, resolved) => match resolved.get() {
+            Some(Binding::Local { depth, slot }) => match vars.get(depth, slot) {
+                Some(v) => v.clone(),
+                None => return fail(ErrorKind::Undefined, format!("未定義の変数: {}", name)),
+            },
+            binding => global_value(name, binding, globals)?,
+        },
+        Expr::Call(name, args, span, resolved) => {
+            let binding = resolved.get();
+            if let Some(Binding::Local { depth, slot }) = binding
+                && let Some(Value::Closure(f)) = vars.get(depth, slot)
+            {
+                let f = f.clone();
+                let mut arg_vals = Vec::new();
+                for a in args {
+                    arg_vals.push(eval_expr(a, globals, vars)?);
+                }
+                call_closure(&f, arg_vals, globals).map_err(|e| e.called(name, *span))?
+            } else if let Some(Binding::Global(i)) = binding
+                && let Some(f) = globals.funcs.at(i)
+            {
+                call_func(name, f, args, *span, globals, vars)?
+            } else if let Some(f) = globals.natives.get(name) {
+                let mut arg_vals = Vec::new();
+                for a in args {
+                    arg_vals.push(eval_expr(a, globals, vars)?);
+                }
+                f(arg_vals)?
+            } else if let Some(f) = globals.std_funcs.get(name) {
+                let mut arg_vals = Vec::new();
+                for a in args {
+                    arg_vals.push(eval_expr(a, globals, vars)?);
+                }
+                f(arg_vals)?
+            } else if let Some(f) = globals.funcs.get(name) {
+                call_func(name, f, args, *span, globals, vars)?
+            } else if let Some((enum_name, index, arity)) = globals.variants.get(name) {
+                if *arity != args.len() {
+                    return fail(
+                        ErrorKind::Type,
+                        format!("{} の要素の数が一致しません", name),
+                    );
+                }
+                let mut arg_vals = Vec::new();
+                for a in args {
+                    arg_vals.push(eval_expr(a, globals, vars)?);
+                }
+                enum_value(enum_name, *index, name, arg_vals)
+            } else {
+                return fail(ErrorKind::Undefined, format!("未定義の関数: {}", name));
+            }
+        }
+        // map.entry(k).or_insert(v) はマップの中の場所を返す
+        Expr::MethodCall(..) if entry_parts(expr).is_some() => {
+            match eval_place(expr, globals, vars)? {
+                Some((root, steps)) => place::resolve(lookup_mut(vars, root)?, &steps)?.clone(),
+                None => {
+                    let (map, key, default) = entry_parts(expr).unwrap();
+                    let mut tmp = eval_expr(map, globals, vars)?;
+                    let key = eval_expr(key, globals, vars)?;
+                    let default = eval_expr(default, globals, vars)?;
+                    place::resolve(&mut tmp, &[Step::Entry(key, default)])?.clone()
+                }
+            }
+        }
+        Expr::MethodCall(recv, name, args, span) => {
+            let mut arg_vals = Vec::new();
+            for a in args {
+                arg_vals.push(eval_expr(a, globals, vars)?);
+            }
+            // map・and_then などに渡されたクロージャの呼び出し
+            let call = |f: &Value, args: Vec<Value>| match f {
+                Value::Closure(f) => {
+                    call_closure(f, args, globals).map_err(|e| e.called("クロージャ", *span))
+                }
+                other => fail(
+                    ErrorKind::Type,
+                    format!("{} は呼び出せません", other.type_name()),
+                ),
+            };
+            // 変数などの場所に対する呼び出しはその場で書き換えられるようにする
+            let mut tmp;
+            let place = match eval_place(recv, globals, vars)? {
+                Some((root, steps)) if !is_global_name(recv) => {
+                    place::resolve(lookup_mut(vars, root)?, &steps)?
+                }
+                _ => {
+                    tmp = eval_expr(recv, globals, vars)?;
+                    &mut tmp
+                }
+            };
+            // impl で定義したメソッド・登録したメソッドを組み込みのメソッドより先に探す
+            let method = format!("{}::{}", place.type_name(), name);
+            if let Some(f) = globals.funcs.get(&method) {
+                call_self_method(&method, f, place, arg_vals, globals)
+                    .map_err(|e| e.called(&method, *span))?
+            } else if let Some(f) = globals.natives.get(&method) {
+                // 登録したメソッドは受け手を最初の引数として受け取る
+                arg_vals.insert(0, place.clone());
+                f(arg_vals)?
+            } else {
+                methods::call_method(place, name, arg_vals, &call)?
+            }
+        }
+        Expr::Tuple(items) => {
+            let mut values = Vec::new();
+            for item in items {
+                values.push(eval_expr(item, globals, vars)?);
+            }
+            if values.is_empty() {
+                Value::Unit
+            } else {
+                Value::Tuple(values)
+            }
+        }
+        Expr::StructInit(name, inits) => {
+            let Some(defs) = globals.structs.get(name) else {
+                return fail(ErrorKind::Undefined, format!("未定義の構造体: {}", name));
+            };
+            let mut values = Vec::new();
+            for (field, e, span) in inits {
+                let Some((_, ty)) = defs.iter().find(|(f, _)| f == field) else {
+                    return Err(no_field(name, field).at(*span).into());
+                };
+                let v = eval_expr(e, globals, vars)?;
+                let v = coerce::coerce(v, &Ty::parse(ty)).map_err(|e| e.at(*span))?;
+                values.push((field.clone(), v));
+            }
+            struct_value(name, defs, values)?
+        }
+        Expr::FieldAccess(target, field) => match eval_expr(target, globals, vars)? {
+            t @ Value::Host(_) => property(globals, t, field, None)?,
+            t => place::field_value(&t, field)?,
+        },
+        Expr::Array(items) => {
+            let mut values = Vec::new();
+            for item in items {
+                values.push(eval_expr(item, globals, vars)?);
+            }
+            Value::Array(Rc::new(values))
+        }
+        Expr::ArrayRepeat(value, count) => {
+            let v = eval_expr(value, globals, vars)?;
+            array_repeat(v, eval_expr(count, globals, vars)?)?
+        }
+        Expr::Index {
+            target,
+            index,
+            span,
+        } => {
+            let t = eval_expr(target, globals, vars)?;
+            let i = eval_expr(index, globals, vars)?;
+            place::index_value(&t, &i, *span)?
+        }
+        Expr::Assign { target, op, value } => {
+            let v = eval_expr(value, globals, vars)?;
+            let Some((root, steps)) = eval_place(target, globals, vars)? else {
+                return fail(ErrorKind::Type, format!("代入できない式です: {:?}", target));
+            };
+            let root = lookup_mut(vars, root)?;
+            // ホストの値のプロパティへの代入は登録した setter を呼ぶ
+            if let [prefix @ .., Step::Field(field)] = steps.as_slice()
+                && let obj @ Value::Host(_) = place::resolve(root, prefix)?
+            {
+                let obj = obj.clone();
+                let v = match op {
+                    Some(op) => binary_op(*op, property(globals, obj.clone(), field, None)?, v)?,
+                    None => v,
+                };
+                property(globals, obj, field, Some(v))?;
+                return Ok(Value::Unit);
+            }
+            let place = place::resolve(root, &steps)?;
+            *place = match op {
+                Some(op) => binary_op(*op, place.clone(), v)?,
+                None => v,
+            };
+            Value::Unit
+        }
+        Expr::Block(stmts) => {
+            let mark = vars.mark();
+            let mut last = Ok(Value::Unit);
+            for stmt in stmts {
+                last = eval_stmt(stmt, globals, vars);
+                if last.is_err() {
+                    break;
+                }
+            }
+            vars.reset(mark);
+            return last;
+        }
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            let c = eval_expr(cond, globals, vars)?;
+            if expect_bool(&c, "if")? {
+                eval_expr(then_branch, globals, vars)?
+            } else if let Some(else_b) = else_branch {
+                eval_expr(else_b, globals, vars)?
+            } else {
+                Value::Unit
+            }
+        }
+        Expr::For {
+            pattern,
+            iter: iterable,
+            body,
+        } => {
+            let mut source = Source::new(eval_expr(iterable, globals, vars)?, globals)?;
+            let span = expr_span(iterable).unwrap_or_default();
+            loop {
+                let Some(item) = source.next(globals, span)? else {
+                    break;
+                };
+                let mark = vars.mark();
+                let result = match declare_pattern(pattern, &item, vars) {
+                    Ok(true) => eval_expr(body, globals, vars),
+                    Ok(false) => fail(
+                        ErrorKind::Pattern,
+                        format!("forのパターンに一致しません: {:?}", item),
+                    ),
+                    Err(e) => Err(e.into()),
+                };
+                vars.reset(mark);
+                result?;
+            }
+            Value::Unit
+        }
+        Expr::Range {
+            start,
+            end,
+            inclusive,
+        } => {
+            let mut bound = |e: &Option<Box<Expr>>| -> Result<Option<i64>, Flow> {
+                match e {
+                    Some(e) => Ok(Some(range_bound(eval_expr(e, globals, vars)?)?)),
+                    None => Ok(None),
+                }
+            };
+            Value::Range {
+                start: bound(start)?,
+                end: bound(end)?,
+                inclusive: *inclusive,
+            }
+        }
+        Expr::Match { scrutinee, arms } => {
+            let v = eval_expr(scrutinee, globals, vars)?;
+            for (pattern, body) in arms {
+                let mark = vars.mark();
+                let result = match declare_pattern(pattern, &v, vars) {
+                    Ok(true) => Some(eval_expr(body, globals, vars)),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(e.into())),
+                };
+                vars.reset(mark);
+                if let Some(result) = result {
+                    return result;
+                }
+            }
+            Value::Unit
+        }
+        Expr::Return(value) => {
+            let v = match value {
+                Some(e) => eval_expr(e, globals, vars)?,
+                None => Value::Unit,
+            };
+            return Err(Flow::Return(v));
+        }
+        Expr::Try(e) => match try_value(eval_expr(e, globals, vars)?)? {
+            Ok(v) => v,
+            Err(v) => return Err(Flow::Return(v)),
+        },
+        Expr::Cast(e, ty, _) => {
+            let v = eval_expr(e, globals, vars)?;
+            cast(v, ty)?
+        }
+        Expr::Panic {
+            kind,
+            args,
+            text,
+            message,
+            ..
+        } => {
+            let mut values = Vec::new();
+            for a in args {
+                values.push(eval_expr(a, globals, vars)?);
+            }
+            let message = match message {
+                Some(m) => Some(eval_expr(m, globals, vars)?.to_string()),
+                None => None,
+            };
+            panic_macro(*kind, values, text, message)?;
+            Value::Unit
+        }
+        Expr::Closure { params, body } => Value::Closure(Rc::new(Closure {
+            params: params.clone(),
+            body: (**body).clone(),
+            captured: vars.frames.clone(),
+            code: None,
+        })),
+    };
+    Ok(v)
+}
+
+// 局所変数でない名前の値（関数・None・浮動小数点数の定数・中身のないバリアント）。
+// 名前の解決で関数に結びついた名前は番号で引き、それ以外は名前で探す
+fn global_value(
+    name: &str,
+    binding: Option<Binding>,
+    globals: &Globals,
+) -> Result<Value, RuntimeError> {
+    if name == "None" || name == "Option::None" {
+        return Ok(Value::none());
+    }
+    // 関数名は値として渡せる（map(double) など）
+    if let Some(f) = global_func(name, binding, globals) {
+        return Ok(Value::Closure(Rc::new(Closure {
+            params: f.params.clone(),
+            body: f.body.clone(),
+            captured: Vec::new(),
+            code: None,
+        })));
+    }
+    match (float::constant(name), globals.variants.get(name)) {
+        (Some((ty, x)), _) => Ok(Value::from_float(ty, x)),
+        (None, None) if let Some((ty, bits)) = int::constant(name) => Ok(Value::from_int(ty, bits)),
+        // 中身のないバリアント: Color::Red
+        (None, Some((enum_name, index, 0))) => Ok(enum_value(enum_name, *index, name, Vec::new())),
+        _ => fail(ErrorKind::Undefined, format!("未定義の変数: {}", name)),
+    }
+}
+
+// 名前の解決で関数に結びついた名前の関数。解決の結果がない名前（impl に写したトレイトの
+// 既定の実装の中の Self::new など）は名前で探す
+fn global_func<'g>(name: &str, binding: Option<Binding>, globals: &'g Globals) -> Option<&'g Func> {
+    match binding {
+        Some(Binding::Global(i)) => globals.funcs.at(i),
+        _ => globals.funcs.get(name),
+    }
+}
+
+// ユーザー定義関数の呼び出し。&mut x で渡した引数は、関数の中で変更した値を呼び出し元に書き戻す
+fn call_func(
+    name: &str,
+    f: &Func,
+    args: &[Expr],
+    span: Span,
+    globals: &Globals,
+    vars: &mut Env,
+) -> Result<Value, Flow> {
+    check_arity(name, f.params.len(), args.len())?;
+    let mut arg_vals = Vec::new();
+    for (a, ty) in args.iter().zip(&f.param_tys) {
+        arg_vals.push(coerce::coerce(eval_expr(a, globals, vars)?, ty)?);
+    }
+    let (v, env) = call_body(name, &f.params, &f.body, Env::new(), arg_vals, globals)
+        .and_then(|(v, env)| Ok((coerce::coerce(v, &f.ret)?, env)))
+        .map_err(|e| e.called(name, span))?;
+    // 引数の変数は引数の順にスロットに並ぶ
+    let mut slot = 0;
+    for (a, p) in args.iter().zip(&f.params) {
+        if let (Expr::Ref(target, true, _), Pattern::Bind(..)) = (a, p)
+            && let Some(v) = env.get(0, slot)
+            && let Some((root, steps)) = eval_place(target, globals, vars)?
+        {
+            *place::resolve(lookup_mut(vars, root)?, &steps)? = v.clone();
+        }
+        slot += p.names().len();
+    }
+    Ok(v)
+}
+
+// 接尾辞付きか i64 に収まらない整数リテラル（negative なら符号を含めて）の値。範囲外ならエラー。
+// 接尾辞のないものは型注釈に合わせる前の既定の型（u64 / u128 など）にする
+fn int_literal(n: u128, ty: Option<IntTy>, negative: bool) -> Result<Value, RuntimeError> {
+    let ty = ty.unwrap_or_else(|| IntTy::unsuffixed(n, negative));
+    match ty.literal(n, negative) {
+        Some(bits) => Ok(Value::from_int(ty, bits)),
+        None => fail(
+            ErrorKind::Overflow,
+            format!(
+                "リテラル {}{} は {} の範囲外です",
+                if negative { "-" } else { "" },
+                n,
+                ty.name()
+            ),
+        ),
+    }
+}
+
+fn format_pieces(pieces: &[FmtPiece], values: &[Value]) -> Result<Value, RuntimeError> {
+    let mut out = String::new();
+    for piece in pieces {
+        match piece {
+            FmtPiece::Lit(s) => out.push_str(s),
+            FmtPiece::Arg { index, spec } => match values.get(*index) {
+                Some(v) => {
+                    let s = format::format_value(v, spec)?;
+                    limits::reserve(out.len().saturating_add(s.len()))?;
+                    out.push_str(&s);
+                }
+                None => {
+                    return fail(
+                        ErrorKind::Type,
+                        format!("書式文字列の引数が足りません: {}", index),
+                    );
+                }
+            },
+            FmtPiece::Invalid(s) => {
+                return fail(
+                    ErrorKind::Type,
+                    format!("書式指定 {} には対応していません", s),
+                );
+            }
+        }
+    }
+    Ok(Value::Str(out))
+}
+
+fn unary_op(op: UnaryOp, v: Value) -> Result<Value, RuntimeError> {
+    if let (UnaryOp::Not, Value::Bool(b)) = (op, &v) {
+        return Ok(Value::Bool(!b));
+    }
+    if let (UnaryOp::Neg, Some((ty, x))) = (op, v.as_float()) {
+        return Ok(Value::from_float(ty, -x));
+    }
+    match (op, v.as_int()) {
+        (UnaryOp::Neg, Some((ty, bits))) if ty.is_signed() => {
+            Ok(Value::from_int(ty, int::neg(ty, bits)?))
+        }
+        (UnaryOp::Not, Some((ty, bits))) => Ok(Value::from_int(ty, ty.truncate(!bits))),
+        (UnaryOp::Deref, _) => Ok(v),
+        (op, _) => fail(
+            ErrorKind::Type,
+            format!("単項演算子 {:?} は {} に使えません", op, v.type_name()),
+        ),
+    }
+}
+
+fn no_field(name: &str, field: &str) -> RuntimeError {
+    RuntimeError::new(
+        ErrorKind::Undefined,
+        format!("構造体 {} にフィールド {} はありません", name, field),
+    )
+}
+
+// 初期化したフィールドを定義順に並べた構造体の値
+fn struct_value(
+    name: &str,
+    defs: &[(String, String)],
+    mut values: Vec<(String, Value)>,
+) -> Result<Value, RuntimeError> {
+    let mut fields = Vec::new();
+    for (field, _) in defs {
+        let Some(i) = values.iter().position(|(f, _)| f == field) else {
+            return fail(
+                ErrorKind::Type,
+                format!(
+                    "構造体 {} のフィールド {} が初期化されていません",
+                    name, field
+                ),
+            );
+        };
+        fields.push(values.swap_remove(i));
+    }
+    Ok(Value::Struct {
+        name: Rc::from(name),
+        fields: Rc::new(fields),
+    })
+}
+
+// [v; count]
+fn array_repeat(v: Value, count: Value) -> Result<Value, RuntimeError> {
+    match count.as_i64() {
+        Some(n) if n >= 0 => {
+            let n = n as usize;
+            limits::reserve_array(n)?;
+            limits::tick_n(n as u64)?;
+            Ok(Value::Array(Rc::new(vec![v; n])))
+        }
+        _ => fail(
+            ErrorKind::Type,
+            format!("配列の長さが不正です: {:?}", count),
+        ),
+    }
+}
+
+fn range_bound(v: Value) -> Result<i64, RuntimeError> {
+    match v.as_i64() {
+        Some(n) => Ok(n),
+        None => fail(
+            ErrorKind::Type,
+            format!("範囲の端は i64 に収まる整数である必要があります: {:?}", v),
+        ),
+    }
+}
+
+// for で回すもの。Iterator を実装したユーザー定義の型は next() を呼んで進める
+enum Source<'g> {
+    Iter(Rc<RefCell<iter::Iter>>),
+    User {
+        this: Value,
+        name: String,
+        next: &'g Func,
+    },
+}
+
+impl<'g> Source<'g> {
+    fn new(v: Value, globals: &'g Globals) -> Result<Self, RuntimeError> {
+        let name = format!("{}::next", v.type_name());
+        match globals.funcs.get(&name) {
+            Some(next) if user_iterator(&v) => Ok(Source::User {
+                this: v,
+                name,
+                next,
+            }),
+            _ => Ok(Source::Iter(iter::into_iter(v)?)),
+        }
+    }
+
+    // span は for で回す式の位置（next の呼び出し元として積む）
+    fn next(&mut self, globals: &Globals, span: Span) -> Result<Option<Value>, RuntimeError> {
+        match self {
+            // 本体から同じイテレータを触れるよう、借用はnextの間だけにする
+            Source::Iter(it) => Ok(it.borrow_mut().next()),
+            Source::User { this, name, next } => {
+                call_self_method(name, next, this, Vec::new(), globals)
+                    .and_then(|v| next_item(name, v))
+                    .map_err(|e| e.called(name, span))
+            }
+        }
+    }
+}
+
+// next() を呼んで回す値か（impl で next を定義できるのは構造体・列挙型だけ）
+fn user_iterator(v: &Value) -> bool {
+    matches!(v, Value::Struct { .. } | Value::Enum { .. })
+}
+
+// ユーザー定義の next() の戻り値から次の要素を取り出す
+fn next_item(name: &str, v: Value) -> Result<Option<Value>, RuntimeError> {
+    match v {
+        Value::Option(item) => Ok(item.map(|item| *item)),
+        other => fail(
+            ErrorKind::Type,
+            format!("{} は Option を返す必要があります: {:?}", name, other),
+        ),
+    }
+}
+
+// e? の値。Ok(中身) か、関数から返す None・Err（Err(値)）
+fn try_value(v: Value) -> Result<Result<Value, Value>, RuntimeError> {
+    match v {
+        Value::Option(Some(v)) | Value::Result(Ok(v)) => Ok(Ok(*v)),
+        v @ (Value::Option(None) | Value::Result(Err(_))) => Ok(Err(v)),
+        other => fail(
+            ErrorKind::Type,
+            format!("? は Option か Result にしか使えません: {:?}", other),
+        ),
+    }
+}
+
+fn call_closure(f: &Closure, args: Vec<Value>, globals: &Globals) -> Result<Value, RuntimeError> {
+    check_arity("クロージャ", f.params.len(), args.len())?;
+    let env = Env::closure(&f.captured);
+    Ok(call_body("クロージャ", &f.params, &f.body, env, args, globals)?.0)
+}
+
+fn check_arity(name: &str, expected: usize, given: usize) -> Result<(), RuntimeError> {
+    if expected != given {
+        return fail(
+            ErrorKind::Type,
+            format!(
+                "{} の引数の数が一致しません: {} 個必要ですが {} 個渡されました",
+                name, expected, given
+            ),
+        );
+    }
+    Ok(())
+}
+
+// self を取るメソッドの呼び出し。&mut self なら呼び出し後の self を呼び出し元に書き戻す
+fn call_self_method(
+    name: &str,
+    f: &Func,
+    recv: &mut Value,
+    args: Vec<Value>,
+    globals: &Globals,
+) -> Result<Value, RuntimeError> {
+    if !matches!(f.params.first(), Some(Pattern::Bind(p, _)) if p == "self") {
+        return fail(
+            ErrorKind::Type,
+            format!("{} は self を取らないのでメソッドとして呼べません", name),
+        );
+    }
+    check_arity(name, f.params.len() - 1, args.len())?;
+    let _depth = enter(globals)?;
+    let mut env = Env::new();
+    env.define(recv.clone());
+    for ((p, ty), v) in f.params[1..].iter().zip(&f.param_tys[1..]).zip(args) {
+        let v = coerce::coerce(v, ty)?;
+        if !declare_pattern(p, &v, &mut env)? {
+            return fail(
+                ErrorKind::Pattern,
+                format!("{} の引数がパターンに一致しません: {:?}", name, v),
+            );
+        }
+    }
+    let v = match eval_expr(&f.body, globals, &mut env) {
+        Ok(v) | Err(Flow::Return(v)) => v,
+        Err(Flow::Error(e)) => return Err(e),
+    };
+    // self は引数の最初のスロットにある
+    if f.mut_self
+        && let Some(this) = env.get(0, 0)
+    {
+        *recv = this.clone();
+    }
+    coerce::coerce(v, &f.ret)
+}
+
+fn enum_value(enum_name: &Rc<str>, index: usize, path: &str, fields: Vec<Value>) -> Value {
+    let variant = path.rsplit("::").next().unwrap_or(path);
+    Value::Enum {
+        name: enum_name.clone(),
+        index,
+        variant: Rc::from(variant),
+        fields: Rc::new(fields),
+    }
+}
+
+// 引数を束縛して本体を評価する（関数・クロージャ共通）。評価後の環境も返す
+fn call_body(
+    name: &str,
+    params: &[Pattern],
+    body: &Expr,
+    mut env: Env,
+    args: Vec<Value>,
+    globals: &Globals,
+) -> Result<(Value, Env), RuntimeError> {
+    let _depth = enter(globals)?;
+    for (p, v) in params.iter().zip(args.iter()) {
+        if !declare_pattern(p, v, &mut env)? {
+            return fail(
+                ErrorKind::Pattern,
+                format!("{} の引数がパターンに一致しません: {:?}", name, v),
+            );
+        }
+    }
+    // returnはここ（関数の境界）で受け止める
+    let v = match eval_expr(body, globals, &mut env) {
+        Ok(v) | Err(Flow::Return(v)) => v,
+        Err(Flow::Error(e)) => return Err(e),
+    };
+    Ok((v, env))
+}
+
+// ホストの値のプロパティの読み書き。埋め込む側が登録した getter（"型名.名前"）・
+// setter（"型名.名前="）を呼ぶ。value があれば代入する
+fn property(
+    globals: &Globals,
+    obj: Value,
+    name: &str,
+    value: Option<Value>,
+) -> Result<Value, RuntimeError> {
+    let key = match value {
+        Some(_) => format!("{}.{}=", obj.type_name(), name),
+        None => format!("{}.{}", obj.type_name(), name),
+    };
+    let Some(f) = globals.natives.get(&key) else {
+        let message = match value {
+            Some(_) => format!(
+                "{} のプロパティ {} には代入できません",
+                obj.type_name(),
+                name
+            ),
+            None => format!("{} にプロパティ {} はありません", obj.type_name(), name),
+        };
+        return fail(ErrorKind::Undefined, message);
+    };
+    let mut args = vec![obj];
+    args.extend(value);
+    f(args)
+}
+
+// 場所の根の変数（名前と、名前の解決の結果）
+type Root<'e> = (&'e str, &'e Resolved);
+
+// 場所を表す式（変数・添字アクセス）なら、根の変数とたどり方を返す
+fn eval_place<'e>(
+    expr: &'e Expr,
+    globals: &Globals,
+    vars: &mut Env,
+) -> Result<Option<(Root<'e>, Vec<Step>)>, Flow> {
+    match expr {
+        Expr::Var(name, _, resolved) => Ok(Some(((name, resolved), Vec::new()))),
+        Expr::Index {
+            target,
+            index,
+            span,
+        } => {
+            let Some((root, mut steps)) = eval_place(target, globals, vars)? else {
+                return Ok(None);
+            };
+            let i = eval_expr(index, globals, vars)?;
+            steps.push(Step::Index(i, *span));
+            Ok(Some((root, steps)))
+        }
+        Expr::FieldAccess(target, field) => {
+            let Some((root, mut steps)) = eval_place(target, globals, vars)? else {
+                return Ok(None);
+            };
+            steps.push(Step::Field(field.clone()));
+            Ok(Some((root, steps)))
+        }
+        Expr::Unary(UnaryOp::Deref, target, _) | Expr::Ref(target, ..) => {
+            eval_place(target, globals, vars)
+        }
+        Expr::MethodCall(..) => {
+            let Some((map, key, default)) = entry_parts(expr) else {
+                return Ok(None);
+            };
+            let Some((root, mut steps)) = eval_place(map, globals, vars)? else {
+                return Ok(None);
+            };
+            let key = eval_expr(key, globals, vars)?;
+            let default = eval_expr(default, globals, vars)?;
+            steps.push(Step::Entry(key, default));
+            Ok(Some((root, steps)))
+        }
+        _ => Ok(None),
+    }
+}
+
+// i64 に収まらない接尾辞なしのリテラル（-を付けたものも）か
+fn unsuffixed_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::TypedNumber(_, None, _) => true,
+        Expr::Unary(UnaryOp::Neg, e, _) => matches!(**e, Expr::TypedNumber(_, None, _)),
+        _ => false,
+    }
+}
+
+// 二項演算の一方が i64 に収まらない接尾辞なしのリテラルなら、その値をもう一方の整数型にする
+// （i64 に収まるリテラルの値は演算のときに相手の型に合わせる）
+fn adapt_literal(literal: Value, other: &Value) -> Result<Value, RuntimeError> {
+    match (literal.as_int(), other) {
+        (Some((from, bits)), Value::Sized(ty, _)) if from != *ty => {
+            Ok(Value::from_int(*ty, int::convert(from, bits, *ty)?))
+        }
+        _ => Ok(literal),
+    }
+}
+
+// 局所変数でない名前（i64::MAX などの定数・関数名）。メソッドの受け手なら場所ではなく値として扱う
+fn is_global_name(expr: &Expr) -> bool {
+    matches!(expr, Expr::Var(_, _, r) if !matches!(r.get(), Some(Binding::Local { .. })))
+}
+
+// <map>.entry(<key>).or_insert(<default>) を分解する
+fn entry_parts(expr: &Expr) -> Option<(&Expr, &Expr, &Expr)> {
+    let Expr::MethodCall(entry, or_insert, defaults, _) = expr else {
+        return None;
+    };
+    let Expr::MethodCall(map, name, keys, _) = &**entry else {
+        return None;
+    };
+    match (name.as_str(), or_insert.as_str(), &keys[..], &defaults[..]) {
+        ("entry", "or_insert", [key], [default]) => Some((map, key, default)),
+        _ => None,
+    }
+}
+
+fn lookup_mut<'a>(
+    vars: &'a mut Env,
+    (name, resolved): Root,
+) -> Result<&'a mut Value, RuntimeError> {
+    let var = match resolved.get() {
+        Some(Binding::Local { depth, slot }) => vars.get_mut(depth, slot),
+        _ => None,
+    };
+    var.ok_or_else(|| RuntimeError::new(ErrorKind::Undefined, format!("未定義の変数: {}", name)))
+}
+
+fn expect_bool(v: &Value, context: &str) -> Result<bool, RuntimeError> {
+    match v {
+        Value::Bool(b) => Ok(*b),
+        other => fail(
+            ErrorKind::Type,
+            format!("{} には bool が必要です: {:?}", context, other),
+        ),
+    }
+}
+
+fn binary_op(op: BinOp, l: Value, r: Value) -> Result<Value, RuntimeError> {
+    use std::cmp::Ordering;
+    let unsupported = |ty: &str| {
+        fail(
+            ErrorKind::Type,
+            format!("演算子 {:?} は {} に使えません", op, ty),
+        )
+    };
+    if let Some((ty, a, b)) = int_operands(&l, &r)? {
+        let v = match op {
+            BinOp::Eq => Value::Bool(a == b),
+            BinOp::Ne => Value::Bool(a != b),
+            BinOp::Lt => Value::Bool(ty.compare(a, b) == Ordering::Less),
+            BinOp::Le => Value::Bool(ty.compare(a, b) != Ordering::Greater),
+            BinOp::Gt => Value::Bool(ty.compare(a, b) == Ordering::Greater),
+            BinOp::Ge => Value::Bool(ty.compare(a, b) != Ordering::Less),
+            BinOp::And | BinOp::Or => return unsupported(ty.name()),
+            _ => Value::from_int(ty, int::arith(op, ty, a, b)?),
+        };
+        return Ok(v);
+    }
+    if let Some((ty, a, b)) = float_operands(&l, &r) {
+        let result = match op {
+            BinOp::Eq => return Ok(Value::Bool(a == b)),
+            BinOp::Ne => return Ok(Value::Bool(a != b)),
+            BinOp::Lt => return Ok(Value::Bool(a < b)),
+            BinOp::Le => return Ok(Value::Bool(a <= b)),
+            BinOp::Gt => return Ok(Value::Bool(a > b)),
+            BinOp::Ge => return Ok(Value::Bool(a >= b)),
+            BinOp::Add => a + b,
+            BinOp::Sub => a - b,
+            BinOp::Mul => a * b,
+            BinOp::Div => a / b,
+            BinOp::Rem => a % b,
+            BinOp::And | BinOp::Or => return unsupported(ty.name()),
+        };
+        // f32 同士の四則演算は f64 で計算してから丸めても結果は同じ
+        return Ok(Value::from_float(ty, result));
+    }
+    match op {
+        BinOp::Eq => return Ok(Value::Bool(l == r)),
+        BinOp::Ne => return Ok(Value::Bool(l != r)),
+        BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+            let ord = match (&l, &r) {
+                (Value::Char(a), Value::Char(b)) => a.cmp(b),
+                (Value::Str(a), Value::Str(b)) => a.cmp(b),
+                (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+                _ => {
+                    return fail(
+                        ErrorKind::Type,
+                        format!("{} と {} は比較できません", l.type_name(), r.type_name()),
+                    );
+                }
+            };
+            let result = match op {
+                BinOp::Lt => ord == Ordering::Less,
+                BinOp::Le => ord != Ordering::Greater,
+                BinOp::Gt => ord == Ordering::Greater,
+                _ => ord != Ordering::Less,
+            };
+            return Ok(Value::Bool(result));
+        }
+        _ => {}
+    }
+    match (op, l, r) {
+        // 連結した文字列を確保する前にメモリ使用量の上限を確かめる
+        (BinOp::Add, Value::Str(a), Value::Str(b)) => {
+            limits::reserve(a.len().saturating_add(b.len()))?;
+            Ok(Value::Str(a + &b))
+        }
+        (BinOp::And, Value::Bool(_), Value::Bool(b))
+        | (BinOp::Or, Value::Bool(_), Value::Bool(b)) => Ok(Value::Bool(b)),
+        (op, l, r) => fail(
+            ErrorKind::Type,
+            format!(
+                "演算子 {:?} は {} と {} に使えません",
+                op,
+                l.type_name(),
+                r.type_name()
+            ),
+        ),
+    }
+}
+
+// 整数同士の演算の型とビット列。型の違う整数は、i64 側（型注釈のないリテラル）を相手の型に合わせる
+fn int_operands(l: &Value, r: &Value) -> Result<Option<(IntTy, u128, u128)>, RuntimeError> {
+    let operands = match (l, r) {
+        (Value::Sized(ty, a), Value::Int(n)) => (*ty, *a, int::fit(*ty, *n)?),
+        (Value::Int(n), Value::Sized(ty, b)) => (*ty, int::fit(*ty, *n)?, *b),
+        _ => {
+            let (Some((t1, a)), Some((t2, b))) = (l.as_int(), r.as_int()) else {
+                return Ok(None);
+            };
+            if t1 != t2 {
+                return fail(
+                    ErrorKind::Type,
+                    format!("{} と {} の演算はできません", t1.name(), t2.name()),
+                );
+            }
+            (t1, a, b)
+        }
+    };
+    Ok(Some(operands))
+}
+
+// 浮動小数点数同士の演算の型と値。f32 と f64（型注釈のないリテラル）なら f32 に合わせる
+fn float_operands(l: &Value, r: &Value) -> Option<(FloatTy, f64, f64)> {
+    let ((t1, a), (t2, b)) = (l.as_float()?, r.as_float()?);
+    match (l, r) {
+        (Value::F32(_), Value::Float(_)) => Some((t1, a, t1.round(b))),
+        (Value::Float(_), Value::F32(_)) => Some((t2, t2.round(a), b)),
+        _ => Some((t1, a, b)),
+    }
+}
+
+// panic! などは必ず、assert! などは検査に失敗したら実行時エラーにする。
+// エラーの文言には検査した式の文字列と、assert_eq! などでは両辺の値を {:?} で入れる
+fn panic_macro(
+    kind: PanicKind,
+    values: Vec<Value>,
+    text: &[String],
+    message: Option<String>,
+) -> Result<(), RuntimeError> {
+    let with_message = |base: String| match &message {
+        Some(m) => format!("{}: {}", base, m),
+        None => base,
+    };
+    let (error_kind, base) = match (kind, values.as_slice()) {
+        (PanicKind::Panic, _) => {
+            let message = message.unwrap_or_else(|| "panic! が呼ばれました".to_string());
+            return fail(ErrorKind::Panic, message);
+        }
+        (PanicKind::Unreachable, _) => (
+            ErrorKind::Panic,
+            "到達しないはずのコードに到達しました".to_string(),
+        ),
+        (PanicKind::Todo, _) => (ErrorKind::Panic, "まだ実装されていません".to_string()),
+        (PanicKind::Assert, [cond]) => {
+            if expect_bool(cond, "assert!")? {
+                return Ok(());
+            }
+            (
+                ErrorKind::Assert,
+                format!("アサーション {} に失敗しました", text[0]),
+            )
+        }
+        (PanicKind::AssertEq | PanicKind::AssertNe, [l, r]) => {
+            let op = if kind == PanicKind::AssertEq {
+                BinOp::Eq
+            } else {
+                BinOp::Ne
+            };
+            if binary_op(op, l.clone(), r.clone())? == Value::Bool(true) {
+                return Ok(());
+            }
+            let message = format!(
+                "アサーション {} {} {} に失敗しました（左辺: {:?}, 右辺: {:?}）",
+                text[0],
+                if op == BinOp::Eq { "==" } else { "!=" },
+                text[1],
+                l,
+                r
+            );
+            (ErrorKind::Assert, message)
+        }
+        (kind, values) => {
+            return fail(
+                ErrorKind::Type,
+                format!("{:?} の引数の数が違います: {} 個", kind, values.len()),
+            );
+        }
+    };
+    fail(error_kind, with_message(base))
+}
+
+// <value> as <ty>（Rust と同じく、整数同士は切り詰め・符号拡張、bool・char は整数に、u8 は char に、
+// 小数から整数へは 0 に向けて切り捨てて範囲内に収める）
+fn cast(v: Value, ty: &str) -> Result<Value, RuntimeError> {
+    let unsupported = |v: &Value| {
+        fail(
+            ErrorKind::Type,
+            format!("{} を {} に変換できません", v.type_name(), ty),
+        )
+    };
+    if let Some(to) = FloatTy::from_name(ty) {
+        return match (v.as_float(), v.as_int()) {
+            (Some((_, x)), _) => Ok(Value::from_float(to, x)),
+            (_, Some((from, bits))) => Ok(Value::from_float(to, int::to_float(from, bits, to))),
+            _ => unsupported(&v),
+        };
+    }
+    if let (Some((_, x)), Some(to)) = (v.as_float(), IntTy::from_name(ty)) {
+        return Ok(Value::from_int(to, int::from_float(to, x)));
+    }
+    let from = match &v {
+        Value::Bool(b) => Some((IntTy::U8, *b as u128)),
+        Value::Char(c) => Some((IntTy::U32, *c as u128)),
+        _ => v.as_int(),
+    };
+    match (from, ty) {
+        (Some((from, bits)), _) if IntTy::from_name(ty).is_some() => {
+            let to = IntTy::from_name(ty).unwrap();
+            Ok(Value::from_int(to, int::cast(from, bits, to)))
+        }
+        (Some((from, bits)), "char") if !matches!(v, Value::Char(_)) => {
+            Ok(Value::Char(int::cast(from, bits, IntTy::U8) as u8 as char))
+        }
+        (Some(_), "char") => Ok(v),
+        _ => unsupported(&v),
+    }
+}
+
+// パターンで束縛する変数の置き場所（木をたどる評価では Env、VM ではスロット）
+trait Bind {
+    fn bind(&mut self, name: &str, value: Value);
+}
+
+// パターンの変数を、pattern.names() の順に並べたスロットに束縛する
+struct Declared<'a> {
+    slots: &'a mut [Value],
+    names: &'a [String],
+}
+
+impl Bind for Declared<'_> {
+    fn bind(&mut self, name: &str, value: Value) {
+        if let Some(i) = self.names.iter().position(|n| n == name) {
+            self.slots[i] = value;
+        }
+    }
+}
+
+// パターンに一致すれば、変数を名前の解決と同じ順（pattern.names() の順）に今のフレームに積む。
+// 一致しなければ何も積まずに false を返す
+fn declare_pattern(pattern: &Pattern, value: &Value, vars: &mut Env) -> Result<bool, RuntimeError> {
+    if let Pattern::Bind(..) = pattern {
+        vars.define(value.clone());
+        return Ok(true);
+    }
+    let names = pattern.names();
+    let mark = vars.mark();
+    vars.frame().resize(mark + names.len(), Value::Unit);
+    let mut declared = Declared {
+        slots: &mut vars.frame()[mark..],
+        names: &names,
+    };
+    let matched = bind_pattern(pattern, value, &mut declared);
+    if !matches!(matched, Ok(true)) {
+        vars.reset(mark);
+    }
+    matched
+}
+
+// パターンに一致すれば束縛を現在のスコープに追加してtrueを返す
+fn bind_pattern(
+    pattern: &Pattern,
+    value: &Value,
+    vars: &mut impl Bind,
+) -> Result<bool, RuntimeError> {
+    let matched = match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Bind(name, _), v) => {
+            vars.bind(name, v.clone());
+            true
+        }
+        (Pattern::Number(n), Value::Int(v)) => n == v,
+        (Pattern::Number(n), Value::Sized(ty, v)) => ty.from_i128(*n as i128) == Some(*v),
+        (Pattern::Bool(b), Value::Bool(v)) => b == v,
+        (Pattern::Char(c), Value::Char(v)) => c == v,
+        (Pattern::Str(s), Value::Str(v)) => s == v,
+        (Pattern::Tuple(pats), Value::Unit) => pats.is_empty(),
+        (Pattern::Tuple(pats), Value::Tuple(items)) => return bind_seq(pats, items, vars, false),
+        (Pattern::Slice(pats), Value::Array(items)) => return bind_seq(pats, items, vars, true),
+        (
+            Pattern::Struct {
+                name,
+                fields: pats,
+                rest,
+            },
+            Value::Struct {
+                name: vname,
+                fields,
+            },
+        ) => {
+            if **name != **vname || (!rest && pats.len() != fields.len()) {
+                return Ok(false);
+            }
+            for (field, p) in pats {
+                let Some((_, v)) = fields.iter().find(|(f, _)| f == field) else {
+                    return Err(no_field(name, field));
+                };
+                if !bind_pattern(p, v, vars)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        (Pattern::Variant(name, pats), value) => {
+            let inner = match (name.as_str(), value) {
+                ("None", Value::Option(None)) => return Ok(pats.is_empty()),
+                ("Some", Value::Option(Some(v)))
+                | ("Ok", Value::Result(Ok(v)))
+                | ("Err", Value::Result(Err(v))) => v,
+                (
+                    _,
+                    Value::Enum {
+                        variant, fields, ..
+                    },
+                ) => {
+                    if **variant != **name {
+                        return Ok(false);
+                    }
+                    if pats.len() != fields.len() {
+                        return fail(
+                            ErrorKind::Pattern,
+                            format!("{} のパターンの要素は{}つです", name, fields.len()),
+                        );
+                    }
+                    return bind_seq(pats, fields, vars, false);
+                }
+                _ => return Ok(false),
+            };
+            match &pats[..] {
+                [p] => return bind_pattern(p, inner, vars),
+                _ => {
+                    return fail(
+                        ErrorKind::Pattern,
+                        format!("{} のパターンの要素は1つです", name),
+                    );
+                }
+            }
+        }
+        _ => false,
+    };
+    Ok(matched)
+}
+
+// タプル・スライスパターンの要素ごとの照合。`..` は残りの要素に一致する
+fn bind_seq(
+    pats: &[Pattern],
+    items: &[Value],
+    vars: &mut impl Bind,
+    is_slice: bool,
+) -> Result<bool, RuntimeError> {
+    let Some(rest) = pats.iter().position(|p| matches!(p, Pattern::Rest(_))) else {
+        return if pats.len() == items.len() {
+            bind_all(pats.iter().zip(items), vars)
+        } else {
+            Ok(false)
+        };
+    };
+    let (before, after) = (&pats[..rest], &pats[rest + 1..]);
+    if before.len() + after.len() > items.len() {
+        return Ok(false);
+    }
+    let tail = items.len() - after.len();
+    if let Pattern::Rest(Some(name)) = &pats[rest] {
+        let middle = items[before.len()..tail].to_vec();
+        let v = if is_slice {
+            Value::Array(Rc::new(middle))
+        } else {
+            Value::Tuple(middle)
+        };
+        vars.bind(name, v);
+    }
+    Ok(bind_all(before.iter().zip(items), vars)?
+        && bind_all(after.iter().zip(&items[tail..]), vars)?)
+}
+
+// 一致しない組が見つかったところでやめる
+fn bind_all<'a>(
+    pairs: impl Iterator<Item = (&'a Pattern, &'a Value)>,
+    vars: &mut impl Bind,
+) -> Result<bool, RuntimeError> {
+    for (p, v) in pairs {
+        if !bind_pattern(p, v, vars)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
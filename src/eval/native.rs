@@ -0,0 +1,368 @@
+// 埋め込む側の Rust の関数を nasl から呼べるようにする。
+// 引数は FromNasl で Rust の型に、戻り値は IntoNasl で nasl の値に変換する
+use super::{ErrorKind, RuntimeError, check_arity, fail};
+use crate::int::IntTy;
+use crate::value::{Host, MapKey, Value};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+// 登録した関数。引数の数・型の検査は中で行う
+pub type NativeFn = Rc<dyn Fn(Vec<Value>) -> Result<Value, RuntimeError>>;
+
+// nasl の値から Rust の値への変換
+pub trait FromNasl: Sized {
+    // 変換できないときのエラーメッセージに使う型名
+    fn type_name() -> String;
+    fn from_nasl(v: &Value) -> Option<Self>;
+}
+
+// Rust の値から nasl の値への変換
+pub trait IntoNasl {
+    fn into_nasl(self) -> Value;
+}
+
+impl FromNasl for Value {
+    fn type_name() -> String {
+        "任意の値".to_string()
+    }
+
+    fn from_nasl(v: &Value) -> Option<Self> {
+        Some(v.clone())
+    }
+}
+
+impl IntoNasl for Value {
+    fn into_nasl(self) -> Value {
+        self
+    }
+}
+
+// 整数は型が違っても値が収まれば変換する（型注釈のない整数リテラルは i64 になるため）
+macro_rules! int_conversions {
+    ($($t:ty => $ty:ident),*) => {
+        $(
+            impl FromNasl for $t {
+                fn type_name() -> String {
+                    IntTy::$ty.name().to_string()
+                }
+
+                fn from_nasl(v: &Value) -> Option<Self> {
+                    let (ty, bits) = v.as_int()?;
+                    match ty.to_i128(bits) {
+                        Some(n) => <$t>::try_from(n).ok(),
+                        None => <$t>::try_from(bits).ok(),
+                    }
+                }
+            }
+
+            impl IntoNasl for $t {
+                fn into_nasl(self) -> Value {
+                    Value::from_int(IntTy::$ty, IntTy::$ty.truncate(self as u128))
+                }
+            }
+        )*
+    };
+}
+
+int_conversions!(
+    i8 => I8, i16 => I16, i32 => I32, i64 => I64, i128 => I128, isize => Isize,
+    u8 => U8, u16 => U16, u32 => U32, u64 => U64, u128 => U128, usize => Usize
+);
+
+// 単純な値の変換。(型名, Rust の型, 値のパターン → Rust の値, Rust の値 → nasl の値)
+macro_rules! simple_conversions {
+    ($($name:literal, $t:ty, $pat:pat => $from:expr, $x:ident => $into:expr;)*) => {
+        $(
+            impl FromNasl for $t {
+                fn type_name() -> String {
+                    $name.to_string()
+                }
+
+                fn from_nasl(v: &Value) -> Option<Self> {
+                    match v {
+                        $pat => Some($from),
+                        _ => None,
+                    }
+                }
+            }
+
+            impl IntoNasl for $t {
+                fn into_nasl(self) -> Value {
+                    let $x = self;
+                    $into
+                }
+            }
+        )*
+    };
+}
+
+simple_conversions! {
+    "()", (), Value::Unit => (), _x => Value::Unit;
+    "bool", bool, Value::Bool(b) => *b, b => Value::Bool(b);
+    "char", char, Value::Char(c) => *c, c => Value::Char(c);
+    "f64", f64, Value::Float(x) => *x, x => Value::Float(x);
+    "f32", f32, Value::F32(x) => *x, x => Value::F32(x);
+    "String", String, Value::Str(s) => s.clone(), s => Value::Str(s);
+}
+
+impl IntoNasl for &str {
+    fn into_nasl(self) -> Value {
+        Value::Str(self.to_string())
+    }
+}
+
+impl<T: FromNasl> FromNasl for Vec<T> {
+    fn type_name() -> String {
+        format!("Vec<{}>", T::type_name())
+    }
+
+    fn from_nasl(v: &Value) -> Option<Self> {
+        match v {
+            Value::Array(items) => items.iter().map(T::from_nasl).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: IntoNasl> IntoNasl for Vec<T> {
+    fn into_nasl(self) -> Value {
+        Value::Array(Rc::new(self.into_iter().map(T::into_nasl).collect()))
+    }
+}
+
+impl<K: FromNasl + Eq + Hash, V: FromNasl> FromNasl for HashMap<K, V> {
+    fn type_name() -> String {
+        format!("HashMap<{}, {}>", K::type_name(), V::type_name())
+    }
+
+    fn from_nasl(v: &Value) -> Option<Self> {
+        match v {
+            Value::Map(map) => map
+                .iter()
+                .map(|(k, v)| Some((K::from_nasl(&k.0)?, V::from_nasl(v)?)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+// キーにできない値（浮動小数点数など）をキーにした HashMap は渡せない
+impl<K: IntoNasl, V: IntoNasl> IntoNasl for HashMap<K, V> {
+    #[allow(clippy::mutable_key_type)]
+    fn into_nasl(self) -> Value {
+        let map = self
+            .into_iter()
+            .map(|(k, v)| (MapKey(k.into_nasl()), v.into_nasl()))
+            .collect();
+        Value::Map(Rc::new(map))
+    }
+}
+
+impl<T: FromNasl> FromNasl for Option<T> {
+    fn type_name() -> String {
+        format!("Option<{}>", T::type_name())
+    }
+
+    fn from_nasl(v: &Value) -> Option<Self> {
+        match v {
+            Value::Option(None) => Some(None),
+            Value::Option(Some(v)) => T::from_nasl(v).map(Some),
+            _ => None,
+        }
+    }
+}
+
+impl<T: IntoNasl> IntoNasl for Option<T> {
+    fn into_nasl(self) -> Value {
+        match self {
+            Some(v) => Value::some(v.into_nasl()),
+            None => Value::none(),
+        }
+    }
+}
+
+impl<T: FromNasl, E: FromNasl> FromNasl for Result<T, E> {
+    fn type_name() -> String {
+        format!("Result<{}, {}>", T::type_name(), E::type_name())
+    }
+
+    fn from_nasl(v: &Value) -> Option<Self> {
+        match v {
+            Value::Result(Ok(v)) => T::from_nasl(v).map(Ok),
+            Value::Result(Err(e)) => E::from_nasl(e).map(Err),
+            _ => None,
+        }
+    }
+}
+
+// Err はスクリプトの Result の Err になる（実行時エラーにはしない）
+impl<T: IntoNasl, E: IntoNasl> IntoNasl for Result<T, E> {
+    fn into_nasl(self) -> Value {
+        match self {
+            Ok(v) => Value::ok(v.into_nasl()),
+            Err(e) => Value::err(e.into_nasl()),
+        }
+    }
+}
+
+// ホストの値は同じ Rust の値を指したまま受け渡す
+impl<T: 'static> FromNasl for Host<T> {
+    fn type_name() -> String {
+        std::any::type_name::<T>().to_string()
+    }
+
+    fn from_nasl(v: &Value) -> Option<Self> {
+        v.downcast()
+    }
+}
+
+impl<T: 'static> IntoNasl for Host<T> {
+    fn into_nasl(self) -> Value {
+        self.into()
+    }
+}
+
+macro_rules! tuple_conversions {
+    ($(($($t:ident $i:tt),*))*) => {
+        $(
+            impl<$($t: FromNasl),*> FromNasl for ($($t,)*) {
+                fn type_name() -> String {
+                    let names: Vec<String> = vec![$($t::type_name()),*];
+                    format!("({})", names.join(", "))
+                }
+
+                fn from_nasl(v: &Value) -> Option<Self> {
+                    match v {
+                        Value::Tuple(items) => match items.as_slice() {
+                            [$($i),*] => Some(($($t::from_nasl($i)?,)*)),
+                            _ => None,
+                        },
+                        _ => None,
+                    }
+                }
+            }
+
+            impl<$($t: IntoNasl),*> IntoNasl for ($($t,)*) {
+                fn into_nasl(self) -> Value {
+                    let ($($i,)*) = self;
+                    Value::Tuple(vec![$($i.into_nasl()),*])
+                }
+            }
+        )*
+    };
+}
+
+tuple_conversions! {
+    (A a, B b)
+    (A a, B b, C c)
+    (A a, B b, C c, D d)
+}
+
+// Rust から nasl の関数に渡す引数の組
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Value>;
+}
+
+impl IntoArgs for Vec<Value> {
+    fn into_args(self) -> Vec<Value> {
+        self
+    }
+}
+
+macro_rules! into_args {
+    ($(($($t:ident $i:tt),*))*) => {
+        $(
+            impl<$($t: IntoNasl),*> IntoArgs for ($($t,)*) {
+                fn into_args(self) -> Vec<Value> {
+                    vec![$(self.$i.into_nasl()),*]
+                }
+            }
+        )*
+    };
+}
+
+into_args! {
+    ()
+    (A 0)
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+}
+
+// 引数を Rust の型に変換できる関数。Args は引数の型の組
+pub trait NativeFunction<Args> {
+    // name はエラーメッセージに使う
+    fn into_native(self, name: String) -> NativeFn;
+}
+
+// i 番目（0 から）の引数を変換する
+fn arg<T: FromNasl>(name: &str, i: usize, v: &Value) -> Result<T, RuntimeError> {
+    match T::from_nasl(v) {
+        Some(x) => Ok(x),
+        None => fail(
+            ErrorKind::Type,
+            format!(
+                "{} の {} 番目の引数には {} が必要ですが {} が渡されました: {:?}",
+                name,
+                i + 1,
+                T::type_name(),
+                v.type_name(),
+                v
+            ),
+        ),
+    }
+}
+
+macro_rules! native_functions {
+    ($(($($t:ident $i:tt),*))*) => {
+        $(
+            impl<Func, R, $($t),*> NativeFunction<($($t,)*)> for Func
+            where
+                Func: Fn($($t),*) -> R + 'static,
+                R: IntoNasl,
+                $($t: FromNasl,)*
+            {
+                fn into_native(self, name: String) -> NativeFn {
+                    Rc::new(move |args: Vec<Value>| {
+                        check_arity(&name, <[usize]>::len(&[$($i),*]), args.len())?;
+                        Ok(self($(arg::<$t>(&name, $i, &args[$i])?),*).into_nasl())
+                    })
+                }
+            }
+        )*
+    };
+}
+
+native_functions! {
+    ()
+    (A 0)
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+}
+
+// まとめて登録する関数の集まり。スクリプトからは "モジュール名::関数名" で呼ぶ
+pub struct NativeModule {
+    name: String,
+    pub(super) funcs: Vec<(String, NativeFn)>,
+}
+
+impl NativeModule {
+    pub fn new(name: &str) -> Self {
+        NativeModule {
+            name: name.to_string(),
+            funcs: Vec::new(),
+        }
+    }
+
+    pub fn register_fn<Args>(&mut self, name: &str, f: impl NativeFunction<Args>) -> &mut Self {
+        let name = format!("{}::{}", self.name, name);
+        self.funcs.push((name.clone(), f.into_native(name)));
+        self
+    }
+}
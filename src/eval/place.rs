@@ -0,0 +1,167 @@
+// 添字アクセスと、代入・書き換えメソッドの対象となる場所
+use super::error::{ErrorKind, RuntimeError, fail};
+use crate::lexer::Span;
+use crate::value::{MapKey, Value};
+use std::rc::Rc;
+
+// 変数から目的の場所までのたどり方
+pub enum Step {
+    Index(Value, Span),
+    // 構造体のフィールド・タプルの要素
+    Field(String),
+    // map.entry(key).or_insert(default)（キーがなければdefaultを入れる）
+    Entry(Value, Value),
+}
+
+// target[index] の読み出し。indexが範囲ならスライスを新しい配列として返す
+pub fn index_value(target: &Value, index: &Value, span: Span) -> Result<Value, RuntimeError> {
+    let v = match (target, index) {
+        (Value::Array(items), Value::Int(_) | Value::Sized(..)) => {
+            items[check_index(index, items.len(), span)?].clone()
+        }
+        (Value::Array(items), Value::Range { .. }) => {
+            let (start, end) = slice_bounds(index, items.len(), span)?;
+            Value::Array(Rc::new(items[start..end].to_vec()))
+        }
+        (Value::Map(map), key) => match map.get(&MapKey::new(key.clone())?) {
+            Some(v) => v.clone(),
+            None => {
+                return Err(RuntimeError::new(
+                    ErrorKind::Index,
+                    format!("キーが見つかりません: {:?}", key),
+                )
+                .at(span));
+            }
+        },
+        (Value::Str(s), Value::Range { .. }) => {
+            let (start, end) = slice_bounds(index, s.len(), span)?;
+            if !s.is_char_boundary(start) || !s.is_char_boundary(end) {
+                let message = format!(
+                    "文字の境界ではない位置で文字列をスライスしました: {}..{}",
+                    start, end
+                );
+                return Err(RuntimeError::new(ErrorKind::Index, message).at(span));
+            }
+            Value::Str(s[start..end].to_string())
+        }
+        _ => {
+            let message = format!(
+                "{} を {} で添字アクセスできません",
+                target.type_name(),
+                index.type_name()
+            );
+            return Err(RuntimeError::new(ErrorKind::Type, message).at(span));
+        }
+    };
+    Ok(v)
+}
+
+// 変数の値からstepsをたどって書き換え可能な場所を得る
+pub fn resolve<'a>(root: &'a mut Value, steps: &[Step]) -> Result<&'a mut Value, RuntimeError> {
+    let mut place = root;
+    for step in steps {
+        place = match (place, step) {
+            (Value::Array(items), Step::Index(i @ (Value::Int(_) | Value::Sized(..)), span)) => {
+                let i = check_index(i, items.len(), *span)?;
+                &mut Rc::make_mut(items)[i]
+            }
+            (place, Step::Field(name)) => field_mut(place, name)?,
+            (Value::Map(map), Step::Entry(key, default)) => Rc::make_mut(map)
+                .entry(MapKey::new(key.clone())?)
+                .or_insert_with(|| default.clone()),
+            (place, Step::Entry(..)) => {
+                return fail(
+                    ErrorKind::Undefined,
+                    format!("{} にメソッド entry はありません", place.type_name()),
+                );
+            }
+            (place, Step::Index(index, span)) => {
+                let message = format!(
+                    "{} の {} による添字の場所には代入できません",
+                    place.type_name(),
+                    index.type_name()
+                );
+                return Err(RuntimeError::new(ErrorKind::Type, message).at(*span));
+            }
+        };
+    }
+    Ok(place)
+}
+
+// p.x / t.0 の読み出し
+pub fn field_value(target: &Value, name: &str) -> Result<Value, RuntimeError> {
+    match target {
+        Value::Struct { fields, .. } => {
+            if let Some((_, v)) = fields.iter().find(|(f, _)| f == name) {
+                return Ok(v.clone());
+            }
+        }
+        Value::Tuple(items) => {
+            if let Some(v) = name.parse::<usize>().ok().and_then(|i| items.get(i)) {
+                return Ok(v.clone());
+            }
+        }
+        _ => {}
+    }
+    fail(
+        ErrorKind::Undefined,
+        format!("{} にフィールド {} はありません", target.type_name(), name),
+    )
+}
+
+fn field_mut<'a>(place: &'a mut Value, name: &str) -> Result<&'a mut Value, RuntimeError> {
+    let type_name = place.type_name();
+    let found = match place {
+        Value::Struct { fields, .. } => Rc::make_mut(fields)
+            .iter_mut()
+            .find(|(f, _)| f == name)
+            .map(|(_, v)| v),
+        Value::Tuple(items) => name.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+        _ => None,
+    };
+    found.ok_or_else(|| {
+        RuntimeError::new(
+            ErrorKind::Undefined,
+            format!("{} にフィールド {} はありません", type_name, name),
+        )
+    })
+}
+
+// 添字はどの整数型でもよい
+fn check_index(index: &Value, len: usize, span: Span) -> Result<usize, RuntimeError> {
+    match index.as_i64() {
+        Some(i) if i >= 0 && (i as usize) < len => Ok(i as usize),
+        _ => {
+            let message = format!(
+                "インデックスが範囲外です: 長さ {} に対してインデックス {:?}",
+                len, index
+            );
+            Err(RuntimeError::new(ErrorKind::Index, message).at(span))
+        }
+    }
+}
+
+fn slice_bounds(range: &Value, len: usize, span: Span) -> Result<(usize, usize), RuntimeError> {
+    let Value::Range {
+        start,
+        end,
+        inclusive,
+    } = range
+    else {
+        unreachable!()
+    };
+    let start = start.unwrap_or(0);
+    let end = match end {
+        Some(e) if *inclusive => e + 1,
+        Some(e) => *e,
+        None => len as i64,
+    };
+    if start < 0 || start > end || end as usize > len {
+        let message = format!(
+            "スライスの範囲が不正です: 長さ {} に対して範囲 {}..{}",
+            len, start, end
+        );
+        return Err(RuntimeError::new(ErrorKind::Index, message).at(span));
+    }
+    Ok((start as usize, end as usize))
+}
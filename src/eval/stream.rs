@@ -0,0 +1,140 @@
+// スクリプトの標準入出力（input・print・解析エラーの表示）。
+// Interpreter に入出力を設定していなければプロセスの標準入出力を使う
+use super::error::{ErrorKind, RuntimeError};
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+pub(super) type Input = Rc<RefCell<dyn BufRead>>;
+pub(super) type Output = Rc<RefCell<dyn Write>>;
+
+#[derive(Clone)]
+pub(super) struct Streams {
+    pub(super) stdin: Option<Input>,
+    pub(super) stdout: Option<Output>,
+    pub(super) stderr: Option<Output>,
+    // input で入力を待つときに表示する
+    pub(super) prompt: String,
+}
+
+impl Default for Streams {
+    fn default() -> Self {
+        Streams {
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            prompt: "> ".to_string(),
+        }
+    }
+}
+
+thread_local! {
+    // 評価中の Interpreter の入出力。Rust の関数の中で別の Interpreter を評価すると、
+    // そのあいだは内側の Interpreter の入出力になる
+    static CURRENT: RefCell<Option<Streams>> = const { RefCell::new(None) };
+}
+
+// 評価が終わると、評価を始める前の入出力に戻す
+pub(super) struct Attached(Option<Streams>);
+
+impl Drop for Attached {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        CURRENT.with(|c| *c.borrow_mut() = outer);
+    }
+}
+
+pub(super) fn enter(streams: &Streams) -> Attached {
+    Attached(CURRENT.with(|c| c.replace(Some(streams.clone()))))
+}
+
+fn current() -> Streams {
+    CURRENT.with(|c| c.borrow().clone().unwrap_or_default())
+}
+
+fn output(f: impl FnOnce(&Streams) -> Option<Output>) -> Option<Output> {
+    CURRENT.with(|c| c.borrow().as_ref().and_then(f))
+}
+
+// 設定した出力がなければ fallback（プロセスの標準出力・標準エラー出力）に書く
+fn write(out: Option<Output>, mut fallback: impl Write, text: &str) -> io::Result<()> {
+    match out {
+        Some(out) => {
+            let mut out = out.borrow_mut();
+            out.write_all(text.as_bytes())?;
+            out.flush()
+        }
+        None => {
+            fallback.write_all(text.as_bytes())?;
+            fallback.flush()
+        }
+    }
+}
+
+fn io_error(what: &str, e: io::Error) -> RuntimeError {
+    RuntimeError::new(ErrorKind::Io, format!("{}に書き込めません: {}", what, e))
+}
+
+// 標準出力に1行書く
+pub(super) fn print(line: &str) -> Result<(), RuntimeError> {
+    write(
+        output(|s| s.stdout.clone()),
+        io::stdout(),
+        &format!("{}\n", line),
+    )
+    .map_err(|e| io_error("標準出力", e))
+}
+
+// 標準エラー出力に1行書く
+pub(super) fn eprint(line: &str) -> Result<(), RuntimeError> {
+    write(
+        output(|s| s.stderr.clone()),
+        io::stderr(),
+        &format!("{}\n", line),
+    )
+    .map_err(|e| io_error("標準エラー出力", e))
+}
+
+// プロンプトを表示して1行読む。入力の終わりでは空の文字列を返す
+pub(super) fn read_line() -> io::Result<String> {
+    let streams = current();
+    write(streams.stdout, io::stdout(), &streams.prompt)?;
+    let mut buf = String::new();
+    match &streams.stdin {
+        Some(input) => input.borrow_mut().read_line(&mut buf)?,
+        None => io::stdin().read_line(&mut buf)?,
+    };
+    Ok(buf)
+}
+
+// スクリプトの出力を取っておく Write。複製したものは同じ中身を共有するので、
+// Interpreter に渡したあとも読み出せる
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        OutputBuffer::default()
+    }
+
+    // これまでに書かれた内容
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    // 内容を取り出して空にする
+    pub fn take(&self) -> String {
+        String::from_utf8_lossy(&self.0.take()).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,870 @@
+// 構文木からバイトコードへのコンパイル。名前の解決（crate::resolve）の結果に従って、
+// 変数は番地（さかのぼる関数の数, スロット）からこのフレームのスロット番号に、
+// 関数・標準関数・バリアントの名前は呼び出し先にする
+use super::{
+    CallInfo, Capture, Kind, MethodInfo, Op, PanicInfo, PatternSlots, Place, Program, Proto,
+    StepKind, StructInfo,
+};
+use crate::ast::{BinOp, Expr, Pattern, Resolved, Stmt, UnaryOp};
+use crate::check::ty::Ty;
+use crate::eval::{
+    ErrorKind, Func, Globals, RuntimeError, check_arity, entry_parts, enum_value, expr_span,
+    grow_stack, int_literal, is_global_name, no_field, unsuffixed_literal,
+};
+use crate::float::{self, FloatTy};
+use crate::int;
+use crate::lexer::Span;
+use crate::resolve::{Binding, Resolution};
+use crate::value::Value;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_PROGRAM: AtomicU64 = AtomicU64::new(0);
+
+// 関数をまたいで共有するもの
+struct Context<'a> {
+    globals: &'a Globals,
+    resolution: &'a Resolution,
+    id: u64,
+    func_index: HashMap<String, usize>,
+    closures: Vec<Rc<Proto>>,
+}
+
+// 定義済みの関数すべてと、stmts のうち定義以外の文（トップレベルの処理）をコンパイルする。
+// stmts から使う既存のトップレベルの変数は、実行するときに取り込む。
+// 関数は名前の解決で振った番号の順に並べる
+pub(in crate::eval) fn compile(
+    globals: &Globals,
+    stmts: &[Stmt],
+    resolution: &Resolution,
+) -> Program {
+    let names: Vec<&String> = resolution
+        .globals
+        .iter()
+        .filter(|name| globals.funcs.contains_key(name))
+        .collect();
+    let mut cx = Context {
+        globals,
+        resolution,
+        id: NEXT_PROGRAM.fetch_add(1, Ordering::Relaxed),
+        func_index: names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ((*name).clone(), i))
+            .collect(),
+        closures: Vec::new(),
+    };
+    let funcs = names
+        .iter()
+        .map(|name| Rc::new(compile_func(&mut cx, name, &globals.funcs[*name])))
+        .collect();
+
+    let stmts: Vec<&Stmt> = stmts
+        .iter()
+        .filter(|s| {
+            matches!(
+                s,
+                Stmt::Expr(_) | Stmt::Print(_) | Stmt::Let { .. } | Stmt::Error(..)
+            )
+        })
+        .collect();
+    let mut c = Compiler::new(&mut cx, "トップレベル".to_string(), Kind::Main, 0);
+    for (i, s) in stmts.iter().enumerate() {
+        c.stmt(s, i + 1 == stmts.len());
+    }
+    if stmts.is_empty() {
+        c.emit(Op::Unit);
+    }
+    c.emit(Op::Return);
+    let globals = std::mem::take(&mut c.globals);
+    let main = Rc::new(c.finish());
+    Program {
+        id: cx.id,
+        main,
+        funcs,
+        func_index: cx.func_index,
+        closures: cx.closures,
+        globals,
+    }
+}
+
+fn compile_func(cx: &mut Context, name: &str, f: &Func) -> Proto {
+    let self_method = matches!(f.params.first(), Some(Pattern::Bind(p, _)) if p == "self");
+    let kind = Kind::Func {
+        mut_self: f.mut_self,
+        self_method,
+    };
+    let mut c = Compiler::new(cx, name.to_string(), kind, f.params.len());
+    c.proto.ret = f.ret.clone();
+    for (i, (p, ty)) in f.params.iter().zip(&f.param_tys).enumerate() {
+        // メソッドとして呼ぶときは、self 以外の引数を型注釈に合わせる
+        if self_method && i > 0 && *ty != Ty::Unknown {
+            let ty = c.ty(ty);
+            c.emit(Op::Load(i as u32));
+            c.emit(Op::Coerce(ty));
+            c.emit(Op::Store(i as u32));
+        }
+        c.param(p, i);
+    }
+    c.expr(&f.body);
+    c.emit(Op::Return);
+    c.finish()
+}
+
+// 場所を表す式か（eval_place が Some を返す式）
+fn is_place(e: &Expr) -> bool {
+    match e {
+        Expr::Var(..) => true,
+        Expr::Index { target, .. }
+        | Expr::FieldAccess(target, _)
+        | Expr::Unary(UnaryOp::Deref, target, _)
+        | Expr::Ref(target, ..) => is_place(target),
+        Expr::MethodCall(..) => entry_parts(e).is_some_and(|(map, ..)| is_place(map)),
+        _ => false,
+    }
+}
+
+struct Compiler<'a, 'c> {
+    cx: &'c mut Context<'a>,
+    proto: Proto,
+    // 名前の解決で振ったこの関数の変数のスロットごとの、このフレームでのスロット
+    addrs: Vec<usize>,
+    // 入っているブロックごとの、入ったときの addrs の長さ
+    scopes: Vec<usize>,
+    // 今コンパイルしている、位置のある一番内側の式の位置
+    span: Option<Span>,
+    // トップレベルの let で作った変数
+    globals: Vec<(String, usize, usize)>,
+}
+
+impl<'a, 'c> Compiler<'a, 'c> {
+    fn new(cx: &'c mut Context<'a>, name: String, kind: Kind, arity: usize) -> Self {
+        let proto = Proto {
+            name,
+            kind,
+            program: cx.id,
+            arity,
+            locals: arity,
+            captures: Vec::new(),
+            ret: Ty::Unknown,
+            code: Vec::new(),
+            spans: Vec::new(),
+            consts: Vec::new(),
+            names: Vec::new(),
+            types: Vec::new(),
+            formats: Vec::new(),
+            structs: Vec::new(),
+            variants: Vec::new(),
+            patterns: Vec::new(),
+            places: Vec::new(),
+            calls: Vec::new(),
+            methods: Vec::new(),
+            stds: Vec::new(),
+            panics: Vec::new(),
+            errors: Vec::new(),
+            closures: Vec::new(),
+            source: None,
+        };
+        Compiler {
+            cx,
+            proto,
+            addrs: Vec::new(),
+            scopes: Vec::new(),
+            span: None,
+            globals: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> Proto {
+        self.proto
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.proto.code.push(op);
+        self.proto.spans.push(self.span);
+        self.proto.code.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.proto.code.len() as u32
+    }
+
+    // at の命令の飛び先を今の位置にする
+    fn patch(&mut self, at: usize) {
+        let to = self.here();
+        match &mut self.proto.code[at] {
+            Op::ShortCircuit(_, target)
+            | Op::Jump(target)
+            | Op::JumpIfFalse(target)
+            | Op::IterNext(_, target)
+            | Op::IterSome(_, target)
+            | Op::Match(_, _, target) => *target = to,
+            op => unreachable!("飛び先のない命令です: {:?}", op),
+        }
+    }
+
+    fn slot(&mut self) -> usize {
+        self.proto.locals += 1;
+        self.proto.locals - 1
+    }
+
+    // 名前の解決と同じ順に変数を作る
+    fn declare(&mut self) -> usize {
+        let slot = self.slot();
+        self.addrs.push(slot);
+        slot
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(self.addrs.len());
+    }
+
+    fn pop_scope(&mut self) {
+        if let Some(len) = self.scopes.pop() {
+            self.addrs.truncate(len);
+        }
+    }
+
+    // 番地の変数のこのフレームでのスロット。外側の関数の変数は、初めて使うときに
+    // 取り込むスロットを作る（フレームを積むときに取り込んだ値を置く）
+    fn addr(&mut self, depth: usize, slot: usize, name: &str) -> Option<usize> {
+        if depth == 0 {
+            return self.addrs.get(slot).copied();
+        }
+        let found = self
+            .proto
+            .captures
+            .iter()
+            .find(|c| (c.depth, c.slot) == (depth, slot));
+        if let Some(c) = found {
+            return Some(c.to);
+        }
+        let to = self.slot();
+        self.proto.captures.push(Capture {
+            name: name.to_string(),
+            depth,
+            slot,
+            to,
+        });
+        Some(to)
+    }
+
+    fn index<T>(table: &mut Vec<T>, item: T) -> u32 {
+        table.push(item);
+        (table.len() - 1) as u32
+    }
+
+    fn constant(&mut self, v: Value) -> u32 {
+        Self::index(&mut self.proto.consts, v)
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        match self.proto.names.iter().position(|n| n == name) {
+            Some(i) => i as u32,
+            None => Self::index(&mut self.proto.names, name.to_string()),
+        }
+    }
+
+    fn ty(&mut self, ty: &Ty) -> u32 {
+        Self::index(&mut self.proto.types, ty.clone())
+    }
+
+    // 実行されたときに起こすエラー
+    fn raise(&mut self, e: RuntimeError) {
+        let i = Self::index(&mut self.proto.errors, e);
+        self.emit(Op::Raise(i));
+    }
+
+    fn fail(&mut self, kind: ErrorKind, message: String) {
+        self.raise(RuntimeError::new(kind, message));
+    }
+
+    // コンパイル時に決まる値（範囲外のリテラルなら実行時にエラーにする）
+    fn literal(&mut self, v: Result<Value, RuntimeError>) {
+        match v {
+            Ok(v) => {
+                let i = self.constant(v);
+                self.emit(Op::Const(i));
+            }
+            Err(e) => self.raise(e),
+        }
+    }
+
+    // パターンの変数を今のスコープに作る
+    fn pattern(&mut self, p: &Pattern) -> u32 {
+        let slots = p
+            .names()
+            .into_iter()
+            .map(|name| {
+                let slot = self.declare();
+                (name, slot)
+            })
+            .collect();
+        let pattern = PatternSlots {
+            pattern: p.clone(),
+            slots,
+        };
+        Self::index(&mut self.proto.patterns, pattern)
+    }
+
+    // i 番目の引数をパターンで束縛する
+    fn param(&mut self, p: &Pattern, i: usize) {
+        match p {
+            Pattern::Bind(..) => self.addrs.push(i),
+            _ => {
+                let p = self.pattern(p);
+                self.emit(Op::BindParam(p, i as u32));
+            }
+        }
+    }
+
+    // 文の値を積む（keep でなければ積まない）
+    fn stmt(&mut self, stmt: &Stmt, keep: bool) {
+        match stmt {
+            Stmt::Expr(e) => {
+                self.expr(e);
+                if !keep {
+                    self.emit(Op::Pop);
+                }
+            }
+            Stmt::Print(e) => {
+                self.expr(e);
+                self.emit(Op::Print);
+                if !keep {
+                    self.emit(Op::Pop);
+                }
+            }
+            Stmt::Let {
+                pattern,
+                value,
+                ty,
+                span,
+                ..
+            } => {
+                self.expr(value);
+                // 型の変換・パターンのエラーは let の位置で報告する
+                let outer = self.span.replace(*span);
+                if let Some(ty) = ty.as_deref().map(Ty::parse)
+                    && ty != Ty::Unknown
+                {
+                    let ty = self.ty(&ty);
+                    self.emit(Op::Coerce(ty));
+                }
+                let top = self.proto.kind == Kind::Main && self.scopes.is_empty();
+                match pattern {
+                    Pattern::Bind(name, _) => {
+                        let slot = self.declare();
+                        let at = self.emit(Op::Store(slot as u32));
+                        if top {
+                            self.globals.push((name.clone(), slot, at));
+                        }
+                    }
+                    _ => {
+                        let p = self.pattern(pattern);
+                        let at = self.emit(Op::Let(p));
+                        if top {
+                            for (name, slot) in &self.proto.patterns[p as usize].slots {
+                                self.globals.push((name.clone(), *slot, at));
+                            }
+                        }
+                    }
+                }
+                self.span = outer;
+                if keep {
+                    self.emit(Op::Unit);
+                }
+            }
+            Stmt::Error(msg, _) => {
+                let i = self.name(msg);
+                self.emit(Op::ParseError(i));
+                if keep {
+                    self.emit(Op::Unit);
+                }
+            }
+            // ブロック内の関数定義・import・型定義は未対応
+            _ => {
+                if keep {
+                    self.emit(Op::Unit);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.push_scope();
+        for (i, s) in stmts.iter().enumerate() {
+            self.stmt(s, i + 1 == stmts.len());
+        }
+        if stmts.is_empty() {
+            self.emit(Op::Unit);
+        }
+        self.pop_scope();
+    }
+
+    // 式の値を積む
+    fn expr(&mut self, e: &Expr) {
+        let outer = self.span;
+        if let Some(span) = expr_span(e) {
+            self.span = Some(span);
+        }
+        grow_stack(|| self.expr_inner(e));
+        self.span = outer;
+    }
+
+    fn exprs(&mut self, es: &[Expr]) -> u32 {
+        es.iter().for_each(|e| self.expr(e));
+        es.len() as u32
+    }
+
+    fn expr_inner(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(n) => self.literal(Ok(Value::Int(*n))),
+            Expr::TypedNumber(n, ty, _) => self.literal(int_literal(*n, *ty, false)),
+            Expr::Float(x, ty) => {
+                self.literal(Ok(Value::from_float(ty.unwrap_or(FloatTy::F64), *x)))
+            }
+            Expr::Bool(b) => self.literal(Ok(Value::Bool(*b))),
+            Expr::Char(c) => self.literal(Ok(Value::Char(*c))),
+            Expr::Str(s) => self.literal(Ok(Value::Str(s.clone()))),
+            Expr::Format { pieces, args, .. } => {
+                let argc = self.exprs(args);
+                let i = Self::index(&mut self.proto.formats, pieces.clone());
+                self.emit(Op::Format(i, argc));
+            }
+            Expr::Binary(op, lhs, rhs, _) => {
+                self.expr(lhs);
+                // && と || は短絡評価
+                let short = matches!(op, BinOp::And | BinOp::Or)
+                    .then(|| self.emit(Op::ShortCircuit(*op, 0)));
+                self.expr(rhs);
+                let (l, r) = (unsuffixed_literal(lhs), unsuffixed_literal(rhs));
+                if l != r {
+                    self.emit(Op::AdaptLiteral(l));
+                }
+                self.emit(Op::Binary(*op));
+                if let Some(at) = short {
+                    self.patch(at);
+                }
+            }
+            Expr::Unary(UnaryOp::Neg, operand, _) if matches!(**operand, Expr::TypedNumber(..)) => {
+                let Expr::TypedNumber(n, ty, _) = **operand else {
+                    unreachable!()
+                };
+                self.literal(int_literal(n, ty, true));
+            }
+            Expr::Ref(e, ..) | Expr::Vec(e) => self.expr(e),
+            Expr::Unary(op, operand, _) => {
+                self.expr(operand);
+                self.emit(Op::Unary(*op));
+            }
+            Expr::Var(name, _, resolved) => self.var(name, resolved),
+            Expr::Call(name, args, _, resolved) => self.call(name, args, resolved),
+            // map.entry(k).or_insert(v) はマップの中の場所を返す
+            Expr::MethodCall(..) if entry_parts(expr).is_some() => match self.place(expr) {
+                Some(p) => {
+                    self.emit(Op::LoadPlace(p));
+                }
+                None => {
+                    let (map, key, default) = entry_parts(expr).unwrap();
+                    self.expr(map);
+                    self.expr(key);
+                    self.expr(default);
+                    self.emit(Op::EntryTemp);
+                }
+            },
+            Expr::MethodCall(recv, name, args, _) => {
+                // 引数を先に評価し、受け手が場所ならその場で書き換える
+                let argc = self.exprs(args) as usize;
+                let place = if is_global_name(recv) {
+                    None
+                } else {
+                    self.place(recv).map(|p| p as usize)
+                };
+                if place.is_none() {
+                    self.expr(recv);
+                }
+                let info = MethodInfo {
+                    name: name.clone(),
+                    argc,
+                    place,
+                };
+                let i = Self::index(&mut self.proto.methods, info);
+                self.emit(Op::CallMethod(i));
+            }
+            Expr::Tuple(items) => {
+                if items.is_empty() {
+                    self.emit(Op::Unit);
+                } else {
+                    let n = self.exprs(items);
+                    self.emit(Op::MakeTuple(n));
+                }
+            }
+            Expr::StructInit(name, inits) => {
+                let Some(defs) = self.cx.globals.structs.get(name) else {
+                    self.fail(ErrorKind::Undefined, format!("未定義の構造体: {}", name));
+                    return;
+                };
+                let mut fields = Vec::new();
+                for (field, e, span) in inits {
+                    let Some((_, ty)) = defs.iter().find(|(f, _)| f == field) else {
+                        self.raise(no_field(name, field).at(*span));
+                        return;
+                    };
+                    self.expr(e);
+                    let ty = Ty::parse(ty);
+                    if ty != Ty::Unknown {
+                        let outer = self.span.replace(*span);
+                        let ty = self.ty(&ty);
+                        self.emit(Op::Coerce(ty));
+                        self.span = outer;
+                    }
+                    fields.push(field.clone());
+                }
+                let info = StructInfo {
+                    name: name.clone(),
+                    defs: defs.clone(),
+                    fields,
+                };
+                let i = Self::index(&mut self.proto.structs, info);
+                self.emit(Op::MakeStruct(i));
+            }
+            Expr::FieldAccess(target, field) => {
+                self.expr(target);
+                let i = self.name(field);
+                self.emit(Op::GetField(i));
+            }
+            Expr::Array(items) => {
+                let n = self.exprs(items);
+                self.emit(Op::MakeArray(n));
+            }
+            Expr::ArrayRepeat(value, count) => {
+                self.expr(value);
+                self.expr(count);
+                self.emit(Op::ArrayRepeat);
+            }
+            Expr::Index { target, index, .. } => {
+                self.expr(target);
+                self.expr(index);
+                self.emit(Op::Index);
+            }
+            Expr::Assign { target, op, value } => {
+                self.expr(value);
+                match self.place(target) {
+                    Some(p) => {
+                        self.emit(Op::StorePlace(p, *op));
+                    }
+                    None => self.fail(ErrorKind::Type, format!("代入できない式です: {:?}", target)),
+                }
+            }
+            Expr::Block(stmts) => self.block(stmts),
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                self.expr(then_branch);
+                let to_end = self.emit(Op::Jump(0));
+                self.patch(to_else);
+                match else_branch {
+                    Some(e) => self.expr(e),
+                    None => {
+                        self.emit(Op::Unit);
+                    }
+                }
+                self.patch(to_end);
+            }
+            Expr::For {
+                pattern,
+                iter,
+                body,
+            } => {
+                self.expr(iter);
+                let it = self.slot() as u32;
+                self.emit(Op::IntoIter(it));
+                let start = self.here();
+                // next() の呼び出し元は for で回す式の位置にする
+                let outer = std::mem::replace(&mut self.span, expr_span(iter));
+                let next = self.emit(Op::IterNext(it, 0));
+                let some = self.emit(Op::IterSome(it, 0));
+                self.span = outer;
+                self.push_scope();
+                match pattern {
+                    Pattern::Bind(..) => {
+                        let slot = self.declare() as u32;
+                        self.emit(Op::Store(slot));
+                    }
+                    _ => {
+                        let p = self.pattern(pattern);
+                        self.emit(Op::ForBind(p));
+                    }
+                }
+                self.expr(body);
+                self.emit(Op::Pop);
+                self.pop_scope();
+                self.emit(Op::Jump(start));
+                self.patch(next);
+                self.patch(some);
+                self.emit(Op::Unit);
+            }
+            Expr::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                for e in [start, end].into_iter().flatten() {
+                    self.expr(e);
+                    self.emit(Op::RangeBound);
+                }
+                self.emit(Op::MakeRange {
+                    start: start.is_some(),
+                    end: end.is_some(),
+                    inclusive: *inclusive,
+                });
+            }
+            Expr::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                let v = self.slot() as u32;
+                self.emit(Op::Store(v));
+                let mut ends = Vec::new();
+                for (pattern, body) in arms {
+                    self.push_scope();
+                    let p = self.pattern(pattern);
+                    let next = self.emit(Op::Match(p, v, 0));
+                    self.expr(body);
+                    ends.push(self.emit(Op::Jump(0)));
+                    self.patch(next);
+                    self.pop_scope();
+                }
+                // どの腕にも一致しなければ ()
+                self.emit(Op::Unit);
+                for at in ends {
+                    self.patch(at);
+                }
+            }
+            Expr::Return(value) => {
+                match value {
+                    Some(e) => self.expr(e),
+                    None => {
+                        self.emit(Op::Unit);
+                    }
+                }
+                self.emit(Op::Return);
+            }
+            Expr::Try(e) => {
+                self.expr(e);
+                self.emit(Op::Try);
+            }
+            Expr::Cast(e, ty, _) => {
+                self.expr(e);
+                let i = self.name(ty);
+                self.emit(Op::Cast(i));
+            }
+            Expr::Panic {
+                kind,
+                args,
+                text,
+                message,
+                ..
+            } => {
+                let argc = self.exprs(args) as usize;
+                if let Some(m) = message {
+                    self.expr(m);
+                }
+                let info = PanicInfo {
+                    kind: *kind,
+                    text: text.clone(),
+                    argc,
+                    message: message.is_some(),
+                };
+                let i = Self::index(&mut self.proto.panics, info);
+                self.emit(Op::Panic(i));
+            }
+            Expr::Closure { params, body } => self.closure(params, body),
+        }
+    }
+
+    // 名前の解決で局所変数に結びついた名前のスロット
+    fn local(&mut self, name: &str, resolved: &Resolved) -> Option<usize> {
+        match resolved.get() {
+            Some(Binding::Local { depth, slot }) => self.addr(depth, slot, name),
+            _ => None,
+        }
+    }
+
+    // 名前の解決で関数に結びついた名前の、このプログラムでの関数の番号。
+    // 解決の結果がない名前（impl に写したトレイトの既定の実装の中の Self::new など）は名前で探す
+    fn func(&self, name: &str, resolved: &Resolved) -> Option<usize> {
+        let name = match resolved.get() {
+            Some(Binding::Global(i)) => &self.cx.resolution.globals[i],
+            None => name,
+            Some(_) => return None,
+        };
+        self.cx.func_index.get(name).copied()
+    }
+
+    fn var(&mut self, name: &str, resolved: &Resolved) {
+        if let Some(slot) = self.local(name, resolved) {
+            self.emit(Op::Load(slot as u32));
+        } else if name == "None" || name == "Option::None" {
+            self.literal(Ok(Value::none()));
+        } else if let Some(i) = self.func(name, resolved) {
+            // 関数名は値として渡せる（map(double) など）
+            self.emit(Op::FuncValue(i as u32));
+        } else if let Some((ty, x)) = float::constant(name) {
+            self.literal(Ok(Value::from_float(ty, x)));
+        } else if let Some((ty, bits)) = int::constant(name) {
+            self.literal(Ok(Value::from_int(ty, bits)));
+        } else if let Some((enum_name, index, 0)) = self.cx.globals.variants.get(name) {
+            // 中身のないバリアント: Color::Red
+            let v = enum_value(enum_name, *index, name, Vec::new());
+            self.literal(Ok(v));
+        } else {
+            self.fail(ErrorKind::Undefined, format!("未定義の変数: {}", name));
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], resolved: &Resolved) {
+        let globals = self.cx.globals;
+        if let Some(slot) = self.local(name, resolved) {
+            // 変数に入れたクロージャ（クロージャでなければ実行時に名前で探す）
+            self.emit(Op::Load(slot as u32));
+            let argc = self.exprs(args);
+            let i = self.name(name);
+            self.emit(Op::CallValue(i, argc));
+        } else if globals.natives.contains_key(name) {
+            let argc = self.exprs(args);
+            let i = self.name(name);
+            self.emit(Op::CallNative(i, argc));
+        } else if let Some(f) = globals.std_funcs.get(name) {
+            let argc = self.exprs(args);
+            let i = Self::index(&mut self.proto.stds, (name.to_string(), *f));
+            self.emit(Op::CallStd(i, argc));
+        } else if let Some(func) = self.func(name, resolved) {
+            let f = &globals.funcs[name];
+            if let Err(e) = check_arity(name, f.params.len(), args.len()) {
+                self.raise(e);
+                return;
+            }
+            let mut keep = Vec::new();
+            for (i, ((a, ty), p)) in args.iter().zip(&f.param_tys).zip(&f.params).enumerate() {
+                self.expr(a);
+                if *ty != Ty::Unknown {
+                    let ty = self.ty(ty);
+                    self.emit(Op::Coerce(ty));
+                }
+                // &mut x で渡した引数は、関数の中で変更した値を呼び出し元に書き戻す
+                if let (Expr::Ref(target, true, _), Pattern::Bind(..)) = (a, p)
+                    && is_place(target)
+                {
+                    keep.push((i, &**target));
+                }
+            }
+            let info = CallInfo {
+                name: name.to_string(),
+                func,
+                argc: args.len(),
+                keep: keep.iter().map(|(i, _)| *i).collect(),
+            };
+            let i = Self::index(&mut self.proto.calls, info);
+            self.emit(Op::Call(i));
+            for (_, target) in keep {
+                let p = self.place(target).expect("場所を表す式です");
+                self.emit(Op::Writeback(p));
+            }
+        } else if let Some((enum_name, index, arity)) = globals.variants.get(name) {
+            if *arity != args.len() {
+                self.fail(
+                    ErrorKind::Type,
+                    format!("{} の要素の数が一致しません", name),
+                );
+                return;
+            }
+            let argc = self.exprs(args);
+            let variant = (enum_name.clone(), *index, name.to_string());
+            let i = Self::index(&mut self.proto.variants, variant);
+            self.emit(Op::MakeEnum(i, argc));
+        } else {
+            self.fail(ErrorKind::Undefined, format!("未定義の関数: {}", name));
+        }
+    }
+
+    // 場所を表す式なら、たどるのに使う値を積んで場所表の添字を返す（そうでなければ何もしない）
+    fn place(&mut self, e: &Expr) -> Option<u32> {
+        if !is_place(e) {
+            return None;
+        }
+        let mut steps = Vec::new();
+        let (name, resolved) = self.place_steps(e, &mut steps);
+        let place = Place {
+            root: self.local(name, resolved),
+            name: name.to_string(),
+            steps,
+        };
+        Some(Self::index(&mut self.proto.places, place))
+    }
+
+    // 場所の根の変数の名前と、名前の解決の結果
+    fn place_steps<'e>(
+        &mut self,
+        e: &'e Expr,
+        steps: &mut Vec<StepKind>,
+    ) -> (&'e str, &'e Resolved) {
+        match e {
+            Expr::Index {
+                target,
+                index,
+                span,
+            } => {
+                let root = self.place_steps(target, steps);
+                self.expr(index);
+                steps.push(StepKind::Index(*span));
+                root
+            }
+            Expr::FieldAccess(target, field) => {
+                let root = self.place_steps(target, steps);
+                steps.push(StepKind::Field(field.clone()));
+                root
+            }
+            Expr::Unary(UnaryOp::Deref, target, _) | Expr::Ref(target, ..) => {
+                self.place_steps(target, steps)
+            }
+            Expr::MethodCall(..) => {
+                let (map, key, default) = entry_parts(e).expect("場所を表す式です");
+                let root = self.place_steps(map, steps);
+                self.expr(key);
+                self.expr(default);
+                steps.push(StepKind::Entry);
+                root
+            }
+            Expr::Var(name, _, resolved) => (name, resolved),
+            _ => unreachable!("場所を表す式です"),
+        }
+    }
+
+    // クロージャは本体で使う外側の関数の変数だけを取り込む
+    fn closure(&mut self, params: &[Pattern], body: &Expr) {
+        let mut c = Compiler::new(self.cx, String::new(), Kind::Closure, params.len());
+        for (i, p) in params.iter().enumerate() {
+            c.param(p, i);
+        }
+        c.expr(body);
+        c.emit(Op::Return);
+        c.proto.source = Some((params.to_vec(), body.clone()));
+        let mut proto = c.finish();
+        // 入れ子のクロージャは先に登録される
+        let number = self.cx.closures.len();
+        proto.name = format!("クロージャ #{}", number);
+        // クロージャから1つ外側はこの関数
+        let slots = proto
+            .captures
+            .iter()
+            .map(|c| self.addr(c.depth - 1, c.slot, &c.name))
+            .collect();
+        self.cx.closures.push(Rc::new(proto));
+        let i = Self::index(&mut self.proto.closures, (number, slots));
+        self.emit(Op::Closure(i));
+    }
+}
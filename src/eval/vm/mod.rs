@@ -0,0 +1,326 @@
+// バイトコードの VM。構文木を関数ごとのバイトコード（定数表・スロット番号で指す局所変数・
+// ジャンプ・呼び出し）にコンパイルし、スタックマシンで実行する。
+// 関数呼び出しは Rust のスタックを使わず、VM のフレームを積んで行う。
+// 値・組み込みのメソッド・パターン照合などは木をたどる評価器と同じものを使うので、結果は同じになる
+mod compile;
+mod run;
+
+use super::{RuntimeError, StdFunc};
+use crate::ast::{BinOp, Expr, FmtPiece, PanicKind, Pattern, UnaryOp};
+use crate::check::ty::Ty;
+use crate::lexer::Span;
+use crate::value::Value;
+pub(super) use compile::compile;
+pub(super) use run::run;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// 命令。引数の u32 は関数ごとの表（定数・名前・型など）の添字かスロット番号、ジャンプ先
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    // 定数表の値を積む
+    Const(u32),
+    Unit,
+    // 局所変数（スロット）の読み書き。Store は値を降ろす
+    Load(u32),
+    Store(u32),
+    Pop,
+    Binary(BinOp),
+    // i64 に収まらない接尾辞なしのリテラルの被演算子（左辺なら true）を、もう一方の整数型にする
+    AdaptLiteral(bool),
+    // && ・||: 左辺で結果が決まれば結果を積んで飛ぶ
+    ShortCircuit(BinOp, u32),
+    Unary(UnaryOp),
+    // as 型名（名前表）
+    Cast(u32),
+    // 型注釈に合わせた変換（型表）
+    Coerce(u32),
+    // 書式表, 引数の数
+    Format(u32, u32),
+    MakeTuple(u32),
+    MakeArray(u32),
+    ArrayRepeat,
+    // 範囲の端を確かめる
+    RangeBound,
+    MakeRange {
+        start: bool,
+        end: bool,
+        inclusive: bool,
+    },
+    // 構造体表（初期化したフィールドの順）
+    MakeStruct(u32),
+    // バリアント表, 中身の数
+    MakeEnum(u32, u32),
+    GetField(u32),
+    Index,
+    // クロージャ表のクロージャを作る（見えている変数を取り込む）
+    Closure(u32),
+    // 関数を値として取り出す（関数表）
+    FuncValue(u32),
+    Jump(u32),
+    JumpIfFalse(u32),
+    // イテレータをスロットに入れる
+    IntoIter(u32),
+    // スロットのイテレータの次の要素を積み、IterSome を飛ばす。終わりなら飛ぶ。
+    // Iterator を実装したユーザー定義の値なら next() を呼び、戻ったら IterSome に進む
+    IterNext(u32, u32),
+    // next() が返した Option の中身を積む。None なら飛ぶ（スロット, 飛び先）
+    IterSome(u32, u32),
+    // パターン表のパターンで束縛する
+    Let(u32),
+    ForBind(u32),
+    // パターン, 照合する値のスロット, 一致しないときの飛び先
+    Match(u32, u32, u32),
+    // 関数の引数をパターンで束縛する（パターン, 引数のスロット）
+    BindParam(u32, u32),
+    Return,
+    Try,
+    // 呼び出し表
+    Call(u32),
+    // 名前表（呼び出し履歴に積む名前）, 引数の数。呼び出す値は引数の下にある
+    CallValue(u32, u32),
+    CallNative(u32, u32),
+    // 標準関数表, 引数の数
+    CallStd(u32, u32),
+    // メソッド呼び出し表
+    CallMethod(u32),
+    // 場所表の場所（変数・添字・フィールド・entry）の値を読む・代入する
+    LoadPlace(u32),
+    StorePlace(u32, Option<BinOp>),
+    // &mut で渡した引数に、呼び出し後の値を書き戻す
+    Writeback(u32),
+    // map.entry(k).or_insert(v) の map が場所でないとき
+    EntryTemp,
+    Print,
+    ParseError(u32),
+    // panic! などの表
+    Panic(u32),
+    // エラー表のエラーを起こす（実行されたときにだけ起こるコンパイル時のエラー）
+    Raise(u32),
+}
+
+// 場所のたどり方
+#[derive(Debug, Clone)]
+enum StepKind {
+    Index(Span),
+    Field(String),
+    Entry,
+}
+
+// 変数（root）からたどる場所。たどるのに使う値（添字など）はスタックに積んである
+#[derive(Debug, Clone)]
+struct Place {
+    name: String,
+    // 見つからない変数なら None
+    root: Option<usize>,
+    steps: Vec<StepKind>,
+}
+
+// パターンと、束縛する変数のスロット
+#[derive(Debug, Clone)]
+struct PatternSlots {
+    pattern: Pattern,
+    slots: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone)]
+struct CallInfo {
+    name: String,
+    func: usize,
+    argc: usize,
+    // &mut で渡した引数の番号（呼び出し後の値を返してもらう）
+    keep: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct MethodInfo {
+    name: String,
+    argc: usize,
+    // 受け手が場所なら場所表の添字
+    place: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct StructInfo {
+    name: String,
+    defs: Vec<(String, String)>,
+    // 初期化した順のフィールド名
+    fields: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct PanicInfo {
+    kind: PanicKind,
+    text: Vec<String>,
+    argc: usize,
+    message: bool,
+}
+
+// 関数・クロージャ・トップレベルの文をコンパイルしたもの
+struct Proto {
+    name: String,
+    kind: Kind,
+    // 識別用（別のプログラムのクロージャは木をたどる評価器で呼ぶ）
+    program: u64,
+    arity: usize,
+    locals: usize,
+    // クロージャが取り込んだ外側の関数の変数・トップレベルで使う既存の変数
+    captures: Vec<Capture>,
+    ret: Ty,
+    code: Vec<Op>,
+    // 命令ごとの、それを囲む位置のある一番内側の式の位置（実行時エラーに付ける）
+    spans: Vec<Option<Span>>,
+    consts: Vec<Value>,
+    names: Vec<String>,
+    types: Vec<Ty>,
+    formats: Vec<Vec<FmtPiece>>,
+    structs: Vec<StructInfo>,
+    // (列挙型名, 定義順, "列挙型名::バリアント名")
+    variants: Vec<(Rc<str>, usize, String)>,
+    patterns: Vec<PatternSlots>,
+    places: Vec<Place>,
+    calls: Vec<CallInfo>,
+    methods: Vec<MethodInfo>,
+    stds: Vec<(String, StdFunc)>,
+    panics: Vec<PanicInfo>,
+    errors: Vec<RuntimeError>,
+    // (Program のクロージャの番号, 取り込む変数（クロージャの captures の順）のこの関数でのスロット)
+    closures: Vec<(usize, Vec<Option<usize>>)>,
+    // クロージャの値は木をたどる評価器でも呼べるよう、元の引数と本体を持つ
+    source: Option<(Vec<Pattern>, Expr)>,
+}
+
+// 取り込んだ変数。フレームを積むときに、名前の解決での番地（さかのぼる関数の数 depth,
+// その関数でのスロット slot）の値を、このフレームのスロット to に置く
+struct Capture {
+    name: String,
+    depth: usize,
+    slot: usize,
+    to: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Main,
+    Func { mut_self: bool, self_method: bool },
+    Closure,
+}
+
+// コンパイルしたプログラム。Interpreter::compile で作り、Interpreter::run で実行する。
+// 表示すると逆アセンブルしたバイトコードになる
+pub struct Program {
+    id: u64,
+    main: Rc<Proto>,
+    funcs: Vec<Rc<Proto>>,
+    func_index: HashMap<String, usize>,
+    closures: Vec<Rc<Proto>>,
+    // トップレベルの let で作る変数（名前, スロット, 束縛する命令の位置）。
+    // 実行したところまでの変数を Interpreter に残す
+    globals: Vec<(String, usize, usize)>,
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.main)?;
+        for proto in self.funcs.iter().chain(&self.closures) {
+            write!(f, "\n{}", proto)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Proto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "== {} (引数 {}, 局所変数 {}) ==",
+            self.name, self.arity, self.locals
+        )?;
+        for c in &self.captures {
+            writeln!(f, "     取り込み {} -> ${}", c.name, c.to)?;
+        }
+        for (ip, (op, span)) in self.code.iter().zip(&self.spans).enumerate() {
+            let span = span.map(|s| s.to_string()).unwrap_or_default();
+            writeln!(f, "{:>5} {:>7}  {}", ip, span, self.describe(*op))?;
+        }
+        Ok(())
+    }
+}
+
+impl Proto {
+    // 命令と、引数が指す表の中身
+    fn describe(&self, op: Op) -> String {
+        let name = |i: u32| &self.names[i as usize];
+        let pattern = |i: u32| self.patterns[i as usize].pattern.to_string();
+        match op {
+            Op::Const(i) => format!("Const {:?}", self.consts[i as usize]),
+            Op::Load(slot) => format!("Load ${}", slot),
+            Op::Store(slot) => format!("Store ${}", slot),
+            Op::ShortCircuit(op, to) => format!("ShortCircuit {:?} -> {}", op, to),
+            Op::Cast(i) => format!("Cast {}", name(i)),
+            Op::Coerce(i) => format!("Coerce {}", self.types[i as usize]),
+            Op::Format(_, argc) => format!("Format ({} 個)", argc),
+            Op::MakeStruct(i) => format!("MakeStruct {}", self.structs[i as usize].name),
+            Op::MakeEnum(i, argc) => {
+                format!("MakeEnum {} ({} 個)", self.variants[i as usize].2, argc)
+            }
+            Op::GetField(i) => format!("GetField {}", name(i)),
+            Op::Closure(i) => format!("Closure #{}", self.closures[i as usize].0),
+            Op::FuncValue(i) => format!("FuncValue #{}", i),
+            Op::Jump(to) => format!("Jump -> {}", to),
+            Op::JumpIfFalse(to) => format!("JumpIfFalse -> {}", to),
+            Op::IntoIter(slot) => format!("IntoIter ${}", slot),
+            Op::IterNext(slot, to) => format!("IterNext ${} -> {}", slot, to),
+            Op::IterSome(slot, to) => format!("IterSome ${} -> {}", slot, to),
+            Op::Let(i) => format!("Let {}", pattern(i)),
+            Op::ForBind(i) => format!("ForBind {}", pattern(i)),
+            Op::Match(i, slot, to) => format!("Match ${} {} -> {}", slot, pattern(i), to),
+            Op::BindParam(i, slot) => format!("BindParam ${} {}", slot, pattern(i)),
+            Op::Call(i) => {
+                let call = &self.calls[i as usize];
+                format!("Call {} #{} ({} 個)", call.name, call.func, call.argc)
+            }
+            Op::CallValue(i, argc) => format!("CallValue {} ({} 個)", name(i), argc),
+            Op::CallNative(i, argc) => format!("CallNative {} ({} 個)", name(i), argc),
+            Op::CallStd(i, argc) => {
+                format!("CallStd {} ({} 個)", self.stds[i as usize].0, argc)
+            }
+            Op::CallMethod(i) => {
+                let m = &self.methods[i as usize];
+                let place = m.place.map(|p| self.place(p)).unwrap_or_default();
+                format!("CallMethod {}{} ({} 個)", place, m.name, m.argc)
+            }
+            Op::LoadPlace(i) => format!("LoadPlace {}", self.place(i as usize)),
+            Op::StorePlace(i, Some(op)) => {
+                format!("StorePlace {} {:?}", self.place(i as usize), op)
+            }
+            Op::StorePlace(i, None) => format!("StorePlace {}", self.place(i as usize)),
+            Op::Writeback(i) => format!("Writeback {}", self.place(i as usize)),
+            Op::ParseError(i) => format!("ParseError {:?}", name(i)),
+            Op::Panic(i) => format!("Panic {:?}", self.panics[i as usize].kind),
+            Op::Raise(i) => format!("Raise {:?}", self.errors[i as usize].message),
+            op => format!("{:?}", op),
+        }
+    }
+
+    fn place(&self, i: usize) -> String {
+        let place = &self.places[i];
+        let mut s = match place.root {
+            Some(slot) => format!("${}", slot),
+            None => place.name.clone(),
+        };
+        for step in &place.steps {
+            match step {
+                StepKind::Index(_) => s.push_str("[_]"),
+                StepKind::Field(f) => {
+                    s.push('.');
+                    s.push_str(f);
+                }
+                StepKind::Entry => s.push_str(".entry(_)"),
+            }
+        }
+        s.push(' ');
+        s
+    }
+}
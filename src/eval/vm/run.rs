@@ -0,0 +1,816 @@
+// バイトコードの実行。nasl の関数呼び出しはフレームを積んで同じループで続け、
+// 組み込みのメソッドに渡したクロージャだけは別の VM で呼び出す
+use super::{Kind, Op, PatternSlots, Place, Program, Proto, StepKind};
+use crate::eval::place::{self, Step};
+use crate::eval::{
+    Bind, Depth, ErrorKind, Globals, RuntimeError, Vars, adapt_literal, array_repeat, binary_op,
+    bind_pattern, call_closure, cast, check_arity, coerce, enter, enum_value, expect_bool, fail,
+    format_pieces, grow_stack, iter, limits, methods, next_item, panic_macro, property,
+    range_bound, stream, struct_value, try_value, unary_op, user_iterator,
+};
+use crate::lexer::Span;
+use crate::value::{Closure, Value};
+use std::any::Any;
+use std::rc::Rc;
+
+// フレームから戻るときの処理
+enum Ret {
+    // クロージャ・トップレベル: 値をそのまま返す
+    Closure,
+    // 関数: 戻り値を型注釈に合わせ、&mut で渡された引数（番号）の値も返す
+    Func { keep: Vec<usize> },
+    // self を取るメソッド: &mut self なら呼び出し元の場所（変数のスタック上の位置とたどり方）に書き戻す
+    Method { place: Option<(usize, Vec<Step>)> },
+}
+
+struct Frame<'g> {
+    proto: Rc<Proto>,
+    ip: usize,
+    // 引数・局所変数のスロット 0 のスタック上の位置
+    base: usize,
+    // 呼び出し履歴に積む名前と、呼び出した位置
+    name: String,
+    call_span: Span,
+    ret: Ret,
+    _depth: Option<Depth<'g>>,
+}
+
+impl Frame<'_> {
+    // 実行中の命令を囲む式の位置
+    fn span(&self) -> Option<Span> {
+        self.proto.spans[self.ip.saturating_sub(1)]
+    }
+}
+
+struct Vm<'g> {
+    globals: &'g Globals,
+    program: &'g Program,
+    stack: Vec<Value>,
+    frames: Vec<Frame<'g>>,
+}
+
+// プログラムを実行して最後の文の値を返す。トップレベルの変数は、
+// 実行したところまで（エラーで止まったときも）vars に残す
+pub(in crate::eval) fn run(
+    globals: &Globals,
+    program: &Program,
+    vars: &mut Vars,
+) -> Result<Value, RuntimeError> {
+    let mut vm = Vm {
+        globals,
+        program,
+        stack: Vec::new(),
+        frames: Vec::new(),
+    };
+    let main = &program.main;
+    vm.push_frame(
+        main.clone(),
+        0,
+        String::new(),
+        Span::default(),
+        Ret::Closure,
+        None,
+    );
+    // トップレベルの文から見た既存の変数は、1つ外側の関数の変数
+    for c in &main.captures {
+        if let Some(v) = vars.values.get(c.slot) {
+            vm.stack[c.to] = v.clone();
+        }
+    }
+    let result = vm.execute(0);
+    let executed = vm.frames[0].ip.saturating_sub(1);
+    for c in &main.captures {
+        if let Some(v) = vars.values.get_mut(c.slot) {
+            *v = vm.stack[c.to].clone();
+        }
+    }
+    let created = program
+        .globals
+        .iter()
+        .filter(|(_, _, at)| *at < executed)
+        .map(|(name, slot, _)| (name.clone(), vm.stack[*slot].clone()))
+        .collect::<Vec<_>>();
+    vars.extend(created);
+    result
+}
+
+// 組み込みのメソッドから呼ばれたクロージャを、呼び出し元とは別の VM で実行する
+fn call_closure_in(
+    globals: &Globals,
+    program: &Program,
+    f: &Closure,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let Some(proto) = compiled(program, f) else {
+        return call_closure(f, args, globals);
+    };
+    check_arity("クロージャ", f.params.len(), args.len())?;
+    let depth = enter(globals)?;
+    let mut vm = Vm {
+        globals,
+        program,
+        stack: args,
+        frames: Vec::new(),
+    };
+    let argc = vm.stack.len();
+    vm.push_closure(
+        proto,
+        f,
+        argc,
+        "クロージャ".to_string(),
+        Span::default(),
+        depth,
+    );
+    grow_stack(|| vm.execute(0))
+}
+
+// このプログラムでコンパイルしたクロージャの本体
+fn compiled(program: &Program, f: &Closure) -> Option<Rc<Proto>> {
+    let code: Rc<dyn Any> = f.code.clone()?;
+    code.downcast::<Proto>()
+        .ok()
+        .filter(|proto| proto.program == program.id)
+}
+
+// パターンの変数をスロットに束縛する
+struct Slots<'a> {
+    stack: &'a mut [Value],
+    names: &'a [(String, usize)],
+}
+
+impl Bind for Slots<'_> {
+    fn bind(&mut self, name: &str, value: Value) {
+        if let Some((_, slot)) = self.names.iter().find(|(n, _)| n == name) {
+            self.stack[*slot] = value;
+        }
+    }
+}
+
+impl<'g> Vm<'g> {
+    fn push(&mut self, v: Value) {
+        self.stack.push(v);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("スタックが空です")
+    }
+
+    fn pop_n(&mut self, n: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - n)
+    }
+
+    // スタックの上の argc 個の値を引数とするフレームを積む
+    fn push_frame(
+        &mut self,
+        proto: Rc<Proto>,
+        argc: usize,
+        name: String,
+        call_span: Span,
+        ret: Ret,
+        depth: Option<Depth<'g>>,
+    ) {
+        let base = self.stack.len() - argc;
+        self.stack.resize(base + proto.locals, Value::Unit);
+        self.frames.push(Frame {
+            proto,
+            ip: 0,
+            base,
+            name,
+            call_span,
+            ret,
+            _depth: depth,
+        });
+    }
+
+    // クロージャは取り込んだ変数も置く
+    fn push_closure(
+        &mut self,
+        proto: Rc<Proto>,
+        f: &Closure,
+        argc: usize,
+        name: String,
+        call_span: Span,
+        depth: Depth<'g>,
+    ) {
+        self.push_frame(
+            proto.clone(),
+            argc,
+            name,
+            call_span,
+            Ret::Closure,
+            Some(depth),
+        );
+        let base = self.stack.len() - proto.locals;
+        for c in &proto.captures {
+            let frame = f.captured.len().checked_sub(c.depth);
+            if let Some(v) = frame.and_then(|i| f.captured[i].get(c.slot)) {
+                self.stack[base + c.to] = v.clone();
+            }
+        }
+    }
+
+    // floor 番目のフレームが return するまで実行する
+    fn execute(&mut self, floor: usize) -> Result<Value, RuntimeError> {
+        loop {
+            match self.step(floor) {
+                Ok(Some(v)) => return Ok(v),
+                Ok(None) => {}
+                Err(e) => return Err(self.unwind(e, floor)),
+            }
+        }
+    }
+
+    // エラーの位置と呼び出し履歴を付けながら floor 番目のフレームまで戻る
+    fn unwind(&mut self, mut e: RuntimeError, floor: usize) -> RuntimeError {
+        loop {
+            let frame = self.frames.last().expect("フレームがありません");
+            if let Some(span) = frame.span() {
+                e = e.at(span);
+            }
+            if self.frames.len() - 1 == floor {
+                return e;
+            }
+            let frame = self.frames.pop().expect("フレームがありません");
+            e = e.called(&frame.name, frame.call_span);
+        }
+    }
+
+    // 命令を1つ実行する。floor 番目のフレームが return したらその値を返す
+    fn step(&mut self, floor: usize) -> Result<Option<Value>, RuntimeError> {
+        limits::tick()?;
+        let frame = self.frames.last_mut().expect("フレームがありません");
+        let proto = frame.proto.clone();
+        let ip = frame.ip;
+        let base = frame.base;
+        frame.ip += 1;
+        let span = || proto.spans[ip].unwrap_or_default();
+        match proto.code[ip] {
+            Op::Const(i) => self.push(proto.consts[i as usize].clone()),
+            Op::Unit => self.push(Value::Unit),
+            Op::Load(slot) => self.push(self.stack[base + slot as usize].clone()),
+            Op::Store(slot) => self.stack[base + slot as usize] = self.pop(),
+            Op::Pop => {
+                self.pop();
+            }
+            Op::Binary(op) => {
+                let r = self.pop();
+                let l = self.pop();
+                self.push(binary_op(op, l, r)?);
+            }
+            Op::AdaptLiteral(left) => {
+                let r = self.pop();
+                let l = self.pop();
+                if left {
+                    self.push(adapt_literal(l, &r)?);
+                    self.push(r);
+                } else {
+                    let r = adapt_literal(r, &l)?;
+                    self.push(l);
+                    self.push(r);
+                }
+            }
+            Op::ShortCircuit(op, to) => {
+                let l = self.stack.last_mut().expect("スタックが空です");
+                let short = match op {
+                    crate::ast::BinOp::And => !expect_bool(l, "&&")?,
+                    _ => expect_bool(l, "||")?,
+                };
+                if short {
+                    *l = Value::Bool(op != crate::ast::BinOp::And);
+                    self.jump(to);
+                }
+            }
+            Op::Unary(op) => {
+                let v = self.pop();
+                self.push(unary_op(op, v)?);
+            }
+            Op::Cast(i) => {
+                let v = self.pop();
+                self.push(cast(v, &proto.names[i as usize])?);
+            }
+            Op::Coerce(i) => {
+                let v = self.pop();
+                self.push(coerce::coerce(v, &proto.types[i as usize])?);
+            }
+            Op::Format(i, argc) => {
+                let values = self.pop_n(argc as usize);
+                self.push(format_pieces(&proto.formats[i as usize], &values)?);
+            }
+            Op::MakeTuple(n) => {
+                let items = self.pop_n(n as usize);
+                self.push(Value::Tuple(items));
+            }
+            Op::MakeArray(n) => {
+                let items = self.pop_n(n as usize);
+                self.push(Value::Array(Rc::new(items)));
+            }
+            Op::ArrayRepeat => {
+                let count = self.pop();
+                let v = self.pop();
+                self.push(array_repeat(v, count)?);
+            }
+            Op::RangeBound => {
+                let v = self.pop();
+                self.push(Value::Int(range_bound(v)?));
+            }
+            Op::MakeRange {
+                start,
+                end,
+                inclusive,
+            } => {
+                let mut bound = |present: bool| present.then(|| self.pop().as_i64().unwrap_or(0));
+                let end = bound(end);
+                let start = bound(start);
+                self.push(Value::Range {
+                    start,
+                    end,
+                    inclusive,
+                });
+            }
+            Op::MakeStruct(i) => {
+                let info = &proto.structs[i as usize];
+                let values = self.pop_n(info.fields.len());
+                let values = info.fields.iter().cloned().zip(values).collect();
+                self.push(struct_value(&info.name, &info.defs, values)?);
+            }
+            Op::MakeEnum(i, argc) => {
+                let (enum_name, index, path) = &proto.variants[i as usize];
+                let fields = self.pop_n(argc as usize);
+                self.push(enum_value(enum_name, *index, path, fields));
+            }
+            Op::GetField(i) => {
+                let field = &proto.names[i as usize];
+                let v = match self.pop() {
+                    t @ Value::Host(_) => property(self.globals, t, field, None)?,
+                    t => place::field_value(&t, field)?,
+                };
+                self.push(v);
+            }
+            Op::Index => {
+                let i = self.pop();
+                let t = self.pop();
+                self.push(place::index_value(&t, &i, span())?);
+            }
+            Op::Closure(i) => {
+                let (number, slots) = &proto.closures[i as usize];
+                let code = self.program.closures[*number].clone();
+                // 木をたどる評価器でも呼べるよう、外側の関数ごとのフレームの形にする
+                let depth = code.captures.iter().map(|c| c.depth).max().unwrap_or(0);
+                let mut captured = vec![Vec::new(); depth];
+                for (c, from) in code.captures.iter().zip(slots) {
+                    let frame = &mut captured[depth - c.depth];
+                    if frame.len() <= c.slot {
+                        frame.resize(c.slot + 1, Value::Unit);
+                    }
+                    if let Some(from) = from {
+                        frame[c.slot] = self.stack[base + from].clone();
+                    }
+                }
+                let (params, body) = code.source.clone().expect("クロージャの本体がありません");
+                self.push(Value::Closure(Rc::new(Closure {
+                    params,
+                    body,
+                    captured,
+                    code: Some(code as Rc<dyn Any>),
+                })));
+            }
+            Op::FuncValue(i) => {
+                let code = self.program.funcs[i as usize].clone();
+                let f = &self.globals.funcs[&code.name];
+                // self を取るメソッドは木をたどる評価器で呼ぶ
+                let code = match code.kind {
+                    Kind::Func {
+                        self_method: false, ..
+                    } => Some(code as Rc<dyn Any>),
+                    _ => None,
+                };
+                self.push(Value::Closure(Rc::new(Closure {
+                    params: f.params.clone(),
+                    body: f.body.clone(),
+                    captured: Vec::new(),
+                    code,
+                })));
+            }
+            Op::Jump(to) => self.jump(to),
+            Op::JumpIfFalse(to) => {
+                let c = self.pop();
+                if !expect_bool(&c, "if")? {
+                    self.jump(to);
+                }
+            }
+            Op::IntoIter(slot) => {
+                let v = self.pop();
+                let method = format!("{}::next", v.type_name());
+                // Iterator を実装したユーザー定義の値はそのまま入れ、next() を呼んで進める
+                self.stack[base + slot as usize] =
+                    if user_iterator(&v) && self.program.func_index.contains_key(&method) {
+                        v
+                    } else {
+                        Value::Iter(iter::into_iter(v)?)
+                    };
+            }
+            Op::IterNext(slot, to) => {
+                let slot = base + slot as usize;
+                let Value::Iter(it) = &self.stack[slot] else {
+                    let this = self.stack[slot].clone();
+                    let method = format!("{}::next", this.type_name());
+                    let code = self.program.funcs[self.program.func_index[&method]].clone();
+                    let place = Some((slot, Vec::new()));
+                    return self
+                        .call_self(code, method, this, Vec::new(), span(), place)
+                        .map(|()| None);
+                };
+                // 本体から同じイテレータを触れるよう、借用はnextの間だけにする
+                let next = it.clone().borrow_mut().next();
+                match next {
+                    Some(v) => {
+                        self.push(v);
+                        self.jump(ip as u32 + 2);
+                    }
+                    None => self.jump(to),
+                }
+            }
+            Op::IterSome(slot, to) => {
+                let v = self.pop();
+                let method = format!("{}::next", self.stack[base + slot as usize].type_name());
+                match next_item(&method, v).map_err(|e| e.called(&method, span()))? {
+                    Some(v) => self.push(v),
+                    None => self.jump(to),
+                }
+            }
+            Op::Let(i) => {
+                let v = self.pop();
+                if !self.bind(&proto.patterns[i as usize], base, &v)? {
+                    return fail(
+                        ErrorKind::Pattern,
+                        format!("let のパターンに一致しません: {:?}", v),
+                    );
+                }
+            }
+            Op::ForBind(i) => {
+                let v = self.pop();
+                if !self.bind(&proto.patterns[i as usize], base, &v)? {
+                    return fail(
+                        ErrorKind::Pattern,
+                        format!("forのパターンに一致しません: {:?}", v),
+                    );
+                }
+            }
+            Op::Match(i, slot, to) => {
+                let v = self.stack[base + slot as usize].clone();
+                if !self.bind(&proto.patterns[i as usize], base, &v)? {
+                    self.jump(to);
+                }
+            }
+            Op::BindParam(i, slot) => {
+                let v = self.stack[base + slot as usize].clone();
+                if !self.bind(&proto.patterns[i as usize], base, &v)? {
+                    // クロージャとして呼ばれた関数も call_closure と同じ名前で報告する
+                    let frame = self.frames.last().expect("フレームがありません");
+                    let name = match frame.ret {
+                        Ret::Closure => "クロージャ",
+                        _ => &proto.name,
+                    };
+                    return fail(
+                        ErrorKind::Pattern,
+                        format!("{} の引数がパターンに一致しません: {:?}", name, v),
+                    );
+                }
+            }
+            Op::Return => {
+                let v = self.pop();
+                return self.ret(v, floor);
+            }
+            Op::Try => {
+                let v = self.pop();
+                match try_value(v)? {
+                    Ok(v) => self.push(v),
+                    Err(v) => return self.ret(v, floor),
+                }
+            }
+            Op::Call(i) => {
+                let info = &proto.calls[i as usize];
+                let code = self.program.funcs[info.func].clone();
+                let depth = enter(self.globals).map_err(|e| e.called(&info.name, span()))?;
+                let ret = Ret::Func {
+                    keep: info.keep.clone(),
+                };
+                self.push_frame(code, info.argc, info.name.clone(), span(), ret, Some(depth));
+            }
+            Op::CallValue(i, argc) => {
+                let args = self.pop_n(argc as usize);
+                let f = self.pop();
+                self.call_value(&proto.names[i as usize], f, args, span())?;
+            }
+            Op::CallNative(i, argc) => {
+                let args = self.pop_n(argc as usize);
+                let name = &proto.names[i as usize];
+                let Some(f) = self.globals.natives.get(name) else {
+                    return fail(ErrorKind::Undefined, format!("未定義の関数: {}", name));
+                };
+                self.push(f(args)?);
+            }
+            Op::CallStd(i, argc) => {
+                let args = self.pop_n(argc as usize);
+                self.push((proto.stds[i as usize].1)(args)?);
+            }
+            Op::CallMethod(i) => self.call_method(&proto, i as usize, base, span())?,
+            Op::LoadPlace(i) => {
+                let place = &proto.places[i as usize];
+                let steps = self.steps(place);
+                let root = self.root(place, base)?;
+                let v = place::resolve(root, &steps)?.clone();
+                self.push(v);
+            }
+            Op::StorePlace(i, op) => {
+                let place = &proto.places[i as usize];
+                let steps = self.steps(place);
+                let v = self.pop();
+                self.store(place, base, steps, op, v)?;
+                self.push(Value::Unit);
+            }
+            Op::Writeback(i) => {
+                let place = &proto.places[i as usize];
+                let steps = self.steps(place);
+                let v = self.pop();
+                *place::resolve(self.root(place, base)?, &steps)? = v;
+            }
+            Op::EntryTemp => {
+                let default = self.pop();
+                let key = self.pop();
+                let mut tmp = self.pop();
+                let v = place::resolve(&mut tmp, &[Step::Entry(key, default)])?.clone();
+                self.push(v);
+            }
+            Op::Print => {
+                let v = self.stack.last().expect("スタックが空です").clone();
+                (self.globals.std_funcs["print"])(vec![v])?;
+            }
+            Op::ParseError(i) => {
+                stream::eprint(&format!("[解析エラー] {}", proto.names[i as usize]))?;
+            }
+            Op::Panic(i) => {
+                let info = &proto.panics[i as usize];
+                let message = info.message.then(|| self.pop().to_string());
+                let values = self.pop_n(info.argc);
+                panic_macro(info.kind, values, &info.text, message)?;
+                self.push(Value::Unit);
+            }
+            Op::Raise(i) => return Err(proto.errors[i as usize].clone()),
+        }
+        Ok(None)
+    }
+
+    fn jump(&mut self, to: u32) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.ip = to as usize;
+        }
+    }
+
+    fn bind(&mut self, p: &PatternSlots, base: usize, v: &Value) -> Result<bool, RuntimeError> {
+        let mut slots = Slots {
+            stack: &mut self.stack[base..],
+            names: &p.slots,
+        };
+        bind_pattern(&p.pattern, v, &mut slots)
+    }
+
+    // 関数から戻る。&mut の引数・&mut self は呼び出し元に書き戻し、戻り値を型注釈に合わせる
+    fn ret(&mut self, v: Value, floor: usize) -> Result<Option<Value>, RuntimeError> {
+        if self.frames.len() - 1 == floor {
+            return Ok(Some(v));
+        }
+        let frame = self.frames.pop().expect("フレームがありません");
+        let slot = |i: usize| self.stack[frame.base + i].clone();
+        let called = |e: RuntimeError| e.called(&frame.name, frame.call_span);
+        match &frame.ret {
+            Ret::Closure => {
+                self.stack.truncate(frame.base);
+                self.push(v);
+            }
+            Ret::Func { keep } => {
+                let kept: Vec<Value> = keep.iter().map(|i| slot(*i)).collect();
+                self.stack.truncate(frame.base);
+                let v = coerce::coerce(v, &frame.proto.ret).map_err(called)?;
+                self.push(v);
+                self.stack.extend(kept.into_iter().rev());
+            }
+            Ret::Method { place } => {
+                let this = slot(0);
+                self.stack.truncate(frame.base);
+                if let Some((root, steps)) = place {
+                    *place::resolve(&mut self.stack[*root], steps).map_err(called)? = this;
+                }
+                let v = coerce::coerce(v, &frame.proto.ret).map_err(called)?;
+                self.push(v);
+            }
+        }
+        Ok(None)
+    }
+
+    // 場所をたどるのに使う値をスタックから降ろす
+    fn steps(&mut self, place: &Place) -> Vec<Step> {
+        let n = place
+            .steps
+            .iter()
+            .map(|s| match s {
+                StepKind::Index(_) => 1,
+                StepKind::Field(_) => 0,
+                StepKind::Entry => 2,
+            })
+            .sum();
+        let mut values = self.pop_n(n).into_iter();
+        let mut next = || values.next().expect("場所の値が足りません");
+        place
+            .steps
+            .iter()
+            .map(|s| match s {
+                StepKind::Index(span) => Step::Index(next(), *span),
+                StepKind::Field(f) => Step::Field(f.clone()),
+                StepKind::Entry => {
+                    let key = next();
+                    Step::Entry(key, next())
+                }
+            })
+            .collect()
+    }
+
+    fn root(&mut self, place: &Place, base: usize) -> Result<&mut Value, RuntimeError> {
+        match place.root {
+            Some(slot) => Ok(&mut self.stack[base + slot]),
+            None => fail(
+                ErrorKind::Undefined,
+                format!("未定義の変数: {}", place.name),
+            ),
+        }
+    }
+
+    // 代入（ホストの値のプロパティへの代入は登録した setter を呼ぶ）
+    fn store(
+        &mut self,
+        place: &Place,
+        base: usize,
+        steps: Vec<Step>,
+        op: Option<crate::ast::BinOp>,
+        v: Value,
+    ) -> Result<(), RuntimeError> {
+        let globals = self.globals;
+        let root = self.root(place, base)?;
+        if let [prefix @ .., Step::Field(field)] = steps.as_slice()
+            && let obj @ Value::Host(_) = place::resolve(root, prefix)?
+        {
+            let obj = obj.clone();
+            let v = match op {
+                Some(op) => binary_op(op, property(globals, obj.clone(), field, None)?, v)?,
+                None => v,
+            };
+            property(globals, obj, field, Some(v))?;
+            return Ok(());
+        }
+        let place = place::resolve(root, &steps)?;
+        *place = match op {
+            Some(op) => binary_op(op, place.clone(), v)?,
+            None => v,
+        };
+        Ok(())
+    }
+
+    // 変数に入れた値の呼び出し。クロージャでなければ名前で関数を探す
+    fn call_value(
+        &mut self,
+        name: &str,
+        f: Value,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let globals = self.globals;
+        let v = match f {
+            Value::Closure(f) => match compiled(self.program, &f) {
+                Some(code) => {
+                    check_arity("クロージャ", f.params.len(), args.len())
+                        .map_err(|e| e.called(name, span))?;
+                    let depth = enter(globals).map_err(|e| e.called(name, span))?;
+                    let argc = args.len();
+                    self.stack.extend(args);
+                    self.push_closure(code, &f, argc, name.to_string(), span, depth);
+                    return Ok(());
+                }
+                None => call_closure(&f, args, globals).map_err(|e| e.called(name, span))?,
+            },
+            _ => {
+                if let Some(f) = globals.natives.get(name) {
+                    f(args)?
+                } else if let Some(f) = globals.std_funcs.get(name) {
+                    f(args)?
+                } else if let (Some(f), Some(&i)) =
+                    (globals.funcs.get(name), self.program.func_index.get(name))
+                {
+                    check_arity(name, f.params.len(), args.len())?;
+                    for (v, ty) in args.into_iter().zip(&f.param_tys) {
+                        let v = coerce::coerce(v, ty)?;
+                        self.push(v);
+                    }
+                    let depth = enter(globals).map_err(|e| e.called(name, span))?;
+                    let code = self.program.funcs[i].clone();
+                    let ret = Ret::Func { keep: Vec::new() };
+                    let argc = f.params.len();
+                    self.push_frame(code, argc, name.to_string(), span, ret, Some(depth));
+                    return Ok(());
+                } else if let Some((enum_name, index, arity)) = globals.variants.get(name) {
+                    if *arity != args.len() {
+                        return fail(
+                            ErrorKind::Type,
+                            format!("{} の要素の数が一致しません", name),
+                        );
+                    }
+                    enum_value(enum_name, *index, name, args)
+                } else {
+                    return fail(ErrorKind::Undefined, format!("未定義の関数: {}", name));
+                }
+            }
+        };
+        self.push(v);
+        Ok(())
+    }
+
+    // メソッド呼び出し。impl で定義したメソッドはフレームを積み、
+    // 登録したメソッド・組み込みのメソッドはその場で呼ぶ
+    fn call_method(
+        &mut self,
+        proto: &Proto,
+        i: usize,
+        base: usize,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let info = &proto.methods[i];
+        let (globals, program) = (self.globals, self.program);
+        let place = info.place.map(|p| &proto.places[p]);
+        let steps = place.map(|p| self.steps(p));
+        let mut tmp = match place {
+            Some(_) => Value::Unit,
+            None => self.pop(),
+        };
+        let mut args = self.pop_n(info.argc);
+        let recv = match (place, &steps) {
+            (Some(place), Some(steps)) => place::resolve(self.root(place, base)?, steps)?,
+            _ => &mut tmp,
+        };
+        let method = format!("{}::{}", recv.type_name(), info.name);
+        if let Some(&f) = program.func_index.get(&method) {
+            let this = recv.clone();
+            let code = program.funcs[f].clone();
+            let Kind::Func { mut_self: true, .. } = code.kind else {
+                return self.call_self(code, method, this, args, span, None);
+            };
+            let place = place.and_then(|p| Some((base + p.root?, steps?)));
+            return self.call_self(code, method, this, args, span, place);
+        }
+        let v = if let Some(f) = globals.natives.get(&method) {
+            // 登録したメソッドは受け手を最初の引数として受け取る
+            args.insert(0, recv.clone());
+            f(args)?
+        } else {
+            // map・and_then などに渡されたクロージャの呼び出し
+            let call = |f: &Value, args: Vec<Value>| match f {
+                Value::Closure(f) => call_closure_in(globals, program, f, args)
+                    .map_err(|e| e.called("クロージャ", span)),
+                other => fail(
+                    ErrorKind::Type,
+                    format!("{} は呼び出せません", other.type_name()),
+                ),
+            };
+            methods::call_method(recv, &info.name, args, &call)?
+        };
+        self.push(v);
+        Ok(())
+    }
+
+    // self を取るメソッドのフレームを積む。place は &mut self を書き戻す場所
+    fn call_self(
+        &mut self,
+        code: Rc<Proto>,
+        name: String,
+        this: Value,
+        args: Vec<Value>,
+        span: Span,
+        place: Option<(usize, Vec<Step>)>,
+    ) -> Result<(), RuntimeError> {
+        let Kind::Func {
+            self_method: true, ..
+        } = code.kind
+        else {
+            return Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!("{} は self を取らないのでメソッドとして呼べません", name),
+            )
+            .called(&name, span));
+        };
+        check_arity(&name, code.arity - 1, args.len()).map_err(|e| e.called(&name, span))?;
+        let depth = enter(self.globals).map_err(|e| e.called(&name, span))?;
+        let argc = code.arity;
+        self.push(this);
+        self.stack.extend(args);
+        self.push_frame(code, argc, name, span, Ret::Method { place }, Some(depth));
+        Ok(())
+    }
+}
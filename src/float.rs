@@ -0,0 +1,121 @@
+// 浮動小数点数型（f32, f64）
+// 値は f64 で持ち、f32 の値は f32 で表せる値に丸めておく
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FloatTy {
+    F32,
+    F64,
+}
+
+impl FloatTy {
+    pub fn from_name(name: &str) -> Option<FloatTy> {
+        match name {
+            "f32" => Some(FloatTy::F32),
+            "f64" => Some(FloatTy::F64),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FloatTy::F32 => "f32",
+            FloatTy::F64 => "f64",
+        }
+    }
+
+    // 型で表せる値に丸める
+    pub fn round(self, x: f64) -> f64 {
+        match self {
+            FloatTy::F32 => x as f32 as f64,
+            FloatTy::F64 => x,
+        }
+    }
+}
+
+// f64::consts::PI / f32::MAX などの定数
+pub fn constant(path: &str) -> Option<(FloatTy, f64)> {
+    let path = path.strip_prefix("std::").unwrap_or(path);
+    let (ty, name) = path.split_once("::")?;
+    let ty = FloatTy::from_name(ty)?;
+    let name = name.strip_prefix("consts::").unwrap_or(name);
+    let x = match (ty, name) {
+        (_, "PI") => std::f64::consts::PI,
+        (_, "TAU") => std::f64::consts::TAU,
+        (_, "E") => std::f64::consts::E,
+        (_, "SQRT_2") => std::f64::consts::SQRT_2,
+        (_, "LN_2") => std::f64::consts::LN_2,
+        (_, "LN_10") => std::f64::consts::LN_10,
+        (_, "FRAC_PI_2") => std::f64::consts::FRAC_PI_2,
+        (_, "INFINITY") => f64::INFINITY,
+        (_, "NEG_INFINITY") => f64::NEG_INFINITY,
+        (_, "NAN") => f64::NAN,
+        (FloatTy::F32, "MAX") => f32::MAX as f64,
+        (FloatTy::F32, "MIN") => f32::MIN as f64,
+        (FloatTy::F32, "EPSILON") => f32::EPSILON as f64,
+        (FloatTy::F64, "MAX") => f64::MAX,
+        (FloatTy::F64, "MIN") => f64::MIN,
+        (FloatTy::F64, "EPSILON") => f64::EPSILON,
+        _ => return None,
+    };
+    Some((ty, ty.round(x)))
+}
+
+// 引数を取らない数学メソッド（sqrt, sin など）。ない名前なら None
+pub fn unary_method(ty: FloatTy, name: &str, x: f64) -> Option<f64> {
+    // f32 の計算は f32 で行う（f64 で計算して丸めると結果が変わることがある）
+    if ty == FloatTy::F32 {
+        let x = x as f32;
+        let y = match name {
+            "sqrt" => x.sqrt(),
+            "abs" => x.abs(),
+            "floor" => x.floor(),
+            "ceil" => x.ceil(),
+            "round" => x.round(),
+            "trunc" => x.trunc(),
+            "sin" => x.sin(),
+            "cos" => x.cos(),
+            "tan" => x.tan(),
+            "ln" => x.ln(),
+            "log10" => x.log10(),
+            "exp" => x.exp(),
+            _ => return None,
+        };
+        return Some(y as f64);
+    }
+    let y = match name {
+        "sqrt" => x.sqrt(),
+        "abs" => x.abs(),
+        "floor" => x.floor(),
+        "ceil" => x.ceil(),
+        "round" => x.round(),
+        "trunc" => x.trunc(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "ln" => x.ln(),
+        "log10" => x.log10(),
+        "exp" => x.exp(),
+        _ => return None,
+    };
+    Some(y)
+}
+
+// powi の計算（f32 は f32 で）
+pub fn powi(ty: FloatTy, x: f64, n: i32) -> f64 {
+    match ty {
+        FloatTy::F32 => (x as f32).powi(n) as f64,
+        FloatTy::F64 => x.powi(n),
+    }
+}
+
+// 同じ型の値を2つ取る数学メソッド（powf, min, max）
+pub fn binary_method(ty: FloatTy, name: &str, x: f64, y: f64) -> Option<f64> {
+    let z = match name {
+        "powf" if ty == FloatTy::F32 => (x as f32).powf(y as f32) as f64,
+        "powf" => x.powf(y),
+        "min" => x.min(y),
+        "max" => x.max(y),
+        _ => return None,
+    };
+    Some(z)
+}
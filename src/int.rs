@@ -0,0 +1,389 @@
+// 大きさの決まった整数型（i8..i128, u8..u128, isize, usize）の演算
+// 値は型のビット幅に切り詰めたビット列（u128）で持ち、符号付きの型は2の補数として読む
+use crate::ast::BinOp;
+use crate::float::FloatTy;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IntTy {
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+}
+
+// 算術演算があふれたときの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    // Rust のデバッグビルドと同じく実行時エラーにする（既定）
+    Panic,
+    // リリースビルドと同じく桁あふれを切り捨てる
+    Wrap,
+}
+
+// 整数演算の失敗（評価器が実行時エラーにする）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArithError {
+    DivideByZero,
+    // あふれた演算・範囲に収まらない値の説明
+    Overflow(String),
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithError::DivideByZero => write!(f, "0 で除算しました"),
+            ArithError::Overflow(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+thread_local! {
+    static OVERFLOW: Cell<Overflow> = const { Cell::new(Overflow::Panic) };
+}
+
+pub fn set_overflow(mode: Overflow) {
+    OVERFLOW.with(|o| o.set(mode));
+}
+
+pub fn overflow() -> Overflow {
+    OVERFLOW.with(|o| o.get())
+}
+
+impl IntTy {
+    pub const ALL: [IntTy; 12] = [
+        IntTy::I8,
+        IntTy::I16,
+        IntTy::I32,
+        IntTy::I64,
+        IntTy::I128,
+        IntTy::Isize,
+        IntTy::U8,
+        IntTy::U16,
+        IntTy::U32,
+        IntTy::U64,
+        IntTy::U128,
+        IntTy::Usize,
+    ];
+
+    pub fn from_name(name: &str) -> Option<IntTy> {
+        IntTy::ALL.into_iter().find(|t| t.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntTy::I8 => "i8",
+            IntTy::I16 => "i16",
+            IntTy::I32 => "i32",
+            IntTy::I64 => "i64",
+            IntTy::I128 => "i128",
+            IntTy::Isize => "isize",
+            IntTy::U8 => "u8",
+            IntTy::U16 => "u16",
+            IntTy::U32 => "u32",
+            IntTy::U64 => "u64",
+            IntTy::U128 => "u128",
+            IntTy::Usize => "usize",
+        }
+    }
+
+    // isize・usize は64ビットとする
+    pub fn bits(self) -> u32 {
+        match self {
+            IntTy::I8 | IntTy::U8 => 8,
+            IntTy::I16 | IntTy::U16 => 16,
+            IntTy::I32 | IntTy::U32 => 32,
+            IntTy::I64 | IntTy::U64 | IntTy::Isize | IntTy::Usize => 64,
+            IntTy::I128 | IntTy::U128 => 128,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(
+            self,
+            IntTy::I8 | IntTy::I16 | IntTy::I32 | IntTy::I64 | IntTy::I128 | IntTy::Isize
+        )
+    }
+
+    // ビット幅に切り詰める
+    pub fn truncate(self, bits: u128) -> u128 {
+        match self.bits() {
+            128 => bits,
+            n => bits & ((1 << n) - 1),
+        }
+    }
+
+    // 符号付きの型なら符号拡張して128ビットにする
+    fn extend(self, bits: u128) -> u128 {
+        let n = self.bits();
+        if !self.is_signed() || n == 128 {
+            return bits;
+        }
+        (((bits << (128 - n)) as i128) >> (128 - n)) as u128
+    }
+
+    // 値を i128 として読む（u128 の大きな値は収まらない）
+    pub fn to_i128(self, bits: u128) -> Option<i128> {
+        if self.is_signed() {
+            Some(self.extend(bits) as i128)
+        } else {
+            i128::try_from(bits).ok()
+        }
+    }
+
+    // 範囲内なら値のビット列を返す
+    pub fn from_i128(self, n: i128) -> Option<u128> {
+        let bits = n as u128;
+        if self.is_signed() {
+            (self.extend(self.truncate(bits)) == bits).then(|| self.truncate(bits))
+        } else {
+            (n >= 0 && self.truncate(bits) == bits).then_some(bits)
+        }
+    }
+
+    pub fn from_u128(self, n: u128) -> Option<u128> {
+        match i128::try_from(n) {
+            Ok(n) => self.from_i128(n),
+            Err(_) => (self == IntTy::U128).then_some(n),
+        }
+    }
+
+    // 符号を含めた整数リテラル（-n なら negative）の値。範囲外なら None
+    pub fn literal(self, n: u128, negative: bool) -> Option<u128> {
+        if negative {
+            0i128
+                .checked_sub_unsigned(n)
+                .and_then(|n| self.from_i128(n))
+        } else {
+            self.from_u128(n)
+        }
+    }
+
+    // 接尾辞がなく型も決まらなかった、i64 に収まらない（符号を除けば収まらない）リテラルの型。
+    // 負なら i64 / i128、正なら u64 / u128
+    pub fn unsuffixed(n: u128, negative: bool) -> IntTy {
+        match (negative, IntTy::I64.literal(n, negative)) {
+            (true, Some(_)) => IntTy::I64,
+            (true, None) => IntTy::I128,
+            _ if u64::try_from(n).is_ok() => IntTy::U64,
+            _ => IntTy::U128,
+        }
+    }
+
+    pub fn min(self) -> u128 {
+        if self.is_signed() {
+            self.truncate(1 << (self.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    pub fn max(self) -> u128 {
+        if self.is_signed() {
+            self.truncate(!0) >> 1
+        } else {
+            self.truncate(!0)
+        }
+    }
+
+    pub fn format(self, bits: u128) -> String {
+        match self.to_i128(bits) {
+            Some(n) => n.to_string(),
+            None => bits.to_string(),
+        }
+    }
+
+    pub fn compare(self, a: u128, b: u128) -> Ordering {
+        if self.is_signed() {
+            (self.extend(a) as i128).cmp(&(self.extend(b) as i128))
+        } else {
+            a.cmp(&b)
+        }
+    }
+}
+
+// あふれたら None
+pub fn checked(op: BinOp, ty: IntTy, a: u128, b: u128) -> Option<u128> {
+    if !ty.is_signed() {
+        let n = match op {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Sub => a.checked_sub(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Div => a.checked_div(b),
+            BinOp::Rem => a.checked_rem(b),
+            _ => unreachable!(),
+        }?;
+        return ty.from_u128(n);
+    }
+    let (a, b) = (ty.to_i128(a)?, ty.to_i128(b)?);
+    let n = match op {
+        BinOp::Add => a.checked_add(b),
+        BinOp::Sub => a.checked_sub(b),
+        BinOp::Mul => a.checked_mul(b),
+        BinOp::Div => a.checked_div(b),
+        // MIN % -1 は Rust では商と同じくあふれる（i128 で計算すると 0 になる）
+        BinOp::Rem => {
+            ty.from_i128(a.checked_div(b)?)?;
+            a.checked_rem(b)
+        }
+        _ => unreachable!(),
+    }?;
+    ty.from_i128(n)
+}
+
+// あふれた桁は切り捨てる（0 での除算はエラー）
+pub fn wrapping(op: BinOp, ty: IntTy, a: u128, b: u128) -> Result<u128, ArithError> {
+    if matches!(op, BinOp::Div | BinOp::Rem) && b == 0 {
+        return Err(ArithError::DivideByZero);
+    }
+    let n = match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Sub => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        _ if ty.is_signed() => {
+            let (a, b) = (ty.extend(a) as i128, ty.extend(b) as i128);
+            let n = if op == BinOp::Div {
+                a.wrapping_div(b)
+            } else {
+                a.wrapping_rem(b)
+            };
+            n as u128
+        }
+        BinOp::Div => a / b,
+        _ => a % b,
+    };
+    Ok(ty.truncate(n))
+}
+
+// あふれたら型の最小値・最大値に張り付く
+pub fn saturating(op: BinOp, ty: IntTy, a: u128, b: u128) -> Result<u128, ArithError> {
+    if matches!(op, BinOp::Div | BinOp::Rem) && b == 0 {
+        return Err(ArithError::DivideByZero);
+    }
+    if let Some(n) = checked(op, ty, a, b) {
+        return Ok(n);
+    }
+    let negative = |x: u128| ty.is_signed() && (ty.extend(x) as i128) < 0;
+    let to_min = match op {
+        BinOp::Add => negative(b),
+        BinOp::Sub => !negative(b),
+        BinOp::Mul => negative(a) != negative(b),
+        // i::MIN / -1 だけがあふれる
+        BinOp::Div => false,
+        // i128::MIN % -1 の余りは 0
+        _ => return Ok(0),
+    };
+    Ok(if to_min { ty.min() } else { ty.max() })
+}
+
+// 二項演算（+ - * / %）。あふれたときは設定に従う
+pub fn arith(op: BinOp, ty: IntTy, a: u128, b: u128) -> Result<u128, ArithError> {
+    if matches!(op, BinOp::Div | BinOp::Rem) && b == 0 {
+        return Err(ArithError::DivideByZero);
+    }
+    if let Some(n) = checked(op, ty, a, b) {
+        return Ok(n);
+    }
+    match overflow() {
+        Overflow::Wrap => wrapping(op, ty, a, b),
+        Overflow::Panic => Err(ArithError::Overflow(format!(
+            "{} の演算 {} {} {} があふれました",
+            ty.name(),
+            ty.format(a),
+            op_symbol(op),
+            ty.format(b)
+        ))),
+    }
+}
+
+// 型注釈のない i64 の値を ty の値にする
+pub fn fit(ty: IntTy, n: i64) -> Result<u128, ArithError> {
+    ty.from_i128(n as i128)
+        .ok_or_else(|| ArithError::Overflow(format!("{} は {} の範囲に収まりません", n, ty.name())))
+}
+
+// 符号付きの型の符号反転（符号なしの型かどうかは呼び出し側で確かめる）
+pub fn neg(ty: IntTy, a: u128) -> Result<u128, ArithError> {
+    arith(BinOp::Sub, ty, 0, a).map_err(|_| {
+        ArithError::Overflow(format!(
+            "{} の値 {} の符号反転があふれました",
+            ty.name(),
+            ty.format(a)
+        ))
+    })
+}
+
+// 型注釈に合わせて、別の整数型の値をその型にする（型の決まらなかったリテラルの値など）
+pub fn convert(from: IntTy, bits: u128, to: IntTy) -> Result<u128, ArithError> {
+    let value = from.to_i128(bits);
+    let converted = match value {
+        Some(n) => to.from_i128(n),
+        None => to.from_u128(bits),
+    };
+    converted.ok_or_else(|| {
+        ArithError::Overflow(format!(
+            "{} は {} の範囲に収まりません",
+            from.format(bits),
+            to.name()
+        ))
+    })
+}
+
+// i64::MAX / u8::MIN / u32::BITS などの定数
+pub fn constant(path: &str) -> Option<(IntTy, u128)> {
+    let path = path.strip_prefix("std::").unwrap_or(path);
+    let (ty, name) = path.split_once("::")?;
+    let ty = IntTy::from_name(ty)?;
+    match name {
+        "MAX" => Some((ty, ty.max())),
+        "MIN" => Some((ty, ty.min())),
+        "BITS" => Some((IntTy::U32, ty.bits() as u128)),
+        _ => None,
+    }
+}
+
+// `as` による整数同士の変換（符号拡張してから切り詰める）
+pub fn cast(from: IntTy, bits: u128, to: IntTy) -> u128 {
+    to.truncate(from.extend(bits))
+}
+
+// `as` による浮動小数点数への変換（最も近い値に丸める）
+pub fn to_float(from: IntTy, bits: u128, to: FloatTy) -> f64 {
+    // f32 へは整数から直接丸める（f64 を経由すると二重に丸めてしまう）
+    match (from.to_i128(bits), to) {
+        (Some(n), FloatTy::F64) => n as f64,
+        (Some(n), FloatTy::F32) => n as f32 as f64,
+        (None, FloatTy::F64) => bits as f64,
+        (None, FloatTy::F32) => bits as f32 as f64,
+    }
+}
+
+// `as` による浮動小数点数からの変換（小数部は切り捨て、範囲外は最小値・最大値、NaN は 0）
+pub fn from_float(to: IntTy, x: f64) -> u128 {
+    if to.is_signed() {
+        let (min, max) = (to.to_i128(to.min()).unwrap(), to.to_i128(to.max()).unwrap());
+        to.truncate((x as i128).clamp(min, max) as u128)
+    } else {
+        (x as u128).min(to.max())
+    }
+}
+
+fn op_symbol(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        _ => "%",
+    }
+}
@@ -1,8 +1,25 @@
 // 字句解析（トークナイザー）
+use crate::float::FloatTy;
+use crate::int::IntTy;
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Deref;
+use std::str::Chars;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Number(i64),
+    // 接尾辞のある整数リテラル: 255u8 / 1_000_i128。
+    // 接尾辞がなく i64 に収まらないリテラルは None（型は型注釈や使われ方で決まる）
+    TypedNumber(u128, Option<IntTy>),
+    // 小数リテラル: 1.5 / 2e-3（接尾辞があれば型も）
+    Float(f64, Option<FloatTy>),
     Plus,
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
+    PercentEq,
     Pub,
     Fn,
     Ident(String),
@@ -10,42 +27,245 @@ pub enum Token {
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Comma,
     Let,
     Mut,
     Colon,
+    ColonColon,
+    Semicolon,
     Eq,
+    EqEq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Bang,
+    Amp,
+    At,
+    Hash,
+    AndAnd,
+    OrOr,
+    Pipe,
+    Question,
+    FatArrow,
+    // ->
+    Arrow,
+    Dot,
+    DotDot,
+    DotDotEq,
+    Return,
     StringLiteral(String),
+    CharLiteral(char),
     EOF,
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
-    // BOM（Byte Order Mark）があればスキップ
-    if let Some('\u{feff}') = input.chars().next() {
-        let mut chars = input.chars();
-        chars.next(); // skip BOM
-        return tokenize(&chars.collect::<String>());
+// ソース上の位置（1始まりの行・列）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+// トークン列と各トークンの位置。パーサーからは [Token] として扱える。
+#[derive(Debug, Clone, Default)]
+pub struct Tokens {
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    // 元のソース（assert! などで式の文字列を取り出すのに使う）
+    source: String,
+}
+
+impl Tokens {
+    // pos番目のトークンの位置（範囲外なら最後のトークンの位置）
+    pub fn span(&self, pos: usize) -> Span {
+        self.spans
+            .get(pos)
+            .or(self.spans.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // start番目からend番目の手前までのトークンのソース上の文字列
+    pub fn source_text(&self, start: usize, end: usize) -> String {
+        match (self.offset(start), self.offset(end)) {
+            (Some(from), Some(to)) if from <= to => self.source[from..to].trim().to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // pos番目のトークンの先頭のバイト位置（列は文字単位で数えている）
+    fn offset(&self, pos: usize) -> Option<usize> {
+        let span = self.spans.get(pos)?;
+        let line_start = match span.line {
+            1 => 0,
+            n => self.source.match_indices('\n').nth(n - 2)?.0 + 1,
+        };
+        let line = &self.source[line_start..];
+        let col = line
+            .char_indices()
+            .nth(span.col - 1)
+            .map_or(line.len(), |(i, _)| i);
+        Some(line_start + col)
+    }
+}
+
+impl Deref for Tokens {
+    type Target = [Token];
+
+    fn deref(&self) -> &[Token] {
+        &self.tokens
+    }
+}
+
+// 行・列を数えながら文字を読む
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    col: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
     }
+}
+
+pub fn tokenize(input: &str) -> Tokens {
+    // BOM（Byte Order Mark）があればスキップ
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
 
     let mut tokens = Vec::new();
-    let mut chars = input.chars().peekable();
+    let mut spans = Vec::new();
+    let mut chars = Cursor {
+        chars: input.chars().peekable(),
+        line: 1,
+        col: 1,
+    };
     while let Some(&c) = chars.peek() {
+        let span = Span {
+            line: chars.line,
+            col: chars.col,
+        };
         match c {
             '0'..='9' => {
-                let mut num = 0;
+                // 桁区切りの _ は読み飛ばす（1_000_000）
+                let mut num: u128 = 0;
+                let mut text = String::new();
                 while let Some(&d) = chars.peek() {
-                    if d.is_digit(10) {
-                        num = num * 10 + d.to_digit(10).unwrap() as i64;
+                    if let Some(digit) = d.to_digit(10) {
+                        num = num.saturating_mul(10).saturating_add(digit as u128);
+                        text.push(d);
+                        chars.next();
+                    } else if d == '_' {
                         chars.next();
                     } else {
                         break;
                     }
                 }
-                tokens.push(Token::Number(num));
+                // 小数部と指数部。t.0.1 のようなタプルの要素の並びは小数にしない
+                let mut is_float = false;
+                let mut ahead = chars.chars.clone();
+                if tokens.last() != Some(&Token::Dot)
+                    && ahead.next() == Some('.')
+                    && ahead.next().is_some_and(|c| c.is_ascii_digit())
+                {
+                    is_float = true;
+                    text.push('.');
+                    chars.next();
+                    while let Some(&d) = chars.peek() {
+                        if d.is_ascii_digit() {
+                            text.push(d);
+                        } else if d != '_' {
+                            break;
+                        }
+                        chars.next();
+                    }
+                }
+                let mut ahead = chars.chars.clone();
+                if matches!(ahead.next(), Some('e' | 'E')) {
+                    let sign = ahead.clone().next().filter(|c| matches!(c, '+' | '-'));
+                    if sign.is_some() {
+                        ahead.next();
+                    }
+                    if ahead.next().is_some_and(|c| c.is_ascii_digit()) {
+                        is_float = true;
+                        text.push('e');
+                        chars.next();
+                        if let Some(sign) = sign {
+                            text.push(sign);
+                            chars.next();
+                        }
+                        while let Some(&d) = chars.peek() {
+                            if !d.is_ascii_digit() {
+                                break;
+                            }
+                            text.push(d);
+                            chars.next();
+                        }
+                    }
+                }
+                // 型の接尾辞: 255u8 / 3usize
+                let suffix: String = chars
+                    .chars
+                    .clone()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .collect();
+                let float_ty = FloatTy::from_name(&suffix);
+                if float_ty.is_some() || is_float {
+                    if float_ty.is_some() {
+                        for _ in 0..suffix.len() {
+                            chars.next();
+                        }
+                    }
+                    // f32 のリテラルは f64 を経由せずに丸める
+                    let x = match float_ty {
+                        Some(FloatTy::F32) => text.parse::<f32>().map_or(0.0, f64::from),
+                        _ => text.parse().unwrap_or(0.0),
+                    };
+                    tokens.push(Token::Float(x, float_ty));
+                } else if let Some(ty) = IntTy::from_name(&suffix) {
+                    for _ in 0..suffix.len() {
+                        chars.next();
+                    }
+                    tokens.push(Token::TypedNumber(num, Some(ty)));
+                } else if let Ok(n) = i64::try_from(num) {
+                    tokens.push(Token::Number(n));
+                } else {
+                    tokens.push(Token::TypedNumber(num, None));
+                }
             }
             '+' => {
-                tokens.push(Token::Plus);
                 chars.next();
+                if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::PlusEq);
+                } else {
+                    tokens.push(Token::Plus);
+                }
             }
             '(' => {
                 tokens.push(Token::LParen);
@@ -59,6 +279,14 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                 tokens.push(Token::LBrace);
                 chars.next();
             }
+            '[' => {
+                tokens.push(Token::LBracket);
+                chars.next();
+            }
+            ']' => {
+                tokens.push(Token::RBracket);
+                chars.next();
+            }
             '}' => {
                 tokens.push(Token::RBrace);
                 chars.next();
@@ -68,13 +296,153 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                 chars.next();
             }
             '=' => {
-                tokens.push(Token::Eq);
                 chars.next();
+                match chars.peek() {
+                    Some('=') => {
+                        chars.next();
+                        tokens.push(Token::EqEq);
+                    }
+                    Some('>') => {
+                        chars.next();
+                        tokens.push(Token::FatArrow);
+                    }
+                    _ => tokens.push(Token::Eq),
+                }
+            }
+            '!' => {
+                chars.next();
+                if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::NotEq);
+                } else {
+                    tokens.push(Token::Bang);
+                }
+            }
+            '<' => {
+                chars.next();
+                if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::Le);
+                } else {
+                    tokens.push(Token::Lt);
+                }
+            }
+            '>' => {
+                chars.next();
+                if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::Ge);
+                } else {
+                    tokens.push(Token::Gt);
+                }
+            }
+            '&' => {
+                chars.next();
+                if let Some('&') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::AndAnd);
+                } else {
+                    tokens.push(Token::Amp);
+                }
+            }
+            '|' => {
+                chars.next();
+                if let Some('|') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::OrOr);
+                } else {
+                    tokens.push(Token::Pipe);
+                }
+            }
+            '-' => {
+                chars.next();
+                if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::MinusEq);
+                } else if let Some('>') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::Arrow);
+                } else {
+                    tokens.push(Token::Minus);
+                }
+            }
+            '*' => {
+                chars.next();
+                if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::StarEq);
+                } else {
+                    tokens.push(Token::Star);
+                }
+            }
+            '%' => {
+                chars.next();
+                if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::PercentEq);
+                } else {
+                    tokens.push(Token::Percent);
+                }
             }
             ':' => {
-                tokens.push(Token::Colon);
+                chars.next();
+                if let Some(':') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::ColonColon);
+                } else {
+                    tokens.push(Token::Colon);
+                }
+            }
+            ';' => {
+                tokens.push(Token::Semicolon);
+                chars.next();
+            }
+            '?' => {
+                tokens.push(Token::Question);
+                chars.next();
+            }
+            '@' => {
+                tokens.push(Token::At);
                 chars.next();
             }
+            '#' => {
+                tokens.push(Token::Hash);
+                chars.next();
+            }
+            '.' => {
+                chars.next();
+                if let Some('.') = chars.peek() {
+                    chars.next();
+                    if let Some('=') = chars.peek() {
+                        chars.next();
+                        tokens.push(Token::DotDotEq);
+                    } else {
+                        tokens.push(Token::DotDot);
+                    }
+                } else {
+                    tokens.push(Token::Dot);
+                }
+            }
+            '\'' => {
+                // 文字リテラル: 'a' '\n' など
+                chars.next();
+                let c = match chars.next() {
+                    Some('\\') => match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(other) => other,
+                        None => '\\',
+                    },
+                    Some(other) => other,
+                    None => break,
+                };
+                if let Some('\'') = chars.peek() {
+                    chars.next();
+                }
+                tokens.push(Token::CharLiteral(c));
+            }
             '"' => {
                 chars.next(); // skip opening quote
                 let mut s = String::new();
@@ -99,6 +467,11 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                             break;
                         }
                     }
+                } else if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token::SlashEq);
+                } else {
+                    tokens.push(Token::Slash);
                 }
             }
             ' ' | '\n' | '\r' | '\t' => {
@@ -123,6 +496,7 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                     "mut" => tokens.push(Token::Mut),
                     "pub" => tokens.push(Token::Pub),
                     "fn" => tokens.push(Token::Fn),
+                    "return" => tokens.push(Token::Return),
                     // "import"は予約語から除外
                     _ => tokens.push(Token::Ident(ident)),
                 }
@@ -132,7 +506,16 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                 chars.next();
             }
         }
+        spans.resize(tokens.len(), span);
     }
     tokens.push(Token::EOF);
-    tokens
+    spans.push(Span {
+        line: chars.line,
+        col: chars.col,
+    });
+    Tokens {
+        tokens,
+        spans,
+        source: input.to_string(),
+    }
 }
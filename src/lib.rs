@@ -1,4 +1,10 @@
 pub mod ast;
+pub mod check;
 pub mod eval;
+pub mod float;
+pub mod int;
 pub mod lexer;
 pub mod parser;
+pub mod prelude;
+pub mod resolve;
+pub mod value;
@@ -1,11 +1,7 @@
-mod ast;
-mod eval;
-mod lexer;
-mod parser;
-
-use eval::eval_stmts;
-use lexer::tokenize;
-use parser::parse;
+use nanai_simple_lang::check::check;
+use nanai_simple_lang::eval::{self, eval_stmts};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
 use std::io::{self, Write};
 
 fn main() {
@@ -14,8 +10,25 @@ fn main() {
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
     let input = input.trim();
-    let tokens = tokenize(&input);
+    let tokens = tokenize(input);
     let stmts = parse(&tokens);
-    let result = eval_stmts(&stmts);
-    println!("結果: {}", result);
+    // 評価の前に型を検査する
+    let errors = check(&stmts);
+    if !errors.is_empty() {
+        for e in &errors {
+            eprintln!("[型エラー] {}", e);
+        }
+        return;
+    }
+    // 深い再帰でもスタックがあふれないよう、大きなスタックのスレッドで評価する
+    let result = eval::with_stack(eval::DEFAULT_STACK_SIZE, move || {
+        eval_stmts(&stmts).map(|v| v.to_string())
+    });
+    match result {
+        Ok(result) => println!("結果: {}", result),
+        Err(e) => {
+            eprintln!("[実行時エラー] {}", e);
+            std::process::exit(1);
+        }
+    }
 }
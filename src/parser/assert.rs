@@ -0,0 +1,38 @@
+use crate::ast::{Expr, PanicKind};
+use crate::lexer::{Span, Token, Tokens};
+use crate::parser::expr::parse_expr;
+use crate::parser::format::parse_format_args;
+
+// panic!("...") / unreachable!() / todo!() / assert!(cond, "...") / assert_eq!(a, b) / assert_ne!(a, b)
+// 呼び出し側で名前と `!` を読んだ位置（`(` の位置）から読む
+pub fn parse_assert(tokens: &Tokens, pos: &mut usize, kind: PanicKind, span: Span) -> Expr {
+    *pos += 1; // (
+    let mut args = Vec::new();
+    let mut text = Vec::new();
+    for _ in 0..kind.arity() {
+        let start = *pos;
+        args.push(parse_expr(tokens, pos));
+        // エラーメッセージには式をソースに書かれたとおりに出す
+        text.push(tokens.source_text(start, *pos));
+        if tokens.get(*pos) == Some(&Token::Comma) {
+            *pos += 1;
+        }
+    }
+    // 残りは format! と同じ書式のメッセージ
+    let message = match tokens.get(*pos) {
+        Some(Token::StringLiteral(_)) => Some(Box::new(parse_format_args(tokens, pos, span))),
+        _ => {
+            if tokens.get(*pos) == Some(&Token::RParen) {
+                *pos += 1;
+            }
+            None
+        }
+    };
+    Expr::Panic {
+        kind,
+        args,
+        text,
+        message,
+        span,
+    }
+}
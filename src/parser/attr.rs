@@ -0,0 +1,48 @@
+use crate::lexer::{Token, Tokens};
+
+// 属性: #[name] / #[name(arg, ...)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attr {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+// 項目の前に並ぶ属性をすべて読む。閉じていない属性はエラーにする
+pub fn parse_attrs(tokens: &Tokens, pos: &mut usize) -> Result<Vec<Attr>, String> {
+    let mut attrs = Vec::new();
+    while tokens.get(*pos) == Some(&Token::Hash) && tokens.get(*pos + 1) == Some(&Token::LBracket) {
+        *pos += 2;
+        let Some(Token::Ident(name)) = tokens.get(*pos) else {
+            return Err("属性名が必要です".to_string());
+        };
+        let mut attr = Attr {
+            name: name.clone(),
+            args: Vec::new(),
+        };
+        *pos += 1;
+        if tokens.get(*pos) == Some(&Token::LParen) {
+            *pos += 1;
+            // EOF は読まずに止まる
+            loop {
+                match tokens.get(*pos) {
+                    Some(Token::RParen) => {
+                        *pos += 1;
+                        break;
+                    }
+                    Some(Token::EOF) | None => {
+                        return Err(format!("属性 {} の ( が閉じていません", attr.name));
+                    }
+                    Some(Token::Ident(arg)) => attr.args.push(arg.clone()),
+                    Some(_) => {}
+                }
+                *pos += 1;
+            }
+        }
+        if tokens.get(*pos) != Some(&Token::RBracket) {
+            return Err(format!("属性 {} の ] が必要です", attr.name));
+        }
+        *pos += 1;
+        attrs.push(attr);
+    }
+    Ok(attrs)
+}
@@ -0,0 +1,97 @@
+use crate::ast::Stmt;
+use crate::lexer::{Token, Tokens};
+use crate::parser::attr::Attr;
+use crate::parser::struct_def::derives;
+
+pub fn parse_enum(tokens: &Tokens, pos: &mut usize, attrs: &[Attr]) -> Option<Stmt> {
+    // [pub] enum <name><generics> { <variant>, <variant>(<type>, ...), ... }
+    let start = *pos;
+    if tokens.get(*pos) == Some(&Token::Pub) {
+        *pos += 1;
+    }
+    if tokens.get(*pos) != Some(&Token::Ident("enum".to_string())) {
+        *pos = start;
+        return None;
+    }
+    *pos += 1;
+    let span = tokens.span(*pos);
+    let name = if let Some(Token::Ident(n)) = tokens.get(*pos) {
+        *pos += 1;
+        n.clone()
+    } else {
+        return Some(Stmt::Error(
+            "列挙型の名前が必要です".to_string(),
+            tokens.span(*pos),
+        ));
+    };
+    let Some(mut generics) = crate::parser::ty::parse_generics(tokens, pos) else {
+        return Some(Stmt::Error(
+            format!("列挙型 {} の型引数が不正です", name),
+            tokens.span(*pos),
+        ));
+    };
+    if crate::parser::ty::parse_where(tokens, pos, &mut generics).is_none() {
+        return Some(Stmt::Error(
+            format!("列挙型 {} の where 節が不正です", name),
+            tokens.span(*pos),
+        ));
+    }
+    if tokens.get(*pos) != Some(&Token::LBrace) {
+        return Some(Stmt::Error("{ が必要です".to_string(), tokens.span(*pos)));
+    }
+    *pos += 1;
+    let mut variants = Vec::new();
+    loop {
+        match tokens.get(*pos) {
+            Some(Token::RBrace) => {
+                *pos += 1;
+                break;
+            }
+            Some(Token::Comma) => *pos += 1,
+            Some(Token::Ident(variant)) => {
+                let variant = variant.clone();
+                *pos += 1;
+                let mut fields = Vec::new();
+                match tokens.get(*pos) {
+                    Some(Token::LParen) => {
+                        *pos += 1;
+                        while tokens.get(*pos) != Some(&Token::RParen) {
+                            let Some(ty) = crate::parser::ty::parse_type(tokens, pos) else {
+                                return Some(Stmt::Error(
+                                    format!("バリアント {} の型が不正です", variant),
+                                    tokens.span(*pos),
+                                ));
+                            };
+                            fields.push(ty);
+                            if tokens.get(*pos) == Some(&Token::Comma) {
+                                *pos += 1;
+                            }
+                        }
+                        *pos += 1;
+                    }
+                    Some(Token::LBrace) => {
+                        return Some(Stmt::Error(
+                            format!("構造体形式のバリアント {} には対応していません", variant),
+                            tokens.span(*pos),
+                        ));
+                    }
+                    _ => {}
+                }
+                variants.push((variant, fields));
+            }
+            _ => {
+                return Some(Stmt::Error(
+                    "列挙型の定義が不正です".to_string(),
+                    tokens.span(*pos),
+                ));
+            }
+        }
+    }
+    Some(Stmt::EnumDef {
+        name,
+        generics,
+        variants,
+        derives: derives(attrs),
+        span,
+    })
+}
@@ -1,17 +1,44 @@
-use crate::ast::Expr;
-use crate::lexer::Token;
+use crate::ast::{BinOp, Expr, PanicKind, Pattern, Resolved, UnaryOp};
+use crate::eval::grow_stack;
+use crate::lexer::{Token, Tokens};
+use crate::parser::pattern::parse_pattern;
 
-pub fn parse_expr(tokens: &[Token], pos: &mut usize) -> Expr {
+// 括弧・ブロックなどで入れ子になった式は再帰して読むので、スタックを広げながら読む
+pub fn parse_expr(tokens: &Tokens, pos: &mut usize) -> Expr {
+    grow_stack(|| parse_expr_inner(tokens, pos))
+}
+
+fn parse_expr_inner(tokens: &Tokens, pos: &mut usize) -> Expr {
     // if式
     if let Some(Token::Ident(s)) = tokens.get(*pos) {
+        if s == "if" && tokens.get(*pos + 1) == Some(&Token::Let) {
+            // if let <pattern> = <expr> { .. } else { .. } は match に置き換える
+            *pos += 2;
+            let pattern = parse_pattern(tokens, pos);
+            if tokens.get(*pos) == Some(&Token::Eq) {
+                *pos += 1;
+            }
+            let scrutinee = parse_expr(tokens, pos);
+            let then_branch = parse_block(tokens, pos);
+            let else_branch = if tokens.get(*pos) == Some(&Token::Ident("else".to_string())) {
+                *pos += 1;
+                parse_else(tokens, pos)
+            } else {
+                Expr::Tuple(Vec::new())
+            };
+            return Expr::Match {
+                scrutinee: Box::new(scrutinee),
+                arms: vec![(pattern, then_branch), (Pattern::Wildcard, else_branch)],
+            };
+        }
         if s == "if" {
             *pos += 1;
             let cond = parse_expr(tokens, pos);
-            let then_branch = parse_expr(tokens, pos);
+            let then_branch = parse_block(tokens, pos);
             let else_branch = if let Some(Token::Ident(e)) = tokens.get(*pos) {
                 if e == "else" {
                     *pos += 1;
-                    Some(Box::new(parse_expr(tokens, pos)))
+                    Some(Box::new(parse_else(tokens, pos)))
                 } else {
                     None
                 }
@@ -26,68 +53,555 @@ pub fn parse_expr(tokens: &[Token], pos: &mut usize) -> Expr {
         }
         if s == "for" {
             *pos += 1;
-            // for <var> in <start>..<end> <body>
-            if let Some(Token::Ident(var)) = tokens.get(*pos) {
-                let var = var.clone();
+            // for <pattern> in <iter> <body>
+            let pattern = parse_pattern(tokens, pos);
+            if tokens.get(*pos) == Some(&Token::Ident("in".to_string())) {
                 *pos += 1;
-                if tokens.get(*pos) == Some(&Token::Ident("in".to_string())) {
-                    *pos += 1;
-                    let start = parse_expr(tokens, pos);
-                    if tokens.get(*pos) == Some(&Token::Colon) {
-                        *pos += 1;
-                    }
-                    let end = parse_expr(tokens, pos);
-                    let body = parse_expr(tokens, pos);
-                    return Expr::For {
-                        var,
-                        start: Box::new(start),
-                        end: Box::new(end),
-                        body: Box::new(body),
-                    };
-                }
             }
+            let iter = parse_expr(tokens, pos);
+            let body = parse_block(tokens, pos);
+            return Expr::For {
+                pattern,
+                iter: Box::new(iter),
+                body: Box::new(body),
+            };
+        }
+        if s == "match" {
+            *pos += 1;
+            return parse_match(tokens, pos);
         }
     }
-    // 加算式: term (+ term)*
-    let mut left = parse_term(tokens, pos);
-    while let Some(Token::Plus) = tokens.get(*pos) {
+    // return式: return <expr> / return
+    if let Some(Token::Return) = tokens.get(*pos) {
         *pos += 1;
-        let right = parse_term(tokens, pos);
-        left = Expr::Add(Box::new(left), Box::new(right));
+        let value = match tokens.get(*pos) {
+            None
+            | Some(Token::Semicolon)
+            | Some(Token::RBrace)
+            | Some(Token::Comma)
+            | Some(Token::EOF) => None,
+            _ => Some(Box::new(parse_expr(tokens, pos))),
+        };
+        return Expr::Return(value);
+    }
+    let target = parse_range(tokens, pos);
+    // 代入: <place> = <expr> / <place> += <expr> など（右結合）
+    let op = match tokens.get(*pos) {
+        Some(Token::Eq) => None,
+        Some(Token::PlusEq) => Some(BinOp::Add),
+        Some(Token::MinusEq) => Some(BinOp::Sub),
+        Some(Token::StarEq) => Some(BinOp::Mul),
+        Some(Token::SlashEq) => Some(BinOp::Div),
+        Some(Token::PercentEq) => Some(BinOp::Rem),
+        _ => return target,
+    };
+    *pos += 1;
+    let value = parse_expr(tokens, pos);
+    Expr::Assign {
+        target: Box::new(target),
+        op,
+        value: Box::new(value),
+    }
+}
+
+// 範囲式: <binary>..<binary> / <binary>..=<binary>（両端は省略可）
+fn parse_range(tokens: &Tokens, pos: &mut usize) -> Expr {
+    let start = match tokens.get(*pos) {
+        Some(Token::DotDot) | Some(Token::DotDotEq) => None,
+        _ => Some(parse_binary(tokens, pos, 0)),
+    };
+    let inclusive = match tokens.get(*pos) {
+        Some(Token::DotDot) => false,
+        Some(Token::DotDotEq) => true,
+        _ => return start.unwrap_or(Expr::Number(0)),
+    };
+    *pos += 1;
+    let end = match tokens.get(*pos) {
+        // 終端の省略（a..）
+        None
+        | Some(Token::RParen)
+        | Some(Token::RBrace)
+        | Some(Token::RBracket)
+        | Some(Token::LBrace)
+        | Some(Token::Comma)
+        | Some(Token::Semicolon)
+        | Some(Token::FatArrow)
+        | Some(Token::EOF) => None,
+        _ => Some(Box::new(parse_binary(tokens, pos, 0))),
+    };
+    Expr::Range {
+        start: start.map(Box::new),
+        end,
+        inclusive,
+    }
+}
+
+// 二項演算子の優先順位（大きいほど強く結合する）
+fn binary_op(tok: &Token) -> Option<(BinOp, u8)> {
+    let op = match tok {
+        Token::OrOr => (BinOp::Or, 1),
+        Token::AndAnd => (BinOp::And, 2),
+        Token::EqEq => (BinOp::Eq, 3),
+        Token::NotEq => (BinOp::Ne, 3),
+        Token::Lt => (BinOp::Lt, 3),
+        Token::Le => (BinOp::Le, 3),
+        Token::Gt => (BinOp::Gt, 3),
+        Token::Ge => (BinOp::Ge, 3),
+        Token::Plus => (BinOp::Add, 4),
+        Token::Minus => (BinOp::Sub, 4),
+        Token::Star => (BinOp::Mul, 5),
+        Token::Slash => (BinOp::Div, 5),
+        Token::Percent => (BinOp::Rem, 5),
+        _ => return None,
+    };
+    Some(op)
+}
+
+// 優先順位法による二項演算式: cast (op cast)*
+fn parse_binary(tokens: &Tokens, pos: &mut usize, min_prec: u8) -> Expr {
+    let mut left = parse_cast(tokens, pos);
+    while let Some((op, prec)) = tokens.get(*pos).and_then(binary_op) {
+        if prec <= min_prec {
+            break;
+        }
+        let span = tokens.span(*pos);
+        *pos += 1;
+        let right = parse_binary(tokens, pos, prec);
+        left = Expr::Binary(op, Box::new(left), Box::new(right), span);
     }
     left
 }
 
-pub fn parse_term(tokens: &[Token], pos: &mut usize) -> Expr {
+// 型変換: unary (as 型)*（-x as u8 は (-x) as u8）
+fn parse_cast(tokens: &Tokens, pos: &mut usize) -> Expr {
+    let mut expr = parse_unary(tokens, pos);
+    while tokens.get(*pos) == Some(&Token::Ident("as".to_string())) {
+        let span = tokens.span(*pos);
+        *pos += 1;
+        let ty = crate::parser::ty::parse_type(tokens, pos).unwrap_or_default();
+        expr = Expr::Cast(Box::new(expr), ty, span);
+    }
+    expr
+}
+
+fn parse_unary(tokens: &Tokens, pos: &mut usize) -> Expr {
+    grow_stack(|| parse_unary_inner(tokens, pos))
+}
+
+fn parse_unary_inner(tokens: &Tokens, pos: &mut usize) -> Expr {
+    match tokens.get(*pos) {
+        Some(tok @ (Token::Minus | Token::Bang | Token::Star)) => {
+            let op = match tok {
+                Token::Minus => UnaryOp::Neg,
+                Token::Bang => UnaryOp::Not,
+                _ => UnaryOp::Deref,
+            };
+            let span = tokens.span(*pos);
+            *pos += 1;
+            Expr::Unary(op, Box::new(parse_unary(tokens, pos)), span)
+        }
+        Some(Token::Amp) => {
+            // 参照 &x / &mut x
+            let span = tokens.span(*pos);
+            *pos += 1;
+            let mutable = tokens.get(*pos) == Some(&Token::Mut);
+            if mutable {
+                *pos += 1;
+            }
+            Expr::Ref(Box::new(parse_unary(tokens, pos)), mutable, span)
+        }
+        _ => parse_term(tokens, pos),
+    }
+}
+
+pub fn parse_term(tokens: &Tokens, pos: &mut usize) -> Expr {
+    let mut expr = parse_primary(tokens, pos);
+    loop {
+        match tokens.get(*pos) {
+            // 後置: .method(args) / .method::<T>(args) / .field
+            // 後置: タプルの要素 .0
+            Some(Token::Dot) if matches!(tokens.get(*pos + 1), Some(Token::Number(_))) => {
+                let Some(Token::Number(n)) = tokens.get(*pos + 1) else {
+                    break;
+                };
+                expr = Expr::FieldAccess(Box::new(expr), n.to_string());
+                *pos += 2;
+            }
+            Some(Token::Dot) => {
+                let Some(Token::Ident(name)) = tokens.get(*pos + 1) else {
+                    break;
+                };
+                let name = name.clone();
+                let span = tokens.span(*pos + 1);
+                *pos += 2;
+                if tokens.get(*pos) == Some(&Token::ColonColon) {
+                    // ターボフィッシュの型引数は読み飛ばす
+                    *pos += 1;
+                    crate::parser::ty::parse_generic_args(tokens, pos);
+                }
+                if tokens.get(*pos) == Some(&Token::LParen) {
+                    let args = parse_args(tokens, pos);
+                    expr = Expr::MethodCall(Box::new(expr), name, args, span);
+                } else {
+                    expr = Expr::FieldAccess(Box::new(expr), name);
+                }
+            }
+            // 後置: ?
+            Some(Token::Question) => {
+                *pos += 1;
+                expr = Expr::Try(Box::new(expr));
+            }
+            // 後置: [index]
+            Some(Token::LBracket) => {
+                let span = tokens.span(*pos);
+                *pos += 1;
+                let index = parse_expr(tokens, pos);
+                if tokens.get(*pos) == Some(&Token::RBracket) {
+                    *pos += 1;
+                }
+                expr = Expr::Index {
+                    target: Box::new(expr),
+                    index: Box::new(index),
+                    span,
+                };
+            }
+            _ => break,
+        }
+    }
+    expr
+}
+
+fn parse_primary(tokens: &Tokens, pos: &mut usize) -> Expr {
     match tokens.get(*pos) {
         Some(Token::Number(n)) => {
             *pos += 1;
             Expr::Number(*n)
         }
-        Some(Token::Ident(name)) => {
-            let name = name.clone();
+        Some(Token::TypedNumber(n, ty)) => {
+            let span = tokens.span(*pos);
             *pos += 1;
-            if tokens.get(*pos) == Some(&Token::LParen) {
+            Expr::TypedNumber(*n, *ty, span)
+        }
+        Some(Token::Float(x, ty)) => {
+            *pos += 1;
+            Expr::Float(*x, *ty)
+        }
+        Some(Token::StringLiteral(s)) => {
+            *pos += 1;
+            Expr::Str(s.clone())
+        }
+        Some(Token::CharLiteral(c)) => {
+            *pos += 1;
+            Expr::Char(*c)
+        }
+        Some(Token::LParen) => {
+            // 括弧式 (a) / タプル (a, b) / ユニット ()
+            *pos += 1;
+            let mut items = Vec::new();
+            let mut trailing_comma = false;
+            while !matches!(
+                tokens.get(*pos),
+                Some(Token::RParen) | Some(Token::EOF) | None
+            ) {
+                items.push(parse_expr(tokens, pos));
+                trailing_comma = tokens.get(*pos) == Some(&Token::Comma);
+                if trailing_comma {
+                    *pos += 1;
+                } else {
+                    break;
+                }
+            }
+            if tokens.get(*pos) == Some(&Token::RParen) {
                 *pos += 1;
-                let mut args = Vec::new();
-                while tokens.get(*pos) != Some(&Token::RParen)
-                    && tokens.get(*pos) != Some(&Token::EOF)
-                {
-                    args.push(parse_expr(tokens, pos));
-                    if tokens.get(*pos) == Some(&Token::Comma) {
+            }
+            if items.len() == 1 && !trailing_comma {
+                items.pop().unwrap_or(Expr::Tuple(Vec::new()))
+            } else {
+                Expr::Tuple(items)
+            }
+        }
+        Some(Token::LBrace) => parse_block(tokens, pos),
+        Some(Token::LBracket) => parse_array(tokens, pos),
+        Some(Token::Pipe) | Some(Token::OrOr) => parse_closure(tokens, pos),
+        // move |x| ... （値はいつもコピーして取り込むので move は読み飛ばす）
+        Some(Token::Ident(name))
+            if name == "move"
+                && matches!(tokens.get(*pos + 1), Some(Token::Pipe) | Some(Token::OrOr)) =>
+        {
+            *pos += 1;
+            parse_closure(tokens, pos)
+        }
+        Some(Token::Ident(name)) if name == "true" || name == "false" => {
+            *pos += 1;
+            Expr::Bool(name == "true")
+        }
+        // vec![a, b, c]
+        Some(Token::Ident(name))
+            if name == "vec"
+                && tokens.get(*pos + 1) == Some(&Token::Bang)
+                && tokens.get(*pos + 2) == Some(&Token::LBracket) =>
+        {
+            *pos += 2;
+            Expr::Vec(Box::new(parse_array(tokens, pos)))
+        }
+        // panic!(..) / assert!(..) / assert_eq!(..) など
+        Some(Token::Ident(name))
+            if PanicKind::from_name(name).is_some()
+                && tokens.get(*pos + 1) == Some(&Token::Bang)
+                && tokens.get(*pos + 2) == Some(&Token::LParen) =>
+        {
+            let kind = PanicKind::from_name(name).unwrap();
+            let span = tokens.span(*pos);
+            *pos += 2;
+            crate::parser::assert::parse_assert(tokens, pos, kind, span)
+        }
+        // format!("...", args) / println!("...", args)
+        Some(Token::Ident(name))
+            if (name == "format" || name == "println")
+                && tokens.get(*pos + 1) == Some(&Token::Bang)
+                && tokens.get(*pos + 2) == Some(&Token::LParen) =>
+        {
+            let is_print = name == "println";
+            let span = tokens.span(*pos);
+            *pos += 2;
+            let format = crate::parser::format::parse_format(tokens, pos, span);
+            if is_print {
+                Expr::Call("print".to_string(), vec![format], span, Resolved::default())
+            } else {
+                format
+            }
+        }
+        Some(Token::Ident(name)) => {
+            // パス: Vec::new / Vec::<i64>::new など
+            let mut name = name.clone();
+            let span = tokens.span(*pos);
+            *pos += 1;
+            while tokens.get(*pos) == Some(&Token::ColonColon) {
+                match tokens.get(*pos + 1) {
+                    Some(Token::Ident(seg)) => {
+                        name = format!("{}::{}", name, seg);
+                        *pos += 2;
+                    }
+                    Some(Token::Lt) => {
                         *pos += 1;
-                    } else {
-                        break;
+                        crate::parser::ty::parse_generic_args(tokens, pos);
                     }
+                    _ => break,
                 }
-                if tokens.get(*pos) == Some(&Token::RParen) {
-                    *pos += 1;
-                }
-                Expr::Call(name, args)
+            }
+            if tokens.get(*pos) == Some(&Token::LParen) {
+                let args = parse_args(tokens, pos);
+                Expr::Call(name, args, span, Resolved::default())
+            } else if is_struct_literal(tokens, *pos, &name) {
+                parse_struct_init(tokens, pos, name)
             } else {
-                Expr::Var(name)
+                Expr::Var(name, span, Resolved::default())
             }
         }
         _ => Expr::Number(0),
     }
 }
+
+// Name { ... } を構造体リテラルとみなすか。
+// `if x { .. }` などのブロックと区別するため、大文字で始まる名前で
+// 中身が空・`field:`・`field,`・`field }` のいずれかの場合に限る
+fn is_struct_literal(tokens: &Tokens, pos: usize, name: &str) -> bool {
+    if tokens.get(pos) != Some(&Token::LBrace)
+        || !name.starts_with(|c: char| c.is_ascii_uppercase())
+    {
+        return false;
+    }
+    matches!(
+        (tokens.get(pos + 1), tokens.get(pos + 2)),
+        (Some(Token::RBrace), _)
+            | (
+                Some(Token::Ident(_)),
+                Some(Token::Colon | Token::Comma | Token::RBrace)
+            )
+    )
+}
+
+// 構造体リテラル: Name { field: expr, field }
+fn parse_struct_init(tokens: &Tokens, pos: &mut usize, name: String) -> Expr {
+    *pos += 1; // {
+    let mut fields = Vec::new();
+    loop {
+        match tokens.get(*pos) {
+            Some(Token::RBrace) => {
+                *pos += 1;
+                break;
+            }
+            Some(Token::Comma) => *pos += 1,
+            Some(Token::Ident(field)) => {
+                let field = field.clone();
+                let span = tokens.span(*pos);
+                *pos += 1;
+                let value = if tokens.get(*pos) == Some(&Token::Colon) {
+                    *pos += 1;
+                    parse_expr(tokens, pos)
+                } else {
+                    // 省略形 { x } は { x: x }
+                    Expr::Var(field.clone(), span, Resolved::default())
+                };
+                fields.push((field, value, span));
+            }
+            _ => break,
+        }
+    }
+    Expr::StructInit(name, fields)
+}
+
+// 引数リスト: ( <expr>, ... )
+fn parse_args(tokens: &Tokens, pos: &mut usize) -> Vec<Expr> {
+    *pos += 1; // (
+    let mut args = Vec::new();
+    while tokens.get(*pos) != Some(&Token::RParen) && tokens.get(*pos) != Some(&Token::EOF) {
+        args.push(parse_expr(tokens, pos));
+        if tokens.get(*pos) == Some(&Token::Comma) {
+            *pos += 1;
+        } else {
+            break;
+        }
+    }
+    if tokens.get(*pos) == Some(&Token::RParen) {
+        *pos += 1;
+    }
+    args
+}
+
+// 配列リテラル: [a, b, c] / [value; count]
+fn parse_array(tokens: &Tokens, pos: &mut usize) -> Expr {
+    *pos += 1; // [
+    let mut items = Vec::new();
+    while !matches!(
+        tokens.get(*pos),
+        Some(Token::RBracket) | Some(Token::EOF) | None
+    ) {
+        items.push(parse_expr(tokens, pos));
+        if items.len() == 1 && tokens.get(*pos) == Some(&Token::Semicolon) {
+            *pos += 1;
+            let count = parse_expr(tokens, pos);
+            if tokens.get(*pos) == Some(&Token::RBracket) {
+                *pos += 1;
+            }
+            let value = items.pop().unwrap_or(Expr::Number(0));
+            return Expr::ArrayRepeat(Box::new(value), Box::new(count));
+        }
+        if tokens.get(*pos) == Some(&Token::Comma) {
+            *pos += 1;
+        } else {
+            break;
+        }
+    }
+    if tokens.get(*pos) == Some(&Token::RBracket) {
+        *pos += 1;
+    }
+    Expr::Array(items)
+}
+
+// ブロック式: { stmt1; stmt2; ... }
+pub fn parse_block(tokens: &Tokens, pos: &mut usize) -> Expr {
+    if tokens.get(*pos) == Some(&Token::LBrace) {
+        *pos += 1;
+    }
+    let mut stmts = Vec::new();
+    while let Some(tok) = tokens.get(*pos) {
+        match tok {
+            Token::RBrace => {
+                *pos += 1;
+                break;
+            }
+            Token::EOF => break,
+            _ => stmts.push(crate::parser::parse_stmt(tokens, pos)),
+        }
+    }
+    Expr::Block(stmts)
+}
+
+// else の後: else if ... / else { ... }
+fn parse_else(tokens: &Tokens, pos: &mut usize) -> Expr {
+    if tokens.get(*pos) == Some(&Token::Ident("if".to_string())) {
+        parse_expr(tokens, pos)
+    } else {
+        parse_block(tokens, pos)
+    }
+}
+
+// match式: match <expr> { <pattern> => <expr>, ... }
+fn parse_match(tokens: &Tokens, pos: &mut usize) -> Expr {
+    let scrutinee = parse_expr(tokens, pos);
+    let mut arms = Vec::new();
+    if tokens.get(*pos) == Some(&Token::LBrace) {
+        *pos += 1;
+    }
+    while let Some(tok) = tokens.get(*pos) {
+        match tok {
+            Token::RBrace => {
+                *pos += 1;
+                break;
+            }
+            Token::EOF => break,
+            Token::Comma => {
+                *pos += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let start = *pos;
+        let pattern = parse_pattern(tokens, pos);
+        if tokens.get(*pos) == Some(&Token::FatArrow) {
+            *pos += 1;
+        }
+        // ブロックの腕は } で終わる（Rust と同じく , は省略できる）
+        let body = if tokens.get(*pos) == Some(&Token::LBrace) {
+            parse_block(tokens, pos)
+        } else {
+            parse_expr(tokens, pos)
+        };
+        if *pos == start {
+            // パターンとして読めないトークンは読み飛ばす
+            *pos += 1;
+            continue;
+        }
+        arms.push((pattern, body));
+    }
+    Expr::Match {
+        scrutinee: Box::new(scrutinee),
+        arms,
+    }
+}
+
+// |a, b: i64| -> T { ... } / || expr
+fn parse_closure(tokens: &Tokens, pos: &mut usize) -> Expr {
+    let mut params = Vec::new();
+    if tokens.get(*pos) == Some(&Token::Pipe) {
+        *pos += 1;
+        while !matches!(
+            tokens.get(*pos),
+            Some(Token::Pipe) | Some(Token::EOF) | None
+        ) {
+            let start = *pos;
+            params.push(parse_pattern(tokens, pos));
+            if tokens.get(*pos) == Some(&Token::Colon) {
+                *pos += 1;
+                crate::parser::ty::parse_type(tokens, pos);
+            }
+            if tokens.get(*pos) == Some(&Token::Comma) {
+                *pos += 1;
+            } else if *pos == start {
+                break;
+            }
+        }
+    }
+    // 閉じの | か、引数なしの ||
+    *pos += 1;
+    if tokens.get(*pos) == Some(&Token::Arrow) {
+        *pos += 1;
+        crate::parser::ty::parse_type(tokens, pos);
+    }
+    let body = parse_expr(tokens, pos);
+    Expr::Closure {
+        params,
+        body: Box::new(body),
+    }
+}
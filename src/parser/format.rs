@@ -0,0 +1,132 @@
+use crate::ast::{Expr, FmtPiece, FmtSpec, Resolved};
+use crate::lexer::{Token, Tokens};
+use crate::parser::expr::parse_expr;
+
+// format!("...", args) / println!("...", args)
+// 呼び出し側で `format` `!` を読んだ位置（`(` の位置）から読む
+pub fn parse_format(tokens: &Tokens, pos: &mut usize, span: crate::lexer::Span) -> Expr {
+    *pos += 1; // (
+    parse_format_args(tokens, pos, span)
+}
+
+// 書式文字列から閉じ括弧までを読む（assert!(cond, "...", args) のメッセージにも使う）
+pub fn parse_format_args(tokens: &Tokens, pos: &mut usize, span: crate::lexer::Span) -> Expr {
+    let template = match tokens.get(*pos) {
+        Some(Token::StringLiteral(s)) => {
+            *pos += 1;
+            s.clone()
+        }
+        _ => String::new(),
+    };
+    let mut args = Vec::new();
+    while tokens.get(*pos) == Some(&Token::Comma) {
+        *pos += 1;
+        if matches!(
+            tokens.get(*pos),
+            Some(Token::RParen) | Some(Token::EOF) | None
+        ) {
+            break;
+        }
+        args.push(parse_expr(tokens, pos));
+    }
+    if tokens.get(*pos) == Some(&Token::RParen) {
+        *pos += 1;
+    }
+    let pieces = parse_template(&template, &mut args, span);
+    Expr::Format { pieces, args, span }
+}
+
+// 書式文字列を分解する。{name} の変数は args の後ろに足す
+fn parse_template(template: &str, args: &mut Vec<Expr>, span: crate::lexer::Span) -> Vec<FmtPiece> {
+    let mut pieces = Vec::new();
+    let mut lit = String::new();
+    let mut next_index = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                lit.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                lit.push('}');
+            }
+            '{' => {
+                let mut inner = String::new();
+                for d in chars.by_ref() {
+                    if d == '}' {
+                        break;
+                    }
+                    inner.push(d);
+                }
+                if !lit.is_empty() {
+                    pieces.push(FmtPiece::Lit(std::mem::take(&mut lit)));
+                }
+                let (arg, spec) = inner.split_once(':').unwrap_or((&inner, ""));
+                let index = if arg.is_empty() {
+                    next_index += 1;
+                    Some(next_index - 1)
+                } else if let Ok(i) = arg.parse::<usize>() {
+                    Some(i)
+                } else if arg.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    args.push(Expr::Var(arg.to_string(), span, Resolved::default()));
+                    Some(args.len() - 1)
+                } else {
+                    None
+                };
+                match (index, parse_spec(spec)) {
+                    (Some(index), Some(spec)) => pieces.push(FmtPiece::Arg { index, spec }),
+                    _ => pieces.push(FmtPiece::Invalid(format!("{{{}}}", inner))),
+                }
+            }
+            _ => lit.push(c),
+        }
+    }
+    if !lit.is_empty() {
+        pieces.push(FmtPiece::Lit(lit));
+    }
+    pieces
+}
+
+// :<fill><align><0><width>.<precision><?> の部分
+fn parse_spec(spec: &str) -> Option<FmtSpec> {
+    let mut out = FmtSpec::default();
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+    let is_align = |c: Option<&char>| matches!(c, Some('<' | '^' | '>'));
+    if is_align(chars.get(1)) {
+        out.fill = Some(chars[0]);
+        out.align = Some(chars[1]);
+        i = 2;
+    } else if is_align(chars.first()) {
+        out.align = Some(chars[0]);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        out.zero = true;
+        i += 1;
+    }
+    // 数字がなければ Some(None)。Rust と同じく、u16 に収まらない幅・精度は書式の誤りにする
+    let number = |i: &mut usize| {
+        let start = *i;
+        while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+            *i += 1;
+        }
+        if start == *i {
+            return Some(None);
+        }
+        let digits: String = chars[start..*i].iter().collect();
+        digits.parse::<u16>().ok().map(|n| Some(n as usize))
+    };
+    out.width = number(&mut i)?;
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        out.precision = Some(number(&mut i)??);
+    }
+    if chars.get(i) == Some(&'?') {
+        out.debug = true;
+        i += 1;
+    }
+    (i == chars.len()).then_some(out)
+}
@@ -1,65 +1,138 @@
-use crate::ast::Stmt;
-use crate::lexer::Token;
+use crate::ast::{Expr, Pattern, Stmt};
+use crate::lexer::{Token, Tokens};
 
-pub fn parse_funcdef(tokens: &[Token], pos: &mut usize) -> Stmt {
-    // pub fn/fn <name>(<params>) { <body> }
+pub fn parse_funcdef(tokens: &Tokens, pos: &mut usize) -> Stmt {
+    parse_fn(tokens, pos, false)
+}
+
+// pub fn/fn <name><generics>(<params>) [-> <type>] [where ...] { <body> }
+// decl が真ならトレイトのメソッド宣言として `;` で終わるものも読む（本体は空のブロック）
+pub fn parse_fn(tokens: &Tokens, pos: &mut usize, decl: bool) -> Stmt {
     if let Some(Token::Pub) = tokens.get(*pos) {
         *pos += 1; // pubは現状無視
     }
     if let Some(Token::Fn) = tokens.get(*pos) {
         *pos += 1;
     } else {
-        return Stmt::Error("fnキーワードが必要です".to_string());
+        return Stmt::Error("fnキーワードが必要です".to_string(), tokens.span(*pos));
     }
+    let span = tokens.span(*pos);
     let name = if let Some(Token::Ident(n)) = tokens.get(*pos) {
         *pos += 1;
         n.clone()
     } else {
-        return Stmt::Error("関数名が必要です".to_string());
+        return Stmt::Error("関数名が必要です".to_string(), tokens.span(*pos));
+    };
+    let Some(mut generics) = crate::parser::ty::parse_generics(tokens, pos) else {
+        return Stmt::Error(
+            format!("関数 {} の型引数が不正です", name),
+            tokens.span(*pos),
+        );
     };
     if tokens.get(*pos) != Some(&Token::LParen) {
-        return Stmt::Error("( が必要です".to_string());
+        return Stmt::Error("( が必要です".to_string(), tokens.span(*pos));
     }
     *pos += 1;
     let mut params = Vec::new();
+    let mut param_tys = Vec::new();
     while let Some(tok) = tokens.get(*pos) {
         match tok {
-            Token::Ident(param) => {
-                params.push(param.clone());
-                *pos += 1;
-                if tokens.get(*pos) == Some(&Token::Comma) {
-                    *pos += 1;
-                }
-            }
             Token::RParen => {
                 *pos += 1;
                 break;
             }
             _ => {
-                return Stmt::Error("引数リストが不正です".to_string());
+                if params.is_empty()
+                    && let Some(ty) = self_param(tokens, pos)
+                {
+                    params.push(Pattern::Bind("self".to_string(), tokens.span(*pos - 1)));
+                    param_tys.push(Some(ty));
+                    if tokens.get(*pos) == Some(&Token::Comma) {
+                        *pos += 1;
+                    }
+                    continue;
+                }
+                let start = *pos;
+                let param = crate::parser::pattern::parse_pattern(tokens, pos);
+                if *pos == start {
+                    return Stmt::Error("引数リストが不正です".to_string(), tokens.span(*pos));
+                }
+                if !param.is_irrefutable() {
+                    return Stmt::Error(
+                        format!(
+                            "関数の引数には一致しない可能性のあるパターンは使えません: {}",
+                            param
+                        ),
+                        tokens.span(*pos),
+                    );
+                }
+                // 引数の型注釈: a: i64
+                let ty = if tokens.get(*pos) == Some(&Token::Colon) {
+                    *pos += 1;
+                    let Some(ty) = crate::parser::ty::parse_type(tokens, pos) else {
+                        return Stmt::Error(
+                            format!("引数 {} の型が不正です", param),
+                            tokens.span(*pos),
+                        );
+                    };
+                    Some(ty)
+                } else {
+                    None
+                };
+                params.push(param);
+                param_tys.push(ty);
+                if tokens.get(*pos) == Some(&Token::Comma) {
+                    *pos += 1;
+                }
             }
         }
     }
-    if tokens.get(*pos) != Some(&Token::LBrace) {
-        return Stmt::Error("{ が必要です".to_string());
-    }
-    *pos += 1;
-    // bodyは複数文対応: { stmt1; stmt2; ... }
-    let mut stmts = Vec::new();
-    while let Some(tok) = tokens.get(*pos) {
-        if let Token::RBrace = tok {
-            *pos += 1;
-            break;
-        }
-        if let Some(stmt) = crate::parser::print::parse_print(tokens, pos) {
-            stmts.push(stmt);
-        } else if tokens.get(*pos) == Some(&Token::Let) {
-            stmts.push(crate::parser::let_stmt::parse_let(tokens, pos));
-        } else {
-            let expr = crate::parser::expr::parse_expr(tokens, pos);
-            stmts.push(crate::ast::Stmt::Expr(expr));
+    let mut ret = None;
+    if tokens.get(*pos) == Some(&Token::Arrow) {
+        *pos += 1;
+        match crate::parser::ty::parse_type(tokens, pos) {
+            Some(t) => ret = Some(t),
+            None => return Stmt::Error("-> の後に型名が必要です".to_string(), tokens.span(*pos)),
         }
     }
-    let body = Box::new(crate::ast::Expr::Block(stmts));
-    Stmt::FuncDef { name, params, body }
+    if crate::parser::ty::parse_where(tokens, pos, &mut generics).is_none() {
+        return Stmt::Error(
+            format!("関数 {} の where 節が不正です", name),
+            tokens.span(*pos),
+        );
+    }
+    let body = if decl && tokens.get(*pos) == Some(&Token::Semicolon) {
+        // ; は呼び出し側で読む（本体がないことの目印）
+        Box::new(Expr::Block(Vec::new()))
+    } else if tokens.get(*pos) != Some(&Token::LBrace) {
+        return Stmt::Error("{ が必要です".to_string(), tokens.span(*pos));
+    } else {
+        // bodyは複数文対応: { stmt1; stmt2; ... }
+        Box::new(crate::parser::expr::parse_block(tokens, pos))
+    };
+    Stmt::FuncDef {
+        name,
+        generics,
+        params,
+        param_tys,
+        ret,
+        body,
+        test: false,
+        span,
+    }
+}
+
+// メソッドの最初の引数 self / mut self / &self / &mut self を読み、self の型注釈を返す
+// （self でなければ何も読まずに None）
+fn self_param(tokens: &Tokens, pos: &mut usize) -> Option<String> {
+    let is_self = |i: usize| tokens.get(i) == Some(&Token::Ident("self".to_string()));
+    let (len, ty) = match (tokens.get(*pos), tokens.get(*pos + 1)) {
+        (Some(Token::Amp), Some(Token::Mut)) if is_self(*pos + 2) => (3, "&mut Self"),
+        (Some(Token::Amp), _) if is_self(*pos + 1) => (2, "&Self"),
+        (Some(Token::Mut), _) if is_self(*pos + 1) => (2, "Self"),
+        _ if is_self(*pos) => (1, "Self"),
+        _ => return None,
+    };
+    *pos += len;
+    Some(ty.to_string())
 }
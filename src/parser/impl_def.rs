@@ -0,0 +1,113 @@
+use crate::ast::Stmt;
+use crate::lexer::{Token, Tokens};
+use crate::parser::func::parse_funcdef;
+
+pub fn parse_impl(tokens: &Tokens, pos: &mut usize) -> Option<Stmt> {
+    // impl<generics> [<trait> for] <type> { type <name> = <type>; fn ... }
+    if tokens.get(*pos) != Some(&Token::Ident("impl".to_string())) {
+        return None;
+    }
+    *pos += 1;
+    let span = tokens.span(*pos);
+    let Some(mut generics) = crate::parser::ty::parse_generics(tokens, pos) else {
+        return Some(Stmt::Error(
+            "impl の型引数が不正です".to_string(),
+            tokens.span(*pos),
+        ));
+    };
+    let Some(first) = crate::parser::ty::parse_type(tokens, pos) else {
+        return Some(Stmt::Error(
+            "impl の型名が必要です".to_string(),
+            tokens.span(*pos),
+        ));
+    };
+    let (trait_name, target) = if tokens.get(*pos) == Some(&Token::Ident("for".to_string())) {
+        *pos += 1;
+        let Some(target) = crate::parser::ty::parse_type(tokens, pos) else {
+            return Some(Stmt::Error(
+                format!("impl {} for の後に型名が必要です", first),
+                tokens.span(*pos),
+            ));
+        };
+        (Some(first), target)
+    } else {
+        (None, first)
+    };
+    if crate::parser::ty::parse_where(tokens, pos, &mut generics).is_none() {
+        return Some(Stmt::Error(
+            format!("impl {} の where 節が不正です", target),
+            tokens.span(*pos),
+        ));
+    }
+    if tokens.get(*pos) != Some(&Token::LBrace) {
+        return Some(Stmt::Error("{ が必要です".to_string(), tokens.span(*pos)));
+    }
+    *pos += 1;
+    // Self は型引数を除いた型名に置き換える
+    let self_name = target.split('<').next().unwrap_or(&target).to_string();
+    let mut assoc = Vec::new();
+    let mut methods = Vec::new();
+    loop {
+        if let Err(msg) = crate::parser::attr::parse_attrs(tokens, pos) {
+            return Some(Stmt::Error(msg, tokens.span(*pos)));
+        }
+        match tokens.get(*pos) {
+            Some(Token::RBrace) => {
+                *pos += 1;
+                break;
+            }
+            Some(Token::Pub) | Some(Token::Fn) => {
+                let mut method = parse_funcdef(tokens, pos);
+                if let Stmt::Error(..) = method {
+                    return Some(method);
+                }
+                method.replace_self(&self_name);
+                methods.push(method);
+            }
+            // 関連型: type Item = i64;
+            Some(Token::Ident(kw)) if kw == "type" => {
+                let Some(Token::Ident(item)) = tokens.get(*pos + 1) else {
+                    return Some(Stmt::Error(
+                        "関連型の名前が必要です".to_string(),
+                        tokens.span(*pos),
+                    ));
+                };
+                if tokens.get(*pos + 2) != Some(&Token::Eq) {
+                    return Some(Stmt::Error(
+                        format!("関連型 {} の = が必要です", item),
+                        tokens.span(*pos),
+                    ));
+                }
+                *pos += 3;
+                let Some(ty) = crate::parser::ty::parse_type(tokens, pos) else {
+                    return Some(Stmt::Error(
+                        format!("関連型 {} の型が不正です", item),
+                        tokens.span(*pos),
+                    ));
+                };
+                if tokens.get(*pos) != Some(&Token::Semicolon) {
+                    return Some(Stmt::Error(
+                        format!("関連型 {} の ; が必要です", item),
+                        tokens.span(*pos),
+                    ));
+                }
+                *pos += 1;
+                assoc.push((item.clone(), ty));
+            }
+            _ => {
+                return Some(Stmt::Error(
+                    "impl の中には fn と type しか書けません".to_string(),
+                    tokens.span(*pos),
+                ));
+            }
+        }
+    }
+    Some(Stmt::ImplDef {
+        generics,
+        trait_name,
+        target,
+        assoc,
+        methods,
+        span,
+    })
+}
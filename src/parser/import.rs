@@ -1,7 +1,7 @@
 use crate::ast::Stmt;
-use crate::lexer::Token;
+use crate::lexer::{Token, Tokens};
 
-pub fn parse_import(tokens: &[Token], pos: &mut usize) -> Option<Stmt> {
+pub fn parse_import(tokens: &Tokens, pos: &mut usize) -> Option<Stmt> {
     // import "filename"
     if let Some(Token::Ident(s)) = tokens.get(*pos) {
         if s == "import" {
@@ -1,47 +1,50 @@
 use crate::ast::Stmt;
-use crate::lexer::Token;
+use crate::lexer::{Token, Tokens};
 
-pub fn parse_let(tokens: &[Token], pos: &mut usize) -> Stmt {
-    // let/mut <name> [: <type>] = <expr>;
+pub fn parse_let(tokens: &Tokens, pos: &mut usize) -> Stmt {
+    // let/mut <pattern> [: <type>] = <expr>;
+    let span = tokens.span(*pos);
     *pos += 1; // let
     let mut mutable = false;
     if tokens.get(*pos) == Some(&Token::Mut) {
         mutable = true;
         *pos += 1;
     }
-    let name = if let Some(Token::Ident(n)) = tokens.get(*pos) {
-        *pos += 1;
-        n.clone()
-    } else {
-        return Stmt::Error("変数名が必要です".to_string());
-    };
+    let start = *pos;
+    let pattern = crate::parser::pattern::parse_pattern(tokens, pos);
+    if *pos == start {
+        return Stmt::Error("変数名が必要です".to_string(), tokens.span(*pos));
+    }
+    if !pattern.is_irrefutable() {
+        return Stmt::Error(
+            format!(
+                "let には一致しない可能性のあるパターンは使えません: {}",
+                pattern
+            ),
+            tokens.span(start),
+        );
+    }
     let mut ty = None;
     if tokens.get(*pos) == Some(&Token::Colon) {
         *pos += 1;
-        if let Some(Token::Ident(t)) = tokens.get(*pos) {
-            ty = Some(t.clone());
-            *pos += 1;
-        } else {
-            return Stmt::Error(": の後に型名が必要です".to_string());
+        match crate::parser::ty::parse_type(tokens, pos) {
+            Some(t) => ty = Some(t),
+            None => return Stmt::Error(": の後に型名が必要です".to_string(), tokens.span(*pos)),
         }
     }
     if tokens.get(*pos) != Some(&Token::Eq) {
-        return Stmt::Error("= が必要です".to_string());
+        return Stmt::Error("= が必要です".to_string(), tokens.span(*pos));
     }
     *pos += 1;
     // 値は式としてパース
     let value = crate::parser::expr::parse_expr(tokens, pos);
-    // ; をスキップ
-    if tokens.get(*pos) == Some(&Token::EOF) {
-        // 終端
-    } else {
-        *pos += 1;
-    }
+    // ; はparse_stmtで読み飛ばす
     Stmt::Let {
-        name,
+        pattern,
         value,
         mutable,
         ty,
+        span,
     }
 }
 
@@ -1,37 +1,128 @@
+mod assert;
+mod attr;
+mod enum_def;
 mod expr;
+mod format;
 mod func;
+mod impl_def;
 mod let_stmt;
+mod pattern;
 mod print;
+mod struct_def;
+mod trait_def;
+mod ty;
 // useはRustの予約語のため、use_nasl.rsというファイル名に。
 mod use_nasl;
 
-use crate::ast::Stmt;
-use crate::lexer::Token;
-use expr::parse_expr;
+use crate::ast::{Expr, Stmt};
+use crate::lexer::{Token, Tokens};
+use enum_def::parse_enum;
+use expr::{parse_block, parse_expr};
 use func::parse_funcdef;
+use impl_def::parse_impl;
 use let_stmt::parse_let;
 use print::parse_print;
+use struct_def::parse_struct;
+use trait_def::parse_trait;
 // useはRustの予約語のため、use_nasl.rsというファイル名に。
 use use_nasl::parse_use;
 
-pub fn parse(tokens: &[Token]) -> Vec<Stmt> {
+pub fn parse(tokens: &Tokens) -> Vec<Stmt> {
     let mut pos = 0;
     let mut stmts = Vec::new();
-    while tokens.get(pos) != Some(&Token::EOF) {
-        if let Some(stmt) = parse_use(tokens, &mut pos) {
-            stmts.push(stmt);
-        } else if let Some(expr) = parse_print(tokens, &mut pos) {
-            stmts.push(expr);
-        } else if tokens.get(pos) == Some(&Token::Let) {
-            stmts.push(parse_let(tokens, &mut pos));
-        } else if tokens.get(pos) == Some(&Token::Pub) {
-            stmts.push(parse_funcdef(tokens, &mut pos));
-        } else if tokens.get(pos) == Some(&Token::Fn) {
-            stmts.push(parse_funcdef(tokens, &mut pos));
-        } else {
-            let expr = parse_expr(tokens, &mut pos);
-            stmts.push(Stmt::Expr(expr));
-        }
+    while !matches!(tokens.get(pos), Some(Token::EOF) | None) {
+        stmts.push(parse_stmt(tokens, &mut pos));
     }
     stmts
 }
+
+// 文を1つ読む（トップレベルとブロック内で共通）
+pub(crate) fn parse_stmt(tokens: &Tokens, pos: &mut usize) -> Stmt {
+    let span = tokens.span(*pos);
+    // #[derive(...)] など。構造体・列挙型の derive と関数の test 以外は読み捨てる
+    let attrs = match attr::parse_attrs(tokens, pos) {
+        Ok(attrs) => attrs,
+        Err(msg) => {
+            recover(tokens, pos);
+            return Stmt::Error(msg, span);
+        }
+    };
+    let stmt = if let Some(stmt) = parse_use(tokens, pos) {
+        stmt
+    } else if let Some(stmt) = parse_print(tokens, pos) {
+        stmt
+    } else if let Some(stmt) = parse_struct(tokens, pos, &attrs) {
+        stmt
+    } else if let Some(stmt) = parse_enum(tokens, pos, &attrs) {
+        stmt
+    } else if let Some(stmt) = parse_trait(tokens, pos) {
+        stmt
+    } else if let Some(stmt) = parse_impl(tokens, pos) {
+        stmt
+    } else if tokens.get(*pos) == Some(&Token::Let) {
+        parse_let(tokens, pos)
+    } else if matches!(tokens.get(*pos), Some(Token::Pub) | Some(Token::Fn)) {
+        let mut stmt = parse_funcdef(tokens, pos);
+        if let Stmt::FuncDef { test, .. } = &mut stmt {
+            *test = attrs.iter().any(|a| a.name == "test");
+        }
+        stmt
+    } else if tokens.get(*pos) == Some(&Token::LBrace) {
+        // ブロックの文は } で終わる。続く -x などは次の文になる
+        Stmt::Expr(parse_block(tokens, pos))
+    } else {
+        let start = *pos;
+        let expr = parse_expr(tokens, pos);
+        if *pos == start {
+            // 式として読めないトークンは読み飛ばす（無限ループ防止）
+            *pos += 1;
+            return Stmt::Error(
+                format!("予期しないトークン: {:?}", tokens[start]),
+                tokens.span(start),
+            );
+        }
+        Stmt::Expr(expr)
+    };
+    let stmt = match stmt {
+        Stmt::Error(..) => {
+            recover(tokens, pos);
+            stmt
+        }
+        // forは最後まで読めているので読み飛ばしは不要
+        Stmt::Expr(Expr::For { pattern, .. }) if !pattern.is_irrefutable() => Stmt::Error(
+            format!(
+                "for には一致しない可能性のあるパターンは使えません: {}",
+                pattern
+            ),
+            span,
+        ),
+        stmt => stmt,
+    };
+    // 文末の ; は読み飛ばす
+    while tokens.get(*pos) == Some(&Token::Semicolon) {
+        *pos += 1;
+    }
+    stmt
+}
+
+// 解析エラーの後、文の終わり（; か、読み飛ばし中に開いた { に対応する }）まで読み飛ばす
+fn recover(tokens: &Tokens, pos: &mut usize) {
+    let mut depth = 0usize;
+    while let Some(tok) = tokens.get(*pos) {
+        match tok {
+            Token::EOF => break,
+            Token::Semicolon if depth == 0 => break,
+            Token::LBrace => depth += 1,
+            Token::RBrace if depth == 0 => break,
+            Token::RBrace => {
+                depth -= 1;
+                if depth == 0 {
+                    *pos += 1;
+                    break;
+                }
+            }
+            _ => {}
+        }
+        *pos += 1;
+    }
+}
@@ -0,0 +1,152 @@
+use crate::ast::Pattern;
+use crate::lexer::{Token, Tokens};
+
+// パターン: _ / x / mut x / 1 / 'a' / "s" / (a, b) / Point { x, .. } / [first, .., last]
+// / Some(x) / None / Ok(v) / Err(e) / Shape::Circle(r) / Color::Red
+pub fn parse_pattern(tokens: &Tokens, pos: &mut usize) -> Pattern {
+    match tokens.get(*pos) {
+        Some(Token::Number(n)) => {
+            *pos += 1;
+            Pattern::Number(*n)
+        }
+        // 接尾辞付きのリテラル（255u8）は照合する値の型に合わせて比べる
+        Some(Token::TypedNumber(n, _)) if i64::try_from(*n).is_ok() => {
+            *pos += 1;
+            Pattern::Number(*n as i64)
+        }
+        Some(Token::CharLiteral(c)) => {
+            *pos += 1;
+            Pattern::Char(*c)
+        }
+        Some(Token::StringLiteral(s)) => {
+            *pos += 1;
+            Pattern::Str(s.clone())
+        }
+        Some(Token::LParen) => {
+            // タプルパターン: (p1, p2, ...)
+            *pos += 1;
+            Pattern::Tuple(parse_pattern_list(tokens, pos, &Token::RParen))
+        }
+        Some(Token::LBracket) => {
+            // スライスパターン: [p1, .., pn]
+            *pos += 1;
+            Pattern::Slice(parse_pattern_list(tokens, pos, &Token::RBracket))
+        }
+        Some(Token::DotDot) => {
+            *pos += 1;
+            Pattern::Rest(None)
+        }
+        Some(Token::Minus) => {
+            if let Some(Token::Number(n)) = tokens.get(*pos + 1) {
+                *pos += 2;
+                Pattern::Number(-*n)
+            } else {
+                *pos += 1;
+                Pattern::Wildcard
+            }
+        }
+        Some(Token::Mut) => {
+            // 可変性は束縛の種類を変えないので読み飛ばす
+            *pos += 1;
+            parse_pattern(tokens, pos)
+        }
+        Some(Token::Ident(name)) => {
+            let mut name = name.clone();
+            let span = tokens.span(*pos);
+            *pos += 1;
+            // Option::Some / Shape::Circle などのパス（列挙型の名前は見ない）
+            let mut is_path = false;
+            while tokens.get(*pos) == Some(&Token::ColonColon)
+                && let Some(Token::Ident(seg)) = tokens.get(*pos + 1)
+            {
+                name = seg.clone();
+                is_path = true;
+                *pos += 2;
+            }
+            let upper = name.starts_with(|c: char| c.is_ascii_uppercase());
+            match name.as_str() {
+                "None" => Pattern::Variant(name, Vec::new()),
+                _ if (is_path || upper) && tokens.get(*pos) == Some(&Token::LParen) => {
+                    *pos += 1;
+                    Pattern::Variant(name, parse_pattern_list(tokens, pos, &Token::RParen))
+                }
+                _ if is_path && tokens.get(*pos) != Some(&Token::LBrace) => {
+                    Pattern::Variant(name, Vec::new())
+                }
+                "_" => Pattern::Wildcard,
+                "true" => Pattern::Bool(true),
+                "false" => Pattern::Bool(false),
+                _ if tokens.get(*pos) == Some(&Token::LBrace) => {
+                    *pos += 1;
+                    parse_struct_pattern(tokens, pos, name)
+                }
+                // rest @ ..
+                _ if tokens.get(*pos) == Some(&Token::At)
+                    && tokens.get(*pos + 1) == Some(&Token::DotDot) =>
+                {
+                    *pos += 2;
+                    Pattern::Rest(Some(name))
+                }
+                _ => Pattern::Bind(name, span),
+            }
+        }
+        _ => Pattern::Wildcard,
+    }
+}
+
+// 閉じ括弧までのパターンの並び（閉じ括弧も読む）
+fn parse_pattern_list(tokens: &Tokens, pos: &mut usize, close: &Token) -> Vec<Pattern> {
+    let mut items = Vec::new();
+    while !matches!(tokens.get(*pos), Some(t) if t == close || *t == Token::EOF) {
+        let start = *pos;
+        items.push(parse_pattern(tokens, pos));
+        if tokens.get(*pos) == Some(&Token::Comma) {
+            *pos += 1;
+        } else if *pos == start || tokens.get(*pos) != Some(close) {
+            break;
+        }
+    }
+    if tokens.get(*pos) == Some(close) {
+        *pos += 1;
+    }
+    items
+}
+
+// 構造体パターンの { 以降: { x, y: py, .. }
+fn parse_struct_pattern(tokens: &Tokens, pos: &mut usize, name: String) -> Pattern {
+    let mut fields = Vec::new();
+    let mut rest = false;
+    loop {
+        match tokens.get(*pos) {
+            Some(Token::RBrace) => {
+                *pos += 1;
+                break;
+            }
+            Some(Token::DotDot) => {
+                *pos += 1;
+                rest = true;
+            }
+            Some(Token::Mut) => {
+                *pos += 1;
+            }
+            Some(Token::Ident(field)) => {
+                let field = field.clone();
+                let span = tokens.span(*pos);
+                *pos += 1;
+                let pattern = if tokens.get(*pos) == Some(&Token::Colon) {
+                    *pos += 1;
+                    parse_pattern(tokens, pos)
+                } else {
+                    // 省略形 { x } は { x: x }
+                    Pattern::Bind(field.clone(), span)
+                };
+                fields.push((field, pattern));
+            }
+            Some(Token::Comma) => {
+                *pos += 1;
+            }
+            _ => break,
+        }
+    }
+    Pattern::Struct { name, fields, rest }
+}
@@ -1,27 +1,18 @@
-use crate::ast::Expr;
 use crate::ast::Stmt;
-use crate::lexer::Token;
+use crate::lexer::{Token, Tokens};
 
-pub fn parse_print(tokens: &[Token], pos: &mut usize) -> Option<Stmt> {
+pub fn parse_print(tokens: &Tokens, pos: &mut usize) -> Option<Stmt> {
     // print ( <expr> )
-    if let Some(Token::Ident(s)) = tokens.get(*pos) {
-        if s == "print" {
+    if let Some(Token::Ident(s)) = tokens.get(*pos)
+        && s == "print"
+        && tokens.get(*pos + 1) == Some(&Token::LParen)
+    {
+        *pos += 2;
+        let expr = crate::parser::expr::parse_expr(tokens, pos);
+        if tokens.get(*pos) == Some(&Token::RParen) {
             *pos += 1;
-            if tokens.get(*pos) == Some(&Token::LParen) {
-                *pos += 1;
-                // 仮: 数値リテラルのみ対応
-                let expr = if let Some(Token::Number(n)) = tokens.get(*pos) {
-                    *pos += 1;
-                    Expr::Number(*n)
-                } else {
-                    Expr::Number(0)
-                };
-                if tokens.get(*pos) == Some(&Token::RParen) {
-                    *pos += 1;
-                    return Some(Stmt::Print(Box::new(expr)));
-                }
-            }
         }
+        return Some(Stmt::Print(Box::new(expr)));
     }
     None
 }
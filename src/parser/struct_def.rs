@@ -0,0 +1,103 @@
+use crate::ast::Stmt;
+use crate::lexer::{Token, Tokens};
+use crate::parser::attr::Attr;
+
+pub fn parse_struct(tokens: &Tokens, pos: &mut usize, attrs: &[Attr]) -> Option<Stmt> {
+    // [pub] struct <name><generics> { <field>: <type>, ... } / struct <name>;
+    let start = *pos;
+    if tokens.get(*pos) == Some(&Token::Pub) {
+        *pos += 1;
+    }
+    if tokens.get(*pos) != Some(&Token::Ident("struct".to_string())) {
+        *pos = start;
+        return None;
+    }
+    *pos += 1;
+    let span = tokens.span(*pos);
+    let name = if let Some(Token::Ident(n)) = tokens.get(*pos) {
+        *pos += 1;
+        n.clone()
+    } else {
+        return Some(Stmt::Error(
+            "構造体名が必要です".to_string(),
+            tokens.span(*pos),
+        ));
+    };
+    let Some(mut generics) = crate::parser::ty::parse_generics(tokens, pos) else {
+        return Some(Stmt::Error(
+            format!("構造体 {} の型引数が不正です", name),
+            tokens.span(*pos),
+        ));
+    };
+    if crate::parser::ty::parse_where(tokens, pos, &mut generics).is_none() {
+        return Some(Stmt::Error(
+            format!("構造体 {} の where 節が不正です", name),
+            tokens.span(*pos),
+        ));
+    }
+    let derives = derives(attrs);
+    let mut fields = Vec::new();
+    if tokens.get(*pos) == Some(&Token::Semicolon) {
+        // ユニット構造体
+        return Some(Stmt::StructDef {
+            name,
+            generics,
+            fields,
+            derives,
+            span,
+        });
+    }
+    if tokens.get(*pos) != Some(&Token::LBrace) {
+        return Some(Stmt::Error("{ が必要です".to_string(), tokens.span(*pos)));
+    }
+    *pos += 1;
+    loop {
+        match tokens.get(*pos) {
+            Some(Token::RBrace) => {
+                *pos += 1;
+                break;
+            }
+            Some(Token::Comma) | Some(Token::Pub) => *pos += 1,
+            Some(Token::Ident(field)) => {
+                let field = field.clone();
+                *pos += 1;
+                if tokens.get(*pos) != Some(&Token::Colon) {
+                    return Some(Stmt::Error(
+                        format!("フィールド {} の型が必要です", field),
+                        tokens.span(*pos),
+                    ));
+                }
+                *pos += 1;
+                let Some(ty) = crate::parser::ty::parse_type(tokens, pos) else {
+                    return Some(Stmt::Error(
+                        format!("フィールド {} の型が不正です", field),
+                        tokens.span(*pos),
+                    ));
+                };
+                fields.push((field, ty));
+            }
+            _ => {
+                return Some(Stmt::Error(
+                    "構造体の定義が不正です".to_string(),
+                    tokens.span(*pos),
+                ));
+            }
+        }
+    }
+    Some(Stmt::StructDef {
+        name,
+        generics,
+        fields,
+        derives,
+        span,
+    })
+}
+
+// #[derive(...)] に並んだトレイト名
+pub fn derives(attrs: &[Attr]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|a| a.name == "derive")
+        .flat_map(|a| a.args.iter().cloned())
+        .collect()
+}
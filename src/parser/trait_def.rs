@@ -0,0 +1,103 @@
+use crate::ast::Stmt;
+use crate::lexer::{Token, Tokens};
+use crate::parser::func::parse_fn;
+
+pub fn parse_trait(tokens: &Tokens, pos: &mut usize) -> Option<Stmt> {
+    // [pub] trait <name><generics> [: <super> + ...] { type <name>; fn <sig>; fn <sig> { <default> } ... }
+    let start = *pos;
+    if tokens.get(*pos) == Some(&Token::Pub) {
+        *pos += 1;
+    }
+    if tokens.get(*pos) != Some(&Token::Ident("trait".to_string())) {
+        *pos = start;
+        return None;
+    }
+    *pos += 1;
+    let span = tokens.span(*pos);
+    let name = if let Some(Token::Ident(n)) = tokens.get(*pos) {
+        *pos += 1;
+        n.clone()
+    } else {
+        return Some(Stmt::Error(
+            "トレイト名が必要です".to_string(),
+            tokens.span(*pos),
+        ));
+    };
+    let Some(mut generics) = crate::parser::ty::parse_generics(tokens, pos) else {
+        return Some(Stmt::Error(
+            format!("トレイト {} の型引数が不正です", name),
+            tokens.span(*pos),
+        ));
+    };
+    // 親トレイトは今のところ読み捨てる
+    if tokens.get(*pos) == Some(&Token::Colon) {
+        *pos += 1;
+        while crate::parser::ty::parse_type(tokens, pos).is_some()
+            && tokens.get(*pos) == Some(&Token::Plus)
+        {
+            *pos += 1;
+        }
+    }
+    if crate::parser::ty::parse_where(tokens, pos, &mut generics).is_none() {
+        return Some(Stmt::Error(
+            format!("トレイト {} の where 節が不正です", name),
+            tokens.span(*pos),
+        ));
+    }
+    if tokens.get(*pos) != Some(&Token::LBrace) {
+        return Some(Stmt::Error("{ が必要です".to_string(), tokens.span(*pos)));
+    }
+    *pos += 1;
+    let mut assoc = Vec::new();
+    let mut methods = Vec::new();
+    let mut required = Vec::new();
+    loop {
+        if let Err(msg) = crate::parser::attr::parse_attrs(tokens, pos) {
+            return Some(Stmt::Error(msg, tokens.span(*pos)));
+        }
+        match tokens.get(*pos) {
+            Some(Token::RBrace) => {
+                *pos += 1;
+                break;
+            }
+            Some(Token::Pub) | Some(Token::Fn) => {
+                let method = parse_fn(tokens, pos, true);
+                let Stmt::FuncDef { name, .. } = &method else {
+                    return Some(method);
+                };
+                if tokens.get(*pos) == Some(&Token::Semicolon) {
+                    *pos += 1;
+                    required.push(name.clone());
+                }
+                methods.push(method);
+            }
+            // 関連型: type Item;
+            Some(Token::Ident(kw)) if kw == "type" => {
+                let (Some(Token::Ident(item)), Some(Token::Semicolon)) =
+                    (tokens.get(*pos + 1), tokens.get(*pos + 2))
+                else {
+                    return Some(Stmt::Error(
+                        format!("トレイト {} の関連型の宣言が不正です", name),
+                        tokens.span(*pos),
+                    ));
+                };
+                assoc.push(item.clone());
+                *pos += 3;
+            }
+            _ => {
+                return Some(Stmt::Error(
+                    format!("トレイト {} の中には fn と type しか書けません", name),
+                    tokens.span(*pos),
+                ));
+            }
+        }
+    }
+    Some(Stmt::TraitDef {
+        name,
+        generics,
+        assoc,
+        methods,
+        required,
+        span,
+    })
+}
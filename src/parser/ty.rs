@@ -0,0 +1,157 @@
+use crate::ast::Generics;
+use crate::lexer::{Token, Tokens};
+
+// 型注釈: i64 / Vec<i64> / [i64; 3] / [i64] / &T / &mut T / (i64, bool) / ()
+// 型は現状、注釈の文字列としてそのまま保持する
+pub fn parse_type(tokens: &Tokens, pos: &mut usize) -> Option<String> {
+    match tokens.get(*pos)? {
+        Token::Amp => {
+            *pos += 1;
+            let prefix = if tokens.get(*pos) == Some(&Token::Mut) {
+                *pos += 1;
+                "&mut "
+            } else {
+                "&"
+            };
+            let inner = parse_type(tokens, pos)?;
+            Some(format!("{}{}", prefix, inner))
+        }
+        Token::LBracket => {
+            *pos += 1;
+            let elem = parse_type(tokens, pos)?;
+            let ty = if tokens.get(*pos) == Some(&Token::Semicolon) {
+                *pos += 1;
+                let Some(Token::Number(n)) = tokens.get(*pos) else {
+                    return None;
+                };
+                *pos += 1;
+                format!("[{}; {}]", elem, n)
+            } else {
+                format!("[{}]", elem)
+            };
+            if tokens.get(*pos) != Some(&Token::RBracket) {
+                return None;
+            }
+            *pos += 1;
+            Some(ty)
+        }
+        Token::LParen => {
+            *pos += 1;
+            let mut items = Vec::new();
+            while tokens.get(*pos) != Some(&Token::RParen) {
+                items.push(parse_type(tokens, pos)?);
+                if tokens.get(*pos) == Some(&Token::Comma) {
+                    *pos += 1;
+                } else if tokens.get(*pos) != Some(&Token::RParen) {
+                    return None;
+                }
+            }
+            *pos += 1;
+            if items.len() == 1 {
+                Some(format!("({},)", items[0]))
+            } else {
+                Some(format!("({})", items.join(", ")))
+            }
+        }
+        Token::Ident(name) => {
+            let mut ty = name.clone();
+            *pos += 1;
+            while tokens.get(*pos) == Some(&Token::ColonColon) {
+                let Some(Token::Ident(seg)) = tokens.get(*pos + 1) else {
+                    break;
+                };
+                ty = format!("{}::{}", ty, seg);
+                *pos += 2;
+            }
+            if tokens.get(*pos) == Some(&Token::Lt) {
+                let args = parse_generic_args(tokens, pos)?;
+                ty = format!("{}<{}>", ty, args.join(", "));
+            }
+            Some(ty)
+        }
+        _ => None,
+    }
+}
+
+// 型引数: <T, U, ...>
+pub fn parse_generic_args(tokens: &Tokens, pos: &mut usize) -> Option<Vec<String>> {
+    if tokens.get(*pos) != Some(&Token::Lt) {
+        return None;
+    }
+    *pos += 1;
+    let mut args = Vec::new();
+    while tokens.get(*pos) != Some(&Token::Gt) {
+        args.push(parse_type(tokens, pos)?);
+        if tokens.get(*pos) == Some(&Token::Comma) {
+            *pos += 1;
+        } else if tokens.get(*pos) != Some(&Token::Gt) {
+            return None;
+        }
+    }
+    *pos += 1;
+    Some(args)
+}
+
+// 型引数の宣言: <T, U: PartialOrd + Clone>（なければ空）
+pub fn parse_generics(tokens: &Tokens, pos: &mut usize) -> Option<Generics> {
+    let mut generics = Vec::new();
+    if tokens.get(*pos) != Some(&Token::Lt) {
+        return Some(generics);
+    }
+    *pos += 1;
+    while tokens.get(*pos) != Some(&Token::Gt) {
+        let Some(Token::Ident(name)) = tokens.get(*pos) else {
+            return None;
+        };
+        *pos += 1;
+        let bounds = if tokens.get(*pos) == Some(&Token::Colon) {
+            *pos += 1;
+            parse_bounds(tokens, pos)?
+        } else {
+            Vec::new()
+        };
+        generics.push((name.clone(), bounds));
+        if tokens.get(*pos) == Some(&Token::Comma) {
+            *pos += 1;
+        } else if tokens.get(*pos) != Some(&Token::Gt) {
+            return None;
+        }
+    }
+    *pos += 1;
+    Some(generics)
+}
+
+// where T: Display, U: Clone + Debug（境界は generics の同じ名前に足す）
+pub fn parse_where(tokens: &Tokens, pos: &mut usize, generics: &mut Generics) -> Option<()> {
+    if tokens.get(*pos) != Some(&Token::Ident("where".to_string())) {
+        return Some(());
+    }
+    *pos += 1;
+    while let Some(Token::Ident(name)) = tokens.get(*pos) {
+        *pos += 1;
+        if tokens.get(*pos) != Some(&Token::Colon) {
+            return None;
+        }
+        *pos += 1;
+        let bounds = parse_bounds(tokens, pos)?;
+        match generics.iter_mut().find(|(n, _)| n == name) {
+            Some((_, b)) => b.extend(bounds),
+            None => generics.push((name.clone(), bounds)),
+        }
+        if tokens.get(*pos) != Some(&Token::Comma) {
+            break;
+        }
+        *pos += 1;
+    }
+    Some(())
+}
+
+// トレイト境界: PartialOrd + Container<i64>
+fn parse_bounds(tokens: &Tokens, pos: &mut usize) -> Option<Vec<String>> {
+    let mut bounds = vec![parse_type(tokens, pos)?];
+    while tokens.get(*pos) == Some(&Token::Plus) {
+        *pos += 1;
+        bounds.push(parse_type(tokens, pos)?);
+    }
+    Some(bounds)
+}
@@ -1,25 +1,63 @@
 use crate::ast::Stmt;
-use crate::lexer::Token;
+use crate::lexer::{Token, Tokens};
 
 // useはRustの予約語のため、use_nasl.rsというファイル名に。
 // Rust風: use lib; use foo::bar; use foo::*; など
 // 今後mod.rsや名前空間も拡張可能な設計
-pub fn parse_use(tokens: &[Token], pos: &mut usize) -> Option<Stmt> {
-    if let Some(Token::Ident(s)) = tokens.get(*pos) {
-        if s == "use" {
+pub fn parse_use(tokens: &Tokens, pos: &mut usize) -> Option<Stmt> {
+    if let Some(Token::Ident(s)) = tokens.get(*pos)
+        && s == "use"
+    {
+        *pos += 1;
+        // Rust風: use lib; use foo::bar; use foo::*; など
+        if let Some(Token::Ident(modname)) = tokens.get(*pos) {
+            let modname = modname.clone();
             *pos += 1;
-            // Rust風: use lib; use foo::bar; use foo::*; など
-            if let Some(Token::Ident(modname)) = tokens.get(*pos) {
-                *pos += 1;
-                // use lib; → lib.nasl
-                let fname = format!("{}.nasl", modname);
-                return Some(Stmt::Import(fname));
-            } else if let Some(Token::StringLiteral(filename)) = tokens.get(*pos) {
-                *pos += 1;
-                // use "lib.nasl"; も許容
-                return Some(Stmt::Import(filename.clone()));
+            let path = skip_use_path(tokens, pos);
+            // use std::collections::HashMap; などの標準ライブラリは組み込み済み
+            if modname == "std" {
+                return Some(Stmt::Import(format!("std{}", path)));
             }
+            // use lib; → lib.nasl
+            let fname = format!("{}.nasl", modname);
+            return Some(Stmt::Import(fname));
+        } else if let Some(Token::StringLiteral(filename)) = tokens.get(*pos) {
+            *pos += 1;
+            // use "lib.nasl"; も許容
+            return Some(Stmt::Import(filename.clone()));
         }
     }
     None
 }
+
+// モジュール名より後ろのパス（::bar / ::* / ::{a, b}）を読み、文字列として返す
+fn skip_use_path(tokens: &Tokens, pos: &mut usize) -> String {
+    let mut path = String::new();
+    while tokens.get(*pos) == Some(&Token::ColonColon) {
+        *pos += 1;
+        path.push_str("::");
+        match tokens.get(*pos) {
+            Some(Token::Ident(seg)) => path.push_str(seg),
+            Some(Token::Star) => path.push('*'),
+            Some(Token::LBrace) => {
+                path.push('{');
+                while !matches!(
+                    tokens.get(*pos + 1),
+                    Some(Token::RBrace) | Some(Token::EOF) | None
+                ) {
+                    *pos += 1;
+                    match &tokens[*pos] {
+                        Token::Ident(seg) => path.push_str(seg),
+                        Token::Comma => path.push_str(", "),
+                        _ => {}
+                    }
+                }
+                *pos += 1;
+                path.push('}');
+            }
+            _ => break,
+        }
+        *pos += 1;
+    }
+    path
+}
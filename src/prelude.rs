@@ -0,0 +1,10 @@
+// プレリュード（src/prelude.nasl）の定義
+use crate::ast::Stmt;
+use crate::lexer::tokenize;
+use crate::parser::parse;
+
+const SOURCE: &str = include_str!("prelude.nasl");
+
+pub fn stmts() -> Vec<Stmt> {
+    parse(&tokenize(SOURCE))
+}
@@ -0,0 +1,495 @@
+// 名前の解決（構文解析のあと、評価の前に行う）。
+// 式の中の変数と関数呼び出しの名前を、局所変数の番地（さかのぼる関数の数, スロット）・
+// 関数の番号・組み込みの名前のどれかに結びつけ、構文木の Expr::Var・Expr::Call に書き込む。
+// 関数は定義の順によらずどこからでも呼べる。見つからない名前は、綴りの近い名前を添えて報告する
+use crate::ast::{Expr, Generics, Pattern, Resolved, Stmt};
+use crate::check::TypeError;
+use crate::check::ty::Ty;
+use crate::eval::{get_std_funcs, grow_stack};
+use crate::float;
+use crate::int;
+use crate::lexer::Span;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    // 局所変数。depth は外側へさかのぼる関数（クロージャ）の数で、0 ならその関数の変数。
+    // slot はその関数の中の、変数を作った時点で見えている変数の数（引数が先）。
+    // スコープを抜けると番号は次の変数に使い回すので、評価器は変数をスタックに積めばよい。
+    // トップレベルの文から見た、前の評価で作った変数は depth 1 になる
+    Local { depth: usize, slot: usize },
+    // 関数・impl のメソッド（Resolution::globals の添字）
+    Global(usize),
+    // 標準関数・バリアント・浮動小数点数の定数・Rust から登録した関数
+    Builtin,
+}
+
+// 解決の結果。名前ごとの結びつきは構文木に書き込むので、ここには表にしたものとエラーを持つ
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub errors: Vec<TypeError>,
+    // 関数の名前（Known::funcs の順のあとに定義の順）。impl のメソッドは "型名::メソッド名"
+    pub globals: Vec<String>,
+    // トップレベルの let で作る変数（スロットの順）
+    pub vars: Vec<String>,
+}
+
+// 解決する文より前から見えている名前（埋め込み先で定義済みのもの）
+#[derive(Debug, Clone, Default)]
+pub struct Known {
+    // 定義済みの関数（Global の番号はこの順に振る）
+    pub funcs: Vec<String>,
+    // Rust から登録した関数・定義済みのバリアントなど
+    pub builtins: Vec<String>,
+    // トップレベルの変数
+    pub vars: Vec<String>,
+}
+
+// プログラム全体の名前を解決する
+pub fn resolve(stmts: &[Stmt]) -> Resolution {
+    resolve_with(stmts, &Known::default())
+}
+
+// known の名前も見えているものとして解決する
+pub fn resolve_with(stmts: &[Stmt], known: &Known) -> Resolution {
+    let mut r = Resolver {
+        globals: Vec::new(),
+        index: HashMap::new(),
+        builtins: get_std_funcs().into_keys().collect(),
+        variants: HashSet::new(),
+        frames: Vec::new(),
+        generics: Vec::new(),
+        // import したファイルの関数は読み込むまで分からないので、見つからない呼び出しは報告しない
+        open: stmts
+            .iter()
+            .any(|s| matches!(s, Stmt::Import(path) if !path.starts_with("std::"))),
+        errors: Vec::new(),
+    };
+    r.builtins.extend(known.builtins.iter().cloned());
+    for name in &known.funcs {
+        r.global(name.clone());
+    }
+    let prelude = crate::prelude::stmts();
+    let mut traits = HashMap::new();
+    for stmt in prelude.iter().chain(stmts) {
+        if let Stmt::TraitDef {
+            name,
+            methods,
+            required,
+            ..
+        } = stmt
+        {
+            traits.insert(name.as_str(), (methods, required));
+        }
+    }
+    for stmt in prelude.iter().chain(stmts) {
+        if let Stmt::EnumDef { name, variants, .. } = stmt {
+            for (variant, _) in variants {
+                r.variants.insert(format!("{}::{}", name, variant));
+                // Option・Result のバリアントは列挙型の名前なしでも書ける
+                if matches!(name.as_str(), "Option" | "Result") {
+                    r.variants.insert(variant.clone());
+                }
+            }
+        }
+    }
+    for stmt in stmts {
+        match stmt {
+            Stmt::FuncDef { name, .. } => r.global(name.clone()),
+            // 評価器と同じく、impl に書かなかったメソッドはトレイトの既定の実装になる
+            Stmt::ImplDef {
+                trait_name,
+                target,
+                methods,
+                ..
+            } => {
+                let key = Ty::parse(target).key().unwrap_or_else(|| target.clone());
+                let mut defined = Vec::new();
+                for m in methods {
+                    if let Stmt::FuncDef { name, .. } = m {
+                        r.global(format!("{}::{}", key, name));
+                        defined.push(name);
+                    }
+                }
+                let trait_name = trait_name
+                    .as_deref()
+                    .map(|t| t.split('<').next().unwrap_or(t));
+                if let Some((methods, required)) = trait_name.and_then(|t| traits.get(t)) {
+                    for m in methods.iter() {
+                        if let Stmt::FuncDef { name, .. } = m
+                            && !defined.contains(&name)
+                            && !required.contains(name)
+                        {
+                            r.global(format!("{}::{}", key, name));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 前の評価で作った変数は、トップレベルの文を囲む関数の変数として扱う
+    r.frames.push(Frame::new(false));
+    for name in &known.vars {
+        r.declare(name);
+    }
+    r.frames.push(Frame::new(true));
+    for stmt in stmts {
+        match stmt {
+            Stmt::FuncDef {
+                generics,
+                params,
+                body,
+                ..
+            } => r.func(&[generics], params, body),
+            Stmt::ImplDef {
+                generics, methods, ..
+            }
+            | Stmt::TraitDef {
+                generics, methods, ..
+            } => {
+                for m in methods {
+                    if let Stmt::FuncDef {
+                        generics: own,
+                        params,
+                        body,
+                        ..
+                    } = m
+                    {
+                        r.func(&[generics, own], params, body);
+                    }
+                }
+            }
+            _ => r.stmt(stmt),
+        }
+    }
+    let top = r.frames.pop().expect("トップレベルの文を囲む関数です");
+    Resolution {
+        errors: r.errors,
+        globals: r.globals,
+        vars: top.scopes[0].iter().map(|(name, _)| name.clone()).collect(),
+    }
+}
+
+// 関数（クロージャ）ごとの見えている変数
+struct Frame {
+    // 内側のスコープほど後ろ
+    scopes: Vec<Vec<(String, usize)>>,
+    // 外側の関数の変数が見えるか（クロージャ・トップレベルの文）
+    closure: bool,
+}
+
+impl Frame {
+    fn new(closure: bool) -> Self {
+        Frame {
+            scopes: vec![Vec::new()],
+            closure,
+        }
+    }
+}
+
+struct Resolver {
+    globals: Vec<String>,
+    index: HashMap<String, usize>,
+    // 呼び出せる組み込みの名前
+    builtins: HashSet<String>,
+    // バリアント（"列挙型名::バリアント名"）
+    variants: HashSet<String>,
+    // 外側の関数ほど前
+    frames: Vec<Frame>,
+    // 解決中の関数・impl の型引数（T::default() などは型検査に任せる）
+    generics: Vec<String>,
+    open: bool,
+    errors: Vec<TypeError>,
+}
+
+impl Resolver {
+    fn global(&mut self, name: String) {
+        if !self.index.contains_key(&name) {
+            self.index.insert(name.clone(), self.globals.len());
+            self.globals.push(name);
+        }
+    }
+
+    fn declare(&mut self, name: &str) {
+        let frame = self.frames.last_mut().expect("関数の中です");
+        let slot = frame.scopes.iter().map(Vec::len).sum();
+        if let Some(scope) = frame.scopes.last_mut() {
+            scope.push((name.to_string(), slot));
+        }
+    }
+
+    fn declare_pattern(&mut self, p: &Pattern) {
+        for name in p.names() {
+            self.declare(&name);
+        }
+    }
+
+    fn push(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.push(Vec::new());
+        }
+    }
+
+    fn pop(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.pop();
+        }
+    }
+
+    // 内側から順に探す。関数の本体からは外側の変数は見えない
+    fn local(&self, name: &str) -> Option<Binding> {
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            let found = frame
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.iter().rev().find(|(n, _)| n == name));
+            if let Some((_, slot)) = found {
+                return Some(Binding::Local { depth, slot: *slot });
+            }
+            if !frame.closure {
+                break;
+            }
+        }
+        None
+    }
+
+    // Self::new() / T::default() など、型引数や Self を通した関連関数
+    fn associated(&self, name: &str) -> bool {
+        name.rsplit_once("::").is_some_and(|(prefix, _)| {
+            let prefix = prefix.rsplit("::").next().unwrap_or(prefix);
+            prefix == "Self" || self.generics.iter().any(|g| g == prefix)
+        })
+    }
+
+    // 変数として使われた名前（評価器と同じく、変数・関数・定数・バリアントの順に探す）
+    fn var(&mut self, name: &str, span: Span, resolved: &Resolved) {
+        let binding = if let Some(b) = self.local(name) {
+            Some(b)
+        } else if name == "None" || name == "Option::None" {
+            Some(Binding::Builtin)
+        } else if let Some(&i) = self.index.get(name) {
+            Some(Binding::Global(i))
+        } else if float::constant(name).is_some()
+            || int::constant(name).is_some()
+            || self.variants.contains(name)
+            || self.associated(name)
+        {
+            Some(Binding::Builtin)
+        } else {
+            self.undefined("変数", name, span);
+            None
+        };
+        resolved.set(binding);
+    }
+
+    // 呼び出された名前（評価器と同じく、変数・組み込み・関数の順に探す）
+    fn call(&mut self, name: &str, span: Span, resolved: &Resolved) {
+        let binding = if let Some(b) = self.local(name) {
+            Some(b)
+        } else if self.builtins.contains(name) {
+            Some(Binding::Builtin)
+        } else if let Some(&i) = self.index.get(name) {
+            Some(Binding::Global(i))
+        } else if self.variants.contains(name) || self.associated(name) {
+            Some(Binding::Builtin)
+        } else {
+            if !self.open {
+                self.undefined("関数", name, span);
+            }
+            None
+        };
+        resolved.set(binding);
+    }
+
+    fn undefined(&mut self, what: &str, name: &str, span: Span) {
+        let mut message = format!("未定義の{}: {}", what, name);
+        if let Some(near) = self.suggest(name) {
+            message.push_str(&format!("（もしかして: {}）", near));
+        }
+        self.errors.push(TypeError {
+            span,
+            message,
+            note: None,
+        });
+    }
+
+    // 見えている名前のうち、綴りが一番近いもの（離れすぎていれば None）
+    fn suggest(&self, name: &str) -> Option<String> {
+        let mut candidates: Vec<&String> = Vec::new();
+        for frame in self.frames.iter().rev() {
+            candidates.extend(frame.scopes.iter().flatten().map(|(n, _)| n));
+            if !frame.closure {
+                break;
+            }
+        }
+        candidates.extend(&self.globals);
+        candidates.extend(&self.builtins);
+        candidates.extend(&self.variants);
+        let limit = (name.chars().count() / 3).max(1);
+        candidates
+            .into_iter()
+            .map(|c| (distance(name, c), c))
+            .filter(|(d, _)| *d <= limit)
+            .min()
+            .map(|(_, c)| c.clone())
+    }
+
+    fn func(&mut self, generics: &[&Generics], params: &[Pattern], body: &Expr) {
+        let saved_frames = std::mem::take(&mut self.frames);
+        let saved_generics = self.generics.clone();
+        for g in generics.iter().copied().flatten() {
+            self.generics.push(g.0.clone());
+        }
+        self.frames.push(Frame::new(false));
+        for p in params {
+            self.declare_pattern(p);
+        }
+        self.expr(body);
+        self.frames = saved_frames;
+        self.generics = saved_generics;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::Print(e) => self.expr(e),
+            Stmt::Let { pattern, value, .. } => {
+                self.expr(value);
+                self.declare_pattern(pattern);
+            }
+            // ブロック内の関数定義・import・型定義は評価されない
+            _ => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        grow_stack(|| self.expr_inner(expr))
+    }
+
+    fn expr_inner(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(_)
+            | Expr::TypedNumber(..)
+            | Expr::Float(..)
+            | Expr::Bool(_)
+            | Expr::Char(_)
+            | Expr::Str(_) => {}
+            Expr::Var(name, span, resolved) => self.var(name, *span, resolved),
+            Expr::Call(name, args, span, resolved) => {
+                self.call(name, *span, resolved);
+                args.iter().for_each(|a| self.expr(a));
+            }
+            Expr::Binary(_, a, b, _) | Expr::ArrayRepeat(a, b) => {
+                self.expr(a);
+                self.expr(b);
+            }
+            Expr::Index { target, index, .. } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            Expr::Assign { target, value, .. } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            Expr::Unary(_, e, _)
+            | Expr::Ref(e, ..)
+            | Expr::Vec(e)
+            | Expr::FieldAccess(e, _)
+            | Expr::Cast(e, ..)
+            | Expr::Try(e) => self.expr(e),
+            Expr::Return(e) => {
+                if let Some(e) = e {
+                    self.expr(e);
+                }
+            }
+            Expr::MethodCall(recv, _, args, _) => {
+                self.expr(recv);
+                args.iter().for_each(|a| self.expr(a));
+            }
+            Expr::Tuple(items) | Expr::Array(items) => items.iter().for_each(|e| self.expr(e)),
+            Expr::Format { args, .. } => args.iter().for_each(|e| self.expr(e)),
+            Expr::StructInit(_, fields) => fields.iter().for_each(|(_, e, _)| self.expr(e)),
+            Expr::Panic { args, message, .. } => {
+                args.iter().for_each(|e| self.expr(e));
+                if let Some(m) = message {
+                    self.expr(m);
+                }
+            }
+            Expr::Block(stmts) => {
+                self.push();
+                stmts.iter().for_each(|s| self.stmt(s));
+                self.pop();
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.expr(then_branch);
+                if let Some(e) = else_branch {
+                    self.expr(e);
+                }
+            }
+            Expr::For {
+                pattern,
+                iter,
+                body,
+            } => {
+                self.expr(iter);
+                self.push();
+                self.declare_pattern(pattern);
+                self.expr(body);
+                self.pop();
+            }
+            Expr::Range { start, end, .. } => {
+                for e in [start, end].into_iter().flatten() {
+                    self.expr(e);
+                }
+            }
+            Expr::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for (pattern, body) in arms {
+                    self.push();
+                    self.declare_pattern(pattern);
+                    self.expr(body);
+                    self.pop();
+                }
+            }
+            Expr::Closure { params, body } => {
+                self.frames.push(Frame::new(true));
+                for p in params {
+                    self.declare_pattern(p);
+                }
+                self.expr(body);
+                self.frames.pop();
+            }
+        }
+    }
+}
+
+// 編集距離（隣り合う文字の入れ替えも1回と数える）
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    d[0] = (0..=b.len()).collect();
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
@@ -0,0 +1,581 @@
+// 実行時の値
+use crate::ast::{Expr, Pattern};
+use crate::eval::iter::Iter;
+use crate::eval::{ErrorKind, RuntimeError, fail};
+use crate::float::FloatTy;
+use crate::int::IntTy;
+use std::any::Any;
+use std::cell::{Ref, RefCell, RefMut};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub enum Value {
+    Unit,
+    Int(i64),
+    // i64 以外の整数型（値は型のビット幅に切り詰めたビット列）
+    Sized(IntTy, u128),
+    // f64（型注釈のない小数リテラルもこれになる）
+    Float(f64),
+    F32(f32),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Tuple(Vec<Value>),
+    // 配列・Vec。値として振る舞い、書き換え時に共有されていれば複製する（Rc::make_mut）
+    Array(Rc<Vec<Value>>),
+    // 構造体。フィールドは定義順に並ぶ
+    Struct {
+        name: Rc<str>,
+        fields: Rc<Vec<(String, Value)>>,
+    },
+    // 列挙型の値。index はバリアントの定義順（derive(PartialOrd) の比較に使う）
+    Enum {
+        name: Rc<str>,
+        index: usize,
+        variant: Rc<str>,
+        fields: Rc<Vec<Value>>,
+    },
+    // 範囲: start..end / start..=end（両端は省略可）
+    Range {
+        start: Option<i64>,
+        end: Option<i64>,
+        inclusive: bool,
+    },
+    // HashMap / HashSet。表示・反復の順序が毎回同じになるよう、キーの順序で並べて持つ
+    Map(Rc<BTreeMap<MapKey, Value>>),
+    Set(Rc<BTreeSet<MapKey>>),
+    Option(Option<Box<Value>>),
+    Result(Result<Box<Value>, Box<Value>>),
+    // クロージャ・関数名の参照
+    Closure(Rc<Closure>),
+    // イテレータは状態を持つので共有する（Rustの `let mut it = ...; it.next()` と同じ振る舞い）
+    Iter(Rc<RefCell<Iter>>),
+    // 埋め込む側の Rust の値。複製しても同じ値を指す
+    Host(HostObject),
+}
+
+// クロージャは作られた時点の変数をコピーして持つ
+pub struct Closure {
+    pub params: Vec<Pattern>,
+    pub body: Expr,
+    // 外側の関数ごとの変数（外側ほど前）。本体の名前の解決での番地
+    // （さかのぼる関数の数, スロット）で読む。使わないスロットは () のこともある
+    pub captured: Vec<Vec<Value>>,
+    // VM がコンパイルした本体（木をたどる評価では使わない）
+    pub code: Option<Rc<dyn Any>>,
+}
+
+// 型を消した Host<T>。中身は RefCell<T>
+#[derive(Clone)]
+pub struct HostObject {
+    name: Rc<str>,
+    value: Rc<dyn Any>,
+}
+
+impl HostObject {
+    // スクリプトから見た型名（メソッド・プロパティはこの名前で登録する）
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn downcast<T: 'static>(&self) -> Option<Host<T>> {
+        Some(Host {
+            name: self.name.clone(),
+            value: self.value.clone().downcast::<RefCell<T>>().ok()?,
+        })
+    }
+}
+
+// スクリプトに渡す Rust の値への参照。参照カウントで管理するので、
+// スクリプトが持っている間は解放されない
+pub struct Host<T> {
+    name: Rc<str>,
+    value: Rc<RefCell<T>>,
+}
+
+impl<T: 'static> Host<T> {
+    pub fn new(name: &str, value: T) -> Self {
+        Host {
+            name: Rc::from(name),
+            value: Rc::new(RefCell::new(value)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.value.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.value.borrow_mut()
+    }
+}
+
+impl<T> Clone for Host<T> {
+    fn clone(&self) -> Self {
+        Host {
+            name: self.name.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T: 'static> From<Host<T>> for Value {
+    fn from(h: Host<T>) -> Self {
+        Value::Host(HostObject {
+            name: h.name,
+            value: h.value,
+        })
+    }
+}
+
+impl Value {
+    // Rust の値 value を型名 name の値としてスクリプトに渡す
+    pub fn host<T: 'static>(name: &str, value: T) -> Self {
+        Host::new(name, value).into()
+    }
+
+    // Host で渡した値を取り出す（型が違えば None）
+    pub fn downcast<T: 'static>(&self) -> Option<Host<T>> {
+        match self {
+            Value::Host(h) => h.downcast(),
+            _ => None,
+        }
+    }
+
+    pub fn some(v: Value) -> Self {
+        Value::Option(Some(Box::new(v)))
+    }
+
+    pub fn none() -> Self {
+        Value::Option(None)
+    }
+
+    pub fn ok(v: Value) -> Self {
+        Value::Result(Ok(Box::new(v)))
+    }
+
+    pub fn err(e: Value) -> Self {
+        Value::Result(Err(Box::new(e)))
+    }
+
+    // 整数なら型とビット列
+    pub fn as_int(&self) -> Option<(IntTy, u128)> {
+        match self {
+            Value::Int(n) => Some((IntTy::I64, IntTy::I64.truncate(*n as u128))),
+            Value::Sized(ty, bits) => Some((*ty, *bits)),
+            _ => None,
+        }
+    }
+
+    // 型とビット列から整数を作る（i64 は Value::Int にする）
+    pub fn from_int(ty: IntTy, bits: u128) -> Self {
+        match ty {
+            IntTy::I64 => Value::Int(bits as u64 as i64),
+            _ => Value::Sized(ty, bits),
+        }
+    }
+
+    // 添字・範囲の端など i64 として使う整数（i64 に収まらなければ None）
+    pub fn as_i64(&self) -> Option<i64> {
+        let (ty, bits) = self.as_int()?;
+        ty.to_i128(bits).and_then(|n| i64::try_from(n).ok())
+    }
+
+    // 浮動小数点数なら型と値
+    pub fn as_float(&self) -> Option<(FloatTy, f64)> {
+        match self {
+            Value::Float(x) => Some((FloatTy::F64, *x)),
+            Value::F32(x) => Some((FloatTy::F32, *x as f64)),
+            _ => None,
+        }
+    }
+
+    pub fn from_float(ty: FloatTy, x: f64) -> Self {
+        match ty {
+            FloatTy::F64 => Value::Float(x),
+            FloatTy::F32 => Value::F32(x as f32),
+        }
+    }
+
+    // エラーメッセージ用の型名
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Unit => "()".to_string(),
+            Value::Int(_) => "i64".to_string(),
+            Value::Sized(ty, _) => ty.name().to_string(),
+            Value::Float(_) => "f64".to_string(),
+            Value::F32(_) => "f32".to_string(),
+            Value::Bool(_) => "bool".to_string(),
+            Value::Char(_) => "char".to_string(),
+            Value::Str(_) => "String".to_string(),
+            Value::Tuple(_) => "tuple".to_string(),
+            Value::Array(_) => "Vec".to_string(),
+            Value::Struct { name, .. } | Value::Enum { name, .. } => name.to_string(),
+            Value::Range { .. } => "Range".to_string(),
+            Value::Map(_) => "HashMap".to_string(),
+            Value::Set(_) => "HashSet".to_string(),
+            Value::Option(_) => "Option".to_string(),
+            Value::Result(_) => "Result".to_string(),
+            Value::Closure(_) => "closure".to_string(),
+            Value::Iter(_) => "Iterator".to_string(),
+            Value::Host(h) => h.name().to_string(),
+        }
+    }
+
+    // HashMapのキー・HashSetの要素にできるか
+    pub fn is_hashable(&self) -> bool {
+        match self {
+            Value::Unit
+            | Value::Int(_)
+            | Value::Sized(..)
+            | Value::Bool(_)
+            | Value::Char(_)
+            | Value::Str(_) => true,
+            Value::Tuple(items) => items.iter().all(Value::is_hashable),
+            Value::Array(items) => items.iter().all(Value::is_hashable),
+            Value::Struct { fields, .. } => fields.iter().all(|(_, v)| v.is_hashable()),
+            Value::Enum { fields, .. } => fields.iter().all(Value::is_hashable),
+            Value::Range { .. } => true,
+            Value::Option(v) => v.as_ref().is_none_or(|v| v.is_hashable()),
+            Value::Result(Ok(v) | Err(v)) => v.is_hashable(),
+            // Rust と同じく浮動小数点数はキーにできない
+            Value::Float(_)
+            | Value::F32(_)
+            | Value::Map(_)
+            | Value::Set(_)
+            | Value::Closure(_)
+            | Value::Iter(_)
+            | Value::Host(_) => false,
+        }
+    }
+}
+
+// HashMapのキー。型をまたいでも順序が決まるようにする（型ごとの順位 → 中身の順）
+#[derive(Clone, Debug)]
+pub struct MapKey(pub Value);
+
+impl MapKey {
+    pub fn new(v: Value) -> Result<Self, RuntimeError> {
+        if !v.is_hashable() {
+            return fail(
+                ErrorKind::Type,
+                format!("{} はキーにできません: {:?}", v.type_name(), v),
+            );
+        }
+        Ok(MapKey(v))
+    }
+}
+
+fn key_rank(v: &Value) -> u8 {
+    match v {
+        Value::Unit => 0,
+        Value::Bool(_) => 1,
+        Value::Int(_) | Value::Sized(..) => 2,
+        Value::Char(_) => 3,
+        Value::Str(_) => 4,
+        Value::Tuple(_) => 5,
+        Value::Array(_) => 6,
+        Value::Struct { .. } => 7,
+        Value::Range { .. } => 8,
+        Value::Option(_) => 9,
+        Value::Result(_) => 10,
+        Value::Enum { .. } => 11,
+        Value::Float(_)
+        | Value::F32(_)
+        | Value::Map(_)
+        | Value::Set(_)
+        | Value::Closure(_)
+        | Value::Iter(_)
+        | Value::Host(_) => 12,
+    }
+}
+
+fn key_cmp(a: &Value, b: &Value) -> Ordering {
+    fn seq_cmp<'a>(
+        mut a: impl Iterator<Item = &'a Value>,
+        mut b: impl Iterator<Item = &'a Value>,
+    ) -> Ordering {
+        loop {
+            match (a.next(), b.next()) {
+                (Some(x), Some(y)) => match key_cmp(x, y) {
+                    Ordering::Equal => {}
+                    ord => return ord,
+                },
+                (x, y) => return x.is_some().cmp(&y.is_some()),
+            }
+        }
+    }
+    match (a, b) {
+        // 型の違う整数は型の順に並べる
+        (Value::Int(_) | Value::Sized(..), Value::Int(_) | Value::Sized(..)) => {
+            let ((t1, x), (t2, y)) = (a.as_int().unwrap(), b.as_int().unwrap());
+            t1.cmp(&t2).then_with(|| t1.compare(x, y))
+        }
+        (Value::Tuple(x), Value::Tuple(y)) => seq_cmp(x.iter(), y.iter()),
+        (Value::Array(x), Value::Array(y)) => seq_cmp(x.iter(), y.iter()),
+        (
+            Value::Struct {
+                name: n1,
+                fields: f1,
+            },
+            Value::Struct {
+                name: n2,
+                fields: f2,
+            },
+        ) => n1
+            .cmp(n2)
+            .then_with(|| seq_cmp(f1.iter().map(|(_, v)| v), f2.iter().map(|(_, v)| v))),
+        (
+            Value::Enum {
+                name: n1,
+                index: i1,
+                fields: f1,
+                ..
+            },
+            Value::Enum {
+                name: n2,
+                index: i2,
+                fields: f2,
+                ..
+            },
+        ) => n1
+            .cmp(n2)
+            .then(i1.cmp(i2))
+            .then_with(|| seq_cmp(f1.iter(), f2.iter())),
+        (
+            Value::Range {
+                start: s1,
+                end: e1,
+                inclusive: i1,
+            },
+            Value::Range {
+                start: s2,
+                end: e2,
+                inclusive: i2,
+            },
+        ) => (s1, e1, i1).cmp(&(s2, e2, i2)),
+        (Value::Option(x), Value::Option(y)) => match (x, y) {
+            (Some(x), Some(y)) => key_cmp(x, y),
+            _ => x.is_some().cmp(&y.is_some()),
+        },
+        (Value::Result(x), Value::Result(y)) => match (x, y) {
+            (Ok(x), Ok(y)) | (Err(x), Err(y)) => key_cmp(x, y),
+            _ => x.is_err().cmp(&y.is_err()),
+        },
+        _ => a
+            .partial_cmp(b)
+            .unwrap_or_else(|| key_rank(a).cmp(&key_rank(b))),
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &Self) -> bool {
+        key_cmp(&self.0, &other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for MapKey {}
+
+impl PartialOrd for MapKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MapKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        key_cmp(&self.0, &other.0)
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Sized(t1, a), Value::Sized(t2, b)) => t1 == t2 && a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::F32(a), Value::F32(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (
+                Value::Struct {
+                    name: n1,
+                    fields: f1,
+                },
+                Value::Struct {
+                    name: n2,
+                    fields: f2,
+                },
+            ) => n1 == n2 && f1 == f2,
+            (
+                Value::Enum {
+                    name: n1,
+                    index: i1,
+                    fields: f1,
+                    ..
+                },
+                Value::Enum {
+                    name: n2,
+                    index: i2,
+                    fields: f2,
+                    ..
+                },
+            ) => n1 == n2 && i1 == i2 && f1 == f2,
+            (
+                Value::Range {
+                    start: s1,
+                    end: e1,
+                    inclusive: i1,
+                },
+                Value::Range {
+                    start: s2,
+                    end: e2,
+                    inclusive: i2,
+                },
+            ) => s1 == s2 && e1 == e2 && i1 == i2,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Set(a), Value::Set(b)) => a == b,
+            (Value::Option(a), Value::Option(b)) => a == b,
+            (Value::Result(a), Value::Result(b)) => a == b,
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            (Value::Iter(a), Value::Iter(b)) => Rc::ptr_eq(a, b),
+            (Value::Host(a), Value::Host(b)) => Rc::ptr_eq(&a.value, &b.value),
+            _ => false,
+        }
+    }
+}
+
+// 比較演算子・sortで使う順序（Rustと同じく同じ型同士のみ比較できる）
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Unit, Value::Unit) => Some(Ordering::Equal),
+            (Value::Int(a), Value::Int(b)) => a.partial_cmp(b),
+            (Value::Sized(t1, a), Value::Sized(t2, b)) if t1 == t2 => Some(t1.compare(*a, *b)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::F32(a), Value::F32(b)) => a.partial_cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.partial_cmp(b),
+            (Value::Char(a), Value::Char(b)) => a.partial_cmp(b),
+            (Value::Str(a), Value::Str(b)) => a.partial_cmp(b),
+            (Value::Tuple(a), Value::Tuple(b)) => a.partial_cmp(b),
+            (Value::Array(a), Value::Array(b)) => a.partial_cmp(b),
+            (Value::Option(a), Value::Option(b)) => a.partial_cmp(b),
+            (Value::Result(a), Value::Result(b)) => a.partial_cmp(b),
+            (
+                Value::Enum {
+                    name: n1,
+                    index: i1,
+                    fields: f1,
+                    ..
+                },
+                Value::Enum {
+                    name: n2,
+                    index: i2,
+                    fields: f2,
+                    ..
+                },
+            ) if n1 == n2 => (i1, f1).partial_cmp(&(i2, f2)),
+            _ => None,
+        }
+    }
+}
+
+fn fmt_range(
+    f: &mut fmt::Formatter<'_>,
+    start: &Option<i64>,
+    end: &Option<i64>,
+    inclusive: bool,
+) -> fmt::Result {
+    if let Some(s) = start {
+        write!(f, "{}", s)?;
+    }
+    write!(f, "{}", if inclusive { "..=" } else { ".." })?;
+    if let Some(e) = end {
+        write!(f, "{}", e)?;
+    }
+    Ok(())
+}
+
+// `{}` 相当の表示。精度（{:.3}）は中の数値・文字列の表示にそのまま渡す
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => fmt::Display::fmt(s, f),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Float(x) => fmt::Display::fmt(x, f),
+            Value::F32(x) => fmt::Display::fmt(x, f),
+            _ => fmt::Debug::fmt(self, f),
+        }
+    }
+}
+
+// `{:?}` 相当の表示（文字列は引用符付き）
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Sized(ty, bits) => write!(f, "{}", ty.format(*bits)),
+            Value::Float(x) => fmt::Debug::fmt(x, f),
+            Value::F32(x) => fmt::Debug::fmt(x, f),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Char(c) => write!(f, "{:?}", c),
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::Tuple(items) => {
+                let mut t = f.debug_tuple("");
+                for item in items {
+                    t.field(item);
+                }
+                t.finish()
+            }
+            Value::Array(items) => f.debug_list().entries(items.iter()).finish(),
+            Value::Struct { name, fields } => {
+                if fields.is_empty() {
+                    return write!(f, "{}", name);
+                }
+                let mut s = f.debug_struct(name);
+                for (field, value) in fields.iter() {
+                    s.field(field, value);
+                }
+                s.finish()
+            }
+            Value::Enum {
+                variant, fields, ..
+            } => {
+                let mut t = f.debug_tuple(variant);
+                for field in fields.iter() {
+                    t.field(field);
+                }
+                t.finish()
+            }
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            } => fmt_range(f, start, end, *inclusive),
+            Value::Map(map) => f
+                .debug_map()
+                .entries(map.iter().map(|(k, v)| (&k.0, v)))
+                .finish(),
+            Value::Set(set) => f.debug_set().entries(set.iter().map(|k| &k.0)).finish(),
+            Value::Option(Some(v)) => f.debug_tuple("Some").field(v).finish(),
+            Value::Option(None) => write!(f, "None"),
+            Value::Result(Ok(v)) => f.debug_tuple("Ok").field(v).finish(),
+            Value::Result(Err(e)) => f.debug_tuple("Err").field(e).finish(),
+            Value::Closure(_) => write!(f, "<closure>"),
+            Value::Iter(_) => write!(f, "Iter {{ .. }}"),
+            Value::Host(h) => write!(f, "<{}>", h.name()),
+        }
+    }
+}
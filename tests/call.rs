@@ -0,0 +1,109 @@
+// Rust の型で引数・戻り値を受け渡して nasl の関数・クロージャを呼び出せることを確かめる
+use nanai_simple_lang::eval::{ErrorKind, Interpreter};
+use std::collections::HashMap;
+
+fn load(code: &str) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(code)
+        .unwrap_or_else(|e| panic!("{:?}", e));
+    interpreter
+}
+
+#[test]
+fn typed_calls() {
+    let interpreter = load(
+        r#"
+        fn validate(n: i64, s: String) -> bool { n > 0 && s.len() > 0 }
+        fn stats(xs: Vec<i64>) -> (i64, i64) {
+            let mut sum = 0;
+            for x in &xs { sum += *x; }
+            (xs.len() as i64, sum)
+        }
+        "#,
+    );
+    let ok = interpreter
+        .call::<(i64, String), bool>("validate", (42, "x".into()))
+        .unwrap();
+    assert!(ok);
+    let ok = interpreter
+        .call::<(i64, String), bool>("validate", (42, String::new()))
+        .unwrap();
+    assert!(!ok);
+    let (len, sum) = interpreter
+        .call::<(Vec<i64>,), (i64, i64)>("stats", (vec![1, 2, 3],))
+        .unwrap();
+    assert_eq!((len, sum), (3, 6));
+}
+
+// HashMap・Option・Result も変換する。スクリプトの Err は実行時エラーではなく Rust の Err になる
+#[test]
+fn collections_options_and_results() {
+    let interpreter = load(
+        r#"
+        use std::collections::HashMap;
+        fn lookup(m: HashMap<String, i64>, k: String) -> Option<i64> {
+            if m.contains_key(&k) { Some(m[&k]) } else { None }
+        }
+        fn parse_age(n: i64) -> Result<i64, String> {
+            if n < 0 { Err("負の年齢".to_string()) } else { Ok(n) }
+        }
+        fn counts(words: Vec<String>) -> HashMap<String, i64> {
+            let mut m = HashMap::new();
+            for w in words { *m.entry(w).or_insert(0) += 1; }
+            m
+        }
+        "#,
+    );
+    let m = HashMap::from([("a".to_string(), 1)]);
+    let found = interpreter
+        .call::<(HashMap<String, i64>, &str), Option<i64>>("lookup", (m.clone(), "a"))
+        .unwrap();
+    assert_eq!(found, Some(1));
+    let missing = interpreter
+        .call::<(HashMap<String, i64>, &str), Option<i64>>("lookup", (m, "b"))
+        .unwrap();
+    assert_eq!(missing, None);
+    let age = interpreter
+        .call::<(i64,), Result<i64, String>>("parse_age", (-1,))
+        .unwrap();
+    assert_eq!(age, Err("負の年齢".to_string()));
+    let counts = interpreter
+        .call::<(Vec<&str>,), HashMap<String, i64>>("counts", (vec!["x", "y", "x"],))
+        .unwrap();
+    assert_eq!(counts, HashMap::from([("x".into(), 2), ("y".into(), 1)]));
+}
+
+#[test]
+fn mismatched_return_type_is_an_error() {
+    let interpreter = load("fn name() -> String { \"nasl\".to_string() }");
+    let e = interpreter.call::<(), i64>("name", ()).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Type);
+    assert_eq!(
+        e.message,
+        "name の戻り値には i64 が必要ですが String が返されました: \"nasl\""
+    );
+}
+
+// get_fn は名前で、closure はスクリプトから受け取った値で Rust の関数にする
+#[test]
+fn functions_and_closures_as_rust_fns() {
+    let interpreter = load(
+        "fn add(a: i64, b: i64) -> i64 { a + b } let offset = 100; let shift = |x| x + offset;",
+    );
+    let add = interpreter.get_fn::<(i64, i64), i64>("add");
+    assert_eq!(add((1, 2)).unwrap(), 3);
+    assert_eq!(add((20, 22)).unwrap(), 42);
+
+    let shift = interpreter.get_global("shift").unwrap();
+    let shift = interpreter.closure::<(i64,), i64>(shift).unwrap();
+    assert_eq!(shift((5,)).unwrap(), 105);
+
+    let offset = interpreter.get_global("offset").unwrap();
+    let e = interpreter
+        .closure::<(i64,), i64>(offset)
+        .err()
+        .expect("整数はクロージャにできません");
+    assert_eq!(e.kind, ErrorKind::Type);
+    assert_eq!(e.message, "i64 は呼び出せません");
+}
@@ -0,0 +1,182 @@
+// Capabilities で許可していない入出力が Permission の実行時エラーになることを確かめる
+use nanai_simple_lang::eval::{Capabilities, ErrorKind, Interpreter, OutputBuffer, PathAccess};
+use nanai_simple_lang::value::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// allowed/・secret/ の2つのディレクトリを作る
+fn sandbox(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nasl-caps-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("allowed")).unwrap();
+    fs::create_dir_all(dir.join("secret")).unwrap();
+    fs::write(dir.join("allowed/notes.txt"), "ok").unwrap();
+    fs::write(dir.join("secret/key.txt"), "secret").unwrap();
+    dir
+}
+
+// allowed/ の下だけ読み書きできるインタプリタ
+fn confined(dir: &Path) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_capabilities(Capabilities {
+        read: PathAccess::Within(vec![dir.join("allowed")]),
+        write: PathAccess::Within(vec![dir.join("allowed")]),
+        ..Capabilities::none()
+    });
+    interpreter
+}
+
+fn read(interpreter: &mut Interpreter, path: &Path) -> Result<Value, String> {
+    let code = format!(
+        "std::fs::read_to_string({:?}).unwrap()",
+        path.to_str().unwrap()
+    );
+    interpreter.eval_str(&code).map_err(|e| {
+        assert_eq!(e.kind, ErrorKind::Permission, "{:?}", e);
+        e.message
+    })
+}
+
+#[test]
+fn reads_and_writes_stay_within_the_roots() {
+    let dir = sandbox("roots");
+    let mut interpreter = confined(&dir);
+    let v = read(&mut interpreter, &dir.join("allowed/notes.txt")).unwrap();
+    assert!(matches!(v, Value::Str(s) if &*s == "ok"));
+    let secret = dir.join("secret/key.txt");
+    assert_eq!(
+        read(&mut interpreter, &secret).unwrap_err(),
+        format!(
+            "ファイル {} の読み込みは許可されていません",
+            secret.display()
+        )
+    );
+
+    let created = dir.join("allowed/new.txt");
+    let code = format!(
+        "std::fs::write({:?}, \"hi\").unwrap()",
+        created.to_str().unwrap()
+    );
+    interpreter.eval_str(&code).unwrap();
+    assert_eq!(fs::read_to_string(&created).unwrap(), "hi");
+    let code = format!(
+        "std::fs::write({:?}, \"x\")",
+        dir.join("secret/new.txt").to_str().unwrap()
+    );
+    let e = interpreter.eval_str(&code).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Permission);
+    assert!(!dir.join("secret/new.txt").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// .. でもシンボリックリンクでも、許可したディレクトリの外には出られない
+#[test]
+fn parent_dirs_and_symlinks_cannot_escape() {
+    let dir = sandbox("escape");
+    let mut interpreter = confined(&dir);
+    let dotdot = dir.join("allowed/../secret/key.txt");
+    assert!(read(&mut interpreter, &dotdot).is_err());
+    // 中で .. を使っても外に出なければよい
+    fs::create_dir(dir.join("allowed/sub")).unwrap();
+    let inside = dir.join("allowed/sub/../notes.txt");
+    assert!(read(&mut interpreter, &inside).is_ok());
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(dir.join("secret"), dir.join("allowed/link")).unwrap();
+        assert!(read(&mut interpreter, &dir.join("allowed/link/key.txt")).is_err());
+        // リンクの先の .. はリンク先の親になる
+        let via_link = dir.join("allowed/link/../allowed/notes.txt");
+        assert!(read(&mut interpreter, &via_link).is_ok());
+        let code = format!(
+            "std::fs::write({:?}, \"x\")",
+            dir.join("allowed/link/planted.txt").to_str().unwrap()
+        );
+        let e = interpreter.eval_str(&code).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Permission);
+        assert!(!dir.join("secret/planted.txt").exists());
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// use で読み込むファイルも読み込みの許可が要る
+#[test]
+fn imports_need_read_permission() {
+    let dir = sandbox("import");
+    fs::write(dir.join("secret/lib.nasl"), "fn leak() -> i64 { 1 }").unwrap();
+    let mut interpreter = confined(&dir);
+    let code = format!("use {:?};", dir.join("secret/lib.nasl").to_str().unwrap());
+    let e = interpreter.eval_str(&code).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Permission);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn other_capabilities() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .set_capabilities(Capabilities::none())
+        .set_stdout(OutputBuffer::new());
+    for (code, message) in [
+        (
+            "println!(\"x\")",
+            "標準出力への書き込みは許可されていません",
+        ),
+        ("input()", "標準入力の読み込みは許可されていません"),
+        (
+            "std::env::var(\"HOME\")",
+            "環境変数 HOME の読み込みは許可されていません",
+        ),
+        (
+            "std::process::exit(0)",
+            "プロセスの操作は許可されていません",
+        ),
+        ("time::now_millis()", "時刻の読み出しは許可されていません"),
+    ] {
+        let e = interpreter.eval_str(code).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Permission, "{}", code);
+        assert_eq!(e.message, message);
+    }
+    // 許可すれば使える。登録した Rust の関数は制限しない
+    let out = OutputBuffer::new();
+    interpreter
+        .set_capabilities(Capabilities {
+            stdout: true,
+            ..Capabilities::none()
+        })
+        .set_stdout(out.clone())
+        .register_fn("host_time", || 42);
+    interpreter
+        .eval_str("println!(\"{}\", host_time())")
+        .unwrap();
+    assert_eq!(out.contents(), "42\n");
+}
+
+// 信頼したインタプリタの Rust の関数の中で評価するプラグインには、プラグインの許可がかかる。
+// プラグインから戻ると、外側のインタプリタの許可に戻る
+#[test]
+fn nested_interpreters_keep_their_own_capabilities() {
+    let dir = sandbox("nested");
+    let secret = dir.join("secret/key.txt");
+    let read_secret = format!(
+        "std::fs::read_to_string({:?}).unwrap()",
+        secret.to_str().unwrap()
+    );
+    let plugin_dir = dir.clone();
+    let plugin_code = read_secret.clone();
+    let mut host = Interpreter::new();
+    host.register_fn("run_plugin", move || {
+        match confined(&plugin_dir).eval_str(&plugin_code) {
+            Ok(v) => format!("{}", v),
+            Err(e) => format!("{:?}", e.kind),
+        }
+    });
+    let code = format!("run_plugin() + \" \" + {}", read_secret);
+    let v = host.eval_str(&code).unwrap();
+    assert!(
+        matches!(&v, Value::Str(s) if &**s == "Permission secret"),
+        "{:?}",
+        v
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,182 @@
+// 評価の前の型検査が誤りを位置つきで報告することを確かめる
+use nanai_simple_lang::check::{TypeError, check, infer};
+use nanai_simple_lang::eval::Interpreter;
+use nanai_simple_lang::lexer::{Span, tokenize};
+use nanai_simple_lang::parser::parse;
+
+fn errors(code: &str) -> Vec<TypeError> {
+    check(&parse(&tokenize(code)))
+}
+
+fn span(line: usize, col: usize) -> Span {
+    Span { line, col }
+}
+
+// 誤りの位置とメッセージの組
+fn reported(code: &str) -> Vec<(Span, String)> {
+    errors(code)
+        .into_iter()
+        .map(|e| (e.span, e.message))
+        .collect()
+}
+
+// 注釈がなくても、推論した型で誤りを見つける
+#[test]
+fn inferred_types_are_checked() {
+    assert_eq!(
+        reported("let x = 1; let y = x + \"a\";"),
+        [(
+            span(1, 22),
+            "演算子 + は i64 と String に使えません".to_string()
+        )]
+    );
+    assert_eq!(
+        reported("fn id(x) { x }\nlet n = id(1);\nlet s: String = n;"),
+        [(
+            span(3, 1),
+            "型が一致しません: String が必要ですが i64 です".to_string()
+        )]
+    );
+}
+
+// 型注釈と値・引数・戻り値の型の食い違い
+#[test]
+fn annotation_mismatches() {
+    assert_eq!(
+        reported("let x: i64 = \"s\";"),
+        [(
+            span(1, 1),
+            "型が一致しません: i64 が必要ですが String です".to_string()
+        )]
+    );
+    assert_eq!(
+        reported("fn add(a: i64, b: i64) -> i64 { a + b }\nadd(1, true)"),
+        [(
+            span(2, 1),
+            "add の 2 番目の引数の型が一致しません: i64 が必要ですが bool です".to_string()
+        )]
+    );
+    assert_eq!(
+        reported("fn f() -> bool { 1 }"),
+        [(
+            span(1, 4),
+            "関数 f の戻り値の型が一致しません: bool が必要ですが i64 です".to_string()
+        )]
+    );
+}
+
+// 型引数の境界を満たさない型で呼び出すと誤りになる
+#[test]
+fn trait_bounds_are_checked() {
+    let code = "fn max<T: PartialOrd>(a: T, b: T) -> T { if a > b { a } else { b } }\n\
+                struct P { x: i64 }\n\
+                max(P { x: 1 }, P { x: 2 })";
+    assert_eq!(
+        reported(code),
+        [(span(3, 1), "P は PartialOrd を実装していません".to_string())]
+    );
+    assert_eq!(
+        reported(&code.replace(
+            "struct P { x: i64 }",
+            "#[derive(PartialEq, PartialOrd)]\nstruct P { x: i64 }"
+        )),
+        []
+    );
+}
+
+// 移動した値を使うと、使った位置と移動した位置の両方を報告する
+#[test]
+fn use_after_move_reports_both_spans() {
+    let e = errors("struct P { x: i64 }\nlet p = P { x: 1 };\nlet q = p;\np.x");
+    assert_eq!(e.len(), 1, "{:?}", e);
+    assert_eq!(e[0].span, span(4, 1));
+    assert_eq!(e[0].message, "移動した値 p を使っています");
+    assert_eq!(
+        e[0].note,
+        Some((span(3, 9), "p はここで移動しました".to_string()))
+    );
+    assert_eq!(
+        reported("struct P { x: i64 }\nlet p = P { x: 1 };\nlet q = p.clone();\np.x"),
+        []
+    );
+}
+
+// 変数を束縛する位置でも、使う位置でも推論した型を問い合わせられる
+#[test]
+fn type_of_binders_and_uses() {
+    let code = "let x = 1;\nlet (a, b) = (x, \"s\");\nfn f(n: u8) -> u8 { n }\nlet y = f(2);";
+    let typed = infer(&parse(&tokenize(code)));
+    assert_eq!(typed.errors, []);
+    let type_of = |line, col| typed.type_of(span(line, col)).map(|t| t.to_string());
+    assert_eq!(type_of(1, 5).as_deref(), Some("i64"));
+    assert_eq!(type_of(2, 6).as_deref(), Some("i64"));
+    assert_eq!(type_of(2, 9).as_deref(), Some("String"));
+    assert_eq!(type_of(2, 15).as_deref(), Some("i64"));
+    assert_eq!(type_of(3, 6).as_deref(), Some("u8"));
+    assert_eq!(type_of(3, 21).as_deref(), Some("u8"));
+    assert_eq!(type_of(4, 5).as_deref(), Some("u8"));
+    assert_eq!(type_of(4, 4), None);
+}
+
+// クロージャは作られた時点の値をコピーして持つので、外の変数を書き換えるクロージャは型エラーになる
+#[test]
+fn closures_cannot_mutate_captured_variables() {
+    for (code, col, name) in [
+        (
+            "let mut n = 0; let mut inc = || { n += 1; }; inc(); inc(); print(n);",
+            35,
+            "n",
+        ),
+        (
+            "let mut v = vec![]; let f = || v.push(1); f(); v.len()",
+            32,
+            "v",
+        ),
+        (
+            "let mut m = HashMap::new(); let f = || { *m.entry(1).or_insert(0) += 1; }; f();",
+            43,
+            "m",
+        ),
+        (
+            "let mut n = 0; let f = |x: i64| { n = x; }; f(2); n",
+            35,
+            "n",
+        ),
+    ] {
+        let e = errors(code);
+        assert_eq!(e.len(), 1, "{}: {:?}", code, e);
+        assert_eq!(e[0].span, span(1, col), "{}", code);
+        assert_eq!(
+            e[0].message,
+            format!(
+                "クロージャの外の変数 {} は書き換えられません（クロージャは作られた時点の値をコピーして持ちます）",
+                name
+            )
+        );
+    }
+    // クロージャの中の変数は書き換えられる。値を返して外で代入し直せば、呼ぶたびに増える
+    let code =
+        "let mut n = 0; let inc = |n: i64| { let mut m = n; m += 1; m }; n = inc(n); n = inc(n); n";
+    assert_eq!(errors(code), []);
+    let v = Interpreter::new().eval_str(code).unwrap();
+    assert_eq!(v.as_i64(), Some(2));
+}
+
+// 一致しない可能性のあるパターンなどの解析エラーは、評価の前に位置つきで報告する
+#[test]
+fn parse_errors_are_reported() {
+    let e = errors("let o = Some(1);\nlet Some(x) = o;\nprint(5);");
+    assert_eq!(e.len(), 1, "{:?}", e);
+    assert_eq!(e[0].span, span(2, 5));
+    assert_eq!(
+        e[0].message,
+        "let には一致しない可能性のあるパターンは使えません: Some(x)"
+    );
+    let e = errors("fn f() {\n    for Some(x) in [Some(1)] { print(x); }\n}\nf();");
+    assert_eq!(e.len(), 1, "{:?}", e);
+    assert_eq!(e[0].span, span(2, 5));
+    assert_eq!(
+        e[0].message,
+        "for には一致しない可能性のあるパターンは使えません: Some(x)"
+    );
+}
@@ -0,0 +1,41 @@
+// 複数のテストで使う手助け。同じコードを木をたどる評価器と VM の両方で評価する
+#![allow(dead_code)]
+
+use nanai_simple_lang::ast::Stmt;
+use nanai_simple_lang::eval::{Interpreter, RuntimeError};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
+use nanai_simple_lang::value::Value;
+
+// 評価器の種類。false が木をたどる評価器、true が VM
+pub const ENGINES: [bool; 2] = [false, true];
+
+// interpreter で stmts を評価する（vm なら VM で）
+pub fn eval_on(
+    interpreter: &mut Interpreter,
+    stmts: &[Stmt],
+    vm: bool,
+) -> Result<Value, RuntimeError> {
+    if vm {
+        interpreter.eval_vm(stmts)
+    } else {
+        interpreter.eval(stmts)
+    }
+}
+
+// 評価器ごとに新しいインタプリタを作って f を呼び、結果を評価器の順に集める
+pub fn each_engine<T>(mut f: impl FnMut(&mut Interpreter, bool) -> T) -> Vec<T> {
+    ENGINES
+        .into_iter()
+        .map(|vm| f(&mut Interpreter::new(), vm))
+        .collect()
+}
+
+// 評価器ごとに、setup で準備した新しいインタプリタで code を評価する
+pub fn eval_both(setup: impl Fn(&mut Interpreter), code: &str) -> Vec<Result<Value, RuntimeError>> {
+    let stmts = parse(&tokenize(code));
+    each_engine(|interpreter, vm| {
+        setup(interpreter);
+        eval_on(interpreter, &stmts, vm)
+    })
+}
@@ -0,0 +1,123 @@
+// Rust の値をホストの値としてスクリプトに渡し、登録したメソッド・プロパティで操作できることを確かめる
+mod common;
+
+use common::{each_engine, eval_on};
+use nanai_simple_lang::eval::{ErrorKind, Interpreter};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
+use nanai_simple_lang::value::{Host, Value};
+use std::cell::Cell;
+use std::rc::Rc;
+
+struct Account {
+    owner: String,
+    balance: i64,
+    // 解放されたら真にする
+    dropped: Rc<Cell<bool>>,
+}
+
+impl Drop for Account {
+    fn drop(&mut self) {
+        self.dropped.set(true);
+    }
+}
+
+fn account(owner: &str, balance: i64, dropped: &Rc<Cell<bool>>) -> Host<Account> {
+    Host::new(
+        "Account",
+        Account {
+            owner: owner.to_string(),
+            balance,
+            dropped: dropped.clone(),
+        },
+    )
+}
+
+fn register(interpreter: &mut Interpreter) {
+    interpreter
+        .register_method("Account", "deposit", |a: Host<Account>, n: i64| {
+            a.borrow_mut().balance += n;
+        })
+        .register_getter("Account", "balance", |a: Host<Account>| a.borrow().balance)
+        .register_getter("Account", "owner", |a: Host<Account>| {
+            a.borrow().owner.clone()
+        })
+        .register_setter("Account", "owner", |a: Host<Account>, s: String| {
+            a.borrow_mut().owner = s;
+        });
+}
+
+// スクリプトでの変更は同じ Rust の値に入り、取り出して確かめられる
+#[test]
+fn methods_and_properties_share_the_rust_value() {
+    each_engine(|interpreter, vm| {
+        let dropped = Rc::new(Cell::new(false));
+        let acct = account("alice", 100, &dropped);
+        register(interpreter);
+        interpreter.set_global("acct", acct.clone());
+        let stmts = parse(&tokenize(
+            r#"acct.deposit(50); acct.owner = "bob"; acct.owner += "!"; acct.balance"#,
+        ));
+        let v = eval_on(interpreter, &stmts, vm).unwrap();
+        assert_eq!(v.as_i64(), Some(150));
+        assert_eq!(acct.borrow().balance, 150);
+        assert_eq!(acct.borrow().owner, "bob!");
+        // 変数の値から元の型に戻せる。型が違えば None
+        let back = interpreter.get_global("acct").unwrap();
+        let back = back.downcast::<Account>().unwrap();
+        assert_eq!(back.name(), "Account");
+        assert_eq!(back.borrow().balance, 150);
+        assert!(
+            interpreter
+                .get_global("acct")
+                .unwrap()
+                .downcast::<String>()
+                .is_none()
+        );
+    });
+}
+
+// スクリプトの値が参照している間は解放されず、最後の参照がなくなると解放される
+#[test]
+fn host_values_are_reference_counted() {
+    let dropped = Rc::new(Cell::new(false));
+    let mut interpreter = Interpreter::new();
+    register(&mut interpreter);
+    interpreter.set_global("acct", account("carol", 1, &dropped));
+    interpreter
+        .eval_str(
+            "let copies = vec![acct, acct]; let keep = |n| { acct.deposit(n); acct.balance };",
+        )
+        .unwrap();
+    assert!(!dropped.get());
+    let keep = interpreter.get_global("keep").unwrap();
+    let v = interpreter.closure::<(i64,), i64>(keep.clone()).unwrap()((9,)).unwrap();
+    assert_eq!(v, 10);
+    drop(interpreter);
+    // クロージャが取り込んだ値はまだ生きている
+    assert!(!dropped.get());
+    drop(keep);
+    assert!(dropped.get());
+}
+
+#[test]
+fn unknown_members_are_errors() {
+    let dropped = Rc::new(Cell::new(false));
+    let mut interpreter = Interpreter::new();
+    register(&mut interpreter);
+    interpreter.set_global("acct", account("dave", 0, &dropped));
+    let e = interpreter.eval_str("acct.withdraw(1)").unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Undefined, "{:?}", e);
+    let e = interpreter.eval_str("acct.id").unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Undefined, "{:?}", e);
+    // ホストの値を受け取るメソッドに別の値を渡すと型のエラーになる
+    interpreter.register_fn("balance_of", |a: Host<Account>| a.borrow().balance);
+    let e = interpreter.eval_str("balance_of(5)").unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Type);
+    // Value::host で作った値も型名で区別する
+    let other = Value::host("Account", 0_i64);
+    assert_eq!(other.type_name(), "Account");
+    interpreter.set_global("other", other);
+    let e = interpreter.eval_str("balance_of(other)").unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Type);
+}
@@ -0,0 +1,127 @@
+// 整数リテラルの型・範囲、あふれの検出と報告する位置を確かめる
+mod common;
+
+use common::eval_both;
+use nanai_simple_lang::check::check;
+use nanai_simple_lang::eval::{ErrorKind, RuntimeError};
+use nanai_simple_lang::lexer::{Span, tokenize};
+use nanai_simple_lang::parser::parse;
+
+// 型検査を通ることを確かめてから、木をたどる評価器と VM の両方で評価する
+fn eval_checked(code: &str) -> Vec<Result<String, RuntimeError>> {
+    assert_eq!(check(&parse(&tokenize(code))), [], "{}", code);
+    eval_both(|_| {}, code)
+        .into_iter()
+        .map(|result| result.map(|v| format!("{:?}", v)))
+        .collect()
+}
+
+// i64 に収まらない接尾辞なしのリテラルも、型注釈や使われ方で型が決まる
+#[test]
+fn unsuffixed_literals_take_the_inferred_type() {
+    let cases = [
+        (
+            "let x: i128 = 9223372036854775808; x + 1i128",
+            "9223372036854775809",
+        ),
+        (
+            "let x: i64 = -9223372036854775808; x",
+            "-9223372036854775808",
+        ),
+        (
+            "let x: i128 = 1; x + 9223372036854775808",
+            "9223372036854775809",
+        ),
+        (
+            "fn f(x: i128) -> i128 { x } f(-170141183460469231731687303715884105728)",
+            "-170141183460469231731687303715884105728",
+        ),
+        // 決まらなければ u64 / u128
+        ("18446744073709551615 / 5u64", "3689348814741910323"),
+        (
+            "340282366920938463463374607431768211455 / 5u128",
+            "68056473384187692692674921486353642291",
+        ),
+    ];
+    for (code, expected) in cases {
+        for result in eval_checked(code) {
+            assert_eq!(result.unwrap(), expected, "{}", code);
+        }
+    }
+    // 範囲に収まらなければ、リテラルの位置で報告する
+    let e = check(&parse(&tokenize(
+        "let x: i64 = 1;\nlet y: i64 = 9223372036854775808;",
+    )));
+    assert_eq!(e.len(), 1, "{:?}", e);
+    assert_eq!(e[0].span, Span { line: 2, col: 14 });
+    assert_eq!(
+        e[0].message,
+        "リテラル 9223372036854775808 は i64 の範囲外です"
+    );
+}
+
+// 接尾辞付きのリテラルの範囲の誤りも、リテラル（負の数なら -）の位置で報告する
+#[test]
+fn typed_literal_errors_have_spans() {
+    let e = check(&parse(&tokenize(
+        "let a = 1;\nlet b = (300u8, 1);\nlet c = [1, -129i8];\nlet d = 1 + -5u8;",
+    )));
+    let errors: Vec<(Span, &str)> = e.iter().map(|e| (e.span, e.message.as_str())).collect();
+    assert_eq!(
+        errors,
+        [
+            (Span { line: 2, col: 10 }, "リテラル 300 は u8 の範囲外です"),
+            (
+                Span { line: 3, col: 13 },
+                "リテラル -129 は i8 の範囲外です"
+            ),
+            (Span { line: 4, col: 13 }, "単項演算子 - は u8 に使えません"),
+        ]
+    );
+}
+
+// MIN % -1 は Rust と同じくあふれる。checked_rem は None
+#[test]
+fn min_rem_minus_one_overflows() {
+    for (code, message) in [
+        ("(-128i8) % -1", "i8 の演算 -128 % -1 があふれました"),
+        (
+            "let m = i64::MIN; m % -1",
+            "i64 の演算 -9223372036854775808 % -1 があふれました",
+        ),
+    ] {
+        for result in eval_checked(code) {
+            let e = result.unwrap_err();
+            assert_eq!(e.kind, ErrorKind::Overflow);
+            assert_eq!(e.message, message);
+        }
+    }
+    let code = "((-128i8).checked_rem(-1), i64::MIN.checked_rem(-1), (-128i8).wrapping_rem(-1))";
+    for result in eval_checked(code) {
+        assert_eq!(result.unwrap(), "(None, None, 0)");
+    }
+}
+
+// 符号反転のあふれは - の位置で、内部の計算ではなく元の値で報告する
+#[test]
+fn negation_overflow() {
+    for result in eval_checked("let x = -128i8;\nlet y = 1 + -x;") {
+        let e = result.unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Overflow);
+        assert_eq!(e.message, "i8 の値 -128 の符号反転があふれました");
+        assert_eq!(e.span, Some(Span { line: 2, col: 13 }));
+    }
+}
+
+// i64::MAX などの定数
+#[test]
+fn integer_constants() {
+    let code = "(i64::MAX, u8::MIN, i8::MIN, u128::MAX, u32::BITS, i16::MAX)";
+    let expected =
+        "(9223372036854775807, 0, -128, 340282366920938463463374607431768211455, 32, 32767)";
+    for result in eval_checked(code) {
+        assert_eq!(result.unwrap(), expected);
+    }
+    let e = check(&parse(&tokenize("let x: u8 = i64::MAX;")));
+    assert_eq!(e.len(), 1, "{:?}", e);
+}
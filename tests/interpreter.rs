@@ -0,0 +1,104 @@
+// Interpreter が評価のあいだ定義・変数・読み込んだファイルを持ち続けることを確かめる
+use nanai_simple_lang::eval::{ErrorKind, Interpreter, OutputBuffer};
+use nanai_simple_lang::value::Value;
+use std::fs;
+use std::path::PathBuf;
+
+// テストごとに別の一時ディレクトリを作る
+fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("nasl-interpreter-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn int(v: Value) -> i64 {
+    v.as_i64()
+        .unwrap_or_else(|| panic!("整数ではありません: {:?}", v))
+}
+
+// 一度読み込んだスクリプトの関数を何度も呼び出せる
+#[test]
+fn load_once_and_call_many_times() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str("fn square(x: i64) -> i64 { x * x } let base = 10;")
+        .unwrap();
+    for x in 0..5 {
+        let v = interpreter
+            .call_function("square", vec![Value::Int(x)])
+            .unwrap();
+        assert_eq!(int(v), x * x);
+    }
+    assert_eq!(int(interpreter.get_global("base").unwrap()), 10);
+    // 後の評価からも前の定義と変数が見える
+    let v = interpreter.eval_str("square(base) + 1").unwrap();
+    assert_eq!(int(v), 101);
+    assert_eq!(interpreter.get_global("missing"), None);
+}
+
+#[test]
+fn calling_an_unknown_function_is_an_error() {
+    let interpreter = Interpreter::new();
+    let e = interpreter.call_function("nope", vec![]).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Undefined);
+    assert_eq!(e.message, "未定義の関数: nope");
+}
+
+// トップレベルの変数に入れたクロージャと組み込みの関数も call_function で呼べる
+#[test]
+fn call_closures_and_builtins() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str("let k = 3; let add_k = |x| x + k;")
+        .unwrap();
+    let v = interpreter
+        .call_function("add_k", vec![Value::Int(4)])
+        .unwrap();
+    assert_eq!(int(v), 7);
+    let v = interpreter
+        .call_function("Some", vec![Value::Int(2)])
+        .unwrap();
+    assert!(
+        matches!(&v, Value::Option(Some(x)) if x.as_i64() == Some(2)),
+        "{:?}",
+        v
+    );
+}
+
+// use で読み込んだファイルの定義は同じインタプリタに入り、同じファイルは二度評価しない
+#[test]
+fn imports_are_loaded_once() {
+    let dir = temp_dir("import");
+    let lib = dir.join("counter.nasl");
+    fs::write(
+        &lib,
+        "fn twice(x: i64) -> i64 { x * 2 } println!(\"読み込み\");",
+    )
+    .unwrap();
+    let main = dir.join("main.nasl");
+    fs::write(
+        &main,
+        format!("use \"{}\";\nlet answer = twice(21);", lib.display()),
+    )
+    .unwrap();
+
+    let mut interpreter = Interpreter::new();
+    let out = OutputBuffer::new();
+    interpreter.set_stdout(out.clone());
+    interpreter.eval_file(main.to_str().unwrap()).unwrap();
+    assert_eq!(int(interpreter.get_global("answer").unwrap()), 42);
+    interpreter
+        .eval_str(&format!("use \"{}\";\ntwice(5)", lib.display()))
+        .unwrap();
+    assert_eq!(out.contents(), "読み込み\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let e = Interpreter::new()
+        .eval_file("/nonexistent/nasl/script.nasl")
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Io);
+}
@@ -0,0 +1,155 @@
+// Limits による実行の制限（ステップ数・実行時間・メモリ使用量・配列の長さ）を確かめる
+mod common;
+
+use common::eval_both;
+use nanai_simple_lang::eval::{CountingAlloc, ErrorKind, Interpreter, Limits};
+use nanai_simple_lang::value::Value;
+use std::time::{Duration, Instant};
+
+// max_heap でメモリ使用量を数えるため
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+// 制限をかけて木をたどる評価器と VM の両方で実行し、エラーのメッセージを返す
+fn exceeded(limits: Limits, code: &str) -> Vec<String> {
+    eval_both(
+        |interpreter| {
+            interpreter.set_limits(limits);
+        },
+        code,
+    )
+    .into_iter()
+    .map(|result| {
+        let e = result.expect_err("制限を超えていません");
+        assert_eq!(e.kind, ErrorKind::LimitExceeded, "{:?}", e);
+        e.message
+    })
+    .collect()
+}
+
+#[test]
+fn max_steps() {
+    let limits = Limits {
+        max_steps: Some(1000),
+        ..Limits::default()
+    };
+    for message in exceeded(limits, "let mut n = 0; for i in 0..100000 { n += i; }") {
+        assert_eq!(message, "実行ステップ数が上限 1000 を超えました");
+    }
+    // 配列を作るときは要素の数だけ数える
+    for code in ["[0; 100000]", "(0..100000).step_by(2).collect()"] {
+        for message in exceeded(limits, code) {
+            assert_eq!(message, "実行ステップ数が上限 1000 を超えました");
+        }
+    }
+}
+
+#[test]
+fn timeout() {
+    let limits = Limits {
+        timeout: Some(Duration::from_millis(100)),
+        ..Limits::default()
+    };
+    let start = Instant::now();
+    for message in exceeded(limits, "for i in 0..1000000000000 {}") {
+        assert_eq!(message, "実行時間が上限 100ms を超えました");
+    }
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn max_heap() {
+    let limits = Limits {
+        max_heap: Some(1 << 20),
+        ..Limits::default()
+    };
+    let code = "let mut v = []; for i in 0..1000000000 { v.push([0; 1000]); }";
+    for message in exceeded(limits, code) {
+        assert_eq!(message, "メモリ使用量が上限 1048576 バイトを超えました");
+    }
+    // 一度に確保する大きさは確保する前に確かめる
+    for message in exceeded(limits, "[0; 1000000]") {
+        assert_eq!(message, "メモリ使用量が上限 1048576 バイトを超えました");
+    }
+}
+
+// 制限をかけていなくても、プロセスを落とすほど長い配列は作らない
+#[test]
+fn array_length_without_limits() {
+    for code in ["[0; 4000000000]", "(0..4000000000).collect()"] {
+        for message in exceeded(Limits::default(), code) {
+            assert_eq!(
+                message,
+                "配列の要素数 4000000000 が上限 16777216 を超えました"
+            );
+        }
+    }
+}
+
+// 制限内で終わる評価には影響しない
+#[test]
+fn within_limits() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_limits(Limits {
+        max_steps: Some(100000),
+        max_heap: Some(64 << 20),
+        timeout: Some(Duration::from_secs(60)),
+    });
+    let value = interpreter
+        .eval_str("(0..100).collect().len()")
+        .expect("制限内で終わるはずです");
+    assert!(matches!(value, Value::Int(100)), "{:?}", value);
+}
+
+// Rust の関数の中で評価する別のインタプリタは、そのインタプリタの制限で数える。
+// 戻ると外側の制限の計測を続ける
+#[test]
+fn nested_interpreters_keep_their_own_limits() {
+    let mut host = Interpreter::new();
+    host.set_limits(Limits {
+        max_steps: Some(1000),
+        ..Limits::default()
+    });
+    host.register_fn("run_plugin", |steps: i64| {
+        let mut plugin = Interpreter::new();
+        plugin.set_limits(Limits {
+            max_steps: Some(100000),
+            ..Limits::default()
+        });
+        let code = format!("let mut n = 0; for i in 0..{} {{ n += 1; }} n", steps);
+        match plugin.eval_str(&code) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.message,
+        }
+    });
+    // 外側の上限より多く、プラグインの上限より少ない
+    let v = host.eval_str("run_plugin(5000)").unwrap();
+    assert!(matches!(&v, Value::Str(s) if &**s == "ok"), "{:?}", v);
+    let v = host.eval_str("run_plugin(1000000)").unwrap();
+    assert!(
+        matches!(&v, Value::Str(s) if &**s == "実行ステップ数が上限 100000 を超えました"),
+        "{:?}",
+        v
+    );
+    let e = host
+        .eval_str("run_plugin(1); for i in 0..1000 {}")
+        .unwrap_err();
+    assert_eq!(e.message, "実行ステップ数が上限 1000 を超えました");
+}
+
+// 文字列の連結・書式の幅のように一度に大きく確保するものは、確保する前に確かめる
+#[test]
+fn max_heap_before_growing() {
+    let limits = Limits {
+        max_heap: Some(1 << 20),
+        ..Limits::default()
+    };
+    for code in [
+        "let mut s = \"ab\"; for i in 0..40 { s = s + s; }",
+        "let mut s = \"ab\"; for i in 0..40 { s = format!(\"{:>65535}{}\", s, s); }",
+    ] {
+        for message in exceeded(limits, code) {
+            assert_eq!(message, "メモリ使用量が上限 1048576 バイトを超えました");
+        }
+    }
+}
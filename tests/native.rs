@@ -0,0 +1,124 @@
+// register_fn などで登録した Rust の関数・メソッド・モジュールを nasl から呼べることを確かめる
+mod common;
+
+use common::eval_both;
+use nanai_simple_lang::eval::{ErrorKind, Interpreter, NativeModule, RuntimeError};
+use nanai_simple_lang::value::Value;
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn assert_int(setup: impl Fn(&mut Interpreter), code: &str, expected: i64) {
+    for result in eval_both(setup, code) {
+        let v = result.unwrap_or_else(|e| panic!("{}: {:?}", code, e));
+        assert_eq!(v.as_i64(), Some(expected), "{}: {:?}", code, v);
+    }
+}
+
+#[test]
+fn closures_with_converted_arguments() {
+    let clamp = |i: &mut Interpreter| {
+        i.register_fn("clamp", |x: i64, lo: i64, hi: i64| x.max(lo).min(hi));
+    };
+    assert_int(clamp, "clamp(15, 0, 10) + clamp(-3, 0, 10)", 10);
+    // 文字列・配列・Option も変換する
+    let join = |i: &mut Interpreter| {
+        i.register_fn("join", |parts: Vec<String>, sep: Option<String>| {
+            parts.join(&sep.unwrap_or_default())
+        });
+    };
+    assert_int(join, r#"join(vec!["a", "bc"], Some("-")).len()"#, 4);
+}
+
+// 登録したクロージャは呼び出しをまたいで状態を持てる
+#[test]
+fn closures_can_capture_state() {
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("tick", move || {
+        counter.set(counter.get() + 1);
+        counter.get()
+    });
+    interpreter.eval_str("tick(); tick();").unwrap();
+    let v = interpreter.eval_str("tick()").unwrap();
+    assert_eq!(v.as_i64(), Some(3));
+    assert_eq!(calls.get(), 3);
+}
+
+#[test]
+fn wrong_arguments_are_typed_errors() {
+    let clamp = |i: &mut Interpreter| {
+        i.register_fn("clamp", |x: i64, lo: i64, hi: i64| x.max(lo).min(hi));
+    };
+    for result in eval_both(clamp, "clamp(1, 2)") {
+        let e = result.unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Type);
+        assert_eq!(
+            e.message,
+            "clamp の引数の数が一致しません: 3 個必要ですが 2 個渡されました"
+        );
+    }
+    for result in eval_both(clamp, r#"clamp(1, "0", 10)"#) {
+        let e = result.unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Type);
+        assert_eq!(
+            e.message,
+            "clamp の 2 番目の引数には i64 が必要ですが String が渡されました: \"0\""
+        );
+    }
+}
+
+// register_raw の関数は nasl の値をそのまま受け取り、実行時エラーを返せる
+#[test]
+fn raw_functions_return_errors() {
+    let checked = |i: &mut Interpreter| {
+        i.register_raw("first", |args| match args.first() {
+            Some(v) => Ok(v.clone()),
+            None => Err(RuntimeError::new(ErrorKind::Type, "引数がありません")),
+        });
+    };
+    assert_int(checked, "first(7, true)", 7);
+    for result in eval_both(checked, "first()") {
+        assert_eq!(result.unwrap_err().message, "引数がありません");
+    }
+}
+
+// 組み込みの型と nasl で定義した型にメソッドを足せる
+#[test]
+fn methods_on_nasl_types() {
+    let methods = |i: &mut Interpreter| {
+        i.register_method("String", "shout", |s: String| {
+            format!("{}!", s.to_uppercase())
+        });
+        i.register_method("Point", "manhattan", |p: Value| match p {
+            Value::Struct { fields, .. } => fields
+                .iter()
+                .filter_map(|(_, v)| v.as_i64())
+                .map(i64::abs)
+                .sum::<i64>(),
+            _ => 0,
+        });
+    };
+    assert_int(methods, r#""hey".shout().len()"#, 4);
+    assert_int(
+        methods,
+        "struct Point { x: i64, y: i64 } let p = Point { x: -3, y: 4 }; p.manhattan()",
+        7,
+    );
+}
+
+#[test]
+fn modules_are_called_by_path() {
+    let module = |i: &mut Interpreter| {
+        let mut geometry = NativeModule::new("geometry");
+        geometry
+            .register_fn("area", |w: i64, h: i64| w * h)
+            .register_fn("perimeter", |w: i64, h: i64| 2 * (w + h));
+        i.register_module(geometry);
+    };
+    assert_int(
+        module,
+        "geometry::area(3, 4) + geometry::perimeter(3, 4)",
+        26,
+    );
+}
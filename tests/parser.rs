@@ -0,0 +1,85 @@
+// 構文解析が途中で切れた入力でもパニックせず、エラーの文を返すことを確かめる
+mod common;
+
+use common::eval_both;
+use nanai_simple_lang::ast::Stmt;
+use nanai_simple_lang::eval::{ErrorKind, Interpreter};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
+
+fn errors(code: &str) -> Vec<String> {
+    parse(&tokenize(code))
+        .into_iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Error(msg, _) => Some(msg),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn unclosed_attribute_is_an_error() {
+    assert_eq!(errors("#[derive("), ["属性 derive の ( が閉じていません"]);
+    assert_eq!(
+        errors("#[derive(Debug"),
+        ["属性 derive の ( が閉じていません"]
+    );
+    assert_eq!(errors("#[derive(Debug)"), ["属性 derive の ] が必要です"]);
+    assert_eq!(errors("#["), ["属性名が必要です"]);
+    assert_eq!(
+        errors("impl Foo { #[inline("),
+        ["属性 inline の ( が閉じていません"]
+    );
+}
+
+#[test]
+fn closed_attribute_is_accepted() {
+    assert!(errors("#[derive(Debug, Clone)] struct P { x: i64 }").is_empty());
+}
+
+// format! の幅・精度は Rust と同じく u16 に収まる数だけ。大きすぎる数は書式の誤りになる
+#[test]
+fn format_width_and_precision_fit_in_u16() {
+    for spec in ["{:65536}", "{:.65536}", "{:99999999999999999999}"] {
+        let code = format!("format!(\"{}\", 1.5)", spec);
+        let e = Interpreter::new().eval_str(&code).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Type, "{}", spec);
+        assert_eq!(e.message, format!("書式指定 {} には対応していません", spec));
+    }
+    let v = Interpreter::new()
+        .eval_str("format!(\"{:.65535}\", 1.5).len()")
+        .unwrap();
+    assert_eq!(v.to_string(), "65537");
+}
+
+// if・for・match のブロックや { .. } の文は } で終わる。次の行の -x や *n は続きの演算子にならない
+#[test]
+fn block_like_statements_end_at_the_brace() {
+    let cases = [
+        ("let b = true; let mut x = 1; if b { x = 2; }\n-x", "-2"),
+        (
+            "let b = false; let mut x = 1; if b { x = 2; } else { x = 3; }\n-x",
+            "-3",
+        ),
+        (
+            "let o = Some(4); if let Some(y) = o { print(y); }\n-o.unwrap()",
+            "-4",
+        ),
+        (
+            "fn inc(n: &mut i64) { for x in [1, 2] { print(x); }\n*n += 1; } let mut m = 0; inc(&mut m); m",
+            "1",
+        ),
+        ("let x = 1; match x { _ => { print(x); } }\n-x", "-1"),
+        ("let x = 5; { print(x); }\n-x", "-5"),
+        (
+            "let x = 5; let y = match x { 1 => { 10 } _ => { 20 } }; y",
+            "20",
+        ),
+    ];
+    for (code, expected) in cases {
+        for result in eval_both(|_| {}, code) {
+            let v = result.unwrap_or_else(|e| panic!("{}: {:?}", code, e));
+            assert_eq!(v.to_string(), expected, "{}", code);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+// 名前の解決の結果（番地・関数の番号）で変数と関数を読み書きすることを、埋め込み用の API から確かめる
+mod common;
+
+use common::{ENGINES, each_engine, eval_on};
+use nanai_simple_lang::eval::{ErrorKind, Interpreter};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
+use nanai_simple_lang::value::Value;
+
+// 同じインタプリタで codes を順に評価し、最後の値を返す（木をたどる評価器と VM の両方で）
+fn eval_all(codes: &[&str]) -> Vec<Value> {
+    each_engine(|interpreter, vm| {
+        let mut last = Value::Unit;
+        for code in codes {
+            let stmts = parse(&tokenize(code));
+            last = eval_on(interpreter, &stmts, vm).unwrap_or_else(|e| panic!("{}: {:?}", code, e));
+        }
+        last
+    })
+}
+
+fn assert_int(codes: &[&str], expected: i64) {
+    for v in eval_all(codes) {
+        assert!(matches!(v, Value::Int(n) if n == expected), "{:?}", v);
+    }
+}
+
+#[test]
+fn shadowing_and_blocks() {
+    assert_int(
+        &["let x = 1; let x = x + 1; let y = { let x = 10; x * 2 }; x + y"],
+        22,
+    );
+    assert_int(
+        &["let mut n = 0; for (i, x) in [5, 6].iter().enumerate() { let d = i * x; n += d; } n"],
+        6,
+    );
+    assert_int(&["match (3, 4) { (1, a) => a, (b, c) => b * c }"], 12);
+}
+
+// クロージャは外側の関数の変数を、入れ子の深さをまたいで取り込む
+#[test]
+fn nested_closures() {
+    assert_int(
+        &["fn make(n) { let k = 10; |x| { let m = |y| y + n + k; m(x) } } let f = make(2); f(5)"],
+        17,
+    );
+    // 関数に渡したクロージャも、作った時点の変数を持つ
+    assert_int(
+        &["fn apply(f, x) { f(x) } let q = 4; apply(|s| s * q, 3)"],
+        12,
+    );
+}
+
+// 関数は定義より前から呼べ、&mut で渡した引数は書き戻される
+#[test]
+fn functions_and_mut_args() {
+    assert_int(
+        &["let mut c = 0; inc(&mut c); inc(&mut c); c fn inc(x: &mut i64) { *x += 1; }"],
+        2,
+    );
+}
+
+// トップレベルの変数・クロージャ・関数は次の評価からも使える
+#[test]
+fn variables_persist_across_evals() {
+    assert_int(
+        &[
+            "let a = 1; let mut b = 2;",
+            "b += a; let a = 100; let f = |x| x + b;",
+            "fn g(x) { x * 2 }",
+            "g(f(a))",
+        ],
+        206,
+    );
+}
+
+// 見つからない名前は評価を始める前にエラーになる
+#[test]
+fn undefined_names_are_reported_before_running() {
+    for vm in ENGINES {
+        let stmts = parse(&tokenize("let count = 1; print(\"x\"); count + cuont"));
+        let mut interpreter = Interpreter::new();
+        let e = if vm {
+            interpreter.compile(&stmts).err()
+        } else {
+            interpreter.eval(&stmts).err()
+        }
+        .expect("未定義の変数が見つかりません");
+        assert_eq!(e.kind, ErrorKind::Undefined);
+        assert_eq!(e.message, "未定義の変数: cuont（もしかして: count）");
+        // エラーになった評価の変数は残らない
+        assert_eq!(interpreter.get_global("count"), None);
+    }
+}
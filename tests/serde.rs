@@ -0,0 +1,119 @@
+// serde との橋渡し（from_nasl・to_nasl・deserialize_global・serialize_global）を確かめる
+#![cfg(feature = "serde")]
+use nanai_simple_lang::eval::{ErrorKind, Interpreter, PathItem, from_nasl, to_nasl};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Server {
+    host: String,
+    port: u16,
+    tls: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+enum Mode {
+    Fast,
+    Retry(u32),
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Config {
+    servers: Vec<Server>,
+    limits: HashMap<String, i64>,
+    mode: Mode,
+}
+
+// nasl のスクリプトを設定ファイルとして読む
+const CONFIG: &str = r#"
+struct Server { host: String, port: i64, tls: Option<bool> }
+enum Mode { Fast, Retry(i64) }
+struct Config { servers: Vec<Server>, limits: HashMap<String, i64>, mode: Mode }
+use std::collections::HashMap;
+let mut limits = HashMap::new();
+limits.insert("cpu".to_string(), 2);
+let config = Config {
+    servers: vec![
+        Server { host: "a.example".to_string(), port: 80, tls: None },
+        Server { host: "b.example".to_string(), port: 8000 + 443, tls: Some(true) },
+    ],
+    limits: limits,
+    mode: Mode::Retry(3),
+};
+"#;
+
+#[test]
+fn read_a_script_as_configuration() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(CONFIG).unwrap();
+    let config: Config = interpreter.deserialize_global("config").unwrap();
+    assert_eq!(config.servers[1].port, 8443);
+    assert_eq!(config.servers[0].tls, None);
+    assert_eq!(config.limits["cpu"], 2);
+    assert_eq!(config.mode, Mode::Retry(3));
+    // from_nasl でも同じ値になる
+    let value = interpreter.get_global("config").unwrap();
+    assert_eq!(from_nasl::<Config>(&value).unwrap(), config);
+}
+
+// 変換できないフィールドは、変数を作った式の中のそのフィールドの位置を付けて報告する
+#[test]
+fn errors_point_at_the_offending_field() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            "struct Server { host: String, port: i64, tls: Option<bool> }\n\
+             let servers = vec![\n\
+                 Server { host: \"a\".to_string(), port: 80, tls: None },\n\
+                 Server { host: \"b\".to_string(), port: 70000, tls: None },\n\
+             ];",
+        )
+        .unwrap();
+    let e = interpreter
+        .deserialize_global::<Vec<Server>>("servers")
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Type);
+    assert!(
+        e.message
+            .starts_with("変数 servers を変換できません: [1].port: "),
+        "{}",
+        e.message
+    );
+    assert_eq!(e.span.map(|s| s.line), Some(4), "{:?}", e.span);
+
+    let value = interpreter.get_global("servers").unwrap();
+    let e = from_nasl::<Vec<Server>>(&value).unwrap_err();
+    assert_eq!(e.path, [PathItem::Index(1), PathItem::Field("port".into())]);
+
+    let e = interpreter
+        .deserialize_global::<i64>("missing")
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Undefined);
+}
+
+// Rust の値をトップレベルの変数にして、スクリプトから使う
+#[test]
+fn inject_rust_data_as_globals() {
+    let config = Config {
+        servers: vec![Server {
+            host: "c.example".into(),
+            port: 22,
+            tls: Some(false),
+        }],
+        limits: HashMap::from([("mem".into(), 512)]),
+        mode: Mode::Fast,
+    };
+    let mut interpreter = Interpreter::new();
+    interpreter.serialize_global("injected", &config).unwrap();
+    let v = interpreter
+        .eval_str("injected.servers[0].port + injected.limits[\"mem\"]")
+        .unwrap();
+    assert_eq!(v.as_i64(), Some(534));
+    // 元に戻すと同じ値になる
+    let back: Config = interpreter.deserialize_global("injected").unwrap();
+    assert_eq!(back, config);
+    assert_eq!(
+        from_nasl::<Config>(&to_nasl(&config).unwrap()).unwrap(),
+        config
+    );
+}
@@ -0,0 +1,63 @@
+// 深い再帰がネイティブのスタックをあふれさせず、実行時エラーになることを確かめる
+mod common;
+
+use common::{each_engine, eval_both, eval_on};
+use nanai_simple_lang::check::check;
+use nanai_simple_lang::eval::{self, ErrorKind, Interpreter};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
+use nanai_simple_lang::value::Value;
+
+// with_stack を使わず、テストのスレッドの小さなスタックのまま評価する
+#[test]
+fn infinite_recursion_is_an_error() {
+    let e = Interpreter::new()
+        .eval_str("fn f(n) { f(n) } f(1)")
+        .expect_err("無限の再帰が終わりました");
+    assert_eq!(e.kind, ErrorKind::StackOverflow, "{:?}", e);
+    assert_eq!(
+        e.message,
+        format!(
+            "関数呼び出しの深さが上限 {} を超えました",
+            eval::DEFAULT_MAX_DEPTH
+        )
+    );
+}
+
+// 上限を上げれば、スレッドのスタックより深く再帰できる
+#[test]
+fn deep_recursion_within_max_depth() {
+    eval::set_max_depth(3000);
+    let code = "fn f(n) { if n == 0 { 0 } else { 1 + f(n - 1) } } f(2500)";
+    for result in eval_both(|_| {}, code) {
+        assert!(matches!(result, Ok(Value::Int(2500))), "{:?}", result);
+    }
+}
+
+// 深く入れ子になった式も、解析・名前の解決・型検査・評価でスタックをあふれさせない
+#[test]
+fn deeply_nested_expressions() {
+    let n = 1000;
+    let cases = [
+        (format!("{}1{}", "(".repeat(n), ")".repeat(n)), "1"),
+        (format!("{}1{}", "(1 + ".repeat(n), ")".repeat(n)), "1001"),
+        (format!("{}1{}", "-(".repeat(n), ")".repeat(n)), "1"),
+        (format!("{}1{}.len()", "[".repeat(n), "]".repeat(n)), "1"),
+        (format!("{}1{}", "{ ".repeat(n), " }".repeat(n)), "1"),
+    ];
+    // 8 MiB のスタックのスレッドで試す
+    std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(move || {
+            for (code, expected) in cases {
+                let stmts = parse(&tokenize(&code));
+                assert_eq!(check(&stmts), [], "{}", expected);
+                for v in each_engine(|interpreter, vm| eval_on(interpreter, &stmts, vm)) {
+                    assert_eq!(v.unwrap().to_string(), expected);
+                }
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
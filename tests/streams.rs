@@ -0,0 +1,111 @@
+// set_stdin・set_stdout・set_stderr で差し替えた入出力をスクリプトが使うことを確かめる
+mod common;
+
+use common::{each_engine, eval_on};
+use nanai_simple_lang::eval::{ErrorKind, Interpreter, OutputBuffer};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
+use std::io::{self, Cursor, Write};
+
+// 入出力を差し替えて、木をたどる評価器と VM の両方で code を評価し、(標準出力, 標準エラー出力) を返す
+fn run_both(input: &str, code: &str) -> Vec<(String, String)> {
+    let stmts = parse(&tokenize(code));
+    each_engine(|interpreter, vm| {
+        let (out, err) = (OutputBuffer::new(), OutputBuffer::new());
+        interpreter
+            .set_stdin(Cursor::new(input.to_string()))
+            .set_stdout(out.clone())
+            .set_stderr(err.clone());
+        eval_on(interpreter, &stmts, vm).unwrap_or_else(|e| panic!("{}: {:?}", code, e));
+        (out.take(), err.take())
+    })
+}
+
+#[test]
+fn input_reads_from_the_given_reader() {
+    let code = r#"
+        let a = input().unwrap();
+        let b = input().unwrap();
+        println!("{}", a + b);
+        // 入力の終わりでは空の文字列を変換しようとして Err になる
+        print(input().is_err());
+    "#;
+    for (out, err) in run_both("40\n2\n", code) {
+        assert_eq!(out, "> > 42\n> true\n");
+        assert_eq!(err, "");
+    }
+}
+
+#[test]
+fn prompt_can_be_changed() {
+    let out = OutputBuffer::new();
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .set_stdin(Cursor::new("7\n"))
+        .set_stdout(out.clone())
+        .set_prompt("数を入力: ");
+    let v = interpreter.eval_str("input().unwrap() * 6").unwrap();
+    assert_eq!(v.as_i64(), Some(42));
+    assert_eq!(out.contents(), "数を入力: ");
+}
+
+// 解析エラーは差し替えた標準エラー出力に書く
+#[test]
+fn parse_errors_go_to_stderr() {
+    for (out, err) in run_both("", "println!(\"前\"); ) println!(\"後\");") {
+        assert_eq!(out, "前\n後\n");
+        assert!(err.starts_with("[解析エラー] "), "{}", err);
+    }
+}
+
+// 書き込めない出力は Io の実行時エラーになる
+#[test]
+fn write_failures_are_io_errors() {
+    struct Broken;
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "閉じています"))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let mut interpreter = Interpreter::new();
+    interpreter.set_stdout(Broken);
+    let e = interpreter.eval_str("println!(\"x\")").unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Io);
+    assert_eq!(e.message, "標準出力に書き込めません: 閉じています");
+}
+
+// インタプリタごとに別の出力を使う
+#[test]
+fn interpreters_have_separate_streams() {
+    let (first, second) = (OutputBuffer::new(), OutputBuffer::new());
+    let mut a = Interpreter::new();
+    a.set_stdout(first.clone());
+    let mut b = Interpreter::new();
+    b.set_stdout(second.clone());
+    a.eval_str("print(1)").unwrap();
+    b.eval_str("print(2)").unwrap();
+    a.eval_str("print(3)").unwrap();
+    assert_eq!(first.contents(), "1\n3\n");
+    assert_eq!(second.contents(), "2\n");
+}
+
+// Rust の関数の中で評価する別のインタプリタは、そのインタプリタの出力に書く
+#[test]
+fn nested_interpreters_keep_their_own_streams() {
+    let (outer, inner) = (OutputBuffer::new(), OutputBuffer::new());
+    let mut host = Interpreter::new();
+    host.set_stdout(outer.clone());
+    let plugin_out = inner.clone();
+    host.register_fn("run_plugin", move || {
+        let mut plugin = Interpreter::new();
+        plugin.set_stdout(plugin_out.clone());
+        plugin.eval_str("println!(\"plugin\")").is_ok()
+    });
+    host.eval_str("println!(\"host\"); run_plugin(); println!(\"host again\")")
+        .unwrap();
+    assert_eq!(outer.take(), "host\nhost again\n");
+    assert_eq!(inner.take(), "plugin\n");
+}
@@ -0,0 +1,40 @@
+// #[test] の付いた nasl の関数を Interpreter::run_tests で実行し、
+// アサーションの失敗がその関数の結果になることを確かめる
+use nanai_simple_lang::eval::{ErrorKind, Interpreter};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
+
+#[test]
+fn failed_assertions_fail_only_their_test() {
+    let code = r#"
+        fn double(x: i64) -> i64 { x * 2 }
+        #[test]
+        fn doubles() { assert_eq!(double(4), 8); }
+        #[test]
+        fn wrong() { assert_eq!(double(2), 5, "double(2) は {}", 4); }
+        #[test]
+        fn unfinished() { todo!() }
+        fn helper() { panic!("テストではない関数は呼ばれない") }
+    "#;
+    let results = Interpreter::new()
+        .run_tests(&parse(&tokenize(code)))
+        .unwrap();
+    let names: Vec<&str> = results.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["doubles", "wrong", "unfinished"]);
+    assert!(results[0].1.is_ok());
+    let e = results[1].1.as_ref().unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Assert);
+    assert!(e.message.contains("double(2) は 4"), "{}", e.message);
+    assert!(e.message.contains("左辺: 4, 右辺: 5"), "{}", e.message);
+    assert!(results[2].1.is_err());
+}
+
+// テストより先にトップレベルの文を評価し、そこで失敗すればテストは実行しない
+#[test]
+fn top_level_errors_stop_before_tests() {
+    let code = "let v = [1]; v[3]; #[test] fn never() { panic!() }";
+    let e = Interpreter::new()
+        .run_tests(&parse(&tokenize(code)))
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Index);
+}
@@ -0,0 +1,81 @@
+// examples などのスクリプトを木をたどる評価器とバイトコードの VM の両方で実行し、
+// 出力と結果（実行時エラーを含む）が同じになることを確かめる
+mod common;
+
+use common::eval_on;
+use nanai_simple_lang::ast::{Expr, Stmt};
+use nanai_simple_lang::eval::{self, Interpreter, OutputBuffer};
+use nanai_simple_lang::lexer::tokenize;
+use nanai_simple_lang::parser::parse;
+use std::fs;
+use std::io;
+
+// nasl コマンドと同じく、main 関数があれば最後に main() を呼ぶ
+fn load(path: &str) -> Vec<Stmt> {
+    let code = fs::read_to_string(path).expect("ファイルが読み込めません");
+    let mut stmts = parse(&tokenize(&code));
+    let has_main = stmts
+        .iter()
+        .any(|s| matches!(s, Stmt::FuncDef { name, .. } if name == "main"));
+    if has_main {
+        stmts.push(Stmt::Expr(Expr::Call(
+            "main".to_string(),
+            vec![],
+            Default::default(),
+            Default::default(),
+        )));
+    }
+    stmts
+}
+
+// 出力と、結果の値か実行時エラーを文字列にする
+fn run(path: String, vm: bool) -> (String, String) {
+    eval::with_stack(eval::DEFAULT_STACK_SIZE, move || {
+        let stmts = load(&path);
+        let stdout = OutputBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .set_stdin(io::empty())
+            .set_stdout(stdout.clone())
+            .set_stderr(stdout.clone());
+        let result = match eval_on(&mut interpreter, &stmts, vm) {
+            Ok(value) => format!("{:?}", value),
+            Err(e) => format!("{:?}", e),
+        };
+        (stdout.take(), result)
+    })
+}
+
+fn scripts() -> Vec<String> {
+    let mut paths: Vec<String> = fs::read_dir("examples")
+        .expect("examples が読めません")
+        .map(|entry| entry.unwrap().path().to_string_lossy().into_owned())
+        .filter(|path| path.ends_with(".nasl"))
+        .collect();
+    paths.sort();
+    paths.extend(["main.nasl", "test2.nasl", "lib.nasl"].map(String::from));
+    paths
+}
+
+#[test]
+fn vm_matches_tree_walker() {
+    for path in scripts() {
+        let tree = run(path.clone(), false);
+        let vm = run(path.clone(), true);
+        assert_eq!(tree, vm, "{} の実行結果が違います", path);
+    }
+}
+
+#[test]
+fn disassembles_every_script() {
+    for path in scripts() {
+        let text = eval::with_stack(eval::DEFAULT_STACK_SIZE, move || {
+            let stmts = load(&path);
+            let program = Interpreter::new()
+                .compile(&stmts)
+                .unwrap_or_else(|e| panic!("{} をコンパイルできません: {:?}", path, e));
+            program.to_string()
+        });
+        assert!(text.starts_with("== "), "{}", text);
+    }
+}